@@ -0,0 +1,19 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rustwemoji_parser::{parse_ref, Parser, ParserOptions};
+
+const MESSAGE: &str = "Hello world! 👋 This is a fairly ordinary chat message 🔥🔥 \
+with some text, a flag 🇯🇵, a family 👨‍👩‍👧‍👦 and a thumbs up 👍🏽 at the end.";
+
+fn bench_parse(c: &mut Criterion) {
+    let message = MESSAGE.repeat(16);
+    c.bench_function("parse_ref", |b| b.iter(|| parse_ref(black_box(&message))));
+    let parser = Parser::new();
+    c.bench_function("parse", |b| b.iter(|| parser.parse(black_box(&message))));
+    let parser = Parser::with_options(ParserOptions::builder().coalesce_text(false).build());
+    c.bench_function("parse_per_grapheme", |b| {
+        b.iter(|| parser.parse(black_box(&message)))
+    });
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,111 @@
+use crate::{ParseError, Parser, Token};
+
+/// `:name:` smilies bundled with phpBB and SMF, sorted by name
+const SMILIES: &[(&str, &str)] = &[
+    ("angry", "😠"),
+    ("arrow", "➡️"),
+    ("cheesy", "😁"),
+    ("cool", "😎"),
+    ("cry", "😢"),
+    ("evil", "😈"),
+    ("geek", "🤓"),
+    ("grin", "😁"),
+    ("idea", "💡"),
+    ("lol", "😆"),
+    ("mrgreen", "😁"),
+    ("oops", "😳"),
+    ("razz", "😛"),
+    ("roll", "🙄"),
+    ("sad", "🙁"),
+    ("shock", "😲"),
+    ("smile", "🙂"),
+    ("smiley", "😃"),
+    ("twisted", "👿"),
+    ("ugeek", "🤓"),
+    ("wink", "😉"),
+];
+
+fn smiley(name: &str, parser: &Parser) -> Option<Token> {
+    let i = SMILIES.binary_search_by_key(&name, |(n, _)| n).ok()?;
+    let emoji = SMILIES[i].1;
+    Some(Token::new_emoji(emoji, parser.emoji_png(emoji)?))
+}
+
+impl Parser {
+    /// Parse BBCode, `[emoji]name[/emoji]` and `:smiley:` smilies become emoji tokens
+    ///
+    /// The content of an `[emoji]` tag is an emoji or the name of one, it is kept as text
+    /// when the parser doesn't know it.
+    pub fn parse_bbcode(&self, s: &str) -> Result<Vec<Token>, ParseError> {
+        const OPEN: &str = "[emoji]";
+        const CLOSE: &str = "[/emoji]";
+        let mut tokens = Vec::new();
+        let (mut text, mut at) = (0, 0);
+        while let Some(start) = s[at..].find(OPEN).map(|i| at + i) {
+            let content = start + OPEN.len();
+            let found = s[content..]
+                .find(CLOSE)
+                .and_then(|len| Some((self.bbcode_emoji(s[content..content + len].trim())?, len)));
+            match found {
+                Some((token, len)) => {
+                    tokens.extend(self.tokenize(&s[text..start])?);
+                    tokens.push(token);
+                    at = content + len + CLOSE.len();
+                    text = at;
+                }
+                None => at = content,
+            }
+        }
+        tokens.extend(self.tokenize(&s[text..])?);
+        Ok(crate::replace_shortcodes(tokens, &|name| {
+            smiley(name, self)
+        }))
+    }
+
+    /// Token of the content of an `[emoji]` tag
+    fn bbcode_emoji(&self, name: &str) -> Option<Token> {
+        let single = |s: &str| {
+            let mut tokens = self.parse(s).ok()?;
+            match tokens.len() == 1 && !matches!(tokens[0], Token::Text(_)) {
+                true => tokens.pop(),
+                false => None,
+            }
+        };
+        single(name)
+            .or_else(|| single(&format!(":{}:", name)))
+            .or_else(|| smiley(name, self))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_parse_bbcode() {
+        assert!(SMILIES.windows(2).all(|w| w[0].0 < w[1].0));
+        let emoji = |s| Token::new_emoji(s, crate::get(s).unwrap());
+        let tokens = Parser::new()
+            .parse_bbcode(
+                "[b]hi[/b] [emoji]👍[/emoji][emoji] lol [/emoji] :oops: [emoji]nope[/emoji]",
+            )
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_text("[b]hi[/b] "),
+                emoji("👍"),
+                emoji("😆"),
+                Token::new_text(" "),
+                emoji("😳"),
+                Token::new_text(" [emoji]nope[/emoji]"),
+            ]
+        );
+        #[cfg(feature = "shortcode")]
+        assert_eq!(
+            Parser::new()
+                .parse_bbcode("[emoji]thumbsup[/emoji]")
+                .unwrap(),
+            vec![emoji("👍")]
+        );
+    }
+}
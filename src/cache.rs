@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::future::Future;
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Future returned by [`EmojiCache`] methods
+pub type CacheFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Storage of downloaded emoji images, such as memory, files or a shared cache server
+///
+/// Keys are made of ascii letters, digits and `_`. Failures are cache misses, the image is
+/// downloaded again.
+pub trait EmojiCache: Debug + Send + Sync {
+    /// Image stored under `key`
+    fn get<'a>(&'a self, key: &'a str) -> CacheFuture<'a, Option<Vec<u8>>>;
+    /// Store `image` under `key`
+    fn put<'a>(&'a self, key: &'a str, image: &'a [u8]) -> CacheFuture<'a, ()>;
+    /// Image stored under `key`, even past its ttl, so it can be refreshed with a conditional
+    /// request. Caches dropping expired images return `None`
+    fn get_stale<'a>(&'a self, key: &'a str) -> CacheFuture<'a, Option<Vec<u8>>> {
+        let _ = key;
+        Box::pin(async { None })
+    }
+}
+
+/// In-memory cache dropping the least recently used image when full
+///
+/// Expired images are kept until they are dropped, to be refreshed with conditional requests.
+#[derive(Debug)]
+pub struct MemoryCache {
+    /// Images and the time they were stored
+    cache: LruCache<String, (Vec<u8>, Instant)>,
+    ttl: Option<Duration>,
+}
+
+impl MemoryCache {
+    /// Cache of `capacity` images, each kept for `ttl` or for ever if `None`
+    pub fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+        Self {
+            cache: LruCache::new(capacity, None),
+            ttl,
+        }
+    }
+}
+
+impl EmojiCache for MemoryCache {
+    fn get<'a>(&'a self, key: &'a str) -> CacheFuture<'a, Option<Vec<u8>>> {
+        Box::pin(async move {
+            let (image, stored) = self.cache.get(&key.to_string())?;
+            match self.ttl {
+                Some(ttl) if stored.elapsed() >= ttl => None,
+                _ => Some(image),
+            }
+        })
+    }
+    fn put<'a>(&'a self, key: &'a str, image: &'a [u8]) -> CacheFuture<'a, ()> {
+        Box::pin(async move {
+            self.cache
+                .insert(key.to_string(), (image.to_vec(), Instant::now()))
+        })
+    }
+    fn get_stale<'a>(&'a self, key: &'a str) -> CacheFuture<'a, Option<Vec<u8>>> {
+        Box::pin(async move { self.cache.get(&key.to_string()).map(|(image, _)| image) })
+    }
+}
+
+/// Bounded cache dropping the least recently used entry when full
+pub(crate) struct LruCache<K, V> {
+    capacity: usize,
+    /// How long an entry stays valid, forever if `None`
+    ttl: Option<Duration>,
+    inner: Mutex<Inner<K, V>>,
+}
+
+struct Inner<K, V> {
+    /// Value, time it was inserted and tick it was last used at
+    entries: HashMap<K, (V, Instant, u64)>,
+    tick: u64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    pub(crate) fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+        Self {
+            capacity,
+            ttl,
+            inner: Mutex::new(Inner {
+                entries: HashMap::new(),
+                tick: 0,
+            }),
+        }
+    }
+
+    pub(crate) fn get(&self, key: &K) -> Option<V> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.tick += 1;
+        let tick = inner.tick;
+        let (value, inserted, used) = inner.entries.get_mut(key)?;
+        if self.ttl.is_some_and(|ttl| inserted.elapsed() >= ttl) {
+            inner.entries.remove(key);
+            return None;
+        }
+        *used = tick;
+        Some(value.clone())
+    }
+
+    pub(crate) fn insert(&self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.tick += 1;
+        let tick = inner.tick;
+        if inner.entries.len() >= self.capacity && !inner.entries.contains_key(&key) {
+            // Linear, but caches of emoji are small
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, _, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                inner.entries.remove(&oldest);
+            }
+        }
+        inner.entries.insert(key, (value, Instant::now(), tick));
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.entries.len()
+    }
+}
+
+impl<K, V> std::fmt::Debug for LruCache<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LruCache")
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Cache of files in a directory, which outlives the process
+///
+/// The files are read and written with blocking calls. Expired files are kept until the
+/// directory is trimmed, to be refreshed with conditional requests.
+#[derive(Debug)]
+pub struct FileCache {
+    dir: PathBuf,
+    /// Total size of the files, the oldest ones are removed past it
+    max_size: u64,
+    /// How long a file stays valid after it was written, forever if `None`
+    ttl: Option<Duration>,
+}
+
+impl FileCache {
+    /// Cache in `dir` of at most `max_size` bytes, each file kept for `ttl` or for ever
+    /// if `None`
+    pub fn new(dir: impl Into<PathBuf>, max_size: u64, ttl: Option<Duration>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_size,
+            ttl,
+        }
+    }
+
+    /// Contents of the file `name`, errors are treated as misses
+    fn read(&self, name: &str) -> Option<Vec<u8>> {
+        let path = self.dir.join(name);
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if self.ttl.is_some_and(|ttl| age >= ttl) {
+            return None;
+        }
+        std::fs::read(path).ok()
+    }
+
+    /// Write the file `name`, errors only cost a download on the next start
+    fn write(&self, name: &str, data: &[u8]) {
+        if std::fs::create_dir_all(&self.dir).is_err() {
+            return;
+        }
+        if std::fs::write(self.dir.join(name), data).is_ok() {
+            self.trim();
+        }
+    }
+
+    /// Remove the oldest files until the directory fits in `max_size`
+    fn trim(&self) {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut files = entries
+            .flatten()
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+                Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+            })
+            .collect::<Vec<_>>();
+        let mut size = files.iter().map(|(_, len, _)| len).sum::<u64>();
+        files.sort();
+        for (_, len, path) in files {
+            if size <= self.max_size {
+                break;
+            }
+            if std::fs::remove_file(path).is_ok() {
+                size -= len;
+            }
+        }
+    }
+}
+
+impl EmojiCache for FileCache {
+    fn get<'a>(&'a self, key: &'a str) -> CacheFuture<'a, Option<Vec<u8>>> {
+        Box::pin(async move { self.read(key) })
+    }
+    fn put<'a>(&'a self, key: &'a str, image: &'a [u8]) -> CacheFuture<'a, ()> {
+        Box::pin(async move { self.write(key, image) })
+    }
+    fn get_stale<'a>(&'a self, key: &'a str) -> CacheFuture<'a, Option<Vec<u8>>> {
+        Box::pin(async move { std::fs::read(self.dir.join(key)).ok() })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_lru() {
+        let cache = LruCache::new(2, None);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+        assert_eq!(cache.get(&1), Some("a"));
+        cache.insert(3, "c");
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some("a"));
+        assert_eq!(cache.get(&3), Some("c"));
+        assert_eq!(cache.len(), 2);
+    }
+    #[test]
+    fn test_ttl() {
+        let cache = LruCache::new(2, Some(Duration::ZERO));
+        cache.insert(1, "a");
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.len(), 0);
+        let cache = LruCache::new(0, None);
+        cache.insert(1, "a");
+        assert_eq!(cache.get(&1), None);
+    }
+    #[test]
+    fn test_file_cache() {
+        let dir = std::env::temp_dir().join(format!("rustwemoji-test-{}", std::process::id()));
+        let cache = FileCache::new(&dir, 8, None);
+        cache.write("a", b"aaaa");
+        cache.write("b", b"bbbb");
+        assert_eq!(cache.read("a"), Some(b"aaaa".to_vec()));
+        assert_eq!(cache.read("c"), None);
+        // The oldest files go once the directory is too big
+        std::thread::sleep(Duration::from_millis(10));
+        cache.write("c", b"cccc");
+        assert_eq!(cache.read("c"), Some(b"cccc".to_vec()));
+        assert_eq!(
+            cache.read("a").is_some() as u8 + cache.read("b").is_some() as u8,
+            1
+        );
+        let expired = FileCache::new(&dir, 8, Some(Duration::ZERO));
+        assert_eq!(expired.read("c"), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,331 @@
+use crate::{ParseError, ParserOptions, TokenRef};
+#[cfg(feature = "regex")]
+use regex::{Captures, Regex};
+use std::ops::Range;
+#[cfg(feature = "regex")]
+use std::sync::OnceLock;
+
+#[cfg(feature = "regex")]
+const RE_DISCORD_MARKUP: &str = concat!(
+    r"<(?:(?P<animated>a)?:(?P<emoji>[a-zA-Z0-9_]+):(?P<emoji_id>[0-9]{17,19})",
+    r"|(?P<mention>@!?|@&|#)(?P<mention_id>[0-9]{17,19})",
+    r"|t:(?P<unix>-?[0-9]+)(?::(?P<style>[tTdDfFR]))?",
+    r"|/(?P<command>[-_\p{L}\p{N}]+(?: [-_\p{L}\p{N}]+){0,2}):(?P<command_id>[0-9]{17,19}))>",
+    r"|```(?:(?P<language>[a-zA-Z0-9_+\-.#]+)\n)?(?P<block>[\s\S]+?)```",
+    r"|``(?P<code2>[\s\S]+?)``|`(?P<code>[^`]+)`",
+);
+
+/// Mention of a Discord entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mention {
+    /// `<@id>` or `<@!id>`
+    User(u64),
+    /// `<#id>`
+    Channel(u64),
+    /// `<@&id>`
+    Role(u64),
+}
+
+impl Mention {
+    /// Snowflake id of the mentioned entity
+    pub fn id(self) -> u64 {
+        match self {
+            Self::User(id) | Self::Channel(id) | Self::Role(id) => id,
+        }
+    }
+}
+
+/// Display style of a Discord timestamp
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TimestampStyle {
+    /// `t`, e.g. 16:20
+    ShortTime,
+    /// `T`, e.g. 16:20:30
+    LongTime,
+    /// `d`, e.g. 20/04/2021
+    ShortDate,
+    /// `D`, e.g. 20 April 2021
+    LongDate,
+    /// `f`, e.g. 20 April 2021 16:20, used when no style is given
+    #[default]
+    ShortDateTime,
+    /// `F`, e.g. Tuesday, 20 April 2021 16:20
+    LongDateTime,
+    /// `R`, e.g. 2 months ago
+    Relative,
+}
+
+impl TimestampStyle {
+    /// Style of a markup flag
+    pub fn from_char(c: char) -> Option<Self> {
+        Some(match c {
+            't' => Self::ShortTime,
+            'T' => Self::LongTime,
+            'd' => Self::ShortDate,
+            'D' => Self::LongDate,
+            'f' => Self::ShortDateTime,
+            'F' => Self::LongDateTime,
+            'R' => Self::Relative,
+            _ => return None,
+        })
+    }
+    /// Markup flag of the style
+    pub fn as_char(self) -> char {
+        match self {
+            Self::ShortTime => 't',
+            Self::LongTime => 'T',
+            Self::ShortDate => 'd',
+            Self::LongDate => 'D',
+            Self::ShortDateTime => 'f',
+            Self::LongDateTime => 'F',
+            Self::Relative => 'R',
+        }
+    }
+}
+
+#[cfg(feature = "regex")]
+fn discord_regex() -> Result<&'static Regex, ParseError> {
+    static RE: OnceLock<Result<Regex, regex::Error>> = OnceLock::new();
+    match RE.get_or_init(|| Regex::new(RE_DISCORD_MARKUP)) {
+        Ok(re) => Ok(re),
+        Err(e) => Err(e.clone().into()),
+    }
+}
+
+/// Make sure the markup can be searched for with `options`
+pub(crate) fn check(options: &ParserOptions) -> Result<(), ParseError> {
+    options.validate()?;
+    #[cfg(feature = "regex")]
+    discord_regex()?;
+    Ok(())
+}
+
+/// Find the first markup enabled in `options`, returns its range and token
+#[cfg(feature = "regex")]
+pub(crate) fn find_markup<'a>(
+    s: &'a str,
+    options: &ParserOptions,
+) -> Option<(Range<usize>, TokenRef<'a>)> {
+    let re = discord_regex().ok()?;
+    let mut from = 0;
+    while let Some(c) = re.captures_at(s, from) {
+        let m = c.get(0)?;
+        if let Some(token) = markup_token(&c, options) {
+            return Some((m.range(), token));
+        }
+        // Disabled markup may contain enabled one, e.g. emoji in code
+        from = m.start() + 1;
+    }
+    None
+}
+
+#[cfg(feature = "regex")]
+fn markup_token<'a>(c: &Captures<'a>, options: &ParserOptions) -> Option<TokenRef<'a>> {
+    if let Some(id) = c.name("emoji_id") {
+        return options.custom_emoji.then_some(TokenRef::CustomEmoji {
+            id: id.as_str().parse().ok()?,
+            name: c.name("emoji")?.as_str(),
+            animated: c.name("animated").is_some(),
+        });
+    }
+    if let Some(id) = c.name("mention_id") {
+        let id = id.as_str().parse().ok()?;
+        let mention = match c.name("mention")?.as_str() {
+            "#" => Mention::Channel(id),
+            "@&" => Mention::Role(id),
+            _ => Mention::User(id),
+        };
+        return options.mentions.then_some(TokenRef::Mention(mention));
+    }
+    if let Some(unix) = c.name("unix") {
+        let style = match c.name("style") {
+            Some(m) => TimestampStyle::from_char(m.as_str().chars().next()?)?,
+            None => TimestampStyle::default(),
+        };
+        return options.timestamps.then_some(TokenRef::Timestamp {
+            unix: unix.as_str().parse().ok()?,
+            style,
+        });
+    }
+    if let Some(id) = c.name("command_id") {
+        return options.mentions.then_some(TokenRef::CommandMention {
+            name: c.name("command")?.as_str(),
+            id: id.as_str().parse().ok()?,
+        });
+    }
+    if let Some(code) = c.name("block") {
+        return options.code.then_some(TokenRef::CodeBlock {
+            language: c.name("language").map(|m| m.as_str()),
+            code: code.as_str(),
+        });
+    }
+    let code = c.name("code").or_else(|| c.name("code2"))?;
+    options.code.then_some(TokenRef::Code(code.as_str()))
+}
+
+/// Find the first markup enabled in `options`, returns its range and token
+#[cfg(not(feature = "regex"))]
+pub(crate) fn find_markup<'a>(
+    s: &'a str,
+    options: &ParserOptions,
+) -> Option<(Range<usize>, TokenRef<'a>)> {
+    let mut from = 0;
+    while let Some(i) = s[from..].find(['<', '`']) {
+        let start = from + i;
+        if let Some((len, token)) = scan_markup(&s[start..], options) {
+            return Some((start..start + len, token));
+        }
+        from = start + 1;
+    }
+    None
+}
+
+/// Match markup at the start of `s`, returns the length and token
+#[cfg(not(feature = "regex"))]
+fn scan_markup<'a>(s: &'a str, options: &ParserOptions) -> Option<(usize, TokenRef<'a>)> {
+    if s.starts_with('`') {
+        let (token, rest) = scan_code(s).filter(|_| options.code)?;
+        return Some((s.len() - rest.len(), token));
+    }
+    let rest = s.strip_prefix('<')?;
+    let (token, rest) = match rest.as_bytes().first()? {
+        b'@' | b'#' if options.mentions => scan_mention(rest)?,
+        b'/' if options.mentions => scan_command_mention(rest)?,
+        b'a' | b':' if options.custom_emoji => scan_custom_emoji(rest)?,
+        b't' if options.timestamps => scan_timestamp(rest)?,
+        _ => return None,
+    };
+    Some((s.len() - rest.len(), token))
+}
+
+/// Match `a?:name:id>`, returns the token and the input after it
+#[cfg(not(feature = "regex"))]
+fn scan_custom_emoji(s: &str) -> Option<(TokenRef<'_>, &str)> {
+    let animated = s.starts_with('a');
+    let rest = s.strip_prefix('a').unwrap_or(s);
+    let rest = rest.strip_prefix(':')?;
+    let name = rest
+        .bytes()
+        .take_while(|b| b.is_ascii_alphanumeric() || *b == b'_')
+        .count();
+    if name == 0 {
+        return None;
+    }
+    let (name, rest) = rest.split_at(name);
+    let (id, rest) = scan_id(rest.strip_prefix(':')?)?;
+    Some((TokenRef::CustomEmoji { id, name, animated }, rest))
+}
+
+/// Match `@id>`, `@!id>`, `@&id>` or `#id>`, returns the token and the input after it
+#[cfg(not(feature = "regex"))]
+fn scan_mention(s: &str) -> Option<(TokenRef<'_>, &str)> {
+    let (kind, rest): (fn(u64) -> Mention, _) = if let Some(rest) = s.strip_prefix("@&") {
+        (Mention::Role, rest)
+    } else if let Some(rest) = s.strip_prefix('#') {
+        (Mention::Channel, rest)
+    } else {
+        let rest = s.strip_prefix('@')?;
+        (Mention::User, rest.strip_prefix('!').unwrap_or(rest))
+    };
+    let (id, rest) = scan_id(rest)?;
+    Some((TokenRef::Mention(kind(id)), rest))
+}
+
+/// Match `/name:id>`, the name being up to three words
+#[cfg(not(feature = "regex"))]
+fn scan_command_mention(s: &str) -> Option<(TokenRef<'_>, &str)> {
+    let (name, rest) = s.strip_prefix('/')?.split_once(':')?;
+    let words = name.split(' ');
+    if words.clone().count() > 3 || !words.into_iter().all(is_command_word) {
+        return None;
+    }
+    let (id, rest) = scan_id(rest)?;
+    Some((TokenRef::CommandMention { name, id }, rest))
+}
+
+#[cfg(not(feature = "regex"))]
+fn is_command_word(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(is_command_char)
+}
+
+/// Whether `c` can be part of a slash command name
+pub(crate) fn is_command_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_'
+}
+
+/// Match an inline code span or a code block, returns the token and the input after it
+#[cfg(not(feature = "regex"))]
+fn scan_code(s: &str) -> Option<(TokenRef<'_>, &str)> {
+    if let Some((body, rest)) = delimited(s, "```") {
+        let (language, code) = match body.split_once('\n') {
+            Some((language, code)) if is_language(language) && !code.is_empty() => {
+                (Some(language), code)
+            }
+            _ => (None, body),
+        };
+        return Some((TokenRef::CodeBlock { language, code }, rest));
+    }
+    let (code, rest) =
+        delimited(s, "``").or_else(|| delimited(s, "`").filter(|(code, _)| !code.contains('`')))?;
+    Some((TokenRef::Code(code), rest))
+}
+
+/// Whether markup following `before` is escaped by an odd number of backslashes
+pub(crate) fn is_escaped(before: &str) -> bool {
+    before.bytes().rev().take_while(|b| *b == b'\\').count() % 2 == 1
+}
+
+/// Find the first spoiler, returns its range and token
+pub(crate) fn find_spoiler(s: &str) -> Option<(Range<usize>, TokenRef<'_>)> {
+    let start = s.find("||")?;
+    let (inner, rest) = delimited(&s[start..], "||")?;
+    Some((start..s.len() - rest.len(), TokenRef::Spoiler(inner)))
+}
+
+/// Split `s` into the non-empty content between `delim`s and the input after them
+fn delimited<'a>(s: &'a str, delim: &str) -> Option<(&'a str, &'a str)> {
+    let rest = s.strip_prefix(delim)?;
+    let first = rest.chars().next()?.len_utf8();
+    let end = first + rest[first..].find(delim)?;
+    Some((&rest[..end], &rest[end + delim.len()..]))
+}
+
+#[cfg(not(feature = "regex"))]
+fn is_language(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"_+-.#".contains(&b))
+}
+
+/// Match `t:unix>` or `t:unix:style>`, returns the token and the input after it
+#[cfg(not(feature = "regex"))]
+fn scan_timestamp(s: &str) -> Option<(TokenRef<'_>, &str)> {
+    let rest = s.strip_prefix("t:")?;
+    let sign = usize::from(rest.starts_with('-'));
+    let len = sign + rest[sign..].bytes().take_while(u8::is_ascii_digit).count();
+    if len == sign {
+        return None;
+    }
+    let (unix, rest) = rest.split_at(len);
+    let (style, rest) = match rest.strip_prefix(':') {
+        Some(rest) => {
+            let mut chars = rest.chars();
+            (TimestampStyle::from_char(chars.next()?)?, chars.as_str())
+        }
+        None => (TimestampStyle::default(), rest),
+    };
+    let rest = rest.strip_prefix('>')?;
+    let unix = unix.parse().ok()?;
+    Some((TokenRef::Timestamp { unix, style }, rest))
+}
+
+/// Match a snowflake closing the markup, returns it and the input after `>`
+#[cfg(not(feature = "regex"))]
+fn scan_id(s: &str) -> Option<(u64, &str)> {
+    let len = s.bytes().take_while(u8::is_ascii_digit).count();
+    if !(17..=19).contains(&len) {
+        return None;
+    }
+    let rest = s[len..].strip_prefix('>')?;
+    Some((s[..len].parse().ok()?, rest))
+}
@@ -0,0 +1,164 @@
+/// Names of Discord's emoji picker the bundled table doesn't know, sorted by name
+const NAMES: &[(&str, &str)] = &[
+    ("airplane_small", "🛩️"),
+    ("anger_right", "🗯️"),
+    ("archery", "🏹"),
+    ("asterisk", "*️⃣"),
+    ("back_of_hand", "🤚"),
+    ("ballot_box_with_ballot", "🗳️"),
+    ("basketball_player", "⛹️"),
+    ("bellhop_bell", "🛎️"),
+    ("biohazard_sign", "☣️"),
+    ("bottle_with_popping_cork", "🍾"),
+    ("boxing_gloves", "🥊"),
+    ("call_me", "🤙"),
+    ("card_box", "🗃️"),
+    ("cartwheel", "🤸"),
+    ("champagne_glass", "🥂"),
+    ("cheese_wedge", "🧀"),
+    ("clinking_glass", "🥂"),
+    ("cloud_lightning", "🌩️"),
+    ("cloud_rain", "🌧️"),
+    ("cloud_snow", "🌨️"),
+    ("cloud_tornado", "🌪️"),
+    ("cloud_with_tornado", "🌪️"),
+    ("couch", "🛋️"),
+    ("cricket_bat_ball", "🏏"),
+    ("cross", "✝️"),
+    ("dagger_knife", "🗡️"),
+    ("dark_sunglasses", "🕶️"),
+    ("derelict_house_building", "🏚️"),
+    ("desktop", "🖥️"),
+    ("dividers", "🗂️"),
+    ("double_vertical_bar", "⏸️"),
+    ("dove_of_peace", "🕊️"),
+    ("drool", "🤤"),
+    ("drum_with_drumsticks", "🥁"),
+    ("eight", "8️⃣"),
+    ("eject_symbol", "⏏️"),
+    ("eye_in_speech_bubble", "👁️‍🗨️"),
+    ("face_palm", "🤦"),
+    ("face_with_rolling_eyes", "🙄"),
+    ("face_with_symbols_over_mouth", "🤬"),
+    ("first_place", "🥇"),
+    ("five", "5️⃣"),
+    ("flag_black", "🏴"),
+    ("flag_white", "🏳️"),
+    ("fork_and_knife_with_plate", "🍽️"),
+    ("four", "4️⃣"),
+    ("frame_photo", "🖼️"),
+    ("french_bread", "🥖"),
+    ("frowning2", "☹️"),
+    ("gay_pride_flag", "🏳️‍🌈"),
+    ("goal", "🥅"),
+    ("hammer_pick", "⚒️"),
+    ("hand_splayed", "🖐️"),
+    ("hand_with_index_and_middle_finger_crossed", "🤞"),
+    ("hankey", "💩"),
+    ("hash", "#️⃣"),
+    ("head_bandage", "🤕"),
+    ("heavy_heart_exclamation_mark_ornament", "❣️"),
+    ("hot_dog", "🌭"),
+    ("house_buildings", "🏘️"),
+    ("karate_uniform", "🥋"),
+    ("kayak", "🛶"),
+    ("key2", "🗝️"),
+    ("keycap_ten", "🔟"),
+    ("kiwifruit", "🥝"),
+    ("left_fist", "🤛"),
+    ("liar", "🤥"),
+    ("lifter", "🏋️"),
+    ("linked_paperclips", "🖇️"),
+    ("lower_left_ballpoint_pen", "🖊️"),
+    ("lower_left_crayon", "🖍️"),
+    ("lower_left_fountain_pen", "🖋️"),
+    ("lower_left_paintbrush", "🖌️"),
+    ("man_in_business_suit_levitating", "🕴️"),
+    ("mantlepiece_clock", "🕰️"),
+    ("map", "🗺️"),
+    ("medal", "🏅"),
+    ("microphone2", "🎙️"),
+    ("money_mouth", "🤑"),
+    ("mouse_three_button", "🖱️"),
+    ("newspaper2", "🗞️"),
+    ("nine", "9️⃣"),
+    ("o2", "🅾️"),
+    ("oil", "🛢️"),
+    ("om_symbol", "🕉️"),
+    ("one", "1️⃣"),
+    ("paella", "🥘"),
+    ("park", "🏞️"),
+    ("pause_button", "⏸️"),
+    ("pen_ballpoint", "🖊️"),
+    ("pen_fountain", "🖋️"),
+    ("person_doing_cartwheel", "🤸"),
+    ("person_with_ball", "⛹️"),
+    ("projector", "📽️"),
+    ("race_car", "🏎️"),
+    ("racing_motorcycle", "🏍️"),
+    ("radioactive_sign", "☢️"),
+    ("railroad_track", "🛤️"),
+    ("record_button", "⏺️"),
+    ("reversed_hand_with_middle_finger_extended", "🖕"),
+    ("right_fist", "🤜"),
+    ("rolling_on_the_floor_laughing", "🤣"),
+    ("satellite_orbital", "🛰️"),
+    ("second_place", "🥈"),
+    ("seven", "7️⃣"),
+    ("shaking_hands", "🤝"),
+    ("shelled_peanut", "🥜"),
+    ("shopping_trolley", "🛒"),
+    ("sick", "🤢"),
+    ("six", "6️⃣"),
+    ("skull_crossbones", "☠️"),
+    ("sleuth_or_spy", "🕵️"),
+    ("slight_frown", "🙁"),
+    ("slight_smile", "🙂"),
+    ("sneeze", "🤧"),
+    ("snow_capped_mountain", "🏔️"),
+    ("speech_left", "🗨️"),
+    ("spiral_calendar_pad", "🗓️"),
+    ("spiral_note_pad", "🗒️"),
+    ("spy", "🕵️"),
+    ("stop_button", "⏹️"),
+    ("stuffed_pita", "🥙"),
+    ("table_tennis", "🏓"),
+    ("thermometer_face", "🤒"),
+    ("third_place", "🥉"),
+    ("three", "3️⃣"),
+    ("three_button_mouse", "🖱️"),
+    ("thunder_cloud_rain", "⛈️"),
+    ("timer", "⏲️"),
+    ("tools", "🛠️"),
+    ("track_next", "⏭️"),
+    ("track_previous", "⏮️"),
+    ("two", "2️⃣"),
+    ("umbrella2", "☂️"),
+    ("upside_down", "🙃"),
+    ("urn", "⚱️"),
+    ("white_sun_behind_cloud", "🌥️"),
+    ("white_sun_behind_cloud_with_rain", "🌦️"),
+    ("white_sun_cloud", "🌥️"),
+    ("white_sun_rain_cloud", "🌦️"),
+    ("white_sun_small_cloud", "🌤️"),
+    ("white_sun_with_small_cloud", "🌤️"),
+    ("zero", "0️⃣"),
+];
+
+/// Unicode emoji called `name` by Discord
+pub(crate) fn emoji_by_name(name: &str) -> Option<&'static str> {
+    let i = NAMES.binary_search_by_key(&name, |(n, _)| n).ok()?;
+    Some(NAMES[i].1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_names() {
+        assert!(NAMES.windows(2).all(|w| w[0].0 < w[1].0));
+        for (name, emoji) in NAMES {
+            assert!(crate::get(emoji).is_some(), "no asset for :{}:", name);
+        }
+    }
+}
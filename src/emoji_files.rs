@@ -0,0 +1,268 @@
+use crate::{EmojiAsset, EmojiSet, VS16};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+/// Emoji set loaded at runtime from image files named by their code points
+///
+/// Files are named like `1f44d-1f3fd.png`, code points in hex separated by `-` or `_`, with
+/// an optional `emoji_u` prefix as in Noto Emoji. Emoji without a png are skipped, an svg next to
+/// the png is used for [`EmojiAsset::svg`]. `U+FE0F` is ignored in names and graphemes.
+#[derive(Debug, Clone, Default)]
+pub struct FileEmojiSet {
+    assets: HashMap<String, EmojiAsset>,
+}
+
+impl FileEmojiSet {
+    /// Load the images in the directory at `path`, without its subdirectories
+    pub fn from_dir(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if emoji_file(&name).is_some() {
+                    files.push((name, std::fs::read(entry.path())?));
+                }
+            }
+        }
+        Ok(Self::from_files(files))
+    }
+    /// Load the images in the zip archive at `path`
+    pub fn from_zip(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_zip_bytes(&std::fs::read(path)?)
+    }
+    /// Load the images in a zip archive, stored or deflated
+    pub fn from_zip_bytes(zip: &[u8]) -> io::Result<Self> {
+        Ok(Self::from_files(read_zip(zip)?))
+    }
+    /// Number of emoji in the set
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    fn from_files(files: Vec<(String, Vec<u8>)>) -> Self {
+        let mut images = HashMap::<String, (Option<Vec<u8>>, Option<String>)>::new();
+        for (name, data) in files {
+            let name = name.rsplit('/').next().unwrap_or_default();
+            let Some((emoji, svg)) = emoji_file(name) else {
+                continue;
+            };
+            let image = images.entry(emoji).or_default();
+            match svg {
+                true => image.1 = String::from_utf8(data).ok(),
+                false => image.0 = Some(data),
+            }
+        }
+        let assets = images
+            .into_iter()
+            .filter_map(|(emoji, (png, svg))| {
+                let asset = EmojiAsset {
+                    png: Cow::Owned(png?),
+                    svg: svg.map(Cow::Owned),
+                };
+                Some((emoji, asset))
+            })
+            .collect();
+        Self { assets }
+    }
+}
+
+impl EmojiSet for FileEmojiSet {
+    fn get(&self, grapheme: &str) -> Option<EmojiAsset> {
+        match grapheme.contains(VS16) {
+            true => self.assets.get(&grapheme.replace(VS16, "")),
+            false => self.assets.get(grapheme),
+        }
+        .cloned()
+    }
+}
+
+/// Emoji named by a file name without `U+FE0F`, and whether the file is an svg
+fn emoji_file(name: &str) -> Option<(String, bool)> {
+    let (stem, extension) = name.rsplit_once('.')?;
+    let svg = match extension.to_ascii_lowercase().as_str() {
+        "png" => false,
+        "svg" => true,
+        _ => return None,
+    };
+    let stem = stem.strip_prefix("emoji_u").unwrap_or(stem);
+    let emoji = stem
+        .split(['-', '_'])
+        .map(|code| char::from_u32(u32::from_str_radix(code, 16).ok()?))
+        .filter(|c| *c != Some(VS16))
+        .collect::<Option<String>>()?;
+    (!emoji.is_empty()).then_some((emoji, svg))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid zip: {}", message),
+    )
+}
+
+fn u16_at(b: &[u8], at: usize) -> Option<usize> {
+    Some(u16::from_le_bytes(b.get(at..at + 2)?.try_into().ok()?) as usize)
+}
+
+fn u32_at(b: &[u8], at: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(b.get(at..at + 4)?.try_into().ok()?) as usize)
+}
+
+/// Names and contents of the files of a zip archive, read from its central directory
+fn read_zip(zip: &[u8]) -> io::Result<Vec<(String, Vec<u8>)>> {
+    // The end of central directory record is last, followed by a comment of up to 64KiB
+    let end = (0..=zip.len().saturating_sub(22))
+        .rev()
+        .take(65536 + 22)
+        .find(|at| zip[*at..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(|| invalid("no end of central directory"))?;
+    let count = u16_at(zip, end + 10).ok_or_else(|| invalid("truncated"))?;
+    let mut at = u32_at(zip, end + 16).ok_or_else(|| invalid("truncated"))?;
+    let mut files = Vec::with_capacity(count);
+    for _ in 0..count {
+        if !zip.get(at..).is_some_and(|b| b.starts_with(b"PK\x01\x02")) {
+            return Err(invalid("bad central directory entry"));
+        }
+        let header = (|| {
+            Some((
+                u16_at(zip, at + 10)?,
+                u32_at(zip, at + 20)?,
+                u32_at(zip, at + 24)?,
+                u16_at(zip, at + 28)?,
+                u16_at(zip, at + 30)? + u16_at(zip, at + 32)?,
+                u32_at(zip, at + 42)?,
+            ))
+        })();
+        // Method, compressed and uncompressed size, lengths of the name and of what follows it,
+        // offset of the local header
+        let (method, compressed, size, name_len, skip, local) =
+            header.ok_or_else(|| invalid("truncated"))?;
+        let name = zip
+            .get(at + 46..at + 46 + name_len)
+            .ok_or_else(|| invalid("truncated"))?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        at += 46 + name_len + skip;
+        if name.ends_with('/') {
+            continue;
+        }
+        if [compressed, size, local].contains(&0xffff_ffff) {
+            return Err(invalid("zip64 is not supported"));
+        }
+        let data = (|| {
+            if !zip.get(local..)?.starts_with(b"PK\x03\x04") {
+                return None;
+            }
+            let start = local + 30 + u16_at(zip, local + 26)? + u16_at(zip, local + 28)?;
+            zip.get(start..start + compressed)
+        })()
+        .ok_or_else(|| invalid("bad local header"))?;
+        let data = match method {
+            0 => data.to_vec(),
+            8 => miniz_oxide::inflate::decompress_to_vec_with_limit(data, size)
+                .map_err(|_| invalid("bad deflate stream"))?,
+            _ => return Err(invalid("unsupported compression method")),
+        };
+        if data.len() != size {
+            return Err(invalid("wrong uncompressed size"));
+        }
+        files.push((name, data));
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    /// Zip archive of `files`, deflated when `deflate` is set
+    fn zip(files: &[(&str, &[u8])], deflate: bool) -> Vec<u8> {
+        let (mut out, mut directory) = (Vec::new(), Vec::new());
+        for (name, data) in files {
+            let stored = match deflate {
+                true => miniz_oxide::deflate::compress_to_vec(data, 6),
+                false => data.to_vec(),
+            };
+            let mut header = vec![0; 26];
+            header[4..6].copy_from_slice(&(deflate as u16 * 8).to_le_bytes());
+            header[14..18].copy_from_slice(&(stored.len() as u32).to_le_bytes());
+            header[18..22].copy_from_slice(&(data.len() as u32).to_le_bytes());
+            header[22..24].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            directory.extend(b"PK\x01\x02\0\0");
+            directory.extend(&header);
+            directory.extend([0; 10]);
+            directory.extend((out.len() as u32).to_le_bytes());
+            directory.extend(name.as_bytes());
+            out.extend(b"PK\x03\x04");
+            out.extend(&header);
+            out.extend(name.as_bytes());
+            out.extend(stored);
+        }
+        let at = out.len() as u32;
+        out.extend(&directory);
+        out.extend(b"PK\x05\x06\0\0\0\0");
+        out.extend((files.len() as u16).to_le_bytes());
+        out.extend((files.len() as u16).to_le_bytes());
+        out.extend((directory.len() as u32).to_le_bytes());
+        out.extend(at.to_le_bytes());
+        out.extend([0; 2]);
+        out
+    }
+    #[test]
+    fn test_emoji_file() {
+        assert_eq!(
+            emoji_file("1f44d-1f3fd.png"),
+            Some(("👍🏽".to_string(), false))
+        );
+        assert_eq!(
+            emoji_file("emoji_u2764_fe0f.svg"),
+            Some(("❤".to_string(), true))
+        );
+        assert_eq!(emoji_file("1F600.PNG"), Some(("😀".to_string(), false)));
+        assert_eq!(emoji_file("LICENSE"), None);
+        assert_eq!(emoji_file("smile.png"), None);
+    }
+    #[test]
+    fn test_from_zip() {
+        let files: &[(&str, &[u8])] = &[
+            ("72x72/", b""),
+            ("72x72/1f44d.png", b"thumbs"),
+            ("72x72/2764-fe0f.png", b"heart"),
+            ("72x72/2764-fe0f.svg", b"<svg/>"),
+            ("README.md", b"emoji"),
+        ];
+        for deflate in [false, true] {
+            let set = FileEmojiSet::from_zip_bytes(&zip(files, deflate)).unwrap();
+            assert_eq!(set.len(), 2);
+            assert_eq!(set.get("👍").unwrap().png, b"thumbs".as_slice());
+            let heart = set.get("❤️").unwrap();
+            assert_eq!(heart.png, b"heart".as_slice());
+            assert_eq!(heart.svg.as_deref(), Some("<svg/>"));
+            assert_eq!(set.get("❤").unwrap().png, b"heart".as_slice());
+        }
+        assert!(FileEmojiSet::from_zip_bytes(b"not a zip").is_err());
+    }
+    #[test]
+    fn test_from_dir() {
+        let dir = std::env::temp_dir().join(format!("rustwemoji-set-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("emoji_u1f525.png"), b"fire").unwrap();
+        std::fs::write(dir.join("sub").join("1f44d.png"), b"thumbs").unwrap();
+        let set = FileEmojiSet::from_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(set.len(), 1);
+        let mut parser = crate::Parser::new();
+        parser.set_emoji_set(set);
+        assert_eq!(
+            parser.parse("a🔥").unwrap(),
+            vec![
+                crate::Token::new_text("a"),
+                crate::Token::new_emoji("🔥", b"fire".as_slice()),
+            ]
+        );
+    }
+}
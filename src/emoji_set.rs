@@ -0,0 +1,90 @@
+use crate::Parser;
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Images of an emoji in an [`EmojiSet`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmojiAsset {
+    /// Png image, emitted as [`Token::Emoji`](crate::Token::Emoji)
+    pub png: Cow<'static, [u8]>,
+    /// Svg image, if the set has one
+    pub svg: Option<Cow<'static, str>>,
+}
+
+/// Images the parser turns emoji into, set with [`Parser::set_emoji_set`]
+///
+/// The parser asks for whole graphemes first and falls back to their parts, so a set only
+/// needs the sequences it has images for. [`Twemoji`] is used by default.
+pub trait EmojiSet: Debug + Send + Sync {
+    /// Images of `grapheme`, `None` keeps it as text
+    fn get(&self, grapheme: &str) -> Option<EmojiAsset>;
+}
+
+/// The embedded twemoji images
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Twemoji;
+
+impl EmojiSet for Twemoji {
+    fn get(&self, grapheme: &str) -> Option<EmojiAsset> {
+        Some(EmojiAsset {
+            png: Cow::Borrowed(crate::get(grapheme)?),
+            #[cfg(feature = "svg")]
+            svg: crate::get_svg(grapheme).map(Cow::Borrowed),
+            #[cfg(not(feature = "svg"))]
+            svg: None,
+        })
+    }
+}
+
+impl Parser {
+    /// Take emoji images from `set` instead of twemoji
+    ///
+    /// [`ParserOptions::emoji_urls`](crate::ParserOptions) and `svg_emoji` only apply to
+    /// twemoji images, emoji of other sets are kept as png.
+    pub fn set_emoji_set(&mut self, set: impl EmojiSet + 'static) {
+        self.emoji_set = Arc::new(set);
+        self.changed();
+    }
+    /// Set the emoji images are taken from
+    pub fn emoji_set(&self) -> &dyn EmojiSet {
+        self.emoji_set.as_ref()
+    }
+    /// Png of `grapheme` in the emoji set of the parser
+    pub(crate) fn emoji_png(&self, grapheme: &str) -> Option<Cow<'static, [u8]>> {
+        self.emoji_set.get(grapheme).map(|asset| asset.png)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Token;
+    /// Set with a single emoji
+    #[derive(Debug)]
+    struct Thumbs;
+    impl EmojiSet for Thumbs {
+        fn get(&self, grapheme: &str) -> Option<EmojiAsset> {
+            (grapheme == "👍").then_some(EmojiAsset {
+                png: Cow::Borrowed(b"thumbs"),
+                svg: None,
+            })
+        }
+    }
+    #[test]
+    fn test_emoji_set() {
+        let mut parser = Parser::new();
+        parser.set_emoji_set(Thumbs);
+        assert_eq!(
+            parser.parse("👍🏽🔥").unwrap(),
+            vec![
+                Token::new_emoji("👍🏽", b"thumbs".as_slice()),
+                Token::new_text("🔥"),
+            ]
+        );
+        assert_eq!(
+            Twemoji.get("🔥").map(|a| a.png),
+            crate::get("🔥").map(Cow::Borrowed)
+        );
+    }
+}
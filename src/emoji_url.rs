@@ -0,0 +1,108 @@
+use crate::{ParserOptions, Token};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Token of `emoji` with the url of the twemoji image of its embedded png, the png is kept for
+/// images the index doesn't know
+pub(crate) fn token(emoji: Cow<str>, png: Cow<'static, [u8]>, options: &ParserOptions) -> Token {
+    match emoji_of(&png) {
+        Some(image) => Token::EmojiUrl {
+            emoji: emoji.into_owned(),
+            url: options.twemoji_url(image),
+        },
+        None => Token::new_emoji(emoji, png),
+    }
+}
+
+/// Token of `emoji` with the twemoji svg image of its embedded png, the png is kept for images
+/// without one
+#[cfg(feature = "svg")]
+pub(crate) fn svg_token(emoji: Cow<str>, png: Cow<'static, [u8]>) -> Token {
+    match emoji_of(&png).and_then(crate::get_svg) {
+        Some(svg) => Token::EmojiSvg {
+            emoji: emoji.into_owned(),
+            svg,
+        },
+        None => Token::new_emoji(emoji, png),
+    }
+}
+
+/// Emoji an embedded png is the image of, from an index of the unicode emoji built on first
+/// use, the image of other spellings of the emoji such as without `U+FE0F`
+pub(crate) fn emoji_of(png: &[u8]) -> Option<&'static str> {
+    static INDEX: OnceLock<HashMap<&'static [u8], &'static str>> = OnceLock::new();
+    let index = INDEX.get_or_init(|| {
+        emojis::iter()
+            .flat_map(|emoji| {
+                let tones = emoji.skin_tones().into_iter().flatten();
+                std::iter::once(emoji).chain(tones)
+            })
+            .filter_map(|emoji| Some((crate::get(emoji.as_str())?, emoji.as_str())))
+            .collect()
+    });
+    index.get(png).copied()
+}
+
+/// File name of the twemoji image of `emoji`, its code points without `U+FE0F` unless it is
+/// a ZWJ sequence
+pub(crate) fn file_stem(emoji: &str) -> String {
+    let zwj = emoji.contains('\u{200d}');
+    emoji
+        .chars()
+        .filter(|c| zwj || *c != '\u{fe0f}')
+        .map(|c| format!("{:x}", c as u32))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_file_stem() {
+        assert_eq!(file_stem("😀"), "1f600");
+        assert_eq!(file_stem("❤️"), "2764");
+        assert_eq!(file_stem("👩‍❤️‍👨"), "1f469-200d-2764-fe0f-200d-1f468");
+        assert_eq!(file_stem("#️⃣"), "23-20e3");
+    }
+    #[test]
+    fn test_emoji_urls() {
+        let options = ParserOptions::builder()
+            .emoji_urls(true)
+            .twemoji_base("https://example.com/twemoji/")
+            .twemoji_size("svg")
+            .build();
+        let tokens = crate::Parser::with_options(options).parse("hi 👍🏽").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_text("hi "),
+                Token::EmojiUrl {
+                    emoji: "👍🏽".to_string(),
+                    url: "https://example.com/twemoji/svg/1f44d-1f3fd.svg".to_string(),
+                },
+            ]
+        );
+        let unknown = token("😀".into(), b"\x89PNG".into(), &ParserOptions::default());
+        assert_eq!(unknown, Token::new_emoji("😀", b"\x89PNG".as_slice()));
+    }
+    #[test]
+    #[cfg(feature = "svg")]
+    fn test_svg_emoji() {
+        let options = ParserOptions::builder()
+            .svg_emoji(true)
+            .emoji_urls(true)
+            .build();
+        let tokens = crate::Parser::with_options(options).parse("❤️").unwrap();
+        let [Token::EmojiSvg { emoji, svg }] = tokens.as_slice() else {
+            panic!("{:?}", tokens);
+        };
+        assert_eq!(emoji, "❤️");
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(
+            svg_token("😀".into(), b"\x89PNG".into()),
+            Token::new_emoji("😀", b"\x89PNG".as_slice())
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{ParseError, Parser, TokenRef};
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// Classic emoticons and the emoji they are converted to
+const CLASSIC: &[(&str, &str)] = &[
+    (":)", "🙂"),
+    (":-)", "🙂"),
+    (":(", "🙁"),
+    (":-(", "🙁"),
+    (":D", "😃"),
+    (":-D", "😃"),
+    (";)", "😉"),
+    (";-)", "😉"),
+    (":P", "😛"),
+    (":-P", "😛"),
+    (":p", "😛"),
+    (":-p", "😛"),
+    (":O", "😮"),
+    (":-O", "😮"),
+    (":o", "😮"),
+    (":-o", "😮"),
+    (":'(", "😢"),
+    (":|", "😐"),
+    (":-|", "😐"),
+    (":/", "😕"),
+    (":-/", "😕"),
+    (":*", "😘"),
+    (":-*", "😘"),
+    ("B)", "😎"),
+    ("B-)", "😎"),
+    ("XD", "😆"),
+    ("<3", "❤️"),
+    ("</3", "💔"),
+];
+
+/// Table of emoticons converted to emoji, the classic ones by default
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Emoticons {
+    /// Emoticons with their emoji, longest first
+    table: Vec<(String, String)>,
+}
+
+impl Default for Emoticons {
+    fn default() -> Self {
+        let mut emoticons = Self::empty();
+        for (emoticon, emoji) in CLASSIC {
+            emoticons
+                .insert(*emoticon, emoji)
+                .expect("classic emoticons map to known emoji");
+        }
+        emoticons
+    }
+}
+
+impl Emoticons {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Table without any emoticon
+    pub fn empty() -> Self {
+        Self { table: Vec::new() }
+    }
+    /// Convert `emoticon` to `emoji`, replacing its previous emoji
+    ///
+    /// `emoji` has to be a twemoji one, emoticons whose emoji the parser's
+    /// [`EmojiSet`](crate::EmojiSet) doesn't have are kept as text.
+    pub fn insert(&mut self, emoticon: impl Into<String>, emoji: &str) -> Result<(), ParseError> {
+        let emoticon = emoticon.into();
+        if crate::get(emoji).is_none() {
+            return Err(ParseError::UnknownEmoji(emoji.to_string()));
+        }
+        self.remove(&emoticon);
+        let at = self
+            .table
+            .partition_point(|(e, _)| e.len() >= emoticon.len());
+        self.table.insert(at, (emoticon, emoji.to_string()));
+        Ok(())
+    }
+    /// Keep `emoticon` as text
+    pub fn remove(&mut self, emoticon: &str) {
+        self.table.retain(|(e, _)| e != emoticon);
+    }
+
+    /// Find the first emoticon standing on its own with an emoji in the emoji set of `parser`,
+    /// returns its range and token
+    pub(crate) fn find<'a>(
+        &self,
+        s: &'a str,
+        parser: &Parser,
+    ) -> Option<(Range<usize>, TokenRef<'a>)> {
+        s.char_indices()
+            .filter(|(i, _)| is_boundary(s[..*i].chars().next_back()))
+            .find_map(|(i, _)| {
+                let (emoticon, emoji) = self.table.iter().find(|(e, _)| {
+                    s[i..].starts_with(e.as_str()) && is_boundary(s[i + e.len()..].chars().next())
+                })?;
+                let image = parser.emoji_png(emoji)?;
+                let emoji = Cow::Owned(emoji.clone());
+                Some((i..i + emoticon.len(), TokenRef::Emoji { emoji, image }))
+            })
+    }
+
+    /// Start of an emoticon at the end of `s` later input could still complete, or end a word
+    /// it is part of
+    pub(crate) fn unfinished(&self, s: &str) -> Option<usize> {
+        s.char_indices()
+            .rev()
+            .take(self.table.first().map_or(0, |(e, _)| e.chars().count()))
+            .map(|(i, _)| i)
+            .filter(|i| {
+                self.table
+                    .iter()
+                    .any(|(e, _)| e.len() >= s.len() - i && e.starts_with(&s[*i..]))
+            })
+            .last()
+    }
+
+    /// Latest cut of `s` at or before `at` that doesn't change the emoticons found on either
+    /// side of it, which need the characters around them to stand on their own, nor splits one
+    pub(crate) fn cut(&self, s: &str, mut at: usize) -> usize {
+        let longest = self.table.first().map_or(0, |(e, _)| e.chars().count());
+        while at > 0 && at < s.len() {
+            let (before, after) = s.split_at(at);
+            let across = before.char_indices().rev().take(longest).find(|(i, _)| {
+                let rest = &s[*i..];
+                self.table
+                    .iter()
+                    .any(|(e, _)| rest.starts_with(e.as_str()) && i + e.len() > at)
+            });
+            if let Some((i, _)) = across {
+                at = i;
+                continue;
+            }
+            let ending = self
+                .table
+                .iter()
+                .find(|(e, _)| before.ends_with(e.as_str()));
+            if let Some((e, _)) = ending.filter(|_| !is_boundary(after.chars().next())) {
+                at -= e.len();
+                continue;
+            }
+            let starting = self
+                .table
+                .iter()
+                .any(|(e, _)| after.starts_with(e.as_str()) || e.starts_with(after));
+            match before.chars().next_back() {
+                Some(c) if starting && !is_boundary(Some(c)) => at -= c.len_utf8(),
+                _ => break,
+            }
+        }
+        at
+    }
+}
+
+/// Emoticons only count when they aren't part of a word, so `http://` stays text
+fn is_boundary(c: Option<char>) -> bool {
+    c.is_none_or(|c| !c.is_alphanumeric())
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+#[cfg(feature = "http")]
+use std::sync::Arc;
+
+/// Errors returned by the parser
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ParseError {
+    #[cfg(all(feature = "discord", feature = "regex"))]
+    /// The custom emoji regex failed to compile
+    Regex(regex::Error),
+    #[cfg(feature = "discord")]
+    /// The requested custom emoji size is not one Discord serves
+    InvalidEmojiSize(u32),
+    /// No emoji asset exists for the given emoji
+    UnknownEmoji(String),
+    #[cfg(feature = "tokio")]
+    /// The parsing task panicked or was cancelled
+    Join(tokio::task::JoinError),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, _f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            #[cfg(all(feature = "discord", feature = "regex"))]
+            Self::Regex(ref e) => write!(_f, "invalid custom emoji regex: {}", e),
+            #[cfg(feature = "discord")]
+            Self::InvalidEmojiSize(size) => write!(_f, "invalid custom emoji size: {}", size),
+            Self::UnknownEmoji(ref emoji) => write!(_f, "unknown emoji: {}", emoji),
+            #[cfg(feature = "tokio")]
+            Self::Join(ref e) => write!(_f, "parsing task failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            #[cfg(all(feature = "discord", feature = "regex"))]
+            Self::Regex(ref e) => Some(e),
+            #[cfg(feature = "discord")]
+            Self::InvalidEmojiSize(_) => None,
+            Self::UnknownEmoji(_) => None,
+            #[cfg(feature = "tokio")]
+            Self::Join(ref e) => Some(e),
+        }
+    }
+}
+
+#[cfg(all(feature = "discord", feature = "regex"))]
+impl From<regex::Error> for ParseError {
+    fn from(e: regex::Error) -> Self {
+        Self::Regex(e)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl From<tokio::task::JoinError> for ParseError {
+    fn from(e: tokio::task::JoinError) -> Self {
+        Self::Join(e)
+    }
+}
+
+/// Errors of downloading an image
+#[cfg(feature = "http")]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum FetchError {
+    /// The url isn't allowed by the [`UrlPolicy`](crate::UrlPolicy)
+    Forbidden(String),
+    /// The request failed without a response
+    Http(Arc<dyn std::error::Error + Send + Sync>),
+    /// The server answered with an error status
+    Status(u16),
+    /// The response isn't a PNG, GIF or WebP image, with its content type
+    NotAnImage(Option<String>),
+    /// The download is larger than the limit, with its size in bytes or as much of it as was
+    /// read before giving up
+    TooLarge(usize),
+    /// The image is wider or taller than the limit
+    TooManyPixels { width: u32, height: u32 },
+}
+
+#[cfg(feature = "http")]
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Forbidden(ref url) => write!(f, "url not allowed: {}", url),
+            Self::Http(ref e) => write!(f, "request failed: {}", e),
+            Self::Status(status) => write!(f, "server answered {}", status),
+            Self::NotAnImage(Some(ref content_type)) => {
+                write!(f, "response is not an image: {}", content_type)
+            }
+            Self::NotAnImage(None) => write!(f, "response is not an image"),
+            Self::TooLarge(size) => write!(f, "download too large: {} bytes", size),
+            Self::TooManyPixels { width, height } => {
+                write!(f, "image too large: {}x{} pixels", width, height)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+impl std::error::Error for FetchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Self::Http(ref e) => Some(&**e),
+            _ => None,
+        }
+    }
+}
+
+/// Errors of drawing a Lottie animation with the bundled renderer
+#[cfg(feature = "tgs")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LottieError {
+    /// The json isn't a Lottie animation with frames
+    Malformed,
+    /// The animation uses features the renderer doesn't draw, such as `"masks"`
+    Unsupported(Vec<&'static str>),
+}
+
+#[cfg(feature = "tgs")]
+impl fmt::Display for LottieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Malformed => write!(f, "malformed lottie animation"),
+            Self::Unsupported(ref features) => {
+                write!(f, "unsupported lottie features: {}", features.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tgs")]
+impl std::error::Error for LottieError {}
@@ -0,0 +1,94 @@
+use crate::{Parser, Token};
+use std::fmt::Debug;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Recognizer of extra syntax, registered with [`Parser::register_extension`]
+///
+/// The parser looks for matches in the text between its own markup and emits the token of
+/// each one in its place, the text around it is parsed as usual. [`StreamingParser`](crate::StreamingParser)
+/// doesn't hold back a match split across chunks.
+pub trait SyntaxExtension: Debug + Send + Sync {
+    /// Byte range of the first match in `s`
+    fn find(&self, s: &str) -> Option<Range<usize>>;
+    /// Token replacing `matched`, a match returned by [`SyntaxExtension::find`]
+    fn token(&self, matched: &str) -> Token;
+}
+
+impl Parser {
+    /// Look for the syntax of `extension` too, after the extensions registered before it
+    pub fn register_extension(&mut self, extension: impl SyntaxExtension + 'static) {
+        self.extensions.push(Arc::new(extension));
+        self.changed();
+    }
+
+    /// Token of a match of the extension at `index`, kept as text by other parsers
+    pub(crate) fn extension_token(&self, index: usize, matched: &str) -> Token {
+        match self.extensions.get(index) {
+            Some(extension) => extension.token(matched),
+            None => Token::new_text(matched),
+        }
+    }
+}
+
+/// First match of the extensions in `s`, the earliest registered one wins ties
+pub(crate) fn find_extension(
+    extensions: &[Arc<dyn SyntaxExtension>],
+    s: &str,
+) -> Option<(Range<usize>, usize)> {
+    extensions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, extension)| {
+            let range = extension.find(s)?;
+            // A range that isn't a non empty slice of `s` would break the tokenizer
+            let valid = !range.is_empty()
+                && range.end <= s.len()
+                && s.is_char_boundary(range.start)
+                && s.is_char_boundary(range.end);
+            valid.then_some((range, index))
+        })
+        .min_by_key(|(range, _)| range.start)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    /// `$name` cashtags, emitted as upper case text
+    #[derive(Debug)]
+    struct Cashtag;
+    impl SyntaxExtension for Cashtag {
+        fn find(&self, s: &str) -> Option<Range<usize>> {
+            s.match_indices('$').find_map(|(start, _)| {
+                let rest = &s[start + 1..];
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(rest.len());
+                (len > 0).then(|| start..start + 1 + len)
+            })
+        }
+        fn token(&self, matched: &str) -> Token {
+            Token::new_text(matched.to_uppercase())
+        }
+    }
+    #[test]
+    fn test_extension() {
+        let mut parser = Parser::new();
+        parser.register_extension(Cashtag);
+        assert_eq!(
+            parser.parse("buy $gme 👍 $ $x").unwrap(),
+            vec![
+                Token::new_text("buy "),
+                Token::new_text("$GME"),
+                Token::new_text(" "),
+                Token::new_emoji("👍", crate::get("👍").unwrap()),
+                Token::new_text(" $ "),
+                Token::new_text("$X"),
+            ]
+        );
+        assert_eq!(
+            Parser::new().parse("$gme").unwrap(),
+            vec![Token::new_text("$gme")]
+        );
+    }
+}
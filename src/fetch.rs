@@ -0,0 +1,1128 @@
+use crate::cache::{EmojiCache, FileCache, MemoryCache};
+use crate::http::{HttpError, HttpFuture, HttpGet, HttpResponse};
+use crate::{
+    CustomEmojiResolver, FetchError, ParseError, Parser, ParserOptions, ResolvedEmoji, Token,
+    UrlPolicy,
+};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OnceCell, Semaphore};
+
+/// Resolver downloading images from the Discord CDN
+///
+/// Images are kept in memory, clones of a resolver share its caches.
+#[derive(Debug, Clone)]
+pub struct DiscordCdnResolver {
+    client: Arc<dyn HttpGet>,
+    cdn_base: String,
+    size: u32,
+    /// Caches of downloaded images, looked up in order
+    caches: Vec<Arc<dyn EmojiCache>>,
+    fallback: Option<Fallback>,
+    /// Permits of concurrent downloads
+    downloads: Arc<Semaphore>,
+    /// Downloads in progress by cache key, awaited by later requests of the same image
+    in_flight: Arc<Mutex<HashMap<String, Download>>>,
+    retry: Retry,
+    policy: UrlPolicy,
+    limits: Limits,
+    on_error: Option<ErrorHook>,
+}
+
+/// Bounds of downloaded images, so a huge "emoji" can't exhaust the memory of renderers
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    /// Bytes of a download
+    size: usize,
+    /// Width and height in pixels
+    dimension: u32,
+}
+
+/// Function given the errors of the emoji that can't be downloaded
+#[derive(Clone)]
+struct ErrorHook(Arc<OnError>);
+
+type OnError = dyn Fn(u64, &FetchError) + Send + Sync;
+
+impl std::fmt::Debug for ErrorHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ErrorHook")
+    }
+}
+
+/// Download shared by the requests of an image
+type Download = Arc<OnceCell<Result<Vec<u8>, FetchError>>>;
+
+/// Retries of requests failing with a server error, a rate limit or no response
+#[derive(Debug, Clone, Copy)]
+struct Retry {
+    attempts: u32,
+    delay: Duration,
+    jitter: Duration,
+}
+
+impl Retry {
+    /// Delay after the failed `attempt`, doubled for each attempt and up to `jitter` more
+    fn delay(&self, attempt: u32) -> Duration {
+        let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        self.delay.saturating_mul(1 << attempt.min(16)) + self.jitter.mul_f64(random)
+    }
+}
+
+/// Image given to custom emoji that can't be downloaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fallback {
+    /// Bytes of the image
+    Image(Vec<u8>),
+    /// Url the image is downloaded from, kept in the caches like emoji images
+    Url(String),
+}
+
+impl Default for DiscordCdnResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DiscordCdnResolver {
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+    /// Download from the CDN and at the size of `options`
+    pub fn with_options(options: &ParserOptions) -> Self {
+        Self::builder().options(options).build()
+    }
+    pub fn builder() -> DiscordCdnResolverBuilder {
+        DiscordCdnResolverBuilder::default()
+    }
+    /// Download the images of the emoji `ids` at once into the caches, such as the emoji of a
+    /// guild before its first message, returning how many are cached
+    pub async fn warm_cache(&self, ids: &[u64]) -> usize {
+        let images = ids
+            .iter()
+            .map(|id| async move { self.fetch(*id).await.is_ok() });
+        crate::resolver::join_all(images.collect())
+            .await
+            .into_iter()
+            .filter(|cached| *cached)
+            .count()
+    }
+    /// Image of the custom emoji `id`, from the caches or the CDN, errors are also given to
+    /// the [`on_error`](DiscordCdnResolverBuilder::on_error) hook
+    pub async fn fetch(&self, id: u64) -> Result<Vec<u8>, FetchError> {
+        let key = format!("{}_{}", id, self.size);
+        let image = self.image(&key, &self.url(id)).await;
+        if let (Err(e), Some(on_error)) = (&image, &self.on_error) {
+            (on_error.0)(id, e);
+        }
+        image
+    }
+    /// Url of the image, webp serves animated and still emoji alike
+    fn url(&self, id: u64) -> String {
+        format!(
+            "{}{}.webp?size={}&animated=true",
+            self.cdn_base, id, self.size
+        )
+    }
+}
+
+impl DiscordCdnResolver {
+    /// Image at `url`, from the caches under `key` if they have it
+    async fn image(&self, key: &str, url: &str) -> Result<Vec<u8>, FetchError> {
+        for (missed, cache) in self.caches.iter().enumerate() {
+            if let Some(image) = cache.get(key).await {
+                // Fill the caches in front of the one that had it
+                for cache in &self.caches[..missed] {
+                    cache.put(key, &image).await;
+                }
+                return Ok(image);
+            }
+        }
+        self.download(key, url).await
+    }
+
+    /// Download `url`, sharing the download of the same image in progress
+    async fn download(&self, key: &str, url: &str) -> Result<Vec<u8>, FetchError> {
+        let cell = {
+            let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+            in_flight.entry(key.to_string()).or_default().clone()
+        };
+        let image = cell.get_or_init(|| self.refresh(key, url)).await.clone();
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        if in_flight.get(key).is_some_and(|c| Arc::ptr_eq(c, &cell)) {
+            in_flight.remove(key);
+        }
+        image
+    }
+
+    /// Download `url` into the caches, with a conditional request if they kept an expired
+    /// image, which is used again when the CDN answers `304 Not Modified`
+    async fn refresh(&self, key: &str, url: &str) -> Result<Vec<u8>, FetchError> {
+        let validators_key = format!("{}_validators", key);
+        let mut stale = None;
+        for cache in &self.caches {
+            if let (Some(image), Some(validators)) = (
+                cache.get_stale(key).await,
+                cache.get_stale(&validators_key).await,
+            ) {
+                stale = Some((image, String::from_utf8_lossy(&validators).into_owned()));
+                break;
+            }
+        }
+        let headers = stale
+            .as_ref()
+            .map(|(_, validators)| conditional_headers(validators))
+            .unwrap_or_default();
+        let response = self
+            .send(url, || self.client.get(url, &headers, self.limits.size))
+            .await?;
+        let (image, validators) = match stale {
+            Some((image, validators)) if response.status == 304 => {
+                let new = self::validators(&response);
+                (image, if new.is_empty() { validators } else { new })
+            }
+            _ if response.is_success() => {
+                self.check_image(&response)?;
+                let validators = self::validators(&response);
+                (response.body, validators)
+            }
+            _ => return Err(FetchError::Status(response.status)),
+        };
+        for cache in &self.caches {
+            cache.put(key, &image).await;
+            if !validators.is_empty() {
+                cache.put(&validators_key, validators.as_bytes()).await;
+            }
+        }
+        Ok(image)
+    }
+
+    /// Send the request to `url` made by `request` if the policy allows it, trying again on
+    /// transient failures
+    async fn send<'a>(
+        &'a self,
+        url: &str,
+        request: impl Fn() -> HttpFuture<'a, Result<HttpResponse, HttpError>>,
+    ) -> Result<HttpResponse, FetchError> {
+        if !self.policy.allows(url) {
+            return Err(FetchError::Forbidden(url.to_string()));
+        }
+        let mut attempt = 0;
+        loop {
+            let response = {
+                let _permit = self.downloads.acquire().await;
+                request().await
+            };
+            attempt += 1;
+            match response {
+                Ok(response) if response.status != 429 && response.status < 500 => {
+                    return Ok(response)
+                }
+                response if attempt >= self.retry.attempts => {
+                    return response.map_err(|e| FetchError::Http(e.into()))
+                }
+                _ => tokio::time::sleep(self.retry.delay(attempt - 1)).await,
+            }
+        }
+    }
+
+    async fn fallback(&self) -> Option<Vec<u8>> {
+        match self.fallback.as_ref()? {
+            Fallback::Image(image) => Some(image.clone()),
+            Fallback::Url(url) => self.image("fallback", url).await.ok(),
+        }
+    }
+}
+
+impl DiscordCdnResolver {
+    /// Check that a response is an image of a format emoji are served in, not an error page,
+    /// and within the limits
+    fn check_image(&self, response: &HttpResponse) -> Result<(), FetchError> {
+        let length = response
+            .header("content-length")
+            .and_then(|l| l.trim().parse().ok());
+        let size = length.unwrap_or(0).max(response.body.len());
+        if size > self.limits.size {
+            return Err(FetchError::TooLarge(size));
+        }
+        let content_type = response.header("content-type");
+        let image =
+            content_type.is_none_or(|t| t.trim().to_ascii_lowercase().starts_with("image/"));
+        let dimensions = image_format(&response.body).and(image_dimensions(&response.body));
+        match dimensions.filter(|_| image) {
+            Some((width, height)) if width.max(height) > self.limits.dimension => {
+                Err(FetchError::TooManyPixels { width, height })
+            }
+            Some(_) => Ok(()),
+            None => Err(FetchError::NotAnImage(content_type.map(str::to_string))),
+        }
+    }
+}
+
+/// Format of an image from its first bytes, among the PNG, GIF and WebP images of the CDN
+fn image_format(image: &[u8]) -> Option<&'static str> {
+    if image.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if image.starts_with(b"GIF87a") || image.starts_with(b"GIF89a") {
+        Some("gif")
+    } else if image.len() >= 12 && image.starts_with(b"RIFF") && &image[8..12] == b"WEBP" {
+        Some("webp")
+    } else {
+        None
+    }
+}
+
+/// Width and height of a PNG, GIF or WebP image, from its header
+fn image_dimensions(image: &[u8]) -> Option<(u32, u32)> {
+    let u16_le = |i: usize| Some(u16::from_le_bytes(image.get(i..i + 2)?.try_into().ok()?) as u32);
+    let u24_le = |i: usize| Some(u16_le(i)? | (*image.get(i + 2)? as u32) << 16);
+    let u32_be = |i: usize| Some(u32::from_be_bytes(image.get(i..i + 4)?.try_into().ok()?));
+    match image_format(image)? {
+        "png" => Some((u32_be(16)?, u32_be(20)?)),
+        "gif" => Some((u16_le(6)?, u16_le(8)?)),
+        _ => match image.get(12..16)? {
+            b"VP8 " => Some((u16_le(26)? & 0x3fff, u16_le(28)? & 0x3fff)),
+            b"VP8L" => {
+                let bits = u16_le(21)? | u16_le(23)? << 16;
+                Some(((bits & 0x3fff) + 1, (bits >> 14 & 0x3fff) + 1))
+            }
+            b"VP8X" => Some((u24_le(24)? + 1, u24_le(27)? + 1)),
+            _ => None,
+        },
+    }
+}
+
+/// `ETag` and `Last-Modified` of a response, as the lines of the headers to send them back
+fn validators(response: &HttpResponse) -> String {
+    [
+        ("if-none-match", "etag"),
+        ("if-modified-since", "last-modified"),
+    ]
+    .iter()
+    .filter_map(|(request, name)| Some(format!("{}: {}\n", request, response.header(name)?)))
+    .collect()
+}
+
+/// Headers of a conditional request from stored `validators`
+fn conditional_headers(validators: &str) -> Vec<(&str, &str)> {
+    validators
+        .lines()
+        .filter_map(|line| line.split_once(": "))
+        .collect()
+}
+
+impl CustomEmojiResolver for DiscordCdnResolver {
+    async fn resolve(&self, id: &str) -> Option<ResolvedEmoji> {
+        let image = match id.parse() {
+            Ok(id) => self.fetch(id).await.ok(),
+            Err(_) => None,
+        };
+        let image = match image {
+            Some(image) => image,
+            None => self.fallback().await?,
+        };
+        Some(ResolvedEmoji {
+            name: None,
+            image: Some(image),
+        })
+    }
+    /// Cached images count as existing, other emoji are checked with a `HEAD` request
+    async fn exists(&self, id: &str) -> Option<bool> {
+        let id = id.parse().ok()?;
+        let key = format!("{}_{}", id, self.size);
+        for cache in &self.caches {
+            if cache.get(&key).await.is_some() {
+                return Some(true);
+            }
+        }
+        let url = self.url(id);
+        let response = self.send(&url, || self.client.head(&url)).await.ok()?;
+        match response.status {
+            _ if response.is_success() => Some(true),
+            404 => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// Builder of [`DiscordCdnResolver`]
+#[derive(Debug, Clone)]
+pub struct DiscordCdnResolverBuilder {
+    options: ParserOptions,
+    cache_capacity: usize,
+    cache_ttl: Option<Duration>,
+    disk_cache: Option<PathBuf>,
+    disk_cache_max_size: u64,
+    disk_cache_ttl: Option<Duration>,
+    caches: Vec<Arc<dyn EmojiCache>>,
+    fallback: Option<Fallback>,
+    client: Option<Arc<dyn HttpGet>>,
+    max_concurrent_downloads: usize,
+    retry: Retry,
+    policy: UrlPolicy,
+    limits: Limits,
+    on_error: Option<ErrorHook>,
+}
+
+impl Default for DiscordCdnResolverBuilder {
+    fn default() -> Self {
+        Self {
+            options: ParserOptions::default(),
+            cache_capacity: 256,
+            cache_ttl: Some(Duration::from_secs(60 * 60)),
+            disk_cache: None,
+            disk_cache_max_size: 64 * 1024 * 1024,
+            disk_cache_ttl: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+            caches: Vec::new(),
+            fallback: None,
+            client: None,
+            max_concurrent_downloads: 8,
+            retry: Retry {
+                attempts: 3,
+                delay: Duration::from_millis(250),
+                jitter: Duration::from_millis(100),
+            },
+            policy: UrlPolicy::default(),
+            limits: Limits {
+                size: 4 * 1024 * 1024,
+                dimension: 4096,
+            },
+            on_error: None,
+        }
+    }
+}
+
+impl DiscordCdnResolverBuilder {
+    /// Download from the CDN and at the size of `options`
+    pub fn options(mut self, options: &ParserOptions) -> Self {
+        self.options = options.clone();
+        self
+    }
+    /// Number of images kept in memory, 0 turns the cache off
+    pub fn cache_capacity(mut self, cache_capacity: usize) -> Self {
+        self.cache_capacity = cache_capacity;
+        self
+    }
+    /// How long a downloaded image is used before downloading it again, `None` for ever
+    pub fn cache_ttl(mut self, cache_ttl: Option<Duration>) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+    /// Also keep images in files of `dir`, so they survive restarts, see [`FileCache`]
+    pub fn disk_cache(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.disk_cache = Some(dir.into());
+        self
+    }
+    /// Total size in bytes of the files of the disk cache, the oldest ones are removed past it
+    pub fn disk_cache_max_size(mut self, disk_cache_max_size: u64) -> Self {
+        self.disk_cache_max_size = disk_cache_max_size;
+        self
+    }
+    /// How long a file of the disk cache is used, `None` for ever
+    pub fn disk_cache_ttl(mut self, disk_cache_ttl: Option<Duration>) -> Self {
+        self.disk_cache_ttl = disk_cache_ttl;
+        self
+    }
+    /// Also keep images in `cache`, looked up after the memory and disk caches
+    pub fn cache(mut self, cache: impl EmojiCache + 'static) -> Self {
+        self.caches.push(Arc::new(cache));
+        self
+    }
+    /// Image of the emoji that can't be downloaded, which are left without one otherwise
+    pub fn fallback(mut self, fallback: Fallback) -> Self {
+        self.fallback = Some(fallback);
+        self
+    }
+    /// Download with `client` instead of a new [`reqwest::Client`]
+    pub fn client(mut self, client: impl HttpGet + 'static) -> Self {
+        self.client = Some(Arc::new(client));
+        self
+    }
+    /// Number of images downloaded at once, at least 1
+    pub fn max_concurrent_downloads(mut self, max_concurrent_downloads: usize) -> Self {
+        self.max_concurrent_downloads = max_concurrent_downloads.max(1);
+        self
+    }
+    /// Number of tries of a request failing with a server error, a rate limit or no response,
+    /// at least 1
+    pub fn retry_attempts(mut self, retry_attempts: u32) -> Self {
+        self.retry.attempts = retry_attempts.max(1);
+        self
+    }
+    /// Delay before the first retry, doubled for each next one
+    pub fn retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry.delay = retry_delay;
+        self
+    }
+    /// Random delay of up to `retry_jitter` added to each retry, so clients don't retry at once
+    pub fn retry_jitter(mut self, retry_jitter: Duration) -> Self {
+        self.retry.jitter = retry_jitter;
+        self
+    }
+    /// Urls images may be downloaded from, including the CDN and the fallback, see
+    /// [`UrlPolicy`] for the default
+    ///
+    /// Redirects of the default client follow the policy too, a given client has to check them.
+    pub fn url_policy(mut self, policy: UrlPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+    /// Largest download in bytes, larger images are errors
+    ///
+    /// Checked against the `content-length` before downloading and while reading the body, a
+    /// given [`client`](Self::client) is passed the limit to do the same.
+    pub fn max_download_size(mut self, max_download_size: usize) -> Self {
+        self.limits.size = max_download_size;
+        self
+    }
+    /// Largest width and height in pixels, larger images are errors so they are never decoded
+    pub fn max_dimension(mut self, max_dimension: u32) -> Self {
+        self.limits.dimension = max_dimension;
+        self
+    }
+    /// Call `on_error` with the id and error of each emoji that can't be downloaded, which is
+    /// given the fallback
+    pub fn on_error(mut self, on_error: impl Fn(u64, &FetchError) + Send + Sync + 'static) -> Self {
+        self.on_error = Some(ErrorHook(Arc::new(on_error)));
+        self
+    }
+    pub fn build(self) -> DiscordCdnResolver {
+        let mut caches: Vec<Arc<dyn EmojiCache>> = Vec::new();
+        if self.cache_capacity > 0 {
+            caches.push(Arc::new(MemoryCache::new(
+                self.cache_capacity,
+                self.cache_ttl,
+            )));
+        }
+        if let Some(dir) = self.disk_cache {
+            caches.push(Arc::new(FileCache::new(
+                dir,
+                self.disk_cache_max_size,
+                self.disk_cache_ttl,
+            )));
+        }
+        caches.extend(self.caches);
+        DiscordCdnResolver {
+            client: self
+                .client
+                .unwrap_or_else(|| Arc::new(client(&self.policy))),
+            cdn_base: self.options.cdn_base,
+            size: self.options.emoji_size,
+            caches,
+            fallback: self.fallback,
+            downloads: Arc::new(Semaphore::new(self.max_concurrent_downloads)),
+            in_flight: Arc::default(),
+            retry: self.retry,
+            policy: self.policy,
+            limits: self.limits,
+            on_error: self.on_error,
+        }
+    }
+}
+
+/// Client following the redirects `policy` allows
+fn client(policy: &UrlPolicy) -> reqwest::Client {
+    let policy = policy.clone();
+    let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+        match attempt.previous().len() < 10 && policy.allows(attempt.url().as_str()) {
+            true => attempt.follow(),
+            false => attempt.stop(),
+        }
+    });
+    reqwest::Client::builder()
+        .redirect(redirect)
+        .build()
+        .unwrap_or_default()
+}
+
+impl Parser {
+    /// Parse a string and download the images of its custom emoji with `fetcher`, so they
+    /// carry bytes like unicode emoji
+    ///
+    /// Emoji that can't be downloaded are kept as [`Token::CustomEmoji`].
+    pub async fn parse_and_fetch(
+        &self,
+        s: &str,
+        fetcher: &DiscordCdnResolver,
+    ) -> Result<Vec<Token>, ParseError> {
+        let tokens = self.parse(s)?;
+        Ok(self.resolve_custom_emoji(tokens, fetcher).await)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::CacheFuture;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    /// Headers of images of 16x16 pixels
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x10\0\0\0\x10";
+    const GIF: &[u8] = b"GIF89a\x10\0\x10\0";
+    /// Builder allowing the local server
+    fn local(options: &ParserOptions) -> DiscordCdnResolverBuilder {
+        DiscordCdnResolver::builder()
+            .options(options)
+            .url_policy(UrlPolicy::new().allow_http(true).allow_private_hosts(true))
+    }
+    /// Serve `body` to every request on a local port, answering 404 to paths without `ok`,
+    /// and count the requests
+    fn serve(body: &'static [u8]) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                counter.fetch_add(1, Ordering::Relaxed);
+                let mut request = [0; 1024];
+                let len = stream.read(&mut request).unwrap_or(0);
+                let head = match request[..len].windows(2).any(|w| w == b"ok") {
+                    true => format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n", body.len()),
+                    false => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n".to_string(),
+                };
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(body);
+            }
+        });
+        (format!("http://{}/", addr), requests)
+    }
+    #[test]
+    fn test_cdn_url() {
+        assert_eq!(
+            DiscordCdnResolver::new().url(123456789012345678),
+            "https://cdn.discordapp.com/emojis/123456789012345678.webp?size=96&animated=true"
+        );
+    }
+    #[tokio::test]
+    async fn test_parse_and_fetch() {
+        let (base, _) = serve(PNG);
+        let options = ParserOptions::builder()
+            .cdn_base(format!("{}ok/", base))
+            .build();
+        let fetcher = local(&options).build();
+        let tokens = Parser::new()
+            .parse_and_fetch("hi <:blobcat:123456789012345678>", &fetcher)
+            .await
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_text("hi "),
+                Token::FetchedCustomEmoji {
+                    id: 123456789012345678,
+                    name: "blobcat".to_string(),
+                    animated: false,
+                    image: PNG.to_vec(),
+                },
+            ]
+        );
+        let options = ParserOptions::builder().cdn_base(base).build();
+        let fetcher = local(&options).build();
+        let tokens = Parser::new()
+            .parse_and_fetch("<:blobcat:123456789012345678>", &fetcher)
+            .await
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![Token::new_custom_emoji(
+                123456789012345678,
+                "blobcat",
+                false
+            )]
+        );
+    }
+    #[tokio::test]
+    async fn test_cache() {
+        let (base, requests) = serve(PNG);
+        let options = ParserOptions::builder()
+            .cdn_base(format!("{}ok/", base))
+            .build();
+        let fetcher = local(&options).build();
+        let s = "<:blobcat:123456789012345678>";
+        let parser = Parser::new();
+        let first = parser.parse_and_fetch(s, &fetcher).await.unwrap();
+        let second = parser.parse_and_fetch(s, &fetcher.clone()).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(requests.load(Ordering::Relaxed), 1);
+        let fetcher = local(&options).cache_capacity(0).build();
+        parser.parse_and_fetch(s, &fetcher).await.unwrap();
+        parser.parse_and_fetch(s, &fetcher).await.unwrap();
+        assert_eq!(requests.load(Ordering::Relaxed), 3);
+    }
+    #[tokio::test]
+    async fn test_warm_cache() {
+        let (base, requests) = serve(PNG);
+        let options = ParserOptions::builder()
+            .cdn_base(format!("{}ok/", base))
+            .build();
+        let fetcher = local(&options).build();
+        let ids = [123456789012345678, 234567890123456789];
+        assert_eq!(fetcher.warm_cache(&ids).await, 2);
+        assert_eq!(requests.load(Ordering::Relaxed), 2);
+        let s = "<:a:123456789012345678><:b:234567890123456789>";
+        let tokens = Parser::new().parse_and_fetch(s, &fetcher).await.unwrap();
+        assert!(tokens
+            .iter()
+            .all(|t| matches!(t, Token::FetchedCustomEmoji { .. })));
+        assert_eq!(requests.load(Ordering::Relaxed), 2);
+    }
+    #[tokio::test]
+    async fn test_disk_cache() {
+        let (base, requests) = serve(PNG);
+        let dir = std::env::temp_dir().join(format!("rustwemoji-fetch-{}", std::process::id()));
+        let options = ParserOptions::builder()
+            .cdn_base(format!("{}ok/", base))
+            .build();
+        // A new resolver is a restart, with an empty memory cache
+        let fetcher = || local(&options).disk_cache(&dir).build();
+        let s = "<:blobcat:123456789012345678>";
+        let parser = Parser::new();
+        let first = parser.parse_and_fetch(s, &fetcher()).await.unwrap();
+        let second = parser.parse_and_fetch(s, &fetcher()).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(requests.load(Ordering::Relaxed), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    #[tokio::test]
+    async fn test_custom_cache() {
+        #[derive(Debug, Default)]
+        struct Shared(std::sync::Mutex<Vec<(String, Vec<u8>)>>);
+        impl EmojiCache for Arc<Shared> {
+            fn get<'a>(&'a self, key: &'a str) -> CacheFuture<'a, Option<Vec<u8>>> {
+                let entries = self.0.lock().unwrap();
+                let image = entries
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v.clone());
+                Box::pin(async move { image })
+            }
+            fn put<'a>(&'a self, key: &'a str, image: &'a [u8]) -> CacheFuture<'a, ()> {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((key.to_string(), image.to_vec()));
+                Box::pin(async {})
+            }
+        }
+        let (base, requests) = serve(PNG);
+        let options = ParserOptions::builder()
+            .cdn_base(format!("{}ok/", base))
+            .build();
+        let shared = Arc::new(Shared::default());
+        // Two processes without memory caches sharing one store
+        let fetcher = || {
+            local(&options)
+                .cache_capacity(0)
+                .cache(shared.clone())
+                .build()
+        };
+        let s = "<:blobcat:123456789012345678>";
+        let parser = Parser::new();
+        parser.parse_and_fetch(s, &fetcher()).await.unwrap();
+        parser.parse_and_fetch(s, &fetcher()).await.unwrap();
+        assert_eq!(requests.load(Ordering::Relaxed), 1);
+        assert_eq!(shared.0.lock().unwrap()[0].0, "123456789012345678_96");
+    }
+    #[tokio::test]
+    async fn test_exists() {
+        let (base, _) = serve(b"");
+        let options = ParserOptions::builder().cdn_base(base).build();
+        let fetcher = local(&options).build();
+        assert_eq!(fetcher.exists("123456789012345678").await, Some(false));
+        let tokens = Parser::new().parse("<:gone:123456789012345678>").unwrap();
+        assert_eq!(
+            Parser::new().validate_custom_emoji(tokens, &fetcher).await,
+            vec![Token::new_text(":gone:")]
+        );
+        let (base, _) = serve(b"");
+        let options = ParserOptions::builder()
+            .cdn_base(format!("{}ok/", base))
+            .build();
+        let fetcher = local(&options).build();
+        assert_eq!(fetcher.exists("123456789012345678").await, Some(true));
+    }
+    #[tokio::test]
+    async fn test_fallback() {
+        let (base, _) = serve(GIF);
+        let options = ParserOptions::builder().cdn_base(base.clone()).build();
+        let fetched = |image: &[u8]| {
+            vec![Token::FetchedCustomEmoji {
+                id: 123456789012345678,
+                name: "gone".to_string(),
+                animated: false,
+                image: image.to_vec(),
+            }]
+        };
+        let s = "<:gone:123456789012345678>";
+        let fetcher = local(&options)
+            .fallback(Fallback::Image(b"square".to_vec()))
+            .build();
+        let tokens = Parser::new().parse_and_fetch(s, &fetcher).await.unwrap();
+        assert_eq!(tokens, fetched(b"square"));
+        let fetcher = local(&options)
+            .fallback(Fallback::Url(format!("{}ok/missing.png", base)))
+            .build();
+        let tokens = Parser::new().parse_and_fetch(s, &fetcher).await.unwrap();
+        assert_eq!(tokens, fetched(GIF));
+    }
+    #[tokio::test]
+    async fn test_client() {
+        #[derive(Debug)]
+        struct Client;
+        impl HttpGet for Client {
+            fn get<'a>(
+                &'a self,
+                url: &'a str,
+                _headers: &'a [(&'a str, &'a str)],
+                _limit: usize,
+            ) -> HttpFuture<'a, Result<HttpResponse, HttpError>> {
+                Box::pin(async move {
+                    Ok(HttpResponse {
+                        status: 200,
+                        headers: Vec::new(),
+                        body: [PNG, url.as_bytes()].concat(),
+                    })
+                })
+            }
+        }
+        let fetcher = DiscordCdnResolver::builder().client(Client).build();
+        let tokens = Parser::new()
+            .parse_and_fetch("<:blobcat:123456789012345678>", &fetcher)
+            .await
+            .unwrap();
+        let url = "https://cdn.discordapp.com/emojis/123456789012345678.webp?size=96&animated=true";
+        assert_eq!(
+            tokens,
+            vec![Token::FetchedCustomEmoji {
+                id: 123456789012345678,
+                name: "blobcat".to_string(),
+                animated: false,
+                image: [PNG, url.as_bytes()].concat(),
+            }]
+        );
+        assert_eq!(fetcher.exists("123456789012345678").await, Some(true));
+    }
+    #[tokio::test]
+    async fn test_concurrent_downloads() {
+        #[derive(Debug, Default)]
+        struct Client {
+            requests: AtomicUsize,
+            running: AtomicUsize,
+            max_running: AtomicUsize,
+        }
+        impl HttpGet for Arc<Client> {
+            fn get<'a>(
+                &'a self,
+                _url: &'a str,
+                _headers: &'a [(&'a str, &'a str)],
+                _limit: usize,
+            ) -> HttpFuture<'a, Result<HttpResponse, HttpError>> {
+                Box::pin(async move {
+                    self.requests.fetch_add(1, Ordering::Relaxed);
+                    let running = self.running.fetch_add(1, Ordering::Relaxed) + 1;
+                    self.max_running.fetch_max(running, Ordering::Relaxed);
+                    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                    self.running.fetch_sub(1, Ordering::Relaxed);
+                    Ok(HttpResponse {
+                        status: 200,
+                        headers: Vec::new(),
+                        body: PNG.to_vec(),
+                    })
+                })
+            }
+        }
+        let client = Arc::new(Client::default());
+        let fetcher = DiscordCdnResolver::builder()
+            .client(client.clone())
+            .cache_capacity(0)
+            .max_concurrent_downloads(2)
+            .build();
+        let (a, b) = tokio::join!(fetcher.resolve("1"), fetcher.resolve("1"));
+        assert_eq!(a, b);
+        assert_eq!(client.requests.load(Ordering::Relaxed), 1);
+        let s = (2..8)
+            .map(|id| format!("<:e:12345678901234567{}>", id))
+            .collect::<String>();
+        let tokens = Parser::new().parse_and_fetch(&s, &fetcher).await.unwrap();
+        assert!(tokens
+            .iter()
+            .all(|t| matches!(t, Token::FetchedCustomEmoji { .. })));
+        assert_eq!(client.requests.load(Ordering::Relaxed), 7);
+        assert_eq!(client.max_running.load(Ordering::Relaxed), 2);
+    }
+    #[tokio::test]
+    async fn test_retry() {
+        /// Fails with a server error, then without a response, then succeeds
+        #[derive(Debug, Default)]
+        struct Client(AtomicUsize);
+        impl HttpGet for Arc<Client> {
+            fn get<'a>(
+                &'a self,
+                _url: &'a str,
+                _headers: &'a [(&'a str, &'a str)],
+                _limit: usize,
+            ) -> HttpFuture<'a, Result<HttpResponse, HttpError>> {
+                Box::pin(async move {
+                    let status = match self.0.fetch_add(1, Ordering::Relaxed) % 3 {
+                        0 => 503,
+                        1 => return Err("timed out".into()),
+                        _ => 200,
+                    };
+                    Ok(HttpResponse {
+                        status,
+                        headers: Vec::new(),
+                        body: PNG.to_vec(),
+                    })
+                })
+            }
+        }
+        let fetcher = |client: &Arc<Client>, attempts| {
+            DiscordCdnResolver::builder()
+                .client(client.clone())
+                .cache_capacity(0)
+                .retry_attempts(attempts)
+                .retry_delay(Duration::ZERO)
+                .retry_jitter(Duration::from_millis(1))
+                .build()
+        };
+        let client = Arc::new(Client::default());
+        let emoji = fetcher(&client, 3).resolve("123456789012345678").await;
+        assert_eq!(emoji.unwrap().image.unwrap(), PNG);
+        assert_eq!(client.0.load(Ordering::Relaxed), 3);
+        let client = Arc::new(Client::default());
+        assert!(fetcher(&client, 2)
+            .resolve("123456789012345678")
+            .await
+            .is_none());
+        assert_eq!(client.0.load(Ordering::Relaxed), 2);
+    }
+    #[tokio::test]
+    async fn test_conditional_request() {
+        /// Answers `304` to requests carrying the etag of its image
+        #[derive(Debug, Default)]
+        struct Client(Mutex<Vec<Vec<(String, String)>>>);
+        impl HttpGet for Arc<Client> {
+            fn get<'a>(
+                &'a self,
+                _url: &'a str,
+                headers: &'a [(&'a str, &'a str)],
+                _limit: usize,
+            ) -> HttpFuture<'a, Result<HttpResponse, HttpError>> {
+                Box::pin(async move {
+                    let headers = headers
+                        .iter()
+                        .map(|(n, v)| (n.to_string(), v.to_string()))
+                        .collect::<Vec<_>>();
+                    let modified = !headers.contains(&("if-none-match".into(), "\"v1\"".into()));
+                    self.0.lock().unwrap().push(headers);
+                    Ok(HttpResponse {
+                        status: if modified { 200 } else { 304 },
+                        headers: vec![("etag".into(), "\"v1\"".into())],
+                        body: if modified { PNG.to_vec() } else { Vec::new() },
+                    })
+                })
+            }
+        }
+        let client = Arc::new(Client::default());
+        let fetcher = DiscordCdnResolver::builder()
+            .client(client.clone())
+            .cache_ttl(Some(Duration::ZERO))
+            .build();
+        for _ in 0..2 {
+            let emoji = fetcher.resolve("123456789012345678").await.unwrap();
+            assert_eq!(emoji.image.unwrap(), PNG);
+        }
+        let requests = client.0.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].is_empty());
+        assert_eq!(
+            requests[1],
+            vec![("if-none-match".to_string(), "\"v1\"".to_string())]
+        );
+    }
+    #[tokio::test]
+    async fn test_url_policy() {
+        let (base, requests) = serve(PNG);
+        let options = ParserOptions::builder()
+            .cdn_base(format!("{}ok/", base))
+            .build();
+        let fetcher = DiscordCdnResolver::with_options(&options);
+        assert!(fetcher.resolve("123456789012345678").await.is_none());
+        assert_eq!(fetcher.exists("123456789012345678").await, None);
+        let fetcher = DiscordCdnResolver::builder()
+            .fallback(Fallback::Url(format!("{}ok/fallback.png", base)))
+            .client(reqwest::Client::new())
+            .build();
+        assert!(fetcher.fallback().await.is_none());
+        assert_eq!(requests.load(Ordering::Relaxed), 0);
+    }
+    #[tokio::test]
+    async fn test_not_an_image() {
+        /// Answers with an error page
+        #[derive(Debug)]
+        struct Client(&'static str);
+        impl HttpGet for Client {
+            fn get<'a>(
+                &'a self,
+                _url: &'a str,
+                _headers: &'a [(&'a str, &'a str)],
+                _limit: usize,
+            ) -> HttpFuture<'a, Result<HttpResponse, HttpError>> {
+                Box::pin(async move {
+                    Ok(HttpResponse {
+                        status: 200,
+                        headers: vec![("content-type".into(), self.0.into())],
+                        body: b"<html>rate limited</html>".to_vec(),
+                    })
+                })
+            }
+        }
+        for content_type in ["text/html", "image/png"] {
+            let fetcher = DiscordCdnResolver::builder()
+                .client(Client(content_type))
+                .build();
+            match fetcher.fetch(123456789012345678).await {
+                Err(FetchError::NotAnImage(Some(t))) => assert_eq!(t, content_type),
+                e => panic!("{:?}", e),
+            }
+        }
+        assert_eq!(image_format(PNG), Some("png"));
+        assert_eq!(image_format(GIF), Some("gif"));
+        assert_eq!(image_format(b"RIFF\0\0\0\0WEBPVP8 "), Some("webp"));
+        assert_eq!(image_format(b"RIFF"), None);
+    }
+    #[tokio::test]
+    async fn test_limits() {
+        /// Answers with a PNG of 10000x10000 pixels
+        #[derive(Debug)]
+        struct Client;
+        impl HttpGet for Client {
+            fn get<'a>(
+                &'a self,
+                _url: &'a str,
+                _headers: &'a [(&'a str, &'a str)],
+                _limit: usize,
+            ) -> HttpFuture<'a, Result<HttpResponse, HttpError>> {
+                Box::pin(async move {
+                    let mut body = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+                    body.extend(10000u32.to_be_bytes());
+                    body.extend(10000u32.to_be_bytes());
+                    Ok(HttpResponse {
+                        status: 200,
+                        headers: Vec::new(),
+                        body,
+                    })
+                })
+            }
+        }
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let reported = errors.clone();
+        let fetcher = DiscordCdnResolver::builder()
+            .client(Client)
+            .fallback(Fallback::Image(b"square".to_vec()))
+            .on_error(move |id, e| reported.lock().unwrap().push((id, e.to_string())))
+            .build();
+        let emoji = fetcher.resolve("123456789012345678").await.unwrap();
+        assert_eq!(emoji.image.unwrap(), b"square");
+        assert_eq!(
+            *errors.lock().unwrap(),
+            vec![(
+                123456789012345678,
+                "image too large: 10000x10000 pixels".to_string()
+            )]
+        );
+        let fetcher = DiscordCdnResolver::builder()
+            .client(Client)
+            .max_download_size(16)
+            .build();
+        assert!(matches!(
+            fetcher.fetch(123456789012345678).await,
+            Err(FetchError::TooLarge(24))
+        ));
+        let fetcher = DiscordCdnResolver::builder()
+            .client(Client)
+            .max_dimension(10000)
+            .build();
+        assert!(fetcher.fetch(123456789012345678).await.is_ok());
+    }
+    #[tokio::test]
+    async fn test_download_size() {
+        /// Answers with a `content-length` larger than the limit it is given, without a body
+        #[derive(Debug)]
+        struct Client;
+        impl HttpGet for Client {
+            fn get<'a>(
+                &'a self,
+                _url: &'a str,
+                _headers: &'a [(&'a str, &'a str)],
+                limit: usize,
+            ) -> HttpFuture<'a, Result<HttpResponse, HttpError>> {
+                Box::pin(async move {
+                    Ok(HttpResponse {
+                        status: 200,
+                        headers: vec![("content-length".into(), (limit + 1).to_string())],
+                        body: Vec::new(),
+                    })
+                })
+            }
+        }
+        let fetcher = DiscordCdnResolver::builder()
+            .client(Client)
+            .max_download_size(1000)
+            .build();
+        assert!(matches!(
+            fetcher.fetch(123456789012345678).await,
+            Err(FetchError::TooLarge(1001))
+        ));
+        let (base, _) = serve(PNG);
+        let options = ParserOptions::builder()
+            .cdn_base(format!("{}ok/", base))
+            .build();
+        let fetcher = local(&options).max_download_size(16).build();
+        assert!(matches!(
+            fetcher.fetch(123456789012345678).await,
+            Err(FetchError::TooLarge(24))
+        ));
+        // Without a length, the body is read until it is too large
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = stream.read(&mut [0; 1024]);
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nconnection: close\r\n\r\n");
+                let _ = stream.write_all(PNG);
+                while stream.write_all(&[0; 4096]).is_ok() {}
+            }
+        });
+        let options = ParserOptions::builder()
+            .cdn_base(format!("http://{}/", addr))
+            .build();
+        let fetcher = local(&options).max_download_size(1000).build();
+        match fetcher.fetch(123456789012345678).await {
+            Err(FetchError::TooLarge(size)) => assert!(size > 1000 && size < 1 << 20),
+            result => panic!("{:?}", result),
+        }
+    }
+    #[test]
+    fn test_image_dimensions() {
+        let gif = b"GIF89a\x80\0\x40\0";
+        assert_eq!(image_dimensions(gif), Some((128, 64)));
+        let vp8 = b"RIFF\0\0\0\0WEBPVP8 \0\0\0\0\0\0\0\x9d\x01\x2a\x80\0\x40\0";
+        assert_eq!(image_dimensions(vp8), Some((128, 64)));
+        // 14 bits of width - 1, then 14 bits of height - 1
+        let bits = (128 - 1) | (64 - 1) << 14u32;
+        let mut vp8l = b"RIFFxxxxWEBPVP8Lxxxx\x2f".to_vec();
+        vp8l.extend((bits as u32).to_le_bytes());
+        assert_eq!(image_dimensions(&vp8l), Some((128, 64)));
+        let vp8x = b"RIFF\0\0\0\0WEBPVP8X\0\0\0\0\0\0\0\0\x7f\0\0\x3f\0\0";
+        assert_eq!(image_dimensions(vp8x), Some((128, 64)));
+        assert_eq!(image_dimensions(PNG), Some((16, 16)));
+        assert_eq!(image_dimensions(&PNG[..20]), None);
+    }
+}
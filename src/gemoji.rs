@@ -0,0 +1,46 @@
+/// Names with the image url of GitHub custom emoji
+macro_rules! custom {
+    ($($name:literal),+ $(,)?) => {
+        &[$((
+            $name,
+            concat!("https://github.githubassets.com/images/icons/emoji/", $name, ".png"),
+        )),+]
+    };
+}
+
+/// Names GitHub only has as custom images, with no unicode emoji behind them
+const CUSTOM: &[(&str, &str)] = custom![
+    "accessibility",
+    "atom",
+    "basecamp",
+    "basecampy",
+    "bowtie",
+    "copilot",
+    "dependabot",
+    "electron",
+    "feelsgood",
+    "finnadie",
+    "fishsticks",
+    "goberserk",
+    "godmode",
+    "hurtrealbad",
+    "neckbeard",
+    "octocat",
+    "rage1",
+    "rage2",
+    "rage3",
+    "rage4",
+    "shipit",
+    "suspect",
+    "trollface",
+];
+
+/// Unicode emoji called `name` by gemoji, aliases included
+pub(crate) fn emoji_by_name(name: &str) -> Option<&'static str> {
+    Some(emojis::get_by_shortcode(name)?.as_str())
+}
+
+/// Image url of the GitHub custom emoji called `name`
+pub(crate) fn custom_url(name: &str) -> Option<&'static str> {
+    CUSTOM.iter().find(|(n, _)| *n == name).map(|(_, url)| *url)
+}
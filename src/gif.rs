@@ -0,0 +1,371 @@
+//! Small gif decoder and encoder, enough for animated emoji
+
+use crate::png::RgbaImage;
+#[cfg(feature = "render-image")]
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Larger images are not emoji, and would take a lot of memory
+const MAX_DIMENSION: usize = 4096;
+
+/// Memory the decoded frames of an image may take, later frames are dropped
+const MAX_FRAMES_SIZE: usize = 64 << 20;
+
+/// Delay of frames without one, as browsers show them
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let (bytes, rest) = (self.data.get(..n)?, &self.data[n..]);
+        self.data = rest;
+        Some(bytes)
+    }
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.bytes(2)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+    /// Data sub-blocks up to the empty one
+    fn blocks(&mut self) -> Option<Vec<u8>> {
+        let mut data = Vec::new();
+        loop {
+            match self.u8()? {
+                0 => return Some(data),
+                len => data.extend_from_slice(self.bytes(len as usize)?),
+            }
+        }
+    }
+}
+
+/// Frames of a gif with the time each is shown, composited over the previous ones, `None`
+/// if it is invalid
+pub(crate) fn decode(gif: &[u8]) -> Option<Vec<(RgbaImage, Duration)>> {
+    let mut reader = Reader {
+        data: gif
+            .strip_prefix(b"GIF87a")
+            .or(gif.strip_prefix(b"GIF89a"))?,
+    };
+    let (width, height) = (reader.u16()? as usize, reader.u16()? as usize);
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return None;
+    }
+    let flags = reader.u8()?;
+    reader.bytes(2)?;
+    let global = match flags & 0x80 {
+        0 => &[][..],
+        _ => reader.bytes(3 << ((flags & 7) + 1))?,
+    };
+    let mut canvas = RgbaImage::new(width as u32, height as u32);
+    let mut frames = Vec::new();
+    // Disposal, delay and transparent index of the next image
+    let mut control = (0, DEFAULT_DELAY, None);
+    loop {
+        match reader.u8() {
+            Some(0x21) => match reader.u8()? {
+                0xf9 => {
+                    let block = reader.blocks()?;
+                    if block.len() >= 4 {
+                        let delay = u16::from_le_bytes([block[1], block[2]]);
+                        control = (
+                            block[0] >> 2 & 7,
+                            match delay {
+                                0 | 1 => DEFAULT_DELAY,
+                                _ => Duration::from_millis(delay as u64 * 10),
+                            },
+                            (block[0] & 1 == 1).then_some(block[3]),
+                        );
+                    }
+                }
+                _ => {
+                    reader.blocks()?;
+                }
+            },
+            Some(0x2c) => {
+                let (x, y) = (reader.u16()? as usize, reader.u16()? as usize);
+                let (w, h) = (reader.u16()? as usize, reader.u16()? as usize);
+                let flags = reader.u8()?;
+                let palette = match flags & 0x80 {
+                    0 => global,
+                    _ => reader.bytes(3 << ((flags & 7) + 1))?,
+                };
+                let min_code_size = reader.u8()?;
+                let indices = lzw_decode(&reader.blocks()?, min_code_size, w * h)?;
+                let (disposal, delay, transparent) = control;
+                let previous = (disposal == 3).then(|| canvas.clone());
+                let rows = interlaced_rows(h, flags & 0x40 != 0);
+                for (i, index) in indices.iter().enumerate() {
+                    let (px, py) = (x + i % w, y + rows[i / w]);
+                    if Some(*index) == transparent || px >= width || py >= height {
+                        continue;
+                    }
+                    let Some(rgb) = palette.get(*index as usize * 3..*index as usize * 3 + 3)
+                    else {
+                        continue;
+                    };
+                    let p = (py * width + px) * 4;
+                    canvas.pixels[p..p + 4].copy_from_slice(&[rgb[0], rgb[1], rgb[2], 255]);
+                }
+                frames.push((canvas.clone(), delay));
+                match disposal {
+                    2 => clear(&mut canvas, x, y, w, h),
+                    3 => canvas = previous.unwrap_or(canvas),
+                    _ => {}
+                }
+                control = (0, DEFAULT_DELAY, None);
+                if frames.len() * width * height * 4 > MAX_FRAMES_SIZE {
+                    break;
+                }
+            }
+            _ => break,
+        }
+    }
+    (!frames.is_empty()).then_some(frames)
+}
+
+fn clear(canvas: &mut RgbaImage, x: usize, y: usize, w: usize, h: usize) {
+    let width = canvas.width as usize;
+    for py in y..(y + h).min(canvas.height as usize) {
+        for px in x..(x + w).min(width) {
+            let p = (py * width + px) * 4;
+            canvas.pixels[p..p + 4].fill(0);
+        }
+    }
+}
+
+/// Row of the canvas each row of the data is, as interlaced images have theirs out of order
+fn interlaced_rows(height: usize, interlaced: bool) -> Vec<usize> {
+    match interlaced {
+        false => (0..height).collect(),
+        true => [(0, 8), (4, 8), (2, 4), (1, 2)]
+            .iter()
+            .flat_map(|&(start, step)| (start..height).step_by(step))
+            .collect(),
+    }
+}
+
+/// Color indices of lzw data, `None` if it is invalid
+fn lzw_decode(data: &[u8], min_code_size: u8, len: usize) -> Option<Vec<u8>> {
+    if !(2..=11).contains(&min_code_size) {
+        return None;
+    }
+    let (clear, end) = (1u16 << min_code_size, (1u16 << min_code_size) + 1);
+    // Prefix code and last index of each code
+    let mut table: Vec<(u16, u8)> = (0..clear).map(|i| (u16::MAX, i as u8)).collect();
+    table.extend([(u16::MAX, 0), (u16::MAX, 0)]);
+    let mut out = Vec::with_capacity(len);
+    let mut size = min_code_size + 1;
+    let mut previous: Option<u16> = None;
+    let (mut bits, mut count) = (0u32, 0);
+    let mut data = data.iter();
+    let mut entry = Vec::new();
+    while out.len() < len {
+        while count < size {
+            let Some(byte) = data.next() else {
+                // Some encoders stop short, the rest of the canvas is left as it was
+                return Some(out);
+            };
+            bits |= (*byte as u32) << count;
+            count += 8;
+        }
+        let code = (bits & ((1 << size) - 1)) as u16;
+        bits >>= size;
+        count -= size;
+        if code == clear {
+            table.truncate(end as usize + 1);
+            size = min_code_size + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            break;
+        }
+        // The indices of a code, built backwards from its prefixes
+        entry.clear();
+        let known = (code as usize) < table.len();
+        let mut walk = match (known, previous) {
+            (true, _) => code,
+            (false, Some(previous)) if code as usize == table.len() => previous,
+            _ => return None,
+        };
+        while walk != u16::MAX {
+            let (prefix, index) = table[walk as usize];
+            entry.push(index);
+            walk = prefix;
+        }
+        entry.reverse();
+        if !known {
+            entry.push(entry[0]);
+        }
+        if let Some(previous) = previous {
+            if table.len() < 4096 {
+                table.push((previous, entry[0]));
+            }
+        }
+        if table.len() == 1 << size && size < 12 {
+            size += 1;
+        }
+        out.extend_from_slice(&entry);
+        previous = Some(code);
+    }
+    out.truncate(len);
+    Some(out)
+}
+
+/// Encode frames as a looping gif, with colors of a 6x6x6 cube and transparent pixels,
+/// `None` if a frame is wider or taller than a gif can hold
+#[cfg(feature = "render-image")]
+pub(crate) fn encode(frames: &[(RgbaImage, Duration)]) -> Option<Vec<u8>> {
+    const TRANSPARENT: u8 = 216;
+    let size =
+        |image: &RgbaImage| Some((image.width.try_into().ok()?, image.height.try_into().ok()?));
+    if !frames.iter().all(|(image, _)| size(image).is_some()) {
+        return None;
+    }
+    let (width, height): (u16, u16) = match frames.first() {
+        Some((image, _)) => size(image)?,
+        None => (1, 1),
+    };
+    let mut gif = b"GIF89a".to_vec();
+    gif.extend_from_slice(&width.to_le_bytes());
+    gif.extend_from_slice(&height.to_le_bytes());
+    // Global table of 256 colors
+    gif.extend_from_slice(&[0xf7, 0, 0]);
+    for i in 0..=255u16 {
+        let [r, g, b] = match i < 216 {
+            true => [i / 36, i / 6 % 6, i % 6].map(|c| (c * 51) as u8),
+            false => [0; 3],
+        };
+        gif.extend_from_slice(&[r, g, b]);
+    }
+    // Loop forever
+    gif.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+    for (image, delay) in frames {
+        let delay = (delay.as_millis() / 10).clamp(2, u16::MAX as u128) as u16;
+        // Restored to transparent after each frame, which are all whole
+        gif.extend_from_slice(&[0x21, 0xf9, 4, 0x09]);
+        gif.extend_from_slice(&delay.to_le_bytes());
+        gif.extend_from_slice(&[TRANSPARENT, 0]);
+        gif.push(0x2c);
+        gif.extend_from_slice(&[0, 0, 0, 0]);
+        gif.extend_from_slice(&(image.width as u16).to_le_bytes());
+        gif.extend_from_slice(&(image.height as u16).to_le_bytes());
+        gif.push(0);
+        let indices = image
+            .pixels
+            .chunks(4)
+            .map(|p| match p[3] >= 128 {
+                true => p[..3].iter().fold(0, |n, c| n * 6 + c / 43),
+                false => TRANSPARENT,
+            })
+            .collect::<Vec<_>>();
+        gif.push(8);
+        for block in lzw_encode(&indices, 8).chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend_from_slice(block);
+        }
+        gif.push(0);
+    }
+    gif.push(0x3b);
+    Some(gif)
+}
+
+#[cfg(feature = "render-image")]
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let (clear, end) = (1u16 << min_code_size, (1u16 << min_code_size) + 1);
+    let mut out = Vec::new();
+    let (mut bits, mut count) = (0u32, 0u8);
+    let mut push = |code: u16, size: u8, out: &mut Vec<u8>| {
+        bits |= (code as u32) << count;
+        count += size;
+        while count >= 8 {
+            out.push(bits as u8);
+            bits >>= 8;
+            count -= 8;
+        }
+    };
+    let mut table = HashMap::new();
+    let mut next = end + 1;
+    let mut size = min_code_size + 1;
+    push(clear, size, &mut out);
+    let mut current: Option<u16> = None;
+    for &index in indices {
+        let Some(prefix) = current else {
+            current = Some(index as u16);
+            continue;
+        };
+        if let Some(&code) = table.get(&(prefix, index)) {
+            current = Some(code);
+            continue;
+        }
+        push(prefix, size, &mut out);
+        match next {
+            4096 => {
+                push(clear, size, &mut out);
+                table.clear();
+                next = end + 1;
+                size = min_code_size + 1;
+            }
+            _ => {
+                table.insert((prefix, index), next);
+                // The decoder widens its codes a code later than this table has grown
+                if next == 1 << size && size < 12 {
+                    size += 1;
+                }
+                next += 1;
+            }
+        }
+        current = Some(index as u16);
+    }
+    if let Some(code) = current {
+        push(code, size, &mut out);
+    }
+    push(end, size, &mut out);
+    push(0, 7, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_decode() {
+        // One red pixel
+        let gif =
+            b"GIF89a\x01\0\x01\0\x80\0\0\xff\0\0\0\0\0,\0\0\0\0\x01\0\x01\0\0\x02\x02\x44\x01\0;";
+        let frames = decode(gif).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].0.pixels, [255, 0, 0, 255]);
+        assert_eq!(frames[0].1, DEFAULT_DELAY);
+    }
+    #[test]
+    #[cfg(feature = "render-image")]
+    fn test_lzw() {
+        let indices = (0..20000u32)
+            .map(|i| (i * i % 7 + i / 300) as u8)
+            .collect::<Vec<_>>();
+        let encoded = lzw_encode(&indices, 8);
+        assert_eq!(lzw_decode(&encoded, 8, indices.len()), Some(indices));
+    }
+    #[test]
+    #[cfg(feature = "render-image")]
+    fn test_round_trip() {
+        let mut red = RgbaImage::new(3, 2);
+        red.blend(0, 0, [255, 0, 0, 255]);
+        let mut blue = RgbaImage::new(3, 2);
+        blue.blend(2, 1, [0, 0, 255, 255]);
+        let frames = vec![
+            (red, Duration::from_millis(50)),
+            (blue, Duration::from_millis(200)),
+        ];
+        assert_eq!(decode(&encode(&frames).unwrap()), Some(frames));
+        assert_eq!(encode(&[(RgbaImage::new(65536, 1), Duration::ZERO)]), None);
+        assert!(decode(b"GIF89a").is_none());
+        assert!(decode(b"\x89PNG").is_none());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{ParseError, Parser, Token};
+
+/// Custom emote of a Guilded server, as listed by the API
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuildedEmote {
+    pub id: u32,
+    pub name: String,
+    /// Url of the image on the Guilded CDN, `img.guildedcdn.com`
+    pub url: String,
+}
+
+impl GuildedEmote {
+    fn to_token(&self) -> Token {
+        Token::NamedCustomEmoji {
+            name: self.name.clone(),
+            url: self.url.clone(),
+        }
+    }
+}
+
+impl Parser {
+    /// Parse a Guilded message, `<:name:id>` and `:name:` of `emotes` become custom emoji
+    /// tokens with their CDN url
+    ///
+    /// Markup of an emote missing from `emotes` is kept as text.
+    pub fn parse_guilded(
+        &self,
+        s: &str,
+        emotes: &[GuildedEmote],
+    ) -> Result<Vec<Token>, ParseError> {
+        let by_name = |name: &str| emotes.iter().find(|emote| emote.name == name);
+        let mut tokens = Vec::new();
+        for token in self.parse(s)? {
+            match token {
+                Token::Text(s) => markup(&s, emotes, &mut tokens),
+                // The markup is shared with Discord, which may have parsed it already
+                #[cfg(feature = "discord")]
+                Token::CustomEmoji { id, name, animated } => {
+                    match emotes.iter().find(|emote| u64::from(emote.id) == id) {
+                        Some(emote) => tokens.push(emote.to_token()),
+                        None => match by_name(&name) {
+                            Some(emote) => tokens.push(emote.to_token()),
+                            None => {
+                                let prefix = if animated { "a" } else { "" };
+                                tokens.push(Token::new_text(format!("<{prefix}:{name}:{id}>")));
+                            }
+                        },
+                    }
+                }
+                token => tokens.push(token),
+            }
+        }
+        Ok(crate::replace_shortcodes(tokens, &|name| {
+            by_name(name).map(GuildedEmote::to_token)
+        }))
+    }
+}
+
+/// Split `s` around `<:name:id>` markup of known emotes
+fn markup(s: &str, emotes: &[GuildedEmote], out: &mut Vec<Token>) {
+    let (mut text, mut at) = (0, 0);
+    while let Some(start) = s[at..].find("<:").map(|i| at + i) {
+        let emote = s[start + 2..].find('>').and_then(|len| {
+            let (name, id) = s[start + 2..start + 2 + len].split_once(':')?;
+            let id = id.parse::<u32>().ok()?;
+            let emote = emotes.iter().find(|emote| emote.id == id);
+            let emote = emote.or_else(|| emotes.iter().find(|emote| emote.name == name))?;
+            Some((emote, len))
+        });
+        match emote {
+            Some((emote, len)) => {
+                if text < start {
+                    out.push(Token::new_text(&s[text..start]));
+                }
+                out.push(emote.to_token());
+                at = start + len + 3;
+                text = at;
+            }
+            None => at = start + 2,
+        }
+    }
+    if text < s.len() {
+        out.push(Token::new_text(&s[text..]));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_parse_guilded() {
+        let emotes = [GuildedEmote {
+            id: 90000001,
+            name: "gil".to_string(),
+            url: "https://img.guildedcdn.com/asset/Emojis/gil.webp".to_string(),
+        }];
+        let gil = || emotes[0].to_token();
+        let tokens = Parser::new()
+            .parse_guilded("<:gil:90000001>👍:gil: <:old:1> <:x:2> :x:", &emotes)
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                gil(),
+                Token::new_emoji("👍", crate::get("👍").unwrap()),
+                gil(),
+                Token::new_text(" <:old:1> <:x:2> :x:"),
+            ]
+        );
+    }
+}
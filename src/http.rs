@@ -0,0 +1,114 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Future returned by [`HttpGet`] methods
+pub type HttpFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Error of an [`HttpGet`] client, such as a timeout or a refused connection
+pub type HttpError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Response to a request, whatever its status
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    /// Headers with lower case names
+    pub headers: Vec<(String, String)>,
+    /// Empty for `HEAD` requests
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+    /// Value of the header `name`, given in lower case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Client the images are downloaded with, so callers can bring their own pool, proxy,
+/// timeouts and user agent
+///
+/// Implemented for [`reqwest::Client`].
+pub trait HttpGet: Debug + Send + Sync {
+    /// Send a `GET` request with the extra `headers`
+    ///
+    /// Bodies longer than `limit` bytes are errors of the caller, so the client can stop reading
+    /// them once it has read more, or not start when the `content-length` is larger.
+    fn get<'a>(
+        &'a self,
+        url: &'a str,
+        headers: &'a [(&'a str, &'a str)],
+        limit: usize,
+    ) -> HttpFuture<'a, Result<HttpResponse, HttpError>>;
+    /// Send a `HEAD` request, a `GET` dropping the body unless overridden
+    fn head<'a>(&'a self, url: &'a str) -> HttpFuture<'a, Result<HttpResponse, HttpError>> {
+        Box::pin(async move {
+            let response = self.get(url, &[], 0).await?;
+            Ok(HttpResponse {
+                body: Vec::new(),
+                ..response
+            })
+        })
+    }
+}
+
+impl HttpGet for reqwest::Client {
+    fn get<'a>(
+        &'a self,
+        url: &'a str,
+        headers: &'a [(&'a str, &'a str)],
+        limit: usize,
+    ) -> HttpFuture<'a, Result<HttpResponse, HttpError>> {
+        Box::pin(async move {
+            let mut request = reqwest::Client::get(self, url);
+            for (name, value) in headers {
+                request = request.header(*name, *value);
+            }
+            let mut response = request.send().await?;
+            let (status, headers) = parts(&response);
+            let mut body = Vec::new();
+            if response
+                .content_length()
+                .is_none_or(|len| len <= limit as u64)
+            {
+                while let Some(chunk) = response.chunk().await? {
+                    body.extend_from_slice(&chunk);
+                    if body.len() > limit {
+                        break;
+                    }
+                }
+            }
+            Ok(HttpResponse {
+                status,
+                headers,
+                body,
+            })
+        })
+    }
+    fn head<'a>(&'a self, url: &'a str) -> HttpFuture<'a, Result<HttpResponse, HttpError>> {
+        Box::pin(async move {
+            let response = reqwest::Client::head(self, url).send().await?;
+            let (status, headers) = parts(&response);
+            Ok(HttpResponse {
+                status,
+                headers,
+                body: Vec::new(),
+            })
+        })
+    }
+}
+
+fn parts(response: &reqwest::Response) -> (u16, Vec<(String, String)>) {
+    let headers = response
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    (response.status().as_u16(), headers)
+}
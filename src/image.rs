@@ -0,0 +1,134 @@
+use crate::png::{self, ResizeFilter, RgbaImage};
+use crate::{gif, webp, Token};
+use std::time::Duration;
+
+impl Token {
+    /// Decode the image of an emoji or fetched custom emoji, the first frame if it is
+    /// animated
+    ///
+    /// Png, gif and webp images are decoded, `None` for other formats and for tokens without
+    /// image bytes.
+    pub fn decode_image(&self) -> Option<RgbaImage> {
+        decode_frames(self.image_bytes()?)?
+            .into_iter()
+            .next()
+            .map(|(image, _)| image)
+    }
+    /// Frames of the image of an emoji or fetched custom emoji with the time each is shown,
+    /// such as of an animated custom emoji gif, a still image is a single frame
+    pub fn decode_frames(&self) -> Option<Vec<(RgbaImage, Duration)>> {
+        decode_frames(self.image_bytes()?)
+    }
+    /// Width, height and rgba pixels row by row of the image of an emoji or fetched custom
+    /// emoji, ready to upload as a texture
+    pub fn emoji_rgba(&self) -> Option<(u32, u32, Vec<u8>)> {
+        self.decode_image().map(RgbaImage::into_raw)
+    }
+    /// Png of the image of an emoji or fetched custom emoji scaled to `width` by `height`,
+    /// such as to match the height of a line, the first frame if it is animated
+    pub fn emoji_resized(&self, width: u32, height: u32, filter: ResizeFilter) -> Option<Vec<u8>> {
+        let image = self.decode_image()?;
+        Some(
+            image
+                .resized_with(width.max(1), height.max(1), filter)
+                .to_png(),
+        )
+    }
+    /// Lossless webp of the image of an emoji or fetched custom emoji scaled to `width` by
+    /// `height`, smaller than the png for Discord attachments and web pages
+    #[cfg(feature = "webp")]
+    pub fn emoji_webp(&self, width: u32, height: u32, filter: ResizeFilter) -> Option<Vec<u8>> {
+        let image = self.decode_image()?;
+        image
+            .resized_with(width.max(1), height.max(1), filter)
+            .to_webp()
+    }
+    /// Bytes of the image of an emoji or fetched custom emoji
+    pub(crate) fn image_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Token::Emoji { image, .. } => Some(image),
+            #[cfg(feature = "emoji-url")]
+            Token::EmojiUrl { emoji, .. } => crate::get(emoji),
+            // Drawn from the png of the same emoji
+            #[cfg(feature = "svg")]
+            Token::EmojiSvg { emoji, .. } => crate::get(emoji),
+            #[cfg(feature = "custom-emoji")]
+            Token::FetchedCustomEmoji { image, .. } => Some(image),
+            _ => None,
+        }
+    }
+}
+
+/// Frames of a png, gif or webp with the time each is shown, a still image is a single frame
+///
+/// `None` for other formats, and for broken images.
+pub fn decode_frames(image: &[u8]) -> Option<Vec<(RgbaImage, Duration)>> {
+    match image {
+        [0x89, b'P', b'N', b'G', ..] => Some(vec![(png::decode(image)?, Duration::ZERO)]),
+        [b'G', b'I', b'F', b'8', ..] => gif::decode(image),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => webp::decode(image),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_decode_image() {
+        let image = Token::new_emoji("👍", crate::get("👍").unwrap())
+            .decode_image()
+            .unwrap();
+        assert_eq!((image.width, image.height), (72, 72));
+        assert!(Token::new_emoji("👍", b"RIFF\0\0\0\0WEBPVP8 ".as_slice())
+            .decode_image()
+            .is_none());
+        assert!(Token::new_text("👍").decode_image().is_none());
+    }
+    #[test]
+    fn test_emoji_rgba() {
+        let (width, height, pixels) = Token::new_emoji("👍", crate::get("👍").unwrap())
+            .emoji_rgba()
+            .unwrap();
+        assert_eq!((width, height, pixels.len()), (72, 72, 72 * 72 * 4));
+    }
+    #[test]
+    fn test_emoji_resized() {
+        let token = Token::new_emoji("👍", crate::get("👍").unwrap());
+        let png = token.emoji_resized(20, 10, ResizeFilter::Bilinear).unwrap();
+        let image = png::decode(&png).unwrap();
+        assert_eq!((image.width, image.height), (20, 10));
+        assert!(Token::new_text("a")
+            .emoji_resized(20, 20, ResizeFilter::Area)
+            .is_none());
+    }
+    #[test]
+    #[cfg(feature = "webp")]
+    fn test_emoji_webp() {
+        let token = Token::new_emoji("👍", crate::get("👍").unwrap());
+        let webp = token.emoji_webp(72, 72, ResizeFilter::Area).unwrap();
+        assert!(webp.starts_with(b"RIFF") && &webp[8..16] == b"WEBPVP8L");
+        assert!(webp.len() < crate::get("👍").unwrap().len() * 2);
+    }
+    #[test]
+    #[cfg(all(feature = "custom-emoji", feature = "render-image"))]
+    fn test_decode_image_gif() {
+        let mut frame = RgbaImage::new(2, 2);
+        frame.blend(1, 1, [255, 0, 0, 255]);
+        let frames = vec![(frame.clone(), Duration::from_millis(100)); 2];
+        let token = Token::FetchedCustomEmoji {
+            id: 123456789012345678,
+            name: "blob".to_string(),
+            animated: true,
+            image: gif::encode(&frames).unwrap(),
+        };
+        assert_eq!(token.decode_image(), Some(frame.clone()));
+        assert_eq!(token.decode_frames(), Some(frames));
+        assert_eq!(
+            Token::new_emoji("👍", crate::get("👍").unwrap())
+                .decode_frames()
+                .map(|frames| frames.len()),
+            Some(1)
+        );
+    }
+}
@@ -1,121 +1,1881 @@
-#[cfg(feature = "discord")]
-use regex::Regex;
-use rustwemoji::get;
+use std::borrow::Cow;
+#[cfg(feature = "shortcode")]
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
+use twemoji_assets::png::PngTwemojiAsset;
+#[cfg(feature = "svg")]
+use twemoji_assets::svg::SvgTwemojiAsset;
+use unicode_segmentation::{Graphemes, UnicodeSegmentation};
 
+#[cfg(any(feature = "render", feature = "terminal"))]
+mod base64;
+#[cfg(feature = "bbcode")]
+mod bbcode;
+#[cfg(feature = "http")]
+mod cache;
+#[cfg(feature = "discord")]
+mod discord;
+#[cfg(all(feature = "discord", feature = "shortcode"))]
+mod discord_names;
+#[cfg(feature = "emoji-files")]
+mod emoji_files;
+mod emoji_set;
+#[cfg(feature = "emoji-url")]
+mod emoji_url;
+mod emoticon;
+mod error;
+mod extension;
+#[cfg(feature = "http")]
+mod fetch;
+#[cfg(feature = "gemoji")]
+mod gemoji;
+#[cfg(feature = "image")]
+mod gif;
+#[cfg(feature = "guilded")]
+mod guilded;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "image")]
+mod image;
+#[cfg(feature = "markdown")]
+mod markdown;
+#[cfg(feature = "mastodon")]
+mod mastodon;
+#[cfg(feature = "matrix")]
+mod matrix;
+#[cfg(feature = "matrix-sdk")]
+mod matrix_sdk;
+#[cfg(feature = "discord")]
+mod message;
+mod options;
+mod platform;
+#[cfg(any(feature = "sixel", feature = "image"))]
+mod png;
+#[cfg(feature = "poise")]
+mod poise;
+#[cfg(feature = "http")]
+mod policy;
+mod query;
+#[cfg(feature = "render")]
+pub mod render;
+#[cfg(feature = "custom-emoji")]
+mod resolver;
+#[cfg(feature = "revolt")]
+mod revolt;
+#[cfg(feature = "rocketchat")]
+mod rocketchat;
+#[cfg(feature = "serenity")]
+mod serenity;
+#[cfg(feature = "shortcode")]
+mod shortcode;
+#[cfg(feature = "steam")]
+mod steam;
+mod stream;
+#[cfg(feature = "teams")]
+mod teams;
+#[cfg(feature = "telegram")]
+mod telegram;
+#[cfg(feature = "teloxide")]
+mod teloxide;
+#[cfg(feature = "terminal")]
+pub mod terminal;
+#[cfg(feature = "twilight")]
+mod twilight;
+#[cfg(feature = "twitch")]
+mod twitch;
+#[cfg(feature = "image")]
+mod webp;
+#[cfg(feature = "http")]
+pub use cache::{CacheFuture, EmojiCache, FileCache, MemoryCache};
+#[cfg(feature = "discord")]
+pub use discord::{Mention, TimestampStyle};
+#[cfg(feature = "emoji-files")]
+pub use emoji_files::FileEmojiSet;
+pub use emoji_set::{EmojiAsset, EmojiSet, Twemoji};
+pub use emoticon::Emoticons;
+#[cfg(feature = "http")]
+pub use error::FetchError;
+#[cfg(feature = "tgs")]
+pub use error::LottieError;
+pub use error::ParseError;
+pub use extension::SyntaxExtension;
+#[cfg(feature = "http")]
+pub use fetch::{DiscordCdnResolver, DiscordCdnResolverBuilder, Fallback};
+#[cfg(feature = "guilded")]
+pub use guilded::GuildedEmote;
+#[cfg(feature = "http")]
+pub use http::{HttpError, HttpFuture, HttpGet, HttpResponse};
+#[cfg(feature = "image")]
+pub use image::decode_frames;
+#[cfg(feature = "markdown")]
+pub use markdown::Node;
+#[cfg(feature = "mastodon")]
+pub use mastodon::CustomEmojiDef;
+#[cfg(feature = "matrix")]
+pub use matrix::MxcUri;
 #[cfg(feature = "discord")]
-const RE_DISCORD_EMOJI: &str = r"<a?:[a-zA-Z0-9_]+:([0-9]{17,19})>";
+pub use message::{
+    DiscordMessage, GuildEmojiCache, MessageTokens, Reaction, StickerFormat, StickerItem,
+};
+#[cfg(feature = "discord")]
+pub use options::CustomEmojiFormat;
+pub use options::{ParserOptions, ParserOptionsBuilder};
+pub use platform::Platform;
+#[cfg(feature = "image")]
+pub use png::{ResizeFilter, RgbaImage};
+#[cfg(feature = "poise")]
+pub use poise::guild_emojis;
+#[cfg(feature = "http")]
+pub use policy::UrlPolicy;
+#[cfg(feature = "custom-emoji")]
+pub use query::count_custom_emoji;
+#[cfg(feature = "discord")]
+pub use query::strip_all_emoji;
+pub use query::{
+    contains_emoji, count_emoji, is_emoji_only, jumbo_size, strip_emoji, JUMBO_THRESHOLD,
+};
+#[cfg(feature = "custom-emoji")]
+pub use resolver::{CustomEmojiResolver, NoopResolver, ResolvedEmoji};
+#[cfg(feature = "rocketchat")]
+pub use rocketchat::rocketchat_emoji_url;
+#[cfg(feature = "shortcode")]
+pub use shortcode::Alias;
+pub use stream::StreamingParser;
+#[cfg(feature = "telegram")]
+pub use telegram::{Entity, EntityKind};
+#[cfg(feature = "twitch")]
+pub use twitch::EmoteRanges;
+
+const ZWJ: char = '\u{200d}';
+const VS15: char = '\u{fe0e}';
+const VS16: char = '\u{fe0f}';
+const SKIN_TONES: std::ops::RangeInclusive<char> = '\u{1f3fb}'..='\u{1f3ff}';
+const KEYCAP: char = '\u{20e3}';
+const TAGS: std::ops::RangeInclusive<char> = '\u{e0020}'..='\u{e007f}';
+const REGIONAL_INDICATORS: std::ops::RangeInclusive<char> = '\u{1f1e6}'..='\u{1f1ff}';
 
 /// Tokens parsed
 #[derive(Debug, PartialEq, Eq)]
 pub enum Token {
     /// Text token
     Text(String),
-    /// Emoji token(bytes of png)
-    Emoji(Vec<u8>),
+    /// Emoji token
+    Emoji {
+        /// The emoji as written, usable as alt text
+        emoji: String,
+        /// Bytes of png, borrowed from the embedded assets
+        image: Cow<'static, [u8]>,
+    },
+    #[cfg(feature = "svg")]
+    /// Emoji with its twemoji svg image, see [`ParserOptions::svg_emoji`]
+    EmojiSvg {
+        /// The emoji, usable as alt text
+        emoji: String,
+        /// Svg document, borrowed from the embedded assets
+        svg: &'static str,
+    },
+    #[cfg(feature = "emoji-url")]
+    /// Emoji with the url of its twemoji image, see [`ParserOptions::emoji_urls`]
+    EmojiUrl {
+        /// The emoji, usable as alt text
+        emoji: String,
+        url: String,
+    },
+    #[cfg(feature = "custom-emoji")]
+    /// Custom emoji token
+    CustomEmoji {
+        /// Id of the emoji, a snowflake on Discord
+        id: u64,
+        /// Name of the emoji, usable as alt text
+        name: String,
+        /// Whether the emoji is animated (`<a:name:id>`)
+        animated: bool,
+    },
+    #[cfg(feature = "custom-emoji")]
+    /// Custom emoji with its image, see [`Parser::resolve_custom_emoji`]
+    FetchedCustomEmoji {
+        id: u64,
+        name: String,
+        animated: bool,
+        /// Bytes of the image, in the format the resolver got it in
+        image: Vec<u8>,
+    },
     #[cfg(feature = "discord")]
-    /// Custom emoji token(url)
-    CustomEmoji(String),
+    /// User, channel or role mention
+    Mention(Mention),
+    #[cfg(feature = "discord")]
+    /// Slash command mention (`</name:id>`)
+    CommandMention {
+        /// Command name, including subcommands separated by spaces
+        name: String,
+        /// Snowflake id of the command
+        id: u64,
+    },
+    #[cfg(feature = "discord")]
+    /// Inline code (`` `code` ``), kept verbatim
+    Code(String),
+    #[cfg(feature = "discord")]
+    /// Code block (```` ```language\ncode``` ````), kept verbatim
+    CodeBlock {
+        language: Option<String>,
+        code: String,
+    },
+    #[cfg(feature = "discord")]
+    /// Spoiler (`||spoiler||`) wrapping the tokens hidden by it
+    Spoiler(Vec<Token>),
+    #[cfg(feature = "discord")]
+    /// Timestamp, to be formatted in the reader's locale
+    Timestamp {
+        /// Unix time in seconds
+        unix: i64,
+        /// How the time should be displayed
+        style: TimestampStyle,
+    },
+    #[cfg(feature = "discord")]
+    /// Sticker sent with a message, see [`MessageTokens::into_tokens`]
+    Sticker {
+        id: u64,
+        /// Name of the sticker, usable as alt text
+        name: String,
+        format: StickerFormat,
+    },
+    #[cfg(feature = "named-custom-emoji")]
+    /// Custom emoji known by name and image url, such as GitHub's `:shipit:` or a Matrix pack emoji
+    NamedCustomEmoji {
+        /// Name of the emoji, usable as alt text
+        name: String,
+        /// Url of its image
+        url: String,
+    },
 }
 
 impl Token {
     pub fn new_text(s: impl Into<String>) -> Self {
         Self::Text(s.into())
     }
-    pub fn new_emoji(s: impl Into<Vec<u8>>) -> Self {
-        Self::Emoji(s.into())
+    pub fn new_emoji(emoji: impl Into<String>, image: impl Into<Cow<'static, [u8]>>) -> Self {
+        Self::Emoji {
+            emoji: emoji.into(),
+            image: image.into(),
+        }
+    }
+    #[cfg(feature = "custom-emoji")]
+    pub fn new_custom_emoji(id: u64, name: impl Into<String>, animated: bool) -> Self {
+        Self::CustomEmoji {
+            id,
+            name: name.into(),
+            animated,
+        }
     }
+    /// CDN url of a custom emoji or sticker, `None` for other tokens
     #[cfg(feature = "discord")]
-    pub fn new_custom_emoji(s: String) -> Self {
-        let s = format!("https://cdn.discordapp.com/emojis/{}.png?size=96", s);
-        Self::CustomEmoji(s)
+    pub fn url(&self) -> Option<String> {
+        self.url_with(&ParserOptions::default())
+    }
+    /// CDN url of a custom emoji built from `options` or of a sticker, `None` for other tokens
+    #[cfg(feature = "discord")]
+    pub fn url_with(&self, options: &ParserOptions) -> Option<String> {
+        match *self {
+            Token::CustomEmoji { id, animated, .. }
+            | Token::FetchedCustomEmoji { id, animated, .. } => {
+                Some(options.custom_emoji_url(id, animated, options.emoji_size))
+            }
+            Token::Sticker { id, format, .. } => Some(message::sticker_url(id, format, None)),
+            _ => None,
+        }
+    }
+    /// CDN url of a custom emoji or sticker at `size`, which must be a size Discord serves
+    #[cfg(feature = "discord")]
+    pub fn url_with_size(&self, size: u32) -> Result<Option<String>, ParseError> {
+        options::check_emoji_size(size)?;
+        let options = ParserOptions::default();
+        Ok(match *self {
+            Token::CustomEmoji { id, animated, .. }
+            | Token::FetchedCustomEmoji { id, animated, .. } => {
+                Some(options.custom_emoji_url(id, animated, size))
+            }
+            Token::Sticker { id, format, .. } => Some(message::sticker_url(id, format, Some(size))),
+            _ => None,
+        })
     }
 }
 
-#[cfg(not(feature = "discord"))]
-fn raw_parse(s: String) -> Vec<Token> {
-    raw_parse_emoji(s)
+/// Tokens parsed, borrowing text from the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenRef<'a> {
+    /// Text token
+    Text(&'a str),
+    /// Emoji token
+    Emoji {
+        /// The emoji as written, or the one a shortcode or emoticon stands for
+        emoji: Cow<'a, str>,
+        /// Bytes of png
+        image: Cow<'static, [u8]>,
+    },
+    #[cfg(feature = "custom-emoji")]
+    /// Custom emoji token
+    CustomEmoji {
+        /// Id of the emoji, a snowflake on Discord
+        id: u64,
+        /// Name of the emoji
+        name: &'a str,
+        /// Whether the emoji is animated (`<a:name:id>`)
+        animated: bool,
+    },
+    #[cfg(feature = "discord")]
+    /// User, channel or role mention
+    Mention(Mention),
+    #[cfg(feature = "discord")]
+    /// Slash command mention (`</name:id>`)
+    CommandMention {
+        /// Command name, including subcommands separated by spaces
+        name: &'a str,
+        /// Snowflake id of the command
+        id: u64,
+    },
+    #[cfg(feature = "discord")]
+    /// Inline code, kept verbatim
+    Code(&'a str),
+    #[cfg(feature = "discord")]
+    /// Code block, kept verbatim
+    CodeBlock {
+        language: Option<&'a str>,
+        code: &'a str,
+    },
+    #[cfg(feature = "discord")]
+    /// Spoiler, holding the raw text hidden by it
+    Spoiler(&'a str),
+    #[cfg(feature = "discord")]
+    /// Timestamp, to be formatted in the reader's locale
+    Timestamp {
+        /// Unix time in seconds
+        unix: i64,
+        /// How the time should be displayed
+        style: TimestampStyle,
+    },
+    #[cfg(feature = "named-custom-emoji")]
+    /// Custom emoji known by name and image url, such as GitHub's `:shipit:`
+    NamedCustomEmoji {
+        /// Name of the emoji
+        name: &'a str,
+        /// Url of its image
+        url: &'a str,
+    },
+    #[cfg(feature = "shortcode")]
+    /// Name of an alias to an image url, see [`Parser::alias`]
+    ///
+    /// Converted to [`Token::NamedCustomEmoji`] with the parser that found it,
+    /// other parsers keep it as text.
+    Alias(&'a str),
+    /// Match of a [`SyntaxExtension`], by its index in the parser that found it
+    ///
+    /// Converted with the extension of that parser, other parsers keep it as text.
+    Extension { index: usize, matched: &'a str },
+}
+
+impl TokenRef<'_> {
+    /// Convert to an owned token
+    pub fn to_token(&self) -> Token {
+        self.clone().into_token_with(default_parser())
+    }
+    /// Convert to an owned token, parsing spoilers with `parser`
+    fn into_token_with(self, parser: &Parser) -> Token {
+        match self {
+            TokenRef::Text(s) => Token::new_text(s),
+            #[cfg(feature = "svg")]
+            TokenRef::Emoji { emoji, image } if parser.options.svg_emoji => {
+                emoji_url::svg_token(emoji, image)
+            }
+            #[cfg(feature = "emoji-url")]
+            TokenRef::Emoji { emoji, image } if parser.options.emoji_urls => {
+                emoji_url::token(emoji, image, &parser.options)
+            }
+            TokenRef::Emoji { emoji, image } => Token::new_emoji(emoji, image),
+            #[cfg(feature = "custom-emoji")]
+            TokenRef::CustomEmoji { id, name, animated } => {
+                Token::new_custom_emoji(id, name, animated)
+            }
+            #[cfg(feature = "discord")]
+            TokenRef::Mention(m) => Token::Mention(m),
+            #[cfg(feature = "discord")]
+            TokenRef::CommandMention { name, id } => Token::CommandMention {
+                name: name.to_string(),
+                id,
+            },
+            #[cfg(feature = "discord")]
+            TokenRef::Timestamp { unix, style } => Token::Timestamp { unix, style },
+            #[cfg(feature = "discord")]
+            TokenRef::Code(code) => Token::Code(code.to_string()),
+            #[cfg(feature = "discord")]
+            TokenRef::Spoiler(s) => Token::Spoiler(
+                RawTokens::spoiler(s, parser)
+                    .map(|t| t.into_token_with(parser))
+                    .collect(),
+            ),
+            #[cfg(feature = "discord")]
+            TokenRef::CodeBlock { language, code } => Token::CodeBlock {
+                language: language.map(str::to_string),
+                code: code.to_string(),
+            },
+            #[cfg(feature = "named-custom-emoji")]
+            TokenRef::NamedCustomEmoji { name, url } => Token::NamedCustomEmoji {
+                name: name.to_string(),
+                url: url.to_string(),
+            },
+            #[cfg(feature = "shortcode")]
+            TokenRef::Alias(name) => match parser.alias(name) {
+                Some(Alias::Url(url)) => Token::NamedCustomEmoji {
+                    name: name.to_string(),
+                    url: url.clone(),
+                },
+                _ => Token::new_text(format!(":{}:", name)),
+            },
+            TokenRef::Extension { index, matched } => parser.extension_token(index, matched),
+        }
+    }
+}
+
+impl From<TokenRef<'_>> for Token {
+    fn from(t: TokenRef<'_>) -> Self {
+        t.into_token_with(default_parser())
+    }
+}
+
+/// Reusable parser holding its options and compiled state
+#[derive(Debug, Clone)]
+pub struct Parser {
+    options: ParserOptions,
+    /// Shortcodes registered with [`Parser::register_alias`]
+    #[cfg(feature = "shortcode")]
+    aliases: HashMap<String, Alias>,
+    /// Syntax registered with [`Parser::register_extension`]
+    extensions: Vec<Arc<dyn SyntaxExtension>>,
+    /// Images of emoji, see [`Parser::set_emoji_set`]
+    emoji_set: Arc<dyn EmojiSet>,
+    /// This parser with Discord markup turned off, built by [`Parser::parse_for`]
+    #[cfg(feature = "discord")]
+    without_discord: OnceLock<Arc<Parser>>,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::with_options(ParserOptions::default())
+    }
+    pub fn with_options(options: ParserOptions) -> Self {
+        Self {
+            options,
+            #[cfg(feature = "shortcode")]
+            aliases: HashMap::new(),
+            extensions: Vec::new(),
+            emoji_set: Arc::new(Twemoji),
+            #[cfg(feature = "discord")]
+            without_discord: OnceLock::new(),
+        }
+    }
+    pub fn options(&self) -> &ParserOptions {
+        &self.options
+    }
+    /// Parse a string to tokens
+    pub fn parse(&self, s: &str) -> Result<Vec<Token>, ParseError> {
+        Ok(self.tokenize(s)?.collect())
+    }
+    /// Parse a string into `tokens`, clearing it first
+    pub fn parse_into(&self, s: &str, tokens: &mut Vec<Token>) -> Result<(), ParseError> {
+        tokens.clear();
+        tokens.extend(self.tokenize(s)?);
+        Ok(())
+    }
+    /// Parse a string to tokens borrowing from it
+    pub fn parse_ref<'a>(&self, s: &'a str) -> Result<Vec<TokenRef<'a>>, ParseError> {
+        Ok(RawTokens::new(s, self)?.collect())
+    }
+    /// Lazily parse a string to tokens
+    pub fn tokenize<'a>(&'a self, s: &'a str) -> Result<Tokens<'a>, ParseError> {
+        Ok(Tokens {
+            inner: RawTokens::new(s, self)?,
+        })
+    }
+}
+
+fn default_parser() -> &'static Parser {
+    static PARSER: OnceLock<Parser> = OnceLock::new();
+    PARSER.get_or_init(Parser::new)
+}
+
+#[cfg(not(feature = "async"))]
+/// Parse a string to tokens
+pub fn parse(s: impl AsRef<str>) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse(s.as_ref())
 }
 
 #[cfg(not(feature = "async"))]
-pub fn parse(s: String) -> Vec<Token> {
-    raw_parse(s)
+/// Parse a string to tokens with options
+pub fn parse_with(s: impl AsRef<str>, options: &ParserOptions) -> Result<Vec<Token>, ParseError> {
+    Parser::with_options(options.clone()).parse(s.as_ref())
+}
+
+/// Parse a string into `tokens`, clearing it first
+pub fn parse_into(s: impl AsRef<str>, tokens: &mut Vec<Token>) -> Result<(), ParseError> {
+    default_parser().parse_into(s.as_ref(), tokens)
+}
+
+/// Parse a string to tokens borrowing from it
+pub fn parse_ref(s: &str) -> Result<Vec<TokenRef<'_>>, ParseError> {
+    default_parser().parse_ref(s)
+}
+
+/// Parse a string to tokens borrowing from it with options
+pub fn parse_ref_with<'a>(
+    s: &'a str,
+    options: &ParserOptions,
+) -> Result<Vec<TokenRef<'a>>, ParseError> {
+    Parser::with_options(options.clone()).parse_ref(s)
+}
+
+/// Lazily parse a string to tokens
+pub fn tokenize(s: &str) -> Result<Tokens<'_>, ParseError> {
+    default_parser().tokenize(s)
+}
+
+/// Parse a string with the syntax of `platform`
+pub fn parse_for(platform: Platform, s: &str) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_for(platform, s)
+}
+
+#[cfg(feature = "markdown")]
+/// Parse a string to a tree of markdown nodes
+pub fn parse_markdown(s: &str) -> Result<Vec<Node>, ParseError> {
+    default_parser().parse_markdown(s)
+}
+
+#[cfg(feature = "discord")]
+/// Parse the content and reactions of a message, naming custom emoji from `emojis`
+pub fn parse_message(
+    message: &impl DiscordMessage,
+    emojis: &GuildEmojiCache,
+) -> Result<MessageTokens, ParseError> {
+    default_parser().parse_message(message, emojis)
+}
+
+#[cfg(feature = "matrix")]
+/// Parse a Matrix message, naming custom emoji with `resolve`
+pub fn parse_matrix(
+    s: &str,
+    homeserver: &str,
+    resolve: impl Fn(&str) -> Option<MxcUri>,
+) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_matrix(s, homeserver, resolve)
+}
+
+#[cfg(feature = "matrix-sdk")]
+/// Parse a room message, naming custom emoji from its formatted body and the room image packs
+pub fn parse_room_message(
+    content: &::matrix_sdk::ruma::events::room::message::RoomMessageEventContent,
+    packs: &[::ruma::events::image_pack::RoomImagePackEventContent],
+    homeserver: &str,
+) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_room_message(content, packs, homeserver)
+}
+
+#[cfg(feature = "mastodon")]
+/// Parse the text of a Mastodon status, naming custom emoji from `emojis`
+pub fn parse_mastodon(text: &str, emojis: &[CustomEmojiDef]) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_mastodon(text, emojis)
+}
+
+#[cfg(feature = "telegram")]
+/// Parse the text of a Telegram message with its entities
+pub fn parse_with_entities(text: &str, entities: &[Entity]) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_with_entities(text, entities)
+}
+
+#[cfg(feature = "teloxide")]
+/// Parse the text or caption of a teloxide message with its entities
+pub fn parse_telegram_message(
+    message: &::teloxide::types::Message,
+) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_telegram_message(message)
+}
+
+#[cfg(feature = "twitch")]
+/// Parse a Twitch chat message with the emote ranges of its tags
+pub fn parse_twitch(text: &str, emotes: &EmoteRanges) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_twitch(text, emotes)
+}
+
+#[cfg(feature = "revolt")]
+/// Parse a Revolt message, turning `:id:` markup into custom emoji
+pub fn parse_revolt(s: &str) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_revolt(s)
+}
+
+#[cfg(feature = "guilded")]
+/// Parse a Guilded message, naming custom emoji from `emotes`
+pub fn parse_guilded(s: &str, emotes: &[GuildedEmote]) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_guilded(s, emotes)
+}
+
+#[cfg(feature = "rocketchat")]
+/// Parse a Rocket.Chat message, naming custom emoji from a shortcode to url map
+pub fn parse_rocketchat(
+    s: &str,
+    emojis: &std::collections::HashMap<String, String>,
+) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_rocketchat(s, emojis)
+}
+
+#[cfg(feature = "teams")]
+/// Parse a Teams message, turning `(code)` emoticons and `<emoji>` tags into emoji
+pub fn parse_teams(s: &str) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_teams(s)
+}
+
+#[cfg(feature = "steam")]
+/// Parse a Steam chat message, turning default `:emoticons:` into custom emoji
+pub fn parse_steam(s: &str) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_steam(s)
+}
+
+#[cfg(feature = "bbcode")]
+/// Parse BBCode, turning `[emoji]` tags and `:smiley:` smilies into emoji
+pub fn parse_bbcode(s: &str) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_bbcode(s)
+}
+
+#[cfg(feature = "poise")]
+/// Parse the message or invocation string of a poise command
+pub fn parse_invocation<U, E>(ctx: ::poise::Context<'_, U, E>) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_invocation(ctx)
+}
+
+#[cfg(feature = "poise")]
+/// Parse an argument of a poise command
+pub fn parse_argument<U, E>(
+    ctx: ::poise::Context<'_, U, E>,
+    s: &str,
+) -> Result<Vec<Token>, ParseError> {
+    default_parser().parse_argument(ctx, s)
+}
+
+#[cfg(feature = "http")]
+/// Parse a string and download the images of its custom emoji from the Discord CDN
+pub async fn parse_and_fetch(s: &str) -> Result<Vec<Token>, ParseError> {
+    static FETCHER: OnceLock<DiscordCdnResolver> = OnceLock::new();
+    let fetcher = FETCHER.get_or_init(DiscordCdnResolver::new);
+    default_parser().parse_and_fetch(s, fetcher).await
 }
 
 #[cfg(feature = "tokio")]
 /// Parse a string to tokens
-pub async fn parse(s: String) -> Result<Vec<Token>, tokio::task::JoinError> {
-    tokio::task::spawn(async { raw_parse(s) }).await
+pub async fn parse(s: impl AsRef<str>) -> Result<Vec<Token>, ParseError> {
+    let s = s.as_ref().to_owned();
+    tokio::task::spawn(async move { default_parser().parse(&s) }).await?
+}
+
+#[cfg(feature = "tokio")]
+/// Parse a string to tokens with options
+pub async fn parse_with(
+    s: impl AsRef<str>,
+    options: &ParserOptions,
+) -> Result<Vec<Token>, ParseError> {
+    let (s, parser) = (s.as_ref().to_owned(), Parser::with_options(options.clone()));
+    tokio::task::spawn(async move { parser.parse(&s) }).await?
 }
 
 #[cfg(feature = "async-std")]
 /// Parse a string to tokens
-pub async fn parse(s: String) -> Vec<Token> {
-    async_std::task::spawn(async { raw_parse(s) }).await
-}
-
-fn raw_parse_emoji(s: String) -> Vec<Token> {
-    let s = s.chars();
-    s.map(|f| f.to_string())
-        .map(|f| {
-            if let Some(v) = get(&f) {
-                Token::new_emoji(v)
-            } else {
-                Token::new_text(f)
+pub async fn parse(s: impl AsRef<str>) -> Result<Vec<Token>, ParseError> {
+    let s = s.as_ref().to_owned();
+    async_std::task::spawn(async move { default_parser().parse(&s) }).await
+}
+
+#[cfg(feature = "async-std")]
+/// Parse a string to tokens with options
+pub async fn parse_with(
+    s: impl AsRef<str>,
+    options: &ParserOptions,
+) -> Result<Vec<Token>, ParseError> {
+    let (s, parser) = (s.as_ref().to_owned(), Parser::with_options(options.clone()));
+    async_std::task::spawn(async move { parser.parse(&s) }).await
+}
+
+/// Look up the png of an emoji sequence.
+/// Twemoji drops VS16 from most file names, so retry without it.
+fn get(code: &str) -> Option<&'static [u8]> {
+    let asset = match PngTwemojiAsset::from_emoji(code) {
+        None if code.contains(VS16) => PngTwemojiAsset::from_emoji(&code.replace(VS16, "")),
+        asset => asset,
+    }?;
+    Some(asset.data.0)
+}
+
+#[cfg(feature = "svg")]
+fn get_svg(code: &str) -> Option<&'static str> {
+    let asset = match SvgTwemojiAsset::from_emoji(code) {
+        None if code.contains(VS16) => SvgTwemojiAsset::from_emoji(&code.replace(VS16, "")),
+        asset => asset,
+    }?;
+    Some(asset.data.0)
+}
+
+fn keycap_base(g: &str) -> Option<char> {
+    let mut chars = g.chars();
+    let base = chars
+        .next()
+        .filter(|c| matches!(c, '0'..='9' | '#' | '*'))?;
+    let rest = chars.as_str();
+    let rest = rest.strip_prefix([VS15, VS16]).unwrap_or(rest);
+    (rest.strip_prefix(KEYCAP) == Some("")).then_some(base)
+}
+
+/// Resolve a grapheme into `out`, without allocating for plain text
+fn parse_grapheme<'a>(g: &'a str, parser: &Parser, out: &mut VecDeque<TokenRef<'a>>) {
+    let get = |code: &str| parser.emoji_png(code);
+    // No ascii character has an emoji on its own
+    if g.len() == 1 {
+        out.push_back(TokenRef::Text(g));
+        return;
+    }
+    // A selector without anything to select is dropped
+    if g.chars().all(|c| c == VS15 || c == VS16) {
+        return;
+    }
+    if g.ends_with(VS15) {
+        if parser.options.text_presentation {
+            out.push_back(TokenRef::Text(g));
+            return;
+        }
+        return parse_grapheme(g.trim_end_matches(VS15), parser, out);
+    }
+    let emoji = |image| TokenRef::Emoji {
+        emoji: Cow::Borrowed(g),
+        image,
+    };
+    if let Some(v) = get(g) {
+        out.push_back(emoji(v));
+        return;
+    }
+    // Keycaps are looked up as `base + U+20E3` whatever selector sits between
+    if let Some(base) = keycap_base(g) {
+        if let Some(v) = get(&format!("{}{}", base, KEYCAP)) {
+            out.push_back(emoji(v));
+            return;
+        }
+    }
+    // Fall back to the base emoji if the skin toned one is missing
+    if g.chars().count() > 1 && g.chars().any(|c| SKIN_TONES.contains(&c)) {
+        let base = g.replace(|c| SKIN_TONES.contains(&c), "");
+        if let Some(v) = get(&base) {
+            out.push_back(emoji(v));
+            return;
+        }
+    }
+    // Unknown subdivision flags fall back to the flag the tags are attached to
+    if g.chars().any(|c| TAGS.contains(&c)) {
+        let base = g.replace(|c| TAGS.contains(&c), "");
+        if let Some(v) = get(&base) {
+            out.push_back(emoji(v));
+            return;
+        }
+    }
+    // Regional indicators that don't form a known flag are kept as letters
+    if g.chars().count() > 1 && g.chars().all(|c| REGIONAL_INDICATORS.contains(&c)) {
+        out.extend(g.char_indices().map(|(i, c)| {
+            let c = &g[i..i + c.len_utf8()];
+            get(c).map_or(TokenRef::Text(c), |image| TokenRef::Emoji {
+                emoji: Cow::Borrowed(c),
+                image,
+            })
+        }));
+        return;
+    }
+    // Unknown ZWJ sequences are rendered as their components
+    if g.contains(ZWJ) {
+        let mut tokens = VecDeque::new();
+        for f in g.split(ZWJ).filter(|f| !f.is_empty()) {
+            parse_grapheme(f, parser, &mut tokens);
+        }
+        if tokens.iter().any(|t| matches!(t, TokenRef::Emoji { .. })) {
+            out.extend(tokens);
+            return;
+        }
+    }
+    out.push_back(TokenRef::Text(g));
+}
+
+/// Split text tokens around the `:name:` that `resolve` has a token for
+#[cfg(any(
+    feature = "discord",
+    feature = "matrix",
+    feature = "mastodon",
+    feature = "revolt",
+    feature = "guilded",
+    feature = "rocketchat",
+    feature = "steam",
+    feature = "bbcode"
+))]
+fn replace_shortcodes(tokens: Vec<Token>, resolve: &impl Fn(&str) -> Option<Token>) -> Vec<Token> {
+    let mut out = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        match token {
+            Token::Text(s) => {
+                let mut text = 0;
+                let mut at = 0;
+                while let Some(start) = s[at..].find(':').map(|i| at + i) {
+                    let name = s[start + 1..]
+                        .find(':')
+                        .map(|len| &s[start + 1..start + 1 + len])
+                        .filter(|name| !name.is_empty() && !name.contains(char::is_whitespace));
+                    match name.and_then(|name| Some((name, resolve(name)?))) {
+                        Some((name, token)) => {
+                            if text < start {
+                                out.push(Token::new_text(&s[text..start]));
+                            }
+                            out.push(token);
+                            at = start + name.len() + 2;
+                            text = at;
+                        }
+                        None => at = start + 1,
+                    }
+                }
+                if text < s.len() {
+                    out.push(Token::new_text(&s[text..]));
+                }
             }
-        })
-        .collect::<Vec<_>>()
+            #[cfg(feature = "discord")]
+            Token::Spoiler(tokens) => out.push(Token::Spoiler(replace_shortcodes(tokens, resolve))),
+            token => out.push(token),
+        }
+    }
+    out
 }
 
-#[cfg(feature = "discord")]
-fn raw_parse(s: String) -> Vec<Token> {
-    let mut tokens = Vec::new();
-    let re = Regex::new(RE_DISCORD_EMOJI).unwrap();
-    let mut last = 0;
-    for m in re.find_iter(&s) {
-        let (start, end) = (m.range().start, m.range().end);
-        let text = s[last..start].to_string();
-        let emoji = s[start..end].to_string();
-        let id = re
-            .captures(&emoji)
-            .unwrap()
-            .get(1)
-            .unwrap()
-            .as_str()
-            .to_string();
-        tokens.extend(raw_parse_emoji(text));
-        tokens.push(Token::new_custom_emoji(id));
-        last = end;
+/// Quoted value of `name` in the attributes of a tag
+#[cfg(any(feature = "matrix-sdk", feature = "teams"))]
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    while let Some(at) = rest.find(name) {
+        let after = &rest[at + name.len()..];
+        let boundary = rest[..at].ends_with(char::is_whitespace);
+        if let (true, Some(value)) = (boundary, after.strip_prefix('=')) {
+            let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let value = &value[1..];
+            return value.find(quote).map(|end| &value[..end]);
+        }
+        rest = after;
+    }
+    None
+}
+
+/// Byte offset of `part` in `s`, `part` has to be a slice of `s`
+fn offset(s: &str, part: &str) -> usize {
+    part.as_ptr() as usize - s.as_ptr() as usize
+}
+
+/// Lazy tokenizer yielding borrowed tokens
+#[derive(Debug, Clone)]
+struct RawTokens<'a, 'o> {
+    s: &'a str,
+    parser: &'o Parser,
+    /// Input not split into segments yet
+    rest: &'a str,
+    /// Graphemes of the current text segment
+    graphemes: Graphemes<'a>,
+    /// Tokens of the current grapheme not yielded yet
+    pending: VecDeque<TokenRef<'a>>,
+    /// Token closing the current text segment
+    closing: Option<TokenRef<'a>>,
+    /// Whether the input is the inside of a spoiler
+    #[cfg(feature = "discord")]
+    in_spoiler: bool,
+    /// Token read ahead while coalescing text
+    peeked: Option<TokenRef<'a>>,
+}
+
+impl<'a, 'o> RawTokens<'a, 'o> {
+    fn new(s: &'a str, parser: &'o Parser) -> Result<Self, ParseError> {
+        #[cfg(feature = "discord")]
+        discord::check(&parser.options)?;
+        Ok(Self::unchecked(s, parser))
+    }
+
+    /// Tokens of `s`, with the options already checked
+    fn unchecked(s: &'a str, parser: &'o Parser) -> Self {
+        Self {
+            s,
+            parser,
+            rest: s,
+            graphemes: "".graphemes(true),
+            pending: VecDeque::new(),
+            closing: None,
+            #[cfg(feature = "discord")]
+            in_spoiler: false,
+            peeked: None,
+        }
+    }
+
+    /// Tokens of the inside of a spoiler, which can't contain another one
+    #[cfg(feature = "discord")]
+    fn spoiler(s: &'a str, parser: &'o Parser) -> Self {
+        Self {
+            in_spoiler: true,
+            ..Self::unchecked(s, parser)
+        }
+    }
+
+    /// Whether emoji should be kept as text
+    #[cfg(feature = "discord")]
+    fn suppress_emoji(&self) -> bool {
+        self.in_spoiler && !self.parser.options.spoiler_emoji
+    }
+
+    /// Find the first known shortcode if they are enabled
+    #[cfg(feature = "shortcode")]
+    fn find_shortcode(&self, s: &'a str) -> Option<(Range<usize>, TokenRef<'a>)> {
+        match self.parser.options.shortcodes {
+            true => shortcode::find_shortcode(s, self.parser),
+            false => None,
+        }
+    }
+
+    #[cfg(not(feature = "shortcode"))]
+    fn find_shortcode(&self, _s: &'a str) -> Option<(Range<usize>, TokenRef<'a>)> {
+        None
+    }
+
+    /// Find the first shortcode or emoticon converted to an emoji, or match of an extension
+    fn find_named(&self, s: &'a str) -> Option<(Range<usize>, TokenRef<'a>)> {
+        let emoticon = self
+            .parser
+            .options
+            .emoticons
+            .as_ref()
+            .and_then(|e| e.find(s, self.parser));
+        let named = match (self.find_shortcode(s), emoticon) {
+            (Some(c), Some(e)) if e.0.start < c.0.start => Some(e),
+            (None, e) => e,
+            (c, _) => c,
+        };
+        let extension =
+            extension::find_extension(&self.parser.extensions, s).map(|(range, index)| {
+                let matched = &s[range.clone()];
+                (range, TokenRef::Extension { index, matched })
+            });
+        match (named, extension) {
+            (Some(n), Some(e)) if e.0.start < n.0.start => Some(e),
+            (None, e) => e,
+            (n, _) => n,
+        }
+    }
+
+    #[cfg(not(feature = "discord"))]
+    fn next_segment(&mut self) -> bool {
+        if self.rest.is_empty() {
+            return false;
+        }
+        let rest = std::mem::take(&mut self.rest);
+        match self.find_named(rest) {
+            Some((range, token)) => {
+                self.graphemes = rest[..range.start].graphemes(true);
+                self.closing = Some(token);
+                self.rest = &rest[range.end..];
+            }
+            None => self.graphemes = rest.graphemes(true),
+        }
+        true
+    }
+
+    #[cfg(feature = "discord")]
+    fn next_segment(&mut self) -> bool {
+        if self.rest.is_empty() {
+            return false;
+        }
+        let rest = std::mem::take(&mut self.rest);
+        let options = &self.parser.options;
+        let found = match options.discord {
+            true => discord::find_markup(rest, options),
+            false => None,
+        };
+        let spoiler = match options.discord && options.spoilers && !self.in_spoiler {
+            true => discord::find_spoiler(rest),
+            false => None,
+        };
+        // Whichever starts first contains the others
+        let found = [found, spoiler, self.find_named(rest)]
+            .into_iter()
+            .flatten()
+            .min_by_key(|(range, _)| range.start);
+        match found {
+            Some((range, token)) => {
+                let before = &rest[..range.start];
+                // `\<:name:id>` is shown as is, without the backslash
+                let escaped = rest[range.start..].starts_with('<') && discord::is_escaped(before);
+                let before = match escaped {
+                    true => &before[..before.len() - 1],
+                    false => before,
+                };
+                self.graphemes = before.graphemes(true);
+                self.closing = Some(match token {
+                    _ if escaped => TokenRef::Text(&rest[range.clone()]),
+                    TokenRef::CustomEmoji { .. } | TokenRef::Emoji { .. }
+                        if self.suppress_emoji() =>
+                    {
+                        TokenRef::Text(&rest[range.clone()])
+                    }
+                    token => token,
+                });
+                self.rest = &rest[range.end..];
+            }
+            None => self.graphemes = rest.graphemes(true),
+        }
+        true
+    }
+
+    /// Keep the emoji after a backslash as text, dropping the backslash
+    #[cfg(feature = "discord")]
+    fn escape(&mut self, backslash: &'a str) -> TokenRef<'a> {
+        if let Some(g) = self.graphemes.next() {
+            parse_grapheme(g, self.parser, &mut self.pending);
+            if self
+                .pending
+                .iter()
+                .any(|t| matches!(t, TokenRef::Emoji { .. }))
+            {
+                self.pending.clear();
+                return TokenRef::Text(g);
+            }
+        }
+        TokenRef::Text(backslash)
+    }
+
+    fn next_raw(&mut self) -> Option<TokenRef<'a>> {
+        loop {
+            if let Some(t) = self.pending.pop_front() {
+                return Some(t);
+            }
+            if let Some(g) = self.graphemes.next() {
+                #[cfg(feature = "discord")]
+                if self.suppress_emoji() {
+                    return Some(TokenRef::Text(g));
+                }
+                #[cfg(feature = "discord")]
+                if g == "\\" && self.parser.options.escaped_emoji {
+                    return Some(self.escape(g));
+                }
+                parse_grapheme(g, self.parser, &mut self.pending);
+                continue;
+            }
+            if let Some(t) = self.closing.take() {
+                return Some(t);
+            }
+            if !self.next_segment() {
+                return None;
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for RawTokens<'a, '_> {
+    type Item = TokenRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.peeked.take().or_else(|| self.next_raw())?;
+        let TokenRef::Text(mut text) = token else {
+            return Some(token);
+        };
+        if !self.parser.options.coalesce_text {
+            return Some(token);
+        }
+        let s = self.s;
+        while let Some(next) = self.next_raw() {
+            match next {
+                TokenRef::Text(t) if offset(s, text) + text.len() == offset(s, t) => {
+                    text = &s[offset(s, text)..offset(s, t) + t.len()];
+                }
+                next => {
+                    self.peeked = Some(next);
+                    break;
+                }
+            }
+        }
+        Some(TokenRef::Text(text))
+    }
+}
+
+/// Lazy iterator over the tokens of a string
+#[derive(Debug, Clone)]
+pub struct Tokens<'a> {
+    inner: RawTokens<'a, 'a>,
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let parser = self.inner.parser;
+        self.inner.next().map(|t| t.into_token_with(parser))
     }
-    let text = s[last..].to_string();
-    tokens.extend(raw_parse_emoji(text));
-    tokens
 }
 
 #[cfg(test)]
 mod test {
-    #[cfg(all(feature = "discord", feature = "async"))]
     use super::*;
+    #[cfg(not(feature = "async"))]
+    #[test]
+    fn test_parse_grapheme() {
+        let s = "e\u{301}!";
+        let options = ParserOptions {
+            coalesce_text: false,
+            ..Default::default()
+        };
+        let tokens = parse_with(s, &options).unwrap();
+        assert_eq!(
+            tokens,
+            vec![Token::new_text("e\u{301}"), Token::new_text("!")]
+        );
+    }
+    #[cfg(not(feature = "async"))]
+    #[test]
+    fn test_parse_coalesce() {
+        let tokens = parse("Hello 🔥 World").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_text("Hello "),
+                Token::new_emoji("🔥", get("🔥").unwrap()),
+                Token::new_text(" World"),
+            ]
+        );
+    }
+    #[test]
+    fn test_parse_ref() {
+        let s = "Hello 🔥 World";
+        let tokens = parse_ref(s).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenRef::Text("Hello "),
+                TokenRef::Emoji {
+                    emoji: "🔥".into(),
+                    image: get("🔥").unwrap().into()
+                },
+                TokenRef::Text(" World"),
+            ]
+        );
+        assert_eq!(tokens[0], TokenRef::Text(&s[..6]));
+    }
+    #[test]
+    fn test_parser() {
+        let parser = Parser::with_options(ParserOptions {
+            coalesce_text: false,
+            ..Default::default()
+        });
+        assert_eq!(
+            parser.parse("a🔥").unwrap(),
+            vec![
+                Token::new_text("a"),
+                Token::new_emoji("🔥", get("🔥").unwrap())
+            ]
+        );
+        assert_eq!(parser.parse("bc").unwrap().len(), 2);
+    }
+    #[test]
+    fn test_parse_into() {
+        let mut tokens = vec![Token::new_text("stale")];
+        parse_into("a🔥", &mut tokens).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_text("a"),
+                Token::new_emoji("🔥", get("🔥").unwrap())
+            ]
+        );
+    }
+    #[test]
+    fn test_emoji_borrowed() {
+        let token = tokenize("🔥").unwrap().next().unwrap();
+        assert!(matches!(
+            token,
+            Token::Emoji {
+                image: Cow::Borrowed(_),
+                ..
+            }
+        ));
+    }
+    #[test]
+    fn test_emoji_shared() {
+        let ptr = |t: &TokenRef| match t {
+            TokenRef::Emoji { image, .. } => image.as_ptr(),
+            _ => unreachable!(),
+        };
+        let tokens = parse_ref("🔥🔥🔥").unwrap();
+        assert!(tokens
+            .iter()
+            .all(|t| ptr(t) == ptr(&parse_ref("🔥").unwrap()[0])));
+    }
+    #[test]
+    fn test_tokenize() {
+        let mut tokens = tokenize("🔥 and the rest").unwrap();
+        assert_eq!(
+            tokens.next(),
+            Some(Token::new_emoji("🔥", get("🔥").unwrap()))
+        );
+        assert_eq!(tokens.next(), Some(Token::new_text(" and the rest")));
+        assert_eq!(tokens.next(), None);
+    }
+    #[cfg(not(feature = "async"))]
+    #[test]
+    fn test_parse_skin_tone() {
+        let tokens = parse("👍🏽").unwrap();
+        assert_eq!(tokens, vec![Token::new_emoji("👍🏽", get("👍🏽").unwrap())]);
+        assert_ne!(get("👍🏽"), get("👍"));
+        // No toned asset exists for the heart, so the base one is used
+        let tokens = parse("❤🏽").unwrap();
+        assert_eq!(tokens, vec![Token::new_emoji("❤🏽", get("❤").unwrap())]);
+    }
+    #[cfg(not(feature = "async"))]
+    #[test]
+    fn test_parse_flag() {
+        let tokens = parse("🇺🇸🇯🇵🇺").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_emoji("🇺🇸", get("🇺🇸").unwrap()),
+                Token::new_emoji("🇯🇵", get("🇯🇵").unwrap()),
+                Token::new_emoji("🇺", get("🇺").unwrap()),
+            ]
+        );
+        let tokens = parse("🇦🇦").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_emoji("🇦", get("🇦").unwrap()),
+                Token::new_emoji("🇦", get("🇦").unwrap()),
+            ]
+        );
+    }
+    #[cfg(not(feature = "async"))]
+    #[test]
+    fn test_parse_tag_sequence() {
+        let scotland = "🏴\u{e0067}\u{e0062}\u{e0073}\u{e0063}\u{e0074}\u{e007f}";
+        let texas = "🏴\u{e0075}\u{e0073}\u{e0074}\u{e0078}\u{e007f}";
+        let tokens = parse(format!("{}{}", scotland, texas)).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_emoji(scotland, get(scotland).unwrap()),
+                Token::new_emoji(texas, get("🏴").unwrap()),
+            ]
+        );
+        assert_ne!(get(scotland), get("🏴"));
+    }
+    #[cfg(not(feature = "async"))]
+    #[test]
+    fn test_parse_variation_selector() {
+        let tokens = parse("\u{fe0f}☀\u{fe0f}☀\u{fe0e}").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_emoji("☀\u{fe0f}", get("☀").unwrap()),
+                Token::new_emoji("☀", get("☀").unwrap()),
+            ]
+        );
+        let options = ParserOptions {
+            text_presentation: true,
+            ..Default::default()
+        };
+        let tokens = parse_with("☀\u{fe0f}☀\u{fe0e}", &options).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_emoji("☀\u{fe0f}", get("☀").unwrap()),
+                Token::new_text("☀\u{fe0e}"),
+            ]
+        );
+    }
+    #[cfg(not(feature = "async"))]
+    #[test]
+    fn test_parse_keycap() {
+        let tokens = parse("1\u{fe0f}\u{20e3}*\u{fe0e}\u{20e3}#\u{20e3}1#").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_emoji("1\u{fe0f}\u{20e3}", get("1\u{20e3}").unwrap()),
+                Token::new_emoji("*\u{fe0e}\u{20e3}", get("*\u{20e3}").unwrap()),
+                Token::new_emoji("#\u{20e3}", get("#\u{20e3}").unwrap()),
+                Token::new_text("1#"),
+            ]
+        );
+    }
+    #[cfg(not(feature = "async"))]
+    #[test]
+    fn test_parse_zwj() {
+        let tokens = parse("👨‍👩‍👧‍👦🧑‍🚀").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_emoji("👨‍👩‍👧‍👦", get("👨‍👩‍👧‍👦").unwrap()),
+                Token::new_emoji("🧑‍🚀", get("🧑‍🚀").unwrap()),
+            ]
+        );
+        assert_ne!(get("🧑‍🚀"), get("🧑"));
+        // Not an RGI sequence, falls back to its components
+        let tokens = parse("🐶\u{200d}🔥").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_emoji("🐶", get("🐶").unwrap()),
+                Token::new_emoji("🔥", get("🔥").unwrap()),
+            ]
+        );
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_find_custom_emoji() {
+        let s = "<<:x:123> <a::123456789012345678> <:pepega:123456789012345678>";
+        let token = TokenRef::CustomEmoji {
+            id: 123456789012345678,
+            name: "pepega",
+            animated: false,
+        };
+        let options = ParserOptions::default();
+        let find = |s| discord::find_markup(s, &options);
+        assert_eq!(find(s), Some((34..62, token)));
+        assert_eq!(find("<:pepega:1234567890123456789012>"), None);
+        assert_eq!(find("<:pepega:123456789012345678"), None);
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_parse_adjacent_custom_emoji() {
+        let s = "<:a:123456789012345678><a:b:876543210987654321>!";
+        assert_eq!(
+            parse_ref(s).unwrap(),
+            vec![
+                TokenRef::CustomEmoji {
+                    id: 123456789012345678,
+                    name: "a",
+                    animated: false,
+                },
+                TokenRef::CustomEmoji {
+                    id: 876543210987654321,
+                    name: "b",
+                    animated: true,
+                },
+                TokenRef::Text("!"),
+            ]
+        );
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_parse_mention() {
+        let s = "<@123456789012345678> <@!123456789012345678> <#234567890123456789><@&345678901234567890> <@12>";
+        assert_eq!(
+            parse_ref(s).unwrap(),
+            vec![
+                TokenRef::Mention(Mention::User(123456789012345678)),
+                TokenRef::Text(" "),
+                TokenRef::Mention(Mention::User(123456789012345678)),
+                TokenRef::Text(" "),
+                TokenRef::Mention(Mention::Channel(234567890123456789)),
+                TokenRef::Mention(Mention::Role(345678901234567890)),
+                TokenRef::Text(" <@12>"),
+            ]
+        );
+        let options = ParserOptions::builder().mentions(false).build();
+        assert_eq!(
+            Parser::with_options(options).parse_ref(s).unwrap(),
+            vec![TokenRef::Text(s)]
+        );
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_parse_command_mention() {
+        let s = "</ping:123456789012345678> </config set role:234567890123456789> </a  b:234567890123456789>";
+        assert_eq!(
+            parse_ref(s).unwrap(),
+            vec![
+                TokenRef::CommandMention {
+                    name: "ping",
+                    id: 123456789012345678,
+                },
+                TokenRef::Text(" "),
+                TokenRef::CommandMention {
+                    name: "config set role",
+                    id: 234567890123456789,
+                },
+                TokenRef::Text(" </a  b:234567890123456789>"),
+            ]
+        );
+        assert_eq!(
+            Parser::new().parse("</ping:123456789012345678>").unwrap(),
+            vec![Token::CommandMention {
+                name: "ping".to_string(),
+                id: 123456789012345678,
+            }]
+        );
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_parse_code() {
+        let s = "`<:a:123456789012345678>` ``a`b`` ```rust\nlet 👍 = 1;``` ```👍```👍";
+        assert_eq!(
+            parse_ref(s).unwrap(),
+            vec![
+                TokenRef::Code("<:a:123456789012345678>"),
+                TokenRef::Text(" "),
+                TokenRef::Code("a`b"),
+                TokenRef::Text(" "),
+                TokenRef::CodeBlock {
+                    language: Some("rust"),
+                    code: "let 👍 = 1;",
+                },
+                TokenRef::Text(" "),
+                TokenRef::CodeBlock {
+                    language: None,
+                    code: "👍",
+                },
+                TokenRef::Emoji {
+                    emoji: "👍".into(),
+                    image: get("👍").unwrap().into()
+                },
+            ]
+        );
+        assert_eq!(
+            parse_ref("``x` `").unwrap(),
+            vec![
+                TokenRef::Text("`"),
+                TokenRef::Code("x"),
+                TokenRef::Text(" `")
+            ]
+        );
+        let options = ParserOptions::builder().code(false).build();
+        assert_eq!(
+            Parser::with_options(options)
+                .parse_ref("`<:a:123456789012345678>`")
+                .unwrap(),
+            vec![
+                TokenRef::Text("`"),
+                TokenRef::CustomEmoji {
+                    id: 123456789012345678,
+                    name: "a",
+                    animated: false,
+                },
+                TokenRef::Text("`"),
+            ]
+        );
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_parse_spoiler() {
+        let s = "a ||b 👍 <:x:123456789012345678>|| `||c||` ||d";
+        let tokens = parse_ref(s).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenRef::Text("a "),
+                TokenRef::Spoiler("b 👍 <:x:123456789012345678>"),
+                TokenRef::Text(" "),
+                TokenRef::Code("||c||"),
+                TokenRef::Text(" ||d"),
+            ]
+        );
+        let spoiler = Token::Spoiler(vec![
+            Token::new_text("b "),
+            Token::new_emoji("👍", get("👍").unwrap()),
+            Token::new_text(" "),
+            Token::new_custom_emoji(123456789012345678, "x", false),
+        ]);
+        assert_eq!(tokens[1].to_token(), spoiler);
+        assert_eq!(Parser::new().parse(s).unwrap()[1], spoiler);
+        let options = ParserOptions::builder().spoiler_emoji(false).build();
+        assert_eq!(
+            Parser::with_options(options).parse(s).unwrap()[1],
+            Token::Spoiler(vec![Token::new_text("b 👍 <:x:123456789012345678>")])
+        );
+        let options = ParserOptions::builder().spoilers(false).build();
+        let tokens = Parser::with_options(options).parse_ref("||b||").unwrap();
+        assert_eq!(tokens, vec![TokenRef::Text("||b||")]);
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_parse_escaped_markup() {
+        let s = r"a \<:pepega:123456789012345678> \\<:pepega:123456789012345678> \<@123456789012345678>";
+        assert_eq!(
+            parse_ref(s).unwrap(),
+            vec![
+                TokenRef::Text("a "),
+                TokenRef::Text(r"<:pepega:123456789012345678> \\"),
+                TokenRef::CustomEmoji {
+                    id: 123456789012345678,
+                    name: "pepega",
+                    animated: false,
+                },
+                TokenRef::Text(" "),
+                TokenRef::Text("<@123456789012345678>"),
+            ]
+        );
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_parse_escaped_emoji() {
+        let s = r"\😄 \\😄 \a";
+        let emoji = TokenRef::Emoji {
+            emoji: "😄".into(),
+            image: get("😄").unwrap().into(),
+        };
+        assert_eq!(
+            parse_ref(s).unwrap(),
+            vec![
+                TokenRef::Text(r"😄 \\"),
+                emoji.clone(),
+                TokenRef::Text(r" \a"),
+            ]
+        );
+        let options = ParserOptions::builder().escaped_emoji(false).build();
+        assert_eq!(
+            Parser::with_options(options).parse_ref(r"\😄").unwrap(),
+            vec![TokenRef::Text("\\"), emoji]
+        );
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_parse_timestamp() {
+        let s = "<t:1699999999:F> <t:-86400><t:1699999999:R> <t:12:x> <t:>";
+        assert_eq!(
+            parse_ref(s).unwrap(),
+            vec![
+                TokenRef::Timestamp {
+                    unix: 1699999999,
+                    style: TimestampStyle::LongDateTime,
+                },
+                TokenRef::Text(" "),
+                TokenRef::Timestamp {
+                    unix: -86400,
+                    style: TimestampStyle::ShortDateTime,
+                },
+                TokenRef::Timestamp {
+                    unix: 1699999999,
+                    style: TimestampStyle::Relative,
+                },
+                TokenRef::Text(" <t:12:x> <t:>"),
+            ]
+        );
+        let options = ParserOptions::builder().timestamps(false).build();
+        assert_eq!(
+            Parser::with_options(options).parse_ref(s).unwrap(),
+            vec![TokenRef::Text(s)]
+        );
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_parse_custom_emoji_options() {
+        let s = "<:pepega:123456789012345678>";
+        let options = ParserOptions::builder()
+            .cdn_base("https://proxy.example/e/")
+            .emoji_size(48)
+            .emoji_format(CustomEmojiFormat::Webp)
+            .build();
+        let tokens = Parser::with_options(options.clone()).parse(s).unwrap();
+        assert_eq!(
+            tokens,
+            vec![Token::new_custom_emoji(123456789012345678, "pepega", false)]
+        );
+        assert_eq!(
+            tokens[0].url_with(&options).unwrap(),
+            "https://proxy.example/e/123456789012345678.webp?size=48"
+        );
+        let options = ParserOptions::builder().custom_emoji(false).build();
+        assert_eq!(
+            Parser::with_options(options).parse(s).unwrap(),
+            vec![Token::new_text(s)]
+        );
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_custom_emoji_url() {
+        let options = ParserOptions::builder()
+            .emoji_format(CustomEmojiFormat::Webp)
+            .build();
+        let animated = Token::new_custom_emoji(123456789012345678, "pepega", true);
+        assert_eq!(
+            animated.url_with(&options).unwrap(),
+            "https://cdn.discordapp.com/emojis/123456789012345678.gif?size=96"
+        );
+        let still = Token::new_custom_emoji(876543210987654321, "pepega", false);
+        assert_eq!(
+            still.url().unwrap(),
+            "https://cdn.discordapp.com/emojis/876543210987654321.png?size=96"
+        );
+        assert_eq!(Token::new_text("pepega").url(), None);
+        assert_eq!(
+            still.url_with_size(256).unwrap().unwrap(),
+            "https://cdn.discordapp.com/emojis/876543210987654321.png?size=256"
+        );
+        assert!(matches!(
+            still.url_with_size(100_000),
+            Err(ParseError::InvalidEmojiSize(100_000))
+        ));
+        assert!(Token::new_text("pepega")
+            .url_with_size(64)
+            .unwrap()
+            .is_none());
+        let options = ParserOptions::builder().emoji_size(97).build();
+        assert!(matches!(
+            Parser::with_options(options).parse("a"),
+            Err(ParseError::InvalidEmojiSize(97))
+        ));
+    }
+    #[test]
+    fn test_parse_emoticons() {
+        let s = "hi :) :-D <3 http://x a:)";
+        assert_eq!(Parser::new().parse_ref(s).unwrap(), vec![TokenRef::Text(s)]);
+        let mut emoticons = Emoticons::new();
+        emoticons.insert("^_^", "😊").unwrap();
+        emoticons.remove("<3");
+        assert!(matches!(
+            emoticons.insert("x", "not emoji"),
+            Err(ParseError::UnknownEmoji(_))
+        ));
+        let options = ParserOptions::builder().emoticons(emoticons).build();
+        let tokens = Parser::with_options(options)
+            .parse_ref("hi :) :-D, ^_^ <3 http://x a:)")
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenRef::Text("hi "),
+                TokenRef::Emoji {
+                    emoji: "🙂".into(),
+                    image: get("🙂").unwrap().into()
+                },
+                TokenRef::Text(" "),
+                TokenRef::Emoji {
+                    emoji: "😃".into(),
+                    image: get("😃").unwrap().into()
+                },
+                TokenRef::Text(", "),
+                TokenRef::Emoji {
+                    emoji: "😊".into(),
+                    image: get("😊").unwrap().into()
+                },
+                TokenRef::Text(" <3 http://x a:)"),
+            ]
+        );
+    }
+    #[cfg(feature = "shortcode")]
+    #[test]
+    fn test_parse_shortcode() {
+        let s = "a :smile::unknown: :+1: 12:30:";
+        let tokens = Parser::new().parse_ref(s).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenRef::Text("a "),
+                TokenRef::Emoji {
+                    emoji: "😄".into(),
+                    image: get("😄").unwrap().into()
+                },
+                TokenRef::Text(":unknown: "),
+                TokenRef::Emoji {
+                    emoji: "👍".into(),
+                    image: get("👍").unwrap().into()
+                },
+                TokenRef::Text(" 12:30:"),
+            ]
+        );
+        let options = ParserOptions::builder().shortcodes(false).build();
+        let tokens = Parser::with_options(options).parse_ref(s).unwrap();
+        assert_eq!(tokens, vec![TokenRef::Text(s)]);
+    }
+    #[cfg(feature = "shortcode")]
+    #[test]
+    fn test_parse_shortcode_non_ascii() {
+        for s in ["Note:é", "a:ü", ":é:", "::ü"] {
+            assert_eq!(parse_ref(s).unwrap(), vec![TokenRef::Text(s)]);
+        }
+        assert_eq!(
+            parse_ref("hi :😀").unwrap(),
+            vec![
+                TokenRef::Text("hi :"),
+                TokenRef::Emoji {
+                    emoji: "😀".into(),
+                    image: get("😀").unwrap().into()
+                }
+            ]
+        );
+    }
+    #[cfg(feature = "shortcode")]
+    #[test]
+    fn test_parse_skin_tone_shortcode() {
+        let s = ":+1::skin-tone-3: :wave::skin-tone-6: :+1::skin-tone-9: :skin-tone-2:";
+        let tokens = Parser::new().parse_ref(s).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenRef::Emoji {
+                    emoji: "👍🏼".into(),
+                    image: get("👍🏼").unwrap().into()
+                },
+                TokenRef::Text(" "),
+                TokenRef::Emoji {
+                    emoji: "👋🏿".into(),
+                    image: get("👋🏿").unwrap().into()
+                },
+                TokenRef::Text(" "),
+                TokenRef::Emoji {
+                    emoji: "👍".into(),
+                    image: get("👍").unwrap().into()
+                },
+                TokenRef::Text(":skin-tone-9: :skin-tone-2:"),
+            ]
+        );
+        // The suffix only tones emoji that have toned variants
+        let tokens = Parser::new().parse_ref(":smile::skin-tone-2:").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenRef::Emoji {
+                    emoji: "😄".into(),
+                    image: get("😄").unwrap().into()
+                },
+                TokenRef::Text(":skin-tone-2:"),
+            ]
+        );
+    }
+    #[cfg(feature = "shortcode")]
+    #[test]
+    fn test_parse_alias() {
+        let mut parser = Parser::new();
+        parser.register_alias("smile", "🔥").unwrap();
+        parser
+            .register_alias("kekw", "https://example.com/kekw.png")
+            .unwrap();
+        assert!(matches!(
+            parser.register_alias("nope", "not emoji"),
+            Err(ParseError::UnknownEmoji(_))
+        ));
+        let s = ":smile: :kekw:";
+        let tokens = parser.parse_ref(s).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenRef::Emoji {
+                    emoji: "🔥".into(),
+                    image: get("🔥").unwrap().into()
+                },
+                TokenRef::Text(" "),
+                TokenRef::Alias("kekw"),
+            ]
+        );
+        let kekw = Token::NamedCustomEmoji {
+            name: "kekw".to_string(),
+            url: "https://example.com/kekw.png".to_string(),
+        };
+        assert_eq!(parser.parse(s).unwrap()[2], kekw);
+        assert_eq!(tokens[2].to_token(), Token::new_text(":kekw:"));
+    }
+    #[cfg(all(feature = "shortcode", feature = "discord"))]
+    #[test]
+    fn test_parse_custom_emoji_alias() {
+        let mut parser = Parser::new();
+        parser
+            .register_alias("kekw", "<a:kekw_old:123456789012345678>")
+            .unwrap();
+        assert_eq!(
+            parser.alias("kekw"),
+            Some(&Alias::CustomEmoji {
+                id: 123456789012345678,
+                animated: true,
+            })
+        );
+        assert_eq!(
+            parser.parse(":kekw:").unwrap(),
+            vec![Token::new_custom_emoji(123456789012345678, "kekw", true)]
+        );
+    }
+    #[cfg(all(feature = "shortcode", feature = "discord"))]
+    #[test]
+    fn test_parse_discord_shortcode() {
+        let tokens = Parser::new()
+            .parse_ref(":slight_smile: :regional_indicator_a: :one:")
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenRef::Emoji {
+                    emoji: "🙂".into(),
+                    image: get("🙂").unwrap().into()
+                },
+                TokenRef::Text(" "),
+                TokenRef::Emoji {
+                    emoji: "🇦".into(),
+                    image: get("🇦").unwrap().into()
+                },
+                TokenRef::Text(" "),
+                TokenRef::Emoji {
+                    emoji: "1️⃣".into(),
+                    image: get("1️⃣").unwrap().into()
+                },
+            ]
+        );
+    }
+    #[cfg(feature = "gemoji")]
+    #[test]
+    fn test_parse_gemoji() {
+        let tokens = Parser::new()
+            .parse(":shipit: :satisfied: :thumbsup::skin-tone-2: :nope:")
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::NamedCustomEmoji {
+                    name: "shipit".to_string(),
+                    url: "https://github.githubassets.com/images/icons/emoji/shipit.png"
+                        .to_string(),
+                },
+                Token::new_text(" "),
+                Token::new_emoji("😆", get("😆").unwrap()),
+                Token::new_text(" "),
+                Token::new_emoji("👍🏻", get("👍🏻").unwrap()),
+                Token::new_text(" :nope:"),
+            ]
+        );
+    }
+    #[cfg(all(feature = "shortcode", feature = "discord"))]
+    #[test]
+    fn test_parse_shortcode_markup() {
+        let s = "<:smile:123456789012345678> `:smile:` ||:smile:||";
+        let tokens = Parser::new().parse(s).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_custom_emoji(123456789012345678, "smile", false),
+                Token::new_text(" "),
+                Token::Code(":smile:".to_string()),
+                Token::new_text(" "),
+                Token::Spoiler(vec![Token::new_emoji("😄", get("😄").unwrap())]),
+            ]
+        );
+    }
     #[cfg(all(feature = "discord", feature = "async-std"))]
     #[async_std::test]
     async fn test_parse() {
         let s = "Hello <a:pepega:123456789012345678> World".to_string();
-        let tokens = parse(s).await;
+        let tokens = parse(s).await.unwrap();
         assert_eq!(
             tokens,
             vec![
-                Token::new_text("H"),
-                Token::new_text("e"),
-                Token::new_text("l"),
-                Token::new_text("l"),
-                Token::new_text("o"),
-                Token::new_text(" "),
-                Token::new_custom_emoji("123456789012345678".to_string()),
-                Token::new_text(" "),
-                Token::new_text("W"),
-                Token::new_text("o"),
-                Token::new_text("r"),
-                Token::new_text("l"),
-                Token::new_text("d"),
+                Token::new_text("Hello "),
+                Token::new_custom_emoji(123456789012345678, "pepega", true),
+                Token::new_text(" World"),
             ]
         );
     }
@@ -127,19 +1887,9 @@ mod test {
         assert_eq!(
             tokens,
             vec![
-                Token::new_text("H"),
-                Token::new_text("e"),
-                Token::new_text("l"),
-                Token::new_text("l"),
-                Token::new_text("o"),
-                Token::new_text(" "),
-                Token::new_custom_emoji("123456789012345678".to_string()),
-                Token::new_text(" "),
-                Token::new_text("W"),
-                Token::new_text("o"),
-                Token::new_text("r"),
-                Token::new_text("l"),
-                Token::new_text("d"),
+                Token::new_text("Hello "),
+                Token::new_custom_emoji(123456789012345678, "pepega", true),
+                Token::new_text(" World"),
             ]
         );
     }
@@ -0,0 +1,256 @@
+use crate::{ParseError, Parser, Token};
+
+/// Inline delimiters, longest first so `***` isn't read as `**` and `*`
+const DELIMITERS: [&str; 6] = ["***", "**", "__", "~~", "*", "_"];
+
+/// Node of a markdown tree, styling nodes wrap the tokens they apply to
+#[derive(Debug, PartialEq, Eq)]
+pub enum Node {
+    /// Token with no styling of its own
+    Token(Token),
+    /// `**bold**`
+    Bold(Vec<Node>),
+    /// `*italic*` or `_italic_`
+    Italic(Vec<Node>),
+    /// `__underline__`
+    Underline(Vec<Node>),
+    /// `~~strikethrough~~`
+    Strikethrough(Vec<Node>),
+    /// `# header` to `### header`
+    Header {
+        /// Number of `#`, from 1 to 3
+        level: u8,
+        children: Vec<Node>,
+    },
+    /// `> quote` for a line or `>>> quote` for the rest of the message
+    Quote(Vec<Node>),
+}
+
+impl Parser {
+    /// Parse a string to a tree of markdown nodes
+    pub fn parse_markdown(&self, s: &str) -> Result<Vec<Node>, ParseError> {
+        let mut nodes = Vec::new();
+        self.blocks(s, true, &mut nodes)?;
+        Ok(nodes)
+    }
+
+    /// Parse line level markup, quotes can't be nested
+    fn blocks(&self, s: &str, quotes: bool, out: &mut Vec<Node>) -> Result<(), ParseError> {
+        let (mut paragraph, mut at) = (0, 0);
+        while at < s.len() {
+            let end = s[at..].find('\n').map_or(s.len(), |i| at + i + 1);
+            let line = &s[at..end];
+            if let Some(quoted) = s[at..].strip_prefix(">>> ").filter(|_| quotes) {
+                self.inline(&s[paragraph..at], out)?;
+                let mut children = Vec::new();
+                self.blocks(quoted, false, &mut children)?;
+                out.push(Node::Quote(children));
+                return Ok(());
+            }
+            // The line break is left to the following text
+            let content = line.strip_suffix('\n').unwrap_or(line);
+            if let Some(quoted) = content.strip_prefix("> ").filter(|_| quotes) {
+                self.inline(&s[paragraph..at], out)?;
+                let mut children = Vec::new();
+                self.blocks(quoted, false, &mut children)?;
+                out.push(Node::Quote(children));
+                paragraph = at + content.len();
+            } else if let Some((level, header)) = header(content) {
+                self.inline(&s[paragraph..at], out)?;
+                let mut children = Vec::new();
+                self.inline(header, &mut children)?;
+                out.push(Node::Header { level, children });
+                paragraph = at + content.len();
+            }
+            at = end;
+        }
+        self.inline(&s[paragraph..], out)
+    }
+
+    /// Parse inline styling, the text in between is tokenized as usual
+    fn inline(&self, s: &str, out: &mut Vec<Node>) -> Result<(), ParseError> {
+        let (mut text, mut at) = (0, 0);
+        'outer: while let Some(c) = s[at..].chars().next() {
+            if let Some(len) = self.markup_len(&s[at..]) {
+                at += len;
+                continue;
+            }
+            for delim in DELIMITERS {
+                if !s[at..].starts_with(delim) || !can_open(&s[..at], delim) {
+                    continue;
+                }
+                let start = at + delim.len();
+                let Some(len) = self.closing(&s[start..], delim) else {
+                    continue;
+                };
+                self.leaves(&s[text..at], out)?;
+                let mut children = Vec::new();
+                self.inline(&s[start..start + len], &mut children)?;
+                out.push(match delim {
+                    "***" => Node::Bold(vec![Node::Italic(children)]),
+                    "**" => Node::Bold(children),
+                    "__" => Node::Underline(children),
+                    "~~" => Node::Strikethrough(children),
+                    _ => Node::Italic(children),
+                });
+                at = start + len + delim.len();
+                text = at;
+                continue 'outer;
+            }
+            at += c.len_utf8();
+        }
+        self.leaves(&s[text..], out)
+    }
+
+    /// Length of the content before the delimiter closing it
+    fn closing(&self, s: &str, delim: &str) -> Option<usize> {
+        if delim.len() == 1 && s.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let mut at = s.chars().next()?.len_utf8();
+        while let Some(c) = s[at..].chars().next() {
+            if let Some(len) = self.markup_len(&s[at..]) {
+                at += len;
+                continue;
+            }
+            // A doubled single delimiter belongs to a nested node
+            if delim.len() == 1 && s[at..].starts_with(delim) && s[at + 1..].starts_with(delim) {
+                at += 2;
+                continue;
+            }
+            if s[at..].starts_with(delim) && can_close(&s[at + delim.len()..], delim) {
+                return Some(at);
+            }
+            at += c.len_utf8();
+        }
+        None
+    }
+
+    /// Length of Discord markup at the start of `s`, which styling can't split
+    #[cfg(feature = "discord")]
+    fn markup_len(&self, s: &str) -> Option<usize> {
+        if !s.starts_with(['<', '`']) || !self.options.discord {
+            return None;
+        }
+        let (range, _) = crate::discord::find_markup(s, &self.options)?;
+        (range.start == 0).then_some(range.end)
+    }
+
+    #[cfg(not(feature = "discord"))]
+    fn markup_len(&self, _s: &str) -> Option<usize> {
+        None
+    }
+
+    fn leaves(&self, s: &str, out: &mut Vec<Node>) -> Result<(), ParseError> {
+        if !s.is_empty() {
+            out.extend(self.tokenize(s)?.map(Node::Token));
+        }
+        Ok(())
+    }
+}
+
+/// `_` only styles whole words, so `snake_case` stays text
+fn can_open(before: &str, delim: &str) -> bool {
+    delim != "_" || !before.ends_with(char::is_alphanumeric)
+}
+
+fn can_close(after: &str, delim: &str) -> bool {
+    delim != "_" || !after.starts_with(char::is_alphanumeric)
+}
+
+/// Level and content of a `# header` line
+fn header(line: &str) -> Option<(u8, &str)> {
+    let level = line.bytes().take_while(|b| *b == b'#').count();
+    let content = line[level..].strip_prefix(' ')?;
+    ((1..=3).contains(&level) && !content.trim().is_empty()).then_some((level as u8, content))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn text(s: &str) -> Node {
+        Node::Token(Token::new_text(s))
+    }
+    #[test]
+    fn test_inline() {
+        let nodes = Parser::new()
+            .parse_markdown("a **b __c__** *d* ~~e~~ ***f*** snake_case_name")
+            .unwrap();
+        assert_eq!(
+            nodes,
+            vec![
+                text("a "),
+                Node::Bold(vec![text("b "), Node::Underline(vec![text("c")])]),
+                text(" "),
+                Node::Italic(vec![text("d")]),
+                text(" "),
+                Node::Strikethrough(vec![text("e")]),
+                text(" "),
+                Node::Bold(vec![Node::Italic(vec![text("f")])]),
+                text(" snake_case_name"),
+            ]
+        );
+    }
+    #[test]
+    fn test_nested_italic() {
+        let nodes = Parser::new()
+            .parse_markdown("*a **b** c* **unclosed")
+            .unwrap();
+        assert_eq!(
+            nodes,
+            vec![
+                Node::Italic(vec![text("a "), Node::Bold(vec![text("b")]), text(" c"),]),
+                text(" **unclosed"),
+            ]
+        );
+    }
+    #[test]
+    fn test_blocks() {
+        let nodes = Parser::new()
+            .parse_markdown("# Title\n> **quoted**\nplain\n>>> rest\n> of it")
+            .unwrap();
+        assert_eq!(
+            nodes,
+            vec![
+                Node::Header {
+                    level: 1,
+                    children: vec![text("Title")],
+                },
+                text("\n"),
+                Node::Quote(vec![Node::Bold(vec![text("quoted")])]),
+                text("\nplain\n"),
+                Node::Quote(vec![text("rest\n> of it")]),
+            ]
+        );
+        assert_eq!(
+            Parser::new().parse_markdown("#### no\n#no").unwrap(),
+            vec![text("#### no\n#no")]
+        );
+    }
+    #[test]
+    fn test_emoji_leaves() {
+        let nodes = Parser::new().parse_markdown("**hi 👍**").unwrap();
+        let Node::Bold(children) = &nodes[0] else {
+            panic!("expected bold, got {:?}", nodes);
+        };
+        assert_eq!(children[0], text("hi "));
+        assert!(matches!(children[1], Node::Token(Token::Emoji { .. })));
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_markup_is_not_split() {
+        let s = "_<:my_emoji_name:123456789012345678>_";
+        assert_eq!(
+            Parser::new().parse_markdown(s).unwrap(),
+            vec![Node::Italic(vec![Node::Token(Token::new_custom_emoji(
+                123456789012345678,
+                "my_emoji_name",
+                false
+            ))])]
+        );
+        assert_eq!(
+            Parser::new().parse_markdown("`**not bold**`").unwrap(),
+            vec![Node::Token(Token::Code("**not bold**".to_string()))]
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use crate::{ParseError, Parser, Token};
+
+/// Custom emoji listed alongside a Mastodon status
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomEmojiDef {
+    /// Name used as `:shortcode:` in the text
+    pub shortcode: String,
+    /// Url of the image on the instance
+    pub url: String,
+}
+
+impl Parser {
+    /// Parse the text of a Mastodon status, `:shortcode:` of `emojis` become custom emoji
+    /// tokens with their instance url
+    pub fn parse_mastodon(
+        &self,
+        text: &str,
+        emojis: &[CustomEmojiDef],
+    ) -> Result<Vec<Token>, ParseError> {
+        let tokens = self.parse(text)?;
+        Ok(crate::replace_shortcodes(tokens, &|name| {
+            let emoji = emojis.iter().find(|emoji| emoji.shortcode == name)?;
+            Some(Token::NamedCustomEmoji {
+                name: emoji.shortcode.clone(),
+                url: emoji.url.clone(),
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_parse_mastodon() {
+        let emojis = [CustomEmojiDef {
+            shortcode: "blobfox".to_string(),
+            url: "https://files.mastodon.social/blobfox.png".to_string(),
+        }];
+        let tokens = Parser::new()
+            .parse_mastodon("👍:blobfox::blobfoxes: :blobfox", &emojis)
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_emoji("👍", crate::get("👍").unwrap()),
+                Token::NamedCustomEmoji {
+                    name: "blobfox".to_string(),
+                    url: "https://files.mastodon.social/blobfox.png".to_string(),
+                },
+                Token::new_text(":blobfoxes: :blobfox"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{ParseError, Parser, Token};
+
+/// `mxc://server/media_id` uri of Matrix media
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MxcUri {
+    pub server_name: String,
+    pub media_id: String,
+}
+
+impl MxcUri {
+    /// Split an `mxc://` uri, `None` if it isn't one
+    pub fn parse(uri: &str) -> Option<Self> {
+        let (server_name, media_id) = uri.strip_prefix("mxc://")?.split_once('/')?;
+        if server_name.is_empty() || media_id.is_empty() || media_id.contains('/') {
+            return None;
+        }
+        Some(Self {
+            server_name: server_name.to_string(),
+            media_id: media_id.to_string(),
+        })
+    }
+    /// Url the media is downloaded from on `homeserver`, which requires an access token
+    pub fn http_url(&self, homeserver: &str) -> String {
+        format!(
+            "{}/_matrix/client/v1/media/download/{}/{}",
+            homeserver.trim_end_matches('/'),
+            self.server_name,
+            self.media_id
+        )
+    }
+}
+
+impl Parser {
+    /// Parse a Matrix message, `:shortcode:` that `resolve` knows become custom emoji
+    /// tokens with their media url on `homeserver`
+    pub fn parse_matrix(
+        &self,
+        s: &str,
+        homeserver: &str,
+        resolve: impl Fn(&str) -> Option<MxcUri>,
+    ) -> Result<Vec<Token>, ParseError> {
+        let tokens = self.parse(s)?;
+        Ok(crate::replace_shortcodes(tokens, &|name| {
+            Some(Token::NamedCustomEmoji {
+                name: name.to_string(),
+                url: resolve(name)?.http_url(homeserver),
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_mxc_uri() {
+        let uri = MxcUri::parse("mxc://example.org/abc").unwrap();
+        assert_eq!(
+            uri.http_url("https://matrix.example.org/"),
+            "https://matrix.example.org/_matrix/client/v1/media/download/example.org/abc"
+        );
+        assert_eq!(MxcUri::parse("https://example.org/abc"), None);
+        assert_eq!(MxcUri::parse("mxc://example.org/"), None);
+    }
+    #[test]
+    fn test_parse_matrix() {
+        let tokens = Parser::new()
+            .parse_matrix("hi :blobcat: :nope: 👍", "https://matrix.org", |name| {
+                (name == "blobcat").then(|| MxcUri::parse("mxc://matrix.org/blob").unwrap())
+            })
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_text("hi "),
+                Token::NamedCustomEmoji {
+                    name: "blobcat".to_string(),
+                    url: "https://matrix.org/_matrix/client/v1/media/download/matrix.org/blob"
+                        .to_string(),
+                },
+                Token::new_text(" :nope: "),
+                Token::new_emoji("👍", crate::get("👍").unwrap()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{MxcUri, ParseError, Parser, Token};
+use ::matrix_sdk::ruma::events::room::message::{MessageType, RoomMessageEventContent};
+use ::ruma::events::image_pack::RoomImagePackEventContent;
+use std::collections::HashMap;
+
+impl Parser {
+    /// Parse the body of a room message, naming custom emoji from the `<img data-mx-emoticon>`
+    /// of its formatted body, then from the image packs of the room
+    pub fn parse_room_message(
+        &self,
+        content: &RoomMessageEventContent,
+        packs: &[RoomImagePackEventContent],
+        homeserver: &str,
+    ) -> Result<Vec<Token>, ParseError> {
+        let (body, formatted) = match &content.msgtype {
+            MessageType::Text(text) => (text.body.as_str(), text.formatted.as_ref()),
+            MessageType::Emote(emote) => (emote.body.as_str(), emote.formatted.as_ref()),
+            MessageType::Notice(notice) => (notice.body.as_str(), notice.formatted.as_ref()),
+            msgtype => (msgtype.body(), None),
+        };
+        let mut emoticons = formatted.map_or_else(HashMap::new, |f| emoticons(&f.body));
+        for pack in packs {
+            for (name, image) in &pack.images {
+                if let Some(uri) = MxcUri::parse(image.url.as_str()) {
+                    emoticons.entry(name.as_str()).or_insert(uri);
+                }
+            }
+        }
+        self.parse_matrix(body, homeserver, |name| emoticons.get(name).cloned())
+    }
+}
+
+/// Shortcode and media of the `<img data-mx-emoticon>` tags of a formatted body
+fn emoticons(html: &str) -> HashMap<&str, MxcUri> {
+    let mut emoticons = HashMap::new();
+    for tag in html.split("<img").skip(1) {
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+        if !tag.contains("data-mx-emoticon") {
+            continue;
+        }
+        let (Some(src), Some(alt)) = (crate::attribute(tag, "src"), crate::attribute(tag, "alt"))
+        else {
+            continue;
+        };
+        let name = alt.trim_matches(':');
+        if let Some(uri) = MxcUri::parse(src).filter(|_| !name.is_empty()) {
+            emoticons.entry(name).or_insert(uri);
+        }
+    }
+    emoticons
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::ruma::events::image_pack::PackImage;
+    use ::ruma::OwnedMxcUri;
+    use std::collections::BTreeMap;
+    fn url(media_id: &str) -> String {
+        format!("https://matrix.org/_matrix/client/v1/media/download/matrix.org/{media_id}")
+    }
+    #[test]
+    fn test_emoticons() {
+        let html = r#"<img alt=":a:" data-mx-emoticon src="mxc://matrix.org/a"> <img src='mxc://matrix.org/b' alt='b'> <img data-mx-emoticon alt=":c:" src="https://c">"#;
+        let emoticons = emoticons(html);
+        assert_eq!(emoticons.len(), 1);
+        assert_eq!(emoticons["a"], MxcUri::parse("mxc://matrix.org/a").unwrap());
+    }
+    #[test]
+    fn test_parse_room_message() {
+        let content = RoomMessageEventContent::text_html(
+            ":blobcat: :pack: :nope:",
+            r#"<img data-mx-emoticon src="mxc://matrix.org/blob" alt=":blobcat:"> :pack: :nope:"#,
+        );
+        let pack = RoomImagePackEventContent::new(BTreeMap::from([
+            (
+                "pack".to_string(),
+                PackImage::new(OwnedMxcUri::from("mxc://matrix.org/pack")),
+            ),
+            (
+                "blobcat".to_string(),
+                PackImage::new(OwnedMxcUri::from("mxc://matrix.org/other")),
+            ),
+        ]));
+        let tokens = Parser::new()
+            .parse_room_message(&content, &[pack], "https://matrix.org")
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::NamedCustomEmoji {
+                    name: "blobcat".to_string(),
+                    url: url("blob"),
+                },
+                Token::new_text(" "),
+                Token::NamedCustomEmoji {
+                    name: "pack".to_string(),
+                    url: url("pack"),
+                },
+                Token::new_text(" :nope:"),
+            ]
+        );
+    }
+}
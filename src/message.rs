@@ -0,0 +1,310 @@
+use crate::{ParseError, Parser, Token};
+use std::collections::HashMap;
+
+/// Custom emoji of a guild, used to fill in names markup and reactions don't carry
+#[derive(Debug, Clone, Default)]
+pub struct GuildEmojiCache {
+    emojis: HashMap<u64, (String, bool)>,
+}
+
+impl GuildEmojiCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn insert(&mut self, id: u64, name: impl Into<String>, animated: bool) {
+        self.emojis.insert(id, (name.into(), animated));
+    }
+    /// Current name of the emoji with `id`
+    pub fn name(&self, id: u64) -> Option<&str> {
+        self.emojis.get(&id).map(|(name, _)| name.as_str())
+    }
+    /// Id and animation of the emoji called `name`
+    pub fn find(&self, name: &str) -> Option<(u64, bool)> {
+        self.emojis
+            .iter()
+            .find(|(_, (n, _))| n == name)
+            .map(|(id, (_, animated))| (*id, *animated))
+    }
+}
+
+/// Reaction below a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reaction<'a> {
+    /// Custom emoji, its name is missing once the emoji is deleted
+    Custom {
+        id: u64,
+        name: Option<&'a str>,
+        animated: bool,
+    },
+    /// Unicode emoji
+    Unicode(&'a str),
+}
+
+/// Sticker sent with a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StickerItem {
+    pub id: u64,
+    pub name: String,
+    pub format: StickerFormat,
+}
+
+impl StickerItem {
+    /// The sticker as a [`Token::Sticker`]
+    pub fn to_token(&self) -> Token {
+        Token::Sticker {
+            id: self.id,
+            name: self.name.clone(),
+            format: self.format,
+        }
+    }
+}
+
+/// Image format of a sticker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StickerFormat {
+    #[default]
+    Png,
+    Apng,
+    /// Lottie animation, served as json
+    Lottie,
+    Gif,
+}
+
+impl StickerFormat {
+    /// Format of a Discord `format_type`, unknown ones are fetched as png
+    pub fn from_format_type(format_type: u8) -> Self {
+        match format_type {
+            2 => Self::Apng,
+            3 => Self::Lottie,
+            4 => Self::Gif,
+            _ => Self::Png,
+        }
+    }
+}
+
+/// CDN url of a sticker, gifs are only served by the media proxy
+pub(crate) fn sticker_url(id: u64, format: StickerFormat, size: Option<u32>) -> String {
+    let (base, extension) = match format {
+        StickerFormat::Png | StickerFormat::Apng => ("https://cdn.discordapp.com", "png"),
+        StickerFormat::Lottie => ("https://cdn.discordapp.com", "json"),
+        StickerFormat::Gif => ("https://media.discordapp.net", "gif"),
+    };
+    match size {
+        // Lottie json is drawn at any size
+        Some(size) if format != StickerFormat::Lottie => {
+            format!("{}/stickers/{}.{}?size={}", base, id, extension, size)
+        }
+        _ => format!("{}/stickers/{}.{}", base, id, extension),
+    }
+}
+
+/// Message model of a Discord library
+pub trait DiscordMessage {
+    fn content(&self) -> &str;
+    fn reactions(&self) -> Vec<Reaction<'_>>;
+    fn stickers(&self) -> Vec<StickerItem>;
+}
+
+/// Tokens of a Discord message
+#[derive(Debug, PartialEq, Eq)]
+pub struct MessageTokens {
+    /// Tokens of the content
+    pub content: Vec<Token>,
+    /// One token per reaction, in the order of the message
+    pub reactions: Vec<Token>,
+    /// Stickers sent with the message
+    pub stickers: Vec<StickerItem>,
+}
+
+impl MessageTokens {
+    /// Tokens of the content followed by the stickers, so renderers draw them like emoji
+    pub fn into_tokens(self) -> Vec<Token> {
+        let mut tokens = self.content;
+        tokens.extend(self.stickers.iter().map(StickerItem::to_token));
+        tokens
+    }
+}
+
+impl Parser {
+    /// Parse the content and reactions of a message, naming custom emoji from `emojis`
+    pub fn parse_message(
+        &self,
+        message: &impl DiscordMessage,
+        emojis: &GuildEmojiCache,
+    ) -> Result<MessageTokens, ParseError> {
+        let mut content = self.parse(message.content())?;
+        resolve_names(&mut content, emojis);
+        let reactions = message
+            .reactions()
+            .into_iter()
+            .map(|reaction| match reaction {
+                Reaction::Custom { id, name, animated } => {
+                    let name = emojis.name(id).or(name).unwrap_or("");
+                    Ok(Token::new_custom_emoji(id, name, animated))
+                }
+                Reaction::Unicode(s) => self.parse_reaction(s),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(MessageTokens {
+            content,
+            reactions,
+            stickers: message.stickers(),
+        })
+    }
+
+    /// Parse a string, also turning name-only markup such as `:pepega:` into the guild emoji
+    /// of that name, as clients send it when the emoji can't be used as markup
+    pub fn parse_with_emojis(
+        &self,
+        s: &str,
+        emojis: &GuildEmojiCache,
+    ) -> Result<Vec<Token>, ParseError> {
+        let mut tokens = self.parse(s)?;
+        resolve_names(&mut tokens, emojis);
+        Ok(crate::replace_shortcodes(tokens, &|name| {
+            let (id, animated) = emojis.find(name)?;
+            Some(Token::new_custom_emoji(id, name, animated))
+        }))
+    }
+
+    /// A reaction is a single emoji, anything else is kept as text
+    fn parse_reaction(&self, s: &str) -> Result<Token, ParseError> {
+        let mut tokens = self.parse(s)?;
+        Ok(match tokens.len() {
+            1 => tokens.remove(0),
+            _ => Token::new_text(s),
+        })
+    }
+}
+
+/// Markup may carry an outdated name, prefer the one of the guild
+fn resolve_names(tokens: &mut [Token], emojis: &GuildEmojiCache) {
+    for token in tokens {
+        match token {
+            Token::CustomEmoji { id, name, .. } => {
+                if let Some(current) = emojis.name(*id) {
+                    current.clone_into(name);
+                }
+            }
+            Token::Spoiler(tokens) => resolve_names(tokens, emojis),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    struct Message;
+    impl DiscordMessage for Message {
+        fn content(&self) -> &str {
+            "hi ||<:old:123456789012345678>||"
+        }
+        fn reactions(&self) -> Vec<Reaction<'_>> {
+            vec![
+                Reaction::Unicode("👍"),
+                Reaction::Unicode("not emoji"),
+                Reaction::Custom {
+                    id: 123456789012345678,
+                    name: None,
+                    animated: false,
+                },
+                Reaction::Custom {
+                    id: 234567890123456789,
+                    name: None,
+                    animated: true,
+                },
+            ]
+        }
+        fn stickers(&self) -> Vec<StickerItem> {
+            Vec::new()
+        }
+    }
+    #[test]
+    fn test_parse_message() {
+        let mut emojis = GuildEmojiCache::new();
+        emojis.insert(123456789012345678, "renamed", false);
+        let tokens = Parser::new().parse_message(&Message, &emojis).unwrap();
+        let renamed = Token::new_custom_emoji(123456789012345678, "renamed", false);
+        assert_eq!(
+            tokens.content,
+            vec![
+                Token::new_text("hi "),
+                Token::Spoiler(vec![Token::new_custom_emoji(
+                    123456789012345678,
+                    "renamed",
+                    false
+                )]),
+            ]
+        );
+        assert_eq!(
+            tokens.reactions,
+            vec![
+                Token::new_emoji("👍", crate::get("👍").unwrap()),
+                Token::new_text("not emoji"),
+                renamed,
+                Token::new_custom_emoji(234567890123456789, "", true),
+            ]
+        );
+    }
+    #[test]
+    fn test_parse_with_emojis() {
+        let mut emojis = GuildEmojiCache::new();
+        emojis.insert(123456789012345678, "pepega", true);
+        let tokens = Parser::new()
+            .parse_with_emojis("a:pepega: :unknown: ||:pepega:||:", &emojis)
+            .unwrap();
+        let pepega = || Token::new_custom_emoji(123456789012345678, "pepega", true);
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_text("a"),
+                pepega(),
+                Token::new_text(" :unknown: "),
+                Token::Spoiler(vec![pepega()]),
+                Token::new_text(":"),
+            ]
+        );
+    }
+    #[test]
+    fn test_stickers() {
+        let sticker = |id, format| StickerItem {
+            id,
+            name: "wave".to_string(),
+            format: StickerFormat::from_format_type(format),
+        };
+        let tokens = MessageTokens {
+            content: vec![Token::new_text("hi")],
+            reactions: Vec::new(),
+            stickers: vec![sticker(1, 1), sticker(2, 3), sticker(3, 4), sticker(4, 9)],
+        }
+        .into_tokens();
+        assert_eq!(tokens[0], Token::new_text("hi"));
+        assert_eq!(
+            tokens[1],
+            Token::Sticker {
+                id: 1,
+                name: "wave".to_string(),
+                format: StickerFormat::Png,
+            }
+        );
+        let urls = tokens[1..].iter().map(Token::url).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            [
+                Some("https://cdn.discordapp.com/stickers/1.png".to_string()),
+                Some("https://cdn.discordapp.com/stickers/2.json".to_string()),
+                Some("https://media.discordapp.net/stickers/3.gif".to_string()),
+                Some("https://cdn.discordapp.com/stickers/4.png".to_string()),
+            ]
+        );
+        assert_eq!(
+            tokens[3].url_with_size(160).unwrap().as_deref(),
+            Some("https://media.discordapp.net/stickers/3.gif?size=160")
+        );
+        assert_eq!(
+            tokens[2].url_with_size(160).unwrap().as_deref(),
+            Some("https://cdn.discordapp.com/stickers/2.json")
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use crate::Emoticons;
+#[cfg(feature = "discord")]
+use crate::ParseError;
+
+#[cfg(feature = "emoji-url")]
+const TWEMOJI_CDN: &str = "https://cdn.jsdelivr.net/gh/jdecked/twemoji@latest/assets/";
+#[cfg(feature = "discord")]
+const DISCORD_CDN: &str = "https://cdn.discordapp.com/emojis/";
+/// Sizes accepted by the Discord CDN
+#[cfg(feature = "discord")]
+const DISCORD_SIZES: &[u32] = &[
+    16, 20, 22, 24, 28, 32, 40, 44, 48, 56, 60, 64, 80, 96, 100, 128, 160, 240, 256, 300, 320, 480,
+    512, 600, 640, 1024, 1280, 1536, 2048, 3072, 4096,
+];
+
+/// Options of the parser
+///
+/// New options may be added at any time, build them with [`ParserOptions::builder`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ParserOptions {
+    /// Keep characters followed by VS15 as text instead of emoji
+    pub text_presentation: bool,
+    /// Merge contiguous text into a single token instead of one per grapheme
+    pub coalesce_text: bool,
+    #[cfg(feature = "shortcode")]
+    /// Emit emoji tokens for `:name:` shortcodes of the bundled table
+    pub shortcodes: bool,
+    /// Convert emoticons such as `:)` to emoji, off unless a table is given
+    pub emoticons: Option<Emoticons>,
+    #[cfg(feature = "emoji-url")]
+    /// Emit [`Token::EmojiUrl`](crate::Token::EmojiUrl) with the url of the twemoji image
+    /// instead of the png bytes
+    pub emoji_urls: bool,
+    #[cfg(feature = "svg")]
+    /// Emit [`Token::EmojiSvg`](crate::Token::EmojiSvg) with the svg of the twemoji image
+    /// instead of the png bytes, for rendering at any size, over [`Self::emoji_urls`]
+    pub svg_emoji: bool,
+    #[cfg(feature = "emoji-url")]
+    /// Base url of the twemoji images
+    pub twemoji_base: String,
+    #[cfg(feature = "emoji-url")]
+    /// Directory of the images under the base, `72x72` for pngs or `svg`
+    pub twemoji_size: String,
+    #[cfg(feature = "discord")]
+    /// Parse Discord markup at all
+    pub discord: bool,
+    #[cfg(feature = "discord")]
+    /// Emit custom emoji tokens instead of keeping their markup as text
+    pub custom_emoji: bool,
+    #[cfg(feature = "discord")]
+    /// Emit user, channel, role and command mention tokens instead of keeping their markup as text
+    pub mentions: bool,
+    #[cfg(feature = "discord")]
+    /// Emit timestamp tokens instead of keeping their markup as text
+    pub timestamps: bool,
+    #[cfg(feature = "discord")]
+    /// Keep inline code and code blocks verbatim instead of parsing emoji in them
+    pub code: bool,
+    #[cfg(feature = "discord")]
+    /// Emit spoiler tokens instead of keeping their markup as text
+    pub spoilers: bool,
+    #[cfg(feature = "discord")]
+    /// Parse emoji inside spoilers, they are kept as text otherwise
+    pub spoiler_emoji: bool,
+    #[cfg(feature = "discord")]
+    /// Keep emoji escaped with a backslash as text, like Discord shows them
+    pub escaped_emoji: bool,
+    #[cfg(feature = "discord")]
+    /// Base url custom emoji ids are appended to
+    pub cdn_base: String,
+    #[cfg(feature = "discord")]
+    /// Size requested for custom emoji images, must be one the Discord CDN serves
+    pub emoji_size: u32,
+    #[cfg(feature = "discord")]
+    /// Image format requested for custom emoji
+    pub emoji_format: CustomEmojiFormat,
+}
+
+/// Image format of custom emoji urls
+#[cfg(feature = "discord")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CustomEmojiFormat {
+    #[default]
+    Png,
+    Webp,
+    Gif,
+}
+
+#[cfg(feature = "discord")]
+impl CustomEmojiFormat {
+    /// File extension used in the url
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Webp => "webp",
+            Self::Gif => "gif",
+        }
+    }
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            text_presentation: false,
+            coalesce_text: true,
+            #[cfg(feature = "shortcode")]
+            shortcodes: true,
+            emoticons: None,
+            #[cfg(feature = "emoji-url")]
+            emoji_urls: false,
+            #[cfg(feature = "svg")]
+            svg_emoji: false,
+            #[cfg(feature = "emoji-url")]
+            twemoji_base: TWEMOJI_CDN.to_string(),
+            #[cfg(feature = "emoji-url")]
+            twemoji_size: "72x72".to_string(),
+            #[cfg(feature = "discord")]
+            discord: true,
+            #[cfg(feature = "discord")]
+            custom_emoji: true,
+            #[cfg(feature = "discord")]
+            mentions: true,
+            #[cfg(feature = "discord")]
+            timestamps: true,
+            #[cfg(feature = "discord")]
+            code: true,
+            #[cfg(feature = "discord")]
+            spoilers: true,
+            #[cfg(feature = "discord")]
+            spoiler_emoji: true,
+            #[cfg(feature = "discord")]
+            escaped_emoji: true,
+            #[cfg(feature = "discord")]
+            cdn_base: DISCORD_CDN.to_string(),
+            #[cfg(feature = "discord")]
+            emoji_size: 96,
+            #[cfg(feature = "discord")]
+            emoji_format: CustomEmojiFormat::Png,
+        }
+    }
+}
+
+impl ParserOptions {
+    pub fn builder() -> ParserOptionsBuilder {
+        ParserOptionsBuilder::default()
+    }
+    #[cfg(feature = "discord")]
+    pub(crate) fn validate(&self) -> Result<(), ParseError> {
+        check_emoji_size(self.emoji_size)
+    }
+    #[cfg(feature = "emoji-url")]
+    /// Url of the twemoji image of `emoji`, svg images are in the `svg` directory
+    pub(crate) fn twemoji_url(&self, emoji: &str) -> String {
+        let extension = match self.twemoji_size.as_str() {
+            "svg" => "svg",
+            _ => "png",
+        };
+        format!(
+            "{}{}/{}.{}",
+            self.twemoji_base,
+            self.twemoji_size,
+            crate::emoji_url::file_stem(emoji),
+            extension
+        )
+    }
+    #[cfg(feature = "discord")]
+    /// Animated emoji always use gif so they don't render as a still frame
+    pub(crate) fn custom_emoji_url(&self, id: u64, animated: bool, size: u32) -> String {
+        let format = match animated {
+            true => CustomEmojiFormat::Gif,
+            false => self.emoji_format,
+        };
+        format!(
+            "{}{}.{}?size={}",
+            self.cdn_base,
+            id,
+            format.extension(),
+            size
+        )
+    }
+}
+
+#[cfg(feature = "discord")]
+pub(crate) fn check_emoji_size(size: u32) -> Result<(), ParseError> {
+    match DISCORD_SIZES.contains(&size) {
+        true => Ok(()),
+        false => Err(ParseError::InvalidEmojiSize(size)),
+    }
+}
+
+/// Builder of [`ParserOptions`]
+#[derive(Debug, Clone, Default)]
+pub struct ParserOptionsBuilder {
+    options: ParserOptions,
+}
+
+impl ParserOptionsBuilder {
+    pub fn text_presentation(mut self, text_presentation: bool) -> Self {
+        self.options.text_presentation = text_presentation;
+        self
+    }
+    pub fn coalesce_text(mut self, coalesce_text: bool) -> Self {
+        self.options.coalesce_text = coalesce_text;
+        self
+    }
+    #[cfg(feature = "shortcode")]
+    pub fn shortcodes(mut self, shortcodes: bool) -> Self {
+        self.options.shortcodes = shortcodes;
+        self
+    }
+    pub fn emoticons(mut self, emoticons: Emoticons) -> Self {
+        self.options.emoticons = Some(emoticons);
+        self
+    }
+    #[cfg(feature = "emoji-url")]
+    pub fn emoji_urls(mut self, emoji_urls: bool) -> Self {
+        self.options.emoji_urls = emoji_urls;
+        self
+    }
+    #[cfg(feature = "svg")]
+    pub fn svg_emoji(mut self, svg_emoji: bool) -> Self {
+        self.options.svg_emoji = svg_emoji;
+        self
+    }
+    #[cfg(feature = "emoji-url")]
+    pub fn twemoji_base(mut self, twemoji_base: impl Into<String>) -> Self {
+        self.options.twemoji_base = twemoji_base.into();
+        self
+    }
+    #[cfg(feature = "emoji-url")]
+    pub fn twemoji_size(mut self, twemoji_size: impl Into<String>) -> Self {
+        self.options.twemoji_size = twemoji_size.into();
+        self
+    }
+    #[cfg(feature = "discord")]
+    pub fn discord(mut self, discord: bool) -> Self {
+        self.options.discord = discord;
+        self
+    }
+    #[cfg(feature = "discord")]
+    pub fn custom_emoji(mut self, custom_emoji: bool) -> Self {
+        self.options.custom_emoji = custom_emoji;
+        self
+    }
+    #[cfg(feature = "discord")]
+    pub fn mentions(mut self, mentions: bool) -> Self {
+        self.options.mentions = mentions;
+        self
+    }
+    #[cfg(feature = "discord")]
+    pub fn timestamps(mut self, timestamps: bool) -> Self {
+        self.options.timestamps = timestamps;
+        self
+    }
+    #[cfg(feature = "discord")]
+    pub fn code(mut self, code: bool) -> Self {
+        self.options.code = code;
+        self
+    }
+    #[cfg(feature = "discord")]
+    pub fn spoilers(mut self, spoilers: bool) -> Self {
+        self.options.spoilers = spoilers;
+        self
+    }
+    #[cfg(feature = "discord")]
+    pub fn spoiler_emoji(mut self, spoiler_emoji: bool) -> Self {
+        self.options.spoiler_emoji = spoiler_emoji;
+        self
+    }
+    #[cfg(feature = "discord")]
+    pub fn escaped_emoji(mut self, escaped_emoji: bool) -> Self {
+        self.options.escaped_emoji = escaped_emoji;
+        self
+    }
+    #[cfg(feature = "discord")]
+    pub fn cdn_base(mut self, cdn_base: impl Into<String>) -> Self {
+        self.options.cdn_base = cdn_base.into();
+        self
+    }
+    #[cfg(feature = "discord")]
+    pub fn emoji_size(mut self, emoji_size: u32) -> Self {
+        self.options.emoji_size = emoji_size;
+        self
+    }
+    #[cfg(feature = "discord")]
+    pub fn emoji_format(mut self, emoji_format: CustomEmojiFormat) -> Self {
+        self.options.emoji_format = emoji_format;
+        self
+    }
+    pub fn build(self) -> ParserOptions {
+        self.options
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{ParseError, Parser, Token};
+#[cfg(feature = "discord")]
+use std::sync::Arc;
+
+/// Platform a message comes from, selecting its syntax at runtime
+///
+/// Platforms whose custom emoji come with the message, such as Telegram entities, only get
+/// the syntax of their text here, use their own parsing method for the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Platform {
+    /// Unicode emoji only, plus shortcodes and emoticons when enabled
+    Plain,
+    #[cfg(feature = "discord")]
+    /// Discord markup, see [`ParserOptions::discord`](crate::ParserOptions::discord)
+    Discord,
+    /// Slack `:shortcode:` syntax, which needs the `shortcode` feature to be turned into emoji
+    Slack,
+    #[cfg(feature = "telegram")]
+    /// Text of a Telegram message
+    Telegram,
+    #[cfg(feature = "teams")]
+    /// Teams `(code)` emoticons and `<emoji>` tags
+    Teams,
+    #[cfg(feature = "revolt")]
+    /// Revolt `:id:` custom emoji
+    Revolt,
+    #[cfg(feature = "steam")]
+    /// Steam chat `:emoticons:`
+    Steam,
+    #[cfg(feature = "bbcode")]
+    /// Forum BBCode
+    BbCode,
+}
+
+impl Parser {
+    /// Parse a string with the syntax of `platform`
+    pub fn parse_for(&self, platform: Platform, s: &str) -> Result<Vec<Token>, ParseError> {
+        match platform {
+            #[cfg(feature = "discord")]
+            Platform::Discord => self.parse(s),
+            #[cfg(feature = "teams")]
+            Platform::Teams => self.without_discord().parse_teams(s),
+            #[cfg(feature = "revolt")]
+            Platform::Revolt => self.without_discord().parse_revolt(s),
+            #[cfg(feature = "steam")]
+            Platform::Steam => self.without_discord().parse_steam(s),
+            #[cfg(feature = "bbcode")]
+            Platform::BbCode => self.without_discord().parse_bbcode(s),
+            _ => self.without_discord().parse(s),
+        }
+    }
+
+    /// This parser with Discord markup turned off, built once
+    #[cfg(feature = "discord")]
+    fn without_discord(&self) -> &Parser {
+        if !self.options.discord {
+            return self;
+        }
+        self.without_discord.get_or_init(|| {
+            let mut parser = self.clone();
+            parser.options.discord = false;
+            Arc::new(parser)
+        })
+    }
+
+    #[cfg(not(feature = "discord"))]
+    fn without_discord(&self) -> &Parser {
+        self
+    }
+
+    /// Forget the parsers built from this one, after changing it
+    pub(crate) fn changed(&mut self) {
+        #[cfg(feature = "discord")]
+        self.without_discord.take();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_parse_for() {
+        let parser = Parser::new();
+        let s = "hi 👍 <:blobcat:123456789012345678>";
+        let tokens = parser.parse_for(Platform::Plain, s).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_text("hi "),
+                Token::new_emoji("👍", crate::get("👍").unwrap()),
+                Token::new_text(" <:blobcat:123456789012345678>"),
+            ]
+        );
+        assert_eq!(parser.parse_for(Platform::Slack, s).unwrap(), tokens);
+        #[cfg(feature = "discord")]
+        assert_eq!(
+            parser.parse_for(Platform::Discord, s).unwrap()[3],
+            Token::new_custom_emoji(123456789012345678, "blobcat", false)
+        );
+    }
+    #[test]
+    #[cfg(all(feature = "discord", feature = "shortcode"))]
+    fn test_parse_for_after_change() {
+        let mut parser = Parser::new();
+        let plain = parser.without_discord() as *const Parser;
+        assert_eq!(parser.without_discord() as *const Parser, plain);
+        parser.register_alias("yes", "👍").unwrap();
+        assert_eq!(
+            parser.parse_for(Platform::Slack, ":yes:").unwrap(),
+            vec![Token::new_emoji("👍", crate::get("👍").unwrap())]
+        );
+    }
+}
@@ -0,0 +1,605 @@
+//! Small png decoder and encoder, enough for emoji images
+
+#[cfg(feature = "render-image")]
+use std::time::Duration;
+
+/// Image with 8 bit rgba pixels, row by row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    #[cfg(feature = "image")]
+    /// Transparent image
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+    /// Width, height and pixels
+    #[cfg(feature = "image")]
+    pub fn into_raw(self) -> (u32, u32, Vec<u8>) {
+        (self.width, self.height, self.pixels)
+    }
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+    #[cfg(feature = "image")]
+    /// Draw `rgba` over the pixel at `x`, `y`, nothing if it is outside the image
+    pub fn blend(&mut self, x: i64, y: i64, rgba: [u8; 4]) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        let below = &mut self.pixels[i..i + 4];
+        // Source over, in straight alpha
+        let (src, dst) = (
+            rgba[3] as u32,
+            below[3] as u32 * (255 - rgba[3] as u32) / 255,
+        );
+        let alpha = src + dst;
+        if alpha == 0 {
+            return;
+        }
+        for c in 0..3 {
+            below[c] = ((rgba[c] as u32 * src + below[c] as u32 * dst) / alpha) as u8;
+        }
+        below[3] = alpha as u8;
+    }
+    #[cfg(feature = "image")]
+    /// Draw `image` over this one with its top left corner at `x`, `y`
+    pub fn draw(&mut self, image: &RgbaImage, x: i64, y: i64) {
+        for dy in 0..image.height {
+            for dx in 0..image.width {
+                self.blend(x + dx as i64, y + dy as i64, image.pixel(dx, dy));
+            }
+        }
+    }
+    #[cfg(feature = "image")]
+    /// Encode as a png
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        push_chunk(&mut png, b"IHDR", &self.header());
+        push_chunk(&mut png, b"IDAT", &self.compressed());
+        push_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+    /// Encode as a lossless webp, `None` if it is wider or taller than 16384 pixels
+    #[cfg(feature = "webp")]
+    pub fn to_webp(&self) -> Option<Vec<u8>> {
+        crate::webp::encode(self)
+    }
+    #[cfg(feature = "image")]
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&self.width.to_be_bytes());
+        header.extend_from_slice(&self.height.to_be_bytes());
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+        header
+    }
+    /// Rows without filters, compressed
+    #[cfg(feature = "image")]
+    fn compressed(&self) -> Vec<u8> {
+        let row = self.width as usize * 4;
+        let mut rows = Vec::with_capacity((row + 1) * self.height as usize);
+        for line in self.pixels.chunks(row.max(1)) {
+            rows.push(0);
+            rows.extend_from_slice(line);
+        }
+        miniz_oxide::deflate::compress_to_vec_zlib(&rows, 6)
+    }
+    /// Scale to `width` by `height` with `filter`, transparent if the image is empty
+    #[cfg(feature = "image")]
+    pub fn resized_with(&self, width: u32, height: u32, filter: ResizeFilter) -> RgbaImage {
+        if self.width == 0 || self.height == 0 {
+            return RgbaImage::new(width, height);
+        }
+        match filter {
+            ResizeFilter::Area => self.resized(width, height),
+            ResizeFilter::Nearest => {
+                let mut image = RgbaImage::new(width, height);
+                for y in 0..height {
+                    for x in 0..width {
+                        let (sx, sy) = (x * self.width / width, y * self.height / height);
+                        let i = (y as usize * width as usize + x as usize) * 4;
+                        image.pixels[i..i + 4].copy_from_slice(&self.pixel(sx, sy));
+                    }
+                }
+                image
+            }
+            ResizeFilter::Bilinear => self.bilinear(width, height),
+        }
+    }
+    #[cfg(feature = "image")]
+    fn bilinear(&self, width: u32, height: u32) -> RgbaImage {
+        let mut image = RgbaImage::new(width, height);
+        // Position in the source of the center of a pixel
+        let source = |i: u32, from: u32, to: u32| {
+            let p =
+                ((i as f32 + 0.5) * from as f32 / to as f32 - 0.5).clamp(0.0, from as f32 - 1.0);
+            (p as u32, (p as u32 + 1).min(from - 1), p.fract())
+        };
+        for y in 0..height {
+            let (y0, y1, fy) = source(y, self.height, height);
+            for x in 0..width {
+                let (x0, x1, fx) = source(x, self.width, width);
+                let mut sum = [0.0f32; 4];
+                for (sx, sy, weight) in [
+                    (x0, y0, (1.0 - fx) * (1.0 - fy)),
+                    (x1, y0, fx * (1.0 - fy)),
+                    (x0, y1, (1.0 - fx) * fy),
+                    (x1, y1, fx * fy),
+                ] {
+                    // Weighted by alpha, so transparent pixels don't darken the edges
+                    let [r, g, b, a] = self.pixel(sx, sy).map(f32::from);
+                    let weight = weight * a;
+                    sum[0] += r * weight;
+                    sum[1] += g * weight;
+                    sum[2] += b * weight;
+                    sum[3] += weight;
+                }
+                let alpha = sum[3].max(f32::EPSILON);
+                let i = (y as usize * width as usize + x as usize) * 4;
+                image.pixels[i..i + 4].copy_from_slice(&[
+                    (sum[0] / alpha).round() as u8,
+                    (sum[1] / alpha).round() as u8,
+                    (sum[2] / alpha).round() as u8,
+                    sum[3].round() as u8,
+                ]);
+            }
+        }
+        image
+    }
+    /// Scale to `width` by `height`, averaging the pixels each one covers
+    pub fn resized(&self, width: u32, height: u32) -> RgbaImage {
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        let span = |i: u32, from: u32, to: u32| {
+            let start = i * from / to;
+            start..((i + 1) * from / to).max(start + 1)
+        };
+        for y in 0..height {
+            for x in 0..width {
+                // Sums weighted by alpha, so transparent pixels don't darken the edges
+                let mut sum = [0u64; 4];
+                let mut count = 0;
+                for sy in span(y, self.height, height) {
+                    for sx in span(x, self.width, width) {
+                        let [r, g, b, a] = self.pixel(sx, sy).map(u64::from);
+                        sum[0] += r * a;
+                        sum[1] += g * a;
+                        sum[2] += b * a;
+                        sum[3] += a;
+                        count += 1;
+                    }
+                }
+                let alpha = sum[3].max(1);
+                pixels.extend_from_slice(&[
+                    (sum[0] / alpha) as u8,
+                    (sum[1] / alpha) as u8,
+                    (sum[2] / alpha) as u8,
+                    (sum[3] / count) as u8,
+                ]);
+            }
+        }
+        RgbaImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+/// Encode frames of the same size as a looping apng, shown for their duration in turn
+#[cfg(feature = "render-image")]
+pub(crate) fn encode_apng(frames: &[(RgbaImage, Duration)]) -> Vec<u8> {
+    let Some((first, _)) = frames.first() else {
+        return RgbaImage::new(1, 1).to_png();
+    };
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    push_chunk(&mut png, b"IHDR", &first.header());
+    let mut control = (frames.len() as u32).to_be_bytes().to_vec();
+    control.extend_from_slice(&0u32.to_be_bytes());
+    push_chunk(&mut png, b"acTL", &control);
+    // Frame controls and data after the first frame share a sequence
+    let mut sequence = 0u32;
+    for (i, (image, delay)) in frames.iter().enumerate() {
+        let mut control = sequence.to_be_bytes().to_vec();
+        for n in [image.width, image.height, 0, 0] {
+            control.extend_from_slice(&n.to_be_bytes());
+        }
+        let delay = delay.as_millis().min(u16::MAX as u128) as u16;
+        control.extend_from_slice(&delay.to_be_bytes());
+        control.extend_from_slice(&1000u16.to_be_bytes());
+        control.extend_from_slice(&[0, 0]);
+        push_chunk(&mut png, b"fcTL", &control);
+        sequence += 1;
+        match i {
+            0 => push_chunk(&mut png, b"IDAT", &image.compressed()),
+            _ => {
+                let mut data = sequence.to_be_bytes().to_vec();
+                data.extend_from_slice(&image.compressed());
+                push_chunk(&mut png, b"fdAT", &data);
+                sequence += 1;
+            }
+        }
+    }
+    push_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+#[cfg(feature = "image")]
+fn push_chunk(png: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+    png.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(body);
+    let crc = crc32(kind.iter().chain(body));
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+#[cfg(feature = "image")]
+pub(crate) fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    !bytes.fold(!0u32, |crc, b| {
+        (0..8).fold(crc ^ *b as u32, |crc, _| match crc & 1 {
+            1 => crc >> 1 ^ 0xedb88320,
+            _ => crc >> 1,
+        })
+    })
+}
+
+/// Filter images are scaled with
+#[cfg(feature = "image")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResizeFilter {
+    /// Nearest pixel, for pixel art
+    Nearest,
+    /// Blend of the four nearest pixels, smooth when enlarging
+    Bilinear,
+    /// Average of the pixels each one covers, sharp when shrinking
+    #[default]
+    Area,
+}
+
+/// Larger images are not emoji, and would take a lot of memory
+const MAX_DIMENSION: u32 = 4096;
+
+/// Passes of Adam7 interlacing as x, y, dx and dy
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+struct Header {
+    width: usize,
+    height: usize,
+    depth: u8,
+    color: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.depth as usize
+    }
+    fn row_bytes(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+}
+
+/// Decode a png, `None` if it is invalid or unsupported
+pub(crate) fn decode(png: &[u8]) -> Option<RgbaImage> {
+    let mut chunks = png.strip_prefix(b"\x89PNG\r\n\x1a\n")?;
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut data = Vec::new();
+    while chunks.len() >= 12 {
+        let len = u32::from_be_bytes(chunks[..4].try_into().ok()?) as usize;
+        let kind = &chunks[4..8];
+        let body = chunks.get(8..8 + len)?;
+        match kind {
+            b"IHDR" if body.len() == 13 => header = Some(read_header(body)?),
+            b"PLTE" => palette = body,
+            b"tRNS" => transparency = body,
+            b"IDAT" => data.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        chunks = chunks.get(12 + len..)?;
+    }
+    let header = header?;
+    let passes = match header.interlaced {
+        true => ADAM7.to_vec(),
+        false => vec![(0, 0, 1, 1)],
+    };
+    let size = passes
+        .iter()
+        .map(|&pass| {
+            let (width, height) = pass_size(&header, pass);
+            match width {
+                0 => 0,
+                _ => height * (header.row_bytes(width) + 1),
+            }
+        })
+        .sum();
+    let data = miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&data, size).ok()?;
+    let mut pixels = vec![0; header.width * header.height * 4];
+    let mut data = data.as_slice();
+    for pass in passes {
+        let (width, height) = pass_size(&header, pass);
+        if width == 0 || height == 0 {
+            continue;
+        }
+        let row_bytes = header.row_bytes(width);
+        let rows = unfilter(data.get(..height * (row_bytes + 1))?, &header, row_bytes)?;
+        data = &data[height * (row_bytes + 1)..];
+        let (x0, y0, dx, dy) = pass;
+        for (y, row) in rows.chunks(row_bytes).enumerate() {
+            for x in 0..width {
+                let rgba = color(row, x, &header, palette, transparency)?;
+                let i = ((y0 + y * dy) * header.width + x0 + x * dx) * 4;
+                pixels[i..i + 4].copy_from_slice(&rgba);
+            }
+        }
+    }
+    Some(RgbaImage {
+        width: header.width as u32,
+        height: header.height as u32,
+        pixels,
+    })
+}
+
+fn read_header(body: &[u8]) -> Option<Header> {
+    let width = u32::from_be_bytes(body[..4].try_into().ok()?);
+    let height = u32::from_be_bytes(body[4..8].try_into().ok()?);
+    let (depth, color) = (body[8], body[9]);
+    let valid = match color {
+        0 => matches!(depth, 1 | 2 | 4 | 8 | 16),
+        3 => matches!(depth, 1 | 2 | 4 | 8),
+        2 | 4 | 6 => matches!(depth, 8 | 16),
+        _ => false,
+    };
+    let sized = (1..=MAX_DIMENSION).contains(&width) && (1..=MAX_DIMENSION).contains(&height);
+    (valid && sized && body[10] == 0 && body[11] == 0 && body[12] <= 1).then_some(Header {
+        width: width as usize,
+        height: height as usize,
+        depth,
+        color,
+        interlaced: body[12] == 1,
+    })
+}
+
+fn pass_size(header: &Header, (x, y, dx, dy): (usize, usize, usize, usize)) -> (usize, usize) {
+    (
+        (header.width + dx - 1 - x) / dx,
+        (header.height + dy - 1 - y) / dy,
+    )
+}
+
+/// Undo the filter of each row, giving the rows without their filter byte
+fn unfilter(data: &[u8], header: &Header, row_bytes: usize) -> Option<Vec<u8>> {
+    let bpp = header.bits_per_pixel().div_ceil(8);
+    let mut rows = Vec::with_capacity(data.len());
+    let mut prior = vec![0; row_bytes];
+    for line in data.chunks(row_bytes + 1) {
+        let (filter, line) = (line[0], &line[1..]);
+        let mut row = line.to_vec();
+        for i in 0..row_bytes {
+            let a = match i >= bpp {
+                true => row[i - bpp],
+                false => 0,
+            };
+            let b = prior[i];
+            let c = match i >= bpp {
+                true => prior[i - bpp],
+                false => 0,
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return None,
+            };
+            row[i] = row[i].wrapping_add(predicted);
+        }
+        rows.extend_from_slice(&row);
+        prior = row;
+    }
+    Some(rows)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    match (pa <= pb && pa <= pc, pb <= pc) {
+        (true, _) => a,
+        (false, true) => b,
+        (false, false) => c,
+    }
+}
+
+/// Rgba of the pixel `x` of a row
+fn color(row: &[u8], x: usize, header: &Header, palette: &[u8], trns: &[u8]) -> Option<[u8; 4]> {
+    let depth = header.depth as usize;
+    // Sample `n` of the row, scaled to 8 bits
+    let sample = |n: usize| -> u8 {
+        match depth {
+            16 => row[n * 2],
+            8 => row[n],
+            _ => {
+                let bits = n * depth;
+                let value = row[bits / 8] >> (8 - depth - bits % 8) & ((1 << depth) - 1);
+                (value as u16 * 255 / ((1 << depth) - 1)) as u8
+            }
+        }
+    };
+    // Raw sample, which transparency chunks are compared to
+    let raw = |n: usize| -> u16 {
+        match depth {
+            16 => u16::from_be_bytes([row[n * 2], row[n * 2 + 1]]),
+            8 => row[n] as u16,
+            _ => {
+                let bits = n * depth;
+                (row[bits / 8] >> (8 - depth - bits % 8) & ((1 << depth) - 1)) as u16
+            }
+        }
+    };
+    let transparent = |samples: &[u16]| {
+        trns.len() == samples.len() * 2
+            && samples
+                .iter()
+                .enumerate()
+                .all(|(i, s)| u16::from_be_bytes([trns[i * 2], trns[i * 2 + 1]]) == *s)
+    };
+    Some(match header.color {
+        0 => {
+            let gray = sample(x);
+            let alpha = if transparent(&[raw(x)]) { 0 } else { 255 };
+            [gray, gray, gray, alpha]
+        }
+        2 => {
+            let (r, g, b) = (raw(x * 3), raw(x * 3 + 1), raw(x * 3 + 2));
+            let alpha = if transparent(&[r, g, b]) { 0 } else { 255 };
+            [sample(x * 3), sample(x * 3 + 1), sample(x * 3 + 2), alpha]
+        }
+        3 => {
+            let index = raw(x) as usize;
+            let rgb = palette.get(index * 3..index * 3 + 3)?;
+            [
+                rgb[0],
+                rgb[1],
+                rgb[2],
+                trns.get(index).copied().unwrap_or(255),
+            ]
+        }
+        4 => {
+            let gray = sample(x * 2);
+            [gray, gray, gray, sample(x * 2 + 1)]
+        }
+        _ => [
+            sample(x * 4),
+            sample(x * 4 + 1),
+            sample(x * 4 + 2),
+            sample(x * 4 + 3),
+        ],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_decode() {
+        // Palette with transparency, like most twemoji
+        let image = decode(crate::get("👍").unwrap()).unwrap();
+        assert_eq!((image.width, image.height), (72, 72));
+        assert_eq!(image.pixel(0, 0)[3], 0);
+        assert_eq!(image.pixel(36, 40)[3], 255);
+        assert!(decode(b"\x89PNG\r\n\x1a\n").is_none());
+        assert!(decode(b"GIF89a").is_none());
+    }
+    #[test]
+    #[cfg(feature = "render-image")]
+    fn test_encode_apng() {
+        let frames = [
+            (RgbaImage::new(2, 2), Duration::from_millis(40)),
+            (RgbaImage::new(2, 2), Duration::from_millis(60)),
+        ];
+        let apng = encode_apng(&frames);
+        let count = |kind: &[u8]| apng.windows(4).filter(|w| *w == kind).count();
+        assert_eq!((count(b"acTL"), count(b"fcTL"), count(b"fdAT")), (1, 2, 1));
+        // Decoders without apng support show the first frame
+        assert_eq!(decode(&apng), Some(RgbaImage::new(2, 2)));
+    }
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_resized_with() {
+        let mut image = RgbaImage::new(2, 1);
+        image.blend(0, 0, [255, 0, 0, 255]);
+        image.blend(1, 0, [0, 0, 255, 255]);
+        let nearest = image.resized_with(4, 1, ResizeFilter::Nearest);
+        assert_eq!(nearest.pixel(1, 0), [255, 0, 0, 255]);
+        assert_eq!(nearest.pixel(2, 0), [0, 0, 255, 255]);
+        let bilinear = image.resized_with(4, 1, ResizeFilter::Bilinear);
+        assert_eq!(bilinear.pixel(0, 0), [255, 0, 0, 255]);
+        assert_eq!(bilinear.pixel(1, 0), [191, 0, 64, 255]);
+        let area = image.resized_with(1, 1, ResizeFilter::Area);
+        assert_eq!(area.pixels, [127, 0, 127, 255]);
+        for filter in [
+            ResizeFilter::Nearest,
+            ResizeFilter::Bilinear,
+            ResizeFilter::Area,
+        ] {
+            let empty = RgbaImage::new(0, 3).resized_with(2, 2, filter);
+            assert_eq!(empty, RgbaImage::new(2, 2));
+        }
+    }
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_crc32() {
+        assert_eq!(crc32(b"IEND".iter()), 0xae426082);
+    }
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_blend() {
+        let mut image = RgbaImage::new(2, 1);
+        image.blend(0, 0, [255, 0, 0, 255]);
+        image.blend(0, 0, [0, 0, 255, 0]);
+        image.blend(1, 0, [0, 0, 255, 128]);
+        image.blend(2, 0, [0, 0, 255, 255]);
+        assert_eq!(image.pixels, [255, 0, 0, 255, 0, 0, 255, 128]);
+    }
+    #[test]
+    fn test_decode_rgba() {
+        // Two by one rgba, one pixel opaque red and the other transparent
+        let rows = [0, 255, 0, 0, 255, 0, 0, 0, 0];
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut chunk = |kind: &[u8], body: &[u8]| {
+            png.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            png.extend_from_slice(kind);
+            png.extend_from_slice(body);
+            // The crc isn't checked
+            png.extend_from_slice(&[0; 4]);
+        };
+        chunk(b"IHDR", &[0, 0, 0, 2, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        chunk(
+            b"IDAT",
+            &miniz_oxide::deflate::compress_to_vec_zlib(&rows, 6),
+        );
+        chunk(b"IEND", &[]);
+        let image = decode(&png).unwrap();
+        assert_eq!(image.pixels, [255, 0, 0, 255, 0, 0, 0, 0]);
+        assert_eq!(image.resized(1, 1).pixels, [255, 0, 0, 127]);
+        #[cfg(feature = "image")]
+        assert_eq!(decode(&image.to_png()), Some(image));
+    }
+}
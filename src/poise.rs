@@ -0,0 +1,28 @@
+use crate::{GuildEmojiCache, ParseError, Parser, Token};
+use poise::Context;
+
+/// Custom emoji of the guild a command is invoked in, empty in DMs or when the guild isn't cached
+pub fn guild_emojis<U, E>(ctx: Context<'_, U, E>) -> GuildEmojiCache {
+    ctx.guild()
+        .map(|guild| guild.emojis.values().collect())
+        .unwrap_or_default()
+}
+
+impl Parser {
+    /// Parse the message invoking a prefix command, or the invocation string of a slash command
+    pub fn parse_invocation<U, E>(&self, ctx: Context<'_, U, E>) -> Result<Vec<Token>, ParseError> {
+        let emojis = guild_emojis(ctx);
+        match ctx {
+            Context::Prefix(prefix) => self.parse_with_emojis(&prefix.msg.content, &emojis),
+            Context::Application(_) => self.parse_with_emojis(&ctx.invocation_string(), &emojis),
+        }
+    }
+    /// Parse an argument of a command, naming custom emoji after the guild of `ctx`
+    pub fn parse_argument<U, E>(
+        &self,
+        ctx: Context<'_, U, E>,
+        s: &str,
+    ) -> Result<Vec<Token>, ParseError> {
+        self.parse_with_emojis(s, &guild_emojis(ctx))
+    }
+}
@@ -0,0 +1,112 @@
+use reqwest::Url;
+use std::net::{IpAddr, Ipv6Addr};
+
+/// Urls images may be downloaded from, so urls given by users or resolvers can't reach
+/// services of the local network
+///
+/// By default only `https` urls of domain names are allowed, not ip addresses or `localhost`.
+/// Names are not resolved, an allowlist of hosts is safer when urls come from users.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UrlPolicy {
+    /// Hosts allowed with their subdomains, any host if empty
+    hosts: Vec<String>,
+    allow_http: bool,
+    allow_private_hosts: bool,
+}
+
+impl UrlPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Only allow `host` and its subdomains, along with the other hosts given
+    pub fn allow_host(mut self, host: impl Into<String>) -> Self {
+        self.hosts.push(host.into().to_ascii_lowercase());
+        self
+    }
+    /// Also allow plain `http` urls
+    pub fn allow_http(mut self, allow_http: bool) -> Self {
+        self.allow_http = allow_http;
+        self
+    }
+    /// Also allow ip addresses and `localhost`, such as a CDN proxy of the local network
+    pub fn allow_private_hosts(mut self, allow_private_hosts: bool) -> Self {
+        self.allow_private_hosts = allow_private_hosts;
+        self
+    }
+    /// Whether images may be downloaded from `url`
+    pub fn allows(&self, url: &str) -> bool {
+        let Ok(url) = Url::parse(url) else {
+            return false;
+        };
+        match url.scheme() {
+            "https" => {}
+            "http" if self.allow_http => {}
+            _ => return false,
+        }
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse() {
+            return self.allows_ip(ip);
+        }
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        if !self.allow_private_hosts && (host == "localhost" || host.ends_with(".localhost")) {
+            return false;
+        }
+        self.hosts.is_empty()
+            || self.hosts.iter().any(|allowed| {
+                host == *allowed
+                    || host
+                        .strip_suffix(allowed.as_str())
+                        .is_some_and(|sub| sub.ends_with('.'))
+            })
+    }
+    /// Addresses are only allowed with private hosts, and never when link-local, as cloud
+    /// metadata services are
+    fn allows_ip(&self, ip: IpAddr) -> bool {
+        let link_local = match ip {
+            IpAddr::V4(ip) => ip.is_link_local(),
+            IpAddr::V6(ip) => is_link_local_v6(ip) || is_link_local_mapped(ip),
+        };
+        self.allow_private_hosts && !link_local
+    }
+}
+
+/// `fe80::/10`
+fn is_link_local_v6(ip: Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xffc0 == 0xfe80
+}
+
+/// `::ffff:169.254.0.0/112`
+fn is_link_local_mapped(ip: Ipv6Addr) -> bool {
+    ip.to_ipv4_mapped().is_some_and(|ip| ip.is_link_local())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_allows() {
+        let policy = UrlPolicy::new();
+        assert!(policy.allows("https://cdn.discordapp.com/emojis/1.webp"));
+        assert!(!policy.allows("http://cdn.discordapp.com/emojis/1.webp"));
+        assert!(!policy.allows("file:///etc/passwd"));
+        assert!(!policy.allows("https://127.0.0.1/a.png"));
+        assert!(!policy.allows("https://[::1]/a.png"));
+        assert!(!policy.allows("https://localhost/a.png"));
+        assert!(!policy.allows("not a url"));
+        let policy = UrlPolicy::new().allow_http(true).allow_private_hosts(true);
+        assert!(policy.allows("http://127.0.0.1:8080/a.png"));
+        assert!(!policy.allows("http://169.254.169.254/latest/meta-data"));
+        assert!(!policy.allows("http://[fe80::1]/a.png"));
+        assert!(!policy.allows("http://[::ffff:169.254.169.254]/a.png"));
+    }
+    #[test]
+    fn test_allow_host() {
+        let policy = UrlPolicy::new().allow_host("discordapp.com");
+        assert!(policy.allows("https://discordapp.com/a.png"));
+        assert!(policy.allows("https://cdn.DiscordApp.com./a.png"));
+        assert!(!policy.allows("https://evildiscordapp.com/a.png"));
+        assert!(!policy.allows("https://discordapp.com.evil.net/a.png"));
+    }
+}
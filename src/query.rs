@@ -0,0 +1,275 @@
+use crate::{Parser, ParserOptions, RawTokens, Token, TokenRef};
+use std::sync::OnceLock;
+
+/// Most emoji Discord shows big in a message holding only emoji
+pub const JUMBO_THRESHOLD: usize = 30;
+
+/// Size Discord shows big emoji at, in pixels
+const JUMBO_SIZE: u32 = 48;
+
+/// Whether the tokens hold emoji and nothing but whitespace between them, such as the
+/// messages chat clients show with big emoji
+///
+/// Unicode and custom emoji count, spoilers count when they only hold emoji.
+pub fn is_emoji_only(tokens: &[Token]) -> bool {
+    emoji_count(tokens).is_some_and(|count| count > 0)
+}
+
+/// Size to draw the emoji of the tokens at, in pixels, if they are only emoji and at most
+/// `threshold` of them, as Discord does below [`JUMBO_THRESHOLD`]
+pub fn jumbo_size(tokens: &[Token], threshold: usize) -> Option<u32> {
+    emoji_count(tokens)
+        .filter(|count| (1..=threshold).contains(count))
+        .map(|_| JUMBO_SIZE)
+}
+
+/// Whether `s` holds a unicode or custom emoji, without parsing the rest of it
+pub fn contains_emoji(s: &str) -> bool {
+    count_matching(RawTokens::unchecked(s, query_parser()), &is_emoji_ref, 1) > 0
+}
+
+/// Number of unicode emoji in `s`, including those in spoilers and code, without collecting tokens
+pub fn count_emoji(s: &str) -> usize {
+    let unicode = |token: &TokenRef| matches!(token, TokenRef::Emoji { .. });
+    count_matching(
+        RawTokens::unchecked(s, query_parser()),
+        &unicode,
+        usize::MAX,
+    )
+}
+
+/// Number of custom emoji in `s`, including those in spoilers, without collecting tokens
+#[cfg(feature = "custom-emoji")]
+pub fn count_custom_emoji(s: &str) -> usize {
+    let custom = |token: &TokenRef| !matches!(token, TokenRef::Emoji { .. }) && is_emoji_ref(token);
+    count_matching(RawTokens::unchecked(s, query_parser()), &custom, usize::MAX)
+}
+
+/// `s` without its unicode emoji, whole sequences such as `👨‍👩‍👧` and `👍🏽` included
+///
+/// Everything else is kept as written, markup included. Emoji in code and escaped emoji are
+/// stripped too, as markup isn't parsed.
+pub fn strip_emoji(s: &str) -> String {
+    static PARSER: OnceLock<Parser> = OnceLock::new();
+    strip(
+        s,
+        PARSER.get_or_init(|| Parser::with_options(text_options())),
+    )
+}
+
+/// `s` without its unicode emoji and custom emoji markup such as `<:name:id>`
+#[cfg(feature = "discord")]
+pub fn strip_all_emoji(s: &str) -> String {
+    static PARSER: OnceLock<Parser> = OnceLock::new();
+    let parser = PARSER.get_or_init(|| {
+        Parser::with_options(ParserOptions {
+            custom_emoji: true,
+            ..text_options()
+        })
+    });
+    strip(s, parser)
+}
+
+/// Options of a parser finding nothing but unicode emoji, so the rest is text of the input
+fn text_options() -> ParserOptions {
+    ParserOptions {
+        #[cfg(feature = "shortcode")]
+        shortcodes: false,
+        #[cfg(feature = "discord")]
+        custom_emoji: false,
+        #[cfg(feature = "discord")]
+        mentions: false,
+        #[cfg(feature = "discord")]
+        timestamps: false,
+        #[cfg(feature = "discord")]
+        code: false,
+        #[cfg(feature = "discord")]
+        spoilers: false,
+        #[cfg(feature = "discord")]
+        escaped_emoji: false,
+        ..ParserOptions::default()
+    }
+}
+
+/// Parser of the queries, finding the emoji [`strip_emoji`] and [`strip_all_emoji`] strip,
+/// those in code and escaped ones included
+fn query_parser() -> &'static Parser {
+    static PARSER: OnceLock<Parser> = OnceLock::new();
+    PARSER.get_or_init(|| {
+        Parser::with_options(ParserOptions {
+            #[cfg(feature = "discord")]
+            custom_emoji: true,
+            #[cfg(feature = "discord")]
+            spoilers: true,
+            ..text_options()
+        })
+    })
+}
+
+/// Text of `s` as tokenized by `parser`, without its other tokens
+fn strip(s: &str, parser: &Parser) -> String {
+    RawTokens::unchecked(s, parser)
+        .filter_map(|token| match token {
+            TokenRef::Text(text) => Some(text),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Number of tokens matching `filter`, up to `limit`
+fn count_matching(
+    tokens: RawTokens<'_, '_>,
+    filter: &impl Fn(&TokenRef) -> bool,
+    limit: usize,
+) -> usize {
+    let mut count = 0;
+    for token in tokens {
+        count += match token {
+            #[cfg(feature = "discord")]
+            TokenRef::Spoiler(s) => {
+                count_matching(RawTokens::spoiler(s, query_parser()), filter, limit - count)
+            }
+            token => filter(&token) as usize,
+        };
+        if count >= limit {
+            break;
+        }
+    }
+    count
+}
+
+/// Whether a borrowed token is a unicode or custom emoji
+fn is_emoji_ref(token: &TokenRef) -> bool {
+    match token {
+        TokenRef::Emoji { .. } => true,
+        #[cfg(feature = "custom-emoji")]
+        TokenRef::CustomEmoji { .. } => true,
+        #[cfg(feature = "named-custom-emoji")]
+        TokenRef::NamedCustomEmoji { .. } => true,
+        _ => false,
+    }
+}
+
+/// Number of emoji in the tokens, `None` if anything else but whitespace is in them
+fn emoji_count(tokens: &[Token]) -> Option<usize> {
+    tokens.iter().try_fold(0, |count, token| {
+        let emoji = match token {
+            Token::Text(s) if s.trim().is_empty() => 0,
+            #[cfg(feature = "discord")]
+            Token::Spoiler(tokens) => emoji_count(tokens)?,
+            token if is_emoji(token) => 1,
+            _ => return None,
+        };
+        Some(count + emoji)
+    })
+}
+
+/// Whether a token is a unicode or custom emoji
+fn is_emoji(token: &Token) -> bool {
+    match token {
+        Token::Emoji { .. } => true,
+        #[cfg(feature = "emoji-url")]
+        Token::EmojiUrl { .. } => true,
+        #[cfg(feature = "svg")]
+        Token::EmojiSvg { .. } => true,
+        #[cfg(feature = "custom-emoji")]
+        Token::CustomEmoji { .. } | Token::FetchedCustomEmoji { .. } => true,
+        #[cfg(feature = "named-custom-emoji")]
+        Token::NamedCustomEmoji { .. } => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_is_emoji_only() {
+        let only = |s| is_emoji_only(&crate::Parser::new().parse(s).unwrap());
+        assert!(only("👍"));
+        assert!(only(" 👍\n😀 👨‍👩‍👧 "));
+        assert!(!only("👍 a"));
+        assert!(!only(" "));
+        assert!(!only(""));
+    }
+    #[test]
+    fn test_jumbo_size() {
+        let jumbo = |s, threshold| jumbo_size(&crate::Parser::new().parse(s).unwrap(), threshold);
+        assert_eq!(jumbo("👍 😀", JUMBO_THRESHOLD), Some(48));
+        assert_eq!(jumbo("👍 😀", 2), Some(48));
+        assert_eq!(jumbo("👍 😀 🔥", 2), None);
+        let many = "👍".repeat(31);
+        assert_eq!(jumbo(&many, JUMBO_THRESHOLD), None);
+        assert_eq!(jumbo("hi 👍", JUMBO_THRESHOLD), None);
+        assert_eq!(jumbo("", JUMBO_THRESHOLD), None);
+    }
+    #[test]
+    fn test_count_emoji() {
+        assert!(contains_emoji("hi 👍"));
+        assert!(!contains_emoji("hi :)"));
+        assert_eq!(count_emoji("👍 😀👨‍👩‍👧 a 🇯🇵"), 4);
+        assert_eq!(count_emoji(""), 0);
+    }
+    #[test]
+    #[cfg(feature = "discord")]
+    fn test_count_emoji_discord() {
+        let s = "<:blobcat:123456789012345678> ||👍 <a:party:234567890123456789>|| `😀`";
+        assert!(contains_emoji("<:blobcat:123456789012345678>"));
+        assert!(contains_emoji("`<:blobcat:123456789012345678>`"));
+        assert!(contains_emoji(r"\👍"));
+        assert_eq!(count_emoji(s), 2);
+        assert_eq!(count_custom_emoji(s), 2);
+    }
+    #[test]
+    fn test_strip_emoji() {
+        assert_eq!(strip_emoji("hi 👨‍👩‍👧👍🏽 there🇯🇵!"), "hi  there!");
+        assert_eq!(strip_emoji("1️⃣ #️⃣ # a"), "  # a");
+        assert_eq!(strip_emoji(":smile: :)"), ":smile: :)");
+    }
+    #[test]
+    #[cfg(feature = "shortcode")]
+    fn test_count_shortcodes() {
+        assert!(!contains_emoji(":smile: :)"));
+        assert_eq!(count_emoji(":smile: 👍 :thumbsup:"), 1);
+        let parsed = crate::Parser::new().parse(":smile:").unwrap();
+        assert!(matches!(parsed[..], [Token::Emoji { .. }]));
+    }
+    #[test]
+    #[cfg(feature = "discord")]
+    fn test_strip_all_emoji() {
+        let s = r"<:blobcat:123456789012345678>👍 <@123456789012345678> ||a😀|| `b🔥` \👍";
+        assert_eq!(
+            strip_emoji(s),
+            r"<:blobcat:123456789012345678> <@123456789012345678> ||a|| `b` \"
+        );
+        assert_eq!(strip_all_emoji(s), r" <@123456789012345678> ||a|| `b` \");
+    }
+    #[test]
+    fn test_count_matches_strip() {
+        let inputs = [
+            "hi 👍🏽",
+            ":smile: :)",
+            "`😀` a",
+            r"\👍",
+            "||🔥||",
+            "```\n👨‍👩‍👧```",
+            "<:blobcat:123456789012345678>",
+            "`<:blobcat:123456789012345678>`",
+        ];
+        for s in inputs {
+            let stripped = strip_emoji(s);
+            assert_eq!(count_emoji(&stripped), 0, "{s}");
+            assert_eq!(count_emoji(s) == 0, stripped == s, "{s}");
+            #[cfg(feature = "discord")]
+            assert_eq!(contains_emoji(s), strip_all_emoji(s) != s, "{s}");
+        }
+    }
+    #[test]
+    #[cfg(feature = "discord")]
+    fn test_is_emoji_only_discord() {
+        let only = |s| is_emoji_only(&crate::Parser::new().parse(s).unwrap());
+        assert!(only("<:blobcat:123456789012345678> ||👍||"));
+        assert!(!only("||spoiler|| 👍"));
+        assert!(!only("<@123456789012345678> 👍"));
+    }
+}
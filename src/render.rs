@@ -0,0 +1,447 @@
+//! Rendering of tokens to other formats
+
+use crate::emoji_url::emoji_of;
+use crate::{ParserOptions, Token};
+#[cfg(feature = "discord")]
+use std::fmt::Write;
+
+#[cfg(feature = "avif")]
+mod avif;
+#[cfg(feature = "render-image")]
+mod compose;
+#[cfg(feature = "render-image")]
+mod layout;
+#[cfg(feature = "tgs")]
+mod lottie;
+#[cfg(feature = "sprite")]
+mod sprite;
+#[cfg(feature = "render-image")]
+pub use crate::png::RgbaImage;
+#[cfg(feature = "avif")]
+pub use avif::{to_avif, AvifEncoder, AvifOptions};
+#[cfg(feature = "render-image")]
+pub use compose::{
+    to_animation, to_frames, to_image, AnimationFormat, Font, Glyph, RenderOptions, RenderTheme,
+};
+#[cfg(feature = "render-image")]
+pub use layout::Align;
+#[cfg(feature = "tgs")]
+pub use lottie::{lottie_to_frames, tgs_to_lottie, LottieRenderer};
+#[cfg(feature = "sprite")]
+pub use sprite::{to_sprite_sheet, Sprite, SpriteOptions, SpriteSheet};
+
+/// Options of the html renderer
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HtmlOptions {
+    /// Embed the images tokens carry as `data:` uris instead of linking to them, for html
+    /// that doesn't load anything such as emails and offline archives
+    pub data_uris: bool,
+}
+
+/// Render tokens to html, emoji become `<img class="emoji">` and text is escaped
+///
+/// Unicode emoji link to the twemoji CDN, with the emoji as alt text. Custom emoji use their
+/// url and `:name:` as alt text, or their text when they have no url.
+pub fn to_html(tokens: &[Token]) -> String {
+    to_html_with(tokens, &HtmlOptions::default())
+}
+
+/// Render tokens to html with options
+pub fn to_html_with(tokens: &[Token], options: &HtmlOptions) -> String {
+    let mut html = String::new();
+    tokens
+        .iter()
+        .for_each(|token| push_html(token, options, &mut html));
+    html
+}
+
+fn push_html(token: &Token, options: &HtmlOptions, out: &mut String) {
+    match token {
+        Token::Text(s) => escape(s, out),
+        Token::Emoji { emoji, image } => match emoji_of(image).filter(|_| !options.data_uris) {
+            Some(twemoji) => push_img(&ParserOptions::default().twemoji_url(twemoji), emoji, out),
+            // Images the index doesn't know are embedded
+            None => push_img(&data_uri("image/png", image), emoji, out),
+        },
+        #[cfg(feature = "svg")]
+        Token::EmojiSvg { emoji, svg } => match options.data_uris {
+            true => push_img(&data_uri("image/svg+xml", svg.as_bytes()), emoji, out),
+            false => push_img(&svg_url(emoji), emoji, out),
+        },
+        Token::EmojiUrl { emoji, url } => match crate::get(emoji).filter(|_| options.data_uris) {
+            Some(png) => push_img(&data_uri("image/png", png), emoji, out),
+            None => push_img(url, emoji, out),
+        },
+        #[cfg(feature = "custom-emoji")]
+        Token::FetchedCustomEmoji { name, image, .. } if options.data_uris => {
+            let mime = mime_of(image).unwrap_or("application/octet-stream");
+            push_img(&data_uri(mime, image), &format!(":{}:", name), out)
+        }
+        #[cfg(feature = "custom-emoji")]
+        Token::CustomEmoji { name, .. } | Token::FetchedCustomEmoji { name, .. } => {
+            let alt = format!(":{}:", name);
+            #[cfg(feature = "discord")]
+            if let Some(url) = token.url() {
+                return push_img(&url, &alt, out);
+            }
+            escape(&alt, out)
+        }
+        #[cfg(feature = "discord")]
+        Token::Mention(mention) => {
+            let prefix = match mention {
+                crate::Mention::User(_) => "@",
+                crate::Mention::Channel(_) => "#",
+                crate::Mention::Role(_) => "@&",
+            };
+            let _ = write!(
+                out,
+                r#"<span class="mention">{}{}</span>"#,
+                prefix,
+                mention.id()
+            );
+        }
+        #[cfg(feature = "discord")]
+        Token::CommandMention { name, .. } => {
+            out.push_str(r#"<span class="mention">/"#);
+            escape(name, out);
+            out.push_str("</span>");
+        }
+        #[cfg(feature = "discord")]
+        Token::Code(code) => {
+            out.push_str("<code>");
+            escape(code, out);
+            out.push_str("</code>");
+        }
+        #[cfg(feature = "discord")]
+        Token::CodeBlock { language, code } => {
+            out.push_str("<pre><code");
+            if let Some(language) = language {
+                out.push_str(r#" class="language-"#);
+                escape(language, out);
+                out.push('"');
+            }
+            out.push('>');
+            escape(code, out);
+            out.push_str("</code></pre>");
+        }
+        #[cfg(feature = "discord")]
+        Token::Spoiler(tokens) => {
+            out.push_str(r#"<span class="spoiler">"#);
+            tokens
+                .iter()
+                .for_each(|token| push_html(token, options, out));
+            out.push_str("</span>");
+        }
+        #[cfg(feature = "discord")]
+        Token::Timestamp { unix, style } => {
+            let _ = write!(
+                out,
+                r#"<time data-unix="{}" data-style="{}">{}</time>"#,
+                unix,
+                style.as_char(),
+                unix
+            );
+        }
+        #[cfg(feature = "discord")]
+        // Lottie stickers need a player, their name is shown instead
+        Token::Sticker { name, format, .. } => match format {
+            crate::StickerFormat::Lottie => escape(name, out),
+            _ => push_img(&token.url().unwrap_or_default(), name, out),
+        },
+        #[cfg(feature = "named-custom-emoji")]
+        Token::NamedCustomEmoji { name, url } => push_img(url, &format!(":{}:", name), out),
+    }
+}
+
+/// Render tokens to markdown, emoji become `![alt](url)` images and text is escaped
+///
+/// Images are the same as [`to_html`], spoilers are shown and mentions become `@id`.
+pub fn to_markdown(tokens: &[Token]) -> String {
+    let mut markdown = String::new();
+    tokens
+        .iter()
+        .for_each(|token| push_markdown(token, &mut markdown));
+    markdown
+}
+
+fn push_markdown(token: &Token, out: &mut String) {
+    match token {
+        Token::Text(s) => escape_markdown(s, out),
+        Token::Emoji { emoji, image } => match emoji_of(image) {
+            Some(twemoji) => push_image(&ParserOptions::default().twemoji_url(twemoji), emoji, out),
+            None => push_image(&data_uri("image/png", image), emoji, out),
+        },
+        #[cfg(feature = "svg")]
+        Token::EmojiSvg { emoji, .. } => push_image(&svg_url(emoji), emoji, out),
+        Token::EmojiUrl { emoji, url } => push_image(url, emoji, out),
+        #[cfg(feature = "custom-emoji")]
+        Token::CustomEmoji { name, .. } | Token::FetchedCustomEmoji { name, .. } => {
+            let alt = format!(":{}:", name);
+            #[cfg(feature = "discord")]
+            if let Some(url) = token.url() {
+                return push_image(&url, &alt, out);
+            }
+            escape_markdown(&alt, out)
+        }
+        #[cfg(feature = "discord")]
+        Token::Mention(mention) => {
+            let prefix = match mention {
+                crate::Mention::User(_) => "@",
+                crate::Mention::Channel(_) => "\\#",
+                crate::Mention::Role(_) => "@&",
+            };
+            let _ = write!(out, "{}{}", prefix, mention.id());
+        }
+        #[cfg(feature = "discord")]
+        Token::CommandMention { name, .. } => {
+            out.push('/');
+            escape_markdown(name, out);
+        }
+        #[cfg(feature = "discord")]
+        Token::Code(code) => {
+            // A fence longer than the backticks of the code
+            let fence = "`".repeat(longest_run(code, '`') + 1);
+            let pad = if code.starts_with('`') || code.ends_with('`') {
+                " "
+            } else {
+                ""
+            };
+            let _ = write!(out, "{0}{1}{2}{1}{0}", fence, pad, code);
+        }
+        #[cfg(feature = "discord")]
+        Token::CodeBlock { language, code } => {
+            let fence = "`".repeat(longest_run(code, '`').max(2) + 1);
+            let code = code.strip_suffix('\n').unwrap_or(code);
+            let language = language.as_deref().unwrap_or("");
+            let _ = write!(out, "{0}{1}\n{2}\n{0}", fence, language, code);
+        }
+        #[cfg(feature = "discord")]
+        Token::Spoiler(tokens) => tokens.iter().for_each(|token| push_markdown(token, out)),
+        #[cfg(feature = "discord")]
+        Token::Timestamp { unix, .. } => {
+            let _ = write!(out, "{}", unix);
+        }
+        #[cfg(feature = "discord")]
+        Token::Sticker { name, format, .. } => match format {
+            crate::StickerFormat::Lottie => escape_markdown(name, out),
+            _ => push_image(&token.url().unwrap_or_default(), name, out),
+        },
+        #[cfg(feature = "named-custom-emoji")]
+        Token::NamedCustomEmoji { name, url } => push_image(url, &format!(":{}:", name), out),
+    }
+}
+
+/// Url of the twemoji svg of `emoji`
+#[cfg(feature = "svg")]
+fn svg_url(emoji: &str) -> String {
+    let options = ParserOptions {
+        twemoji_size: "svg".to_string(),
+        ..ParserOptions::default()
+    };
+    options.twemoji_url(emoji)
+}
+
+fn push_image(url: &str, alt: &str, out: &mut String) {
+    out.push_str("![");
+    escape_markdown(alt, out);
+    out.push_str("](<");
+    for c in url.chars() {
+        match c {
+            '<' => out.push_str("%3C"),
+            '>' => out.push_str("%3E"),
+            c => out.push(c),
+        }
+    }
+    out.push_str(">)");
+}
+
+/// Escape the punctuation markdown gives a meaning to
+fn escape_markdown(s: &str, out: &mut String) {
+    for c in s.chars() {
+        if "\\`*_{}[]<>()#+-!|~".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+/// Length of the longest run of `c` in `s`
+#[cfg(feature = "discord")]
+fn longest_run(s: &str, c: char) -> usize {
+    s.split(|x| x != c).map(str::len).max().unwrap_or(0)
+}
+
+fn push_img(src: &str, alt: &str, out: &mut String) {
+    out.push_str(r#"<img class="emoji" src=""#);
+    escape(src, out);
+    out.push_str(r#"" alt=""#);
+    escape(alt, out);
+    out.push_str(r#"">"#);
+}
+
+/// Escape text and attribute values
+fn escape(s: &str, out: &mut String) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Mime type of an image from its first bytes
+#[cfg(feature = "custom-emoji")]
+fn mime_of(image: &[u8]) -> Option<&'static str> {
+    if image.starts_with(b"\x89PNG") {
+        Some("image/png")
+    } else if image.starts_with(b"GIF8") {
+        Some("image/gif")
+    } else if image.len() >= 12 && image.starts_with(b"RIFF") && &image[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+/// `data:` uri of `bytes`, base64 encoded
+fn data_uri(mime: &str, bytes: &[u8]) -> String {
+    format!("data:{};base64,{}", mime, crate::base64::encode(bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_to_html() {
+        let tokens = crate::parse("<b>hi</b> 😀 ❤️").unwrap();
+        assert_eq!(
+            to_html(&tokens),
+            format!(
+                r#"&lt;b&gt;hi&lt;/b&gt; <img class="emoji" src="{0}1f600.png" alt="😀"> <img class="emoji" src="{0}2764.png" alt="❤️">"#,
+                "https://cdn.jsdelivr.net/gh/jdecked/twemoji@latest/assets/72x72/"
+            )
+        );
+        assert_eq!(
+            to_html(&[Token::new_emoji("😀", b"\x89PNG".to_vec())]),
+            r#"<img class="emoji" src="data:image/png;base64,iVBORw==" alt="😀">"#
+        );
+        // Alt text is the emoji as written, even when its image is of another spelling
+        let texas = "🏴\u{e0075}\u{e0073}\u{e0074}\u{e0078}\u{e007f}";
+        let tokens = crate::parse(format!("☀ {}", texas)).unwrap();
+        assert_eq!(
+            to_html(&tokens),
+            format!(
+                r#"<img class="emoji" src="{0}2600.png" alt="☀"> <img class="emoji" src="{0}1f3f4.png" alt="{1}">"#,
+                "https://cdn.jsdelivr.net/gh/jdecked/twemoji@latest/assets/72x72/", texas
+            )
+        );
+    }
+    #[test]
+    fn test_to_markdown() {
+        let tokens = crate::parse("*hi* [a](b) 😀").unwrap();
+        assert_eq!(
+            to_markdown(&tokens),
+            "\\*hi\\* \\[a\\]\\(b\\) ![😀](<https://cdn.jsdelivr.net/gh/jdecked/twemoji@latest/assets/72x72/1f600.png>)"
+        );
+    }
+    #[test]
+    #[cfg(feature = "discord")]
+    fn test_to_markdown_discord() {
+        let tokens =
+            crate::parse("<a:blob_cat:123456789012345678> `` a`b `` ||<#234567890123456789>||")
+                .unwrap();
+        assert_eq!(
+            to_markdown(&tokens),
+            concat!(
+                "![:blob\\_cat:](<https://cdn.discordapp.com/emojis/123456789012345678.gif?size=96>)",
+                " `` a`b `` \\#234567890123456789"
+            )
+        );
+        let block = Token::CodeBlock {
+            language: Some("rust".to_string()),
+            code: "let a = \"```\";\n".to_string(),
+        };
+        assert_eq!(to_markdown(&[block]), "````rust\nlet a = \"```\";\n````");
+    }
+    #[test]
+    fn test_data_uris() {
+        let options = HtmlOptions { data_uris: true };
+        let png = crate::get("😀").unwrap();
+        assert_eq!(
+            to_html_with(&[Token::new_emoji("😀", png)], &options),
+            format!(
+                r#"<img class="emoji" src="{}" alt="😀">"#,
+                data_uri("image/png", png)
+            )
+        );
+    }
+    #[test]
+    #[cfg(feature = "discord")]
+    fn test_data_uris_discord() {
+        let options = HtmlOptions { data_uris: true };
+        let fetched = Token::FetchedCustomEmoji {
+            id: 123456789012345678,
+            name: "blobcat".to_string(),
+            animated: true,
+            image: b"GIF89a".to_vec(),
+        };
+        assert_eq!(
+            to_html_with(&[fetched], &options),
+            r#"<img class="emoji" src="data:image/gif;base64,R0lGODlh" alt=":blobcat:">"#
+        );
+        // Without bytes the url is kept
+        let tokens = crate::parse("<:blobcat:123456789012345678>").unwrap();
+        assert_eq!(to_html_with(&tokens, &options), to_html(&tokens));
+    }
+    #[test]
+    #[cfg(feature = "discord")]
+    fn test_to_html_discord() {
+        let tokens =
+            crate::parse("<:blobcat:123456789012345678> `a<b` ||<@234567890123456789>||").unwrap();
+        assert_eq!(
+            to_html(&tokens),
+            concat!(
+                r#"<img class="emoji" src="https://cdn.discordapp.com/emojis/123456789012345678.png?size=96" alt=":blobcat:">"#,
+                r#" <code>a&lt;b</code> <span class="spoiler"><span class="mention">@234567890123456789</span></span>"#
+            )
+        );
+        let stickers = [crate::StickerFormat::Png, crate::StickerFormat::Lottie].map(|format| {
+            Token::Sticker {
+                id: 345678901234567890,
+                name: "wave".to_string(),
+                format,
+            }
+        });
+        assert_eq!(
+            to_html(&stickers),
+            r#"<img class="emoji" src="https://cdn.discordapp.com/stickers/345678901234567890.png" alt="wave">wave"#
+        );
+        assert_eq!(
+            to_markdown(&stickers[..1]),
+            "![wave](<https://cdn.discordapp.com/stickers/345678901234567890.png>)"
+        );
+    }
+    #[test]
+    #[cfg(feature = "svg")]
+    fn test_to_html_svg() {
+        let options = ParserOptions::builder().svg_emoji(true).build();
+        let tokens = crate::Parser::with_options(options).parse("👍").unwrap();
+        assert_eq!(
+            to_html(&tokens),
+            r#"<img class="emoji" src="https://cdn.jsdelivr.net/gh/jdecked/twemoji@latest/assets/svg/1f44d.svg" alt="👍">"#
+        );
+        let html = to_html_with(&tokens, &HtmlOptions { data_uris: true });
+        assert!(html.contains(r#"src="data:image/svg+xml;base64,"#));
+    }
+    #[test]
+    fn test_data_uri() {
+        assert_eq!(data_uri("a/b", b""), "data:a/b;base64,");
+        assert_eq!(data_uri("a/b", b"f"), "data:a/b;base64,Zg==");
+        assert_eq!(data_uri("a/b", b"fo"), "data:a/b;base64,Zm8=");
+        assert_eq!(data_uri("a/b", b"foobar"), "data:a/b;base64,Zm9vYmFy");
+    }
+}
@@ -0,0 +1,82 @@
+use super::compose::{to_image, RenderOptions};
+use crate::png::RgbaImage;
+use crate::Token;
+
+/// Avif encoder over an AV1 library such as `ravif`, which are too large to bundle
+pub trait AvifEncoder {
+    /// Encode `image` with the quality and speed of `options`
+    fn encode(&self, image: &RgbaImage, options: &AvifOptions) -> Vec<u8>;
+}
+
+/// Quality and speed of an avif encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AvifOptions {
+    /// Quality of colors from 0 to 100, lower gives smaller files
+    pub quality: u8,
+    /// Quality of transparency from 0 to 100
+    pub alpha_quality: u8,
+    /// Speed from 1, the slowest and smallest, to 10
+    pub speed: u8,
+}
+
+impl Default for AvifOptions {
+    fn default() -> Self {
+        Self {
+            quality: 80,
+            alpha_quality: 80,
+            speed: 6,
+        }
+    }
+}
+
+/// Draw tokens like [`to_image`] and encode the image as an avif with `encoder`
+pub fn to_avif(
+    tokens: &[Token],
+    options: &RenderOptions,
+    avif: &AvifOptions,
+    encoder: &impl AvifEncoder,
+) -> Vec<u8> {
+    encoder.encode(&to_image(tokens, options), avif)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::{Font, Glyph};
+    struct Blank;
+    impl Font for Blank {
+        fn line_metrics(&self, _size: f32) -> (f32, f32) {
+            (8.0, 2.0)
+        }
+        fn glyph(&self, _c: char, _size: f32) -> Glyph {
+            Glyph {
+                advance: 5.0,
+                ..Glyph::default()
+            }
+        }
+    }
+    /// Size, quality and speed instead of an image
+    struct Fake;
+    impl AvifEncoder for Fake {
+        fn encode(&self, image: &RgbaImage, options: &AvifOptions) -> Vec<u8> {
+            vec![
+                image.width as u8,
+                image.height as u8,
+                options.quality,
+                options.speed,
+            ]
+        }
+    }
+    #[test]
+    fn test_to_avif() {
+        let options = RenderOptions::new(Blank);
+        let avif = AvifOptions {
+            quality: 50,
+            ..AvifOptions::default()
+        };
+        assert_eq!(
+            to_avif(&[Token::new_text("ab")], &options, &avif, &Fake),
+            [10, 10, 50, 6]
+        );
+    }
+}
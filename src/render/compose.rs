@@ -0,0 +1,565 @@
+use super::layout::{layout, Align, Item, Layout, Piece, Placed};
+#[cfg(feature = "tgs")]
+use super::lottie::LottieRenderer;
+use crate::gif;
+use crate::image::decode_frames;
+use crate::png::{self, RgbaImage};
+use crate::Token;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Source of glyphs for the image renderer, implemented over a font library such as
+/// `fontdue` or `ab_glyph`
+///
+/// Fonts backed by a shaping engine such as `rustybuzz` or `cosmic-text` also implement
+/// [`Font::shape`], so scripts like Arabic and combining marks are drawn right.
+pub trait Font {
+    /// Ascent and descent of a line at `size` in pixels, both positive
+    fn line_metrics(&self, size: f32) -> (f32, f32);
+    /// Rasterize `c` at `size` in pixels
+    fn glyph(&self, c: char, size: f32) -> Glyph;
+    /// Shape and rasterize a word, giving its glyphs from left to right with their offsets
+    /// in `left` and `top`
+    ///
+    /// Text is shaped a word at a time, between spaces and emoji. By default each character
+    /// is a glyph of its own.
+    fn shape(&self, word: &str, size: f32) -> Vec<Glyph> {
+        word.chars().map(|c| self.glyph(c, size)).collect()
+    }
+}
+
+/// Glyph rasterized by a [`Font`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Glyph {
+    pub width: u32,
+    pub height: u32,
+    /// Coverage of each pixel, row by row
+    pub coverage: Vec<u8>,
+    /// Offset of the bitmap right of the pen
+    pub left: i32,
+    /// Offset of the top of the bitmap above the baseline
+    pub top: i32,
+    /// Distance the pen moves after the glyph
+    pub advance: f32,
+}
+
+/// Look of rendered images, to match the branding of a bot
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderTheme {
+    /// Rgba color behind the text, transparent by default
+    pub background: [u8; 4],
+    /// Rgba color of text
+    pub color: [u8; 4],
+    /// Size of the font in pixels
+    pub font_size: f32,
+    /// Space around the text in pixels
+    pub padding: u32,
+    /// Size of emoji relative to the height of a line, larger emoji make their lines higher
+    pub emoji_scale: f32,
+    /// Radius of the corners of the image in pixels
+    pub corner_radius: u32,
+}
+
+impl Default for RenderTheme {
+    fn default() -> Self {
+        Self {
+            background: [0; 4],
+            color: [0, 0, 0, 255],
+            font_size: 16.0,
+            padding: 0,
+            emoji_scale: 1.0,
+            corner_radius: 0,
+        }
+    }
+}
+
+/// Options of the image renderer
+#[derive(Clone)]
+pub struct RenderOptions {
+    pub(super) font: Arc<dyn Font + Send + Sync>,
+    pub(super) theme: RenderTheme,
+    pub(super) max_width: Option<u32>,
+    pub(super) line_spacing: f32,
+    pub(super) align: Align,
+    pub(super) max_frames: usize,
+    pub(super) max_fps: u32,
+    #[cfg(feature = "tgs")]
+    pub(super) lottie: Option<Arc<dyn LottieRenderer + Send + Sync>>,
+}
+
+impl fmt::Debug for RenderOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RenderOptions")
+            .field("theme", &self.theme)
+            .field("max_width", &self.max_width)
+            .field("line_spacing", &self.line_spacing)
+            .field("align", &self.align)
+            .field("max_frames", &self.max_frames)
+            .field("max_fps", &self.max_fps)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RenderOptions {
+    /// Black text of `font` at 16 pixels on a transparent background
+    pub fn new(font: impl Font + Send + Sync + 'static) -> Self {
+        Self {
+            font: Arc::new(font),
+            theme: RenderTheme::default(),
+            max_width: None,
+            line_spacing: 1.0,
+            align: Align::Left,
+            max_frames: 100,
+            max_fps: 25,
+            #[cfg(feature = "tgs")]
+            lottie: None,
+        }
+    }
+    pub fn theme(mut self, theme: RenderTheme) -> Self {
+        self.theme = theme;
+        self
+    }
+    /// Size of the font in pixels, emoji are as large as a line
+    pub fn font_size(mut self, font_size: f32) -> Self {
+        self.theme.font_size = font_size;
+        self
+    }
+    /// Rgba color of text
+    pub fn color(mut self, color: [u8; 4]) -> Self {
+        self.theme.color = color;
+        self
+    }
+    /// Wrap lines at spaces and around emoji to fit in `max_width` pixels, words wider than
+    /// that are split
+    pub fn max_width(mut self, max_width: u32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+    /// Distance between the tops of lines, relative to their height
+    pub fn line_spacing(mut self, line_spacing: f32) -> Self {
+        self.line_spacing = line_spacing;
+        self
+    }
+    /// Alignment of lines narrower than the widest one
+    pub fn align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+    /// Most frames of an animation, later ones are dropped
+    pub fn max_frames(mut self, max_frames: usize) -> Self {
+        self.max_frames = max_frames.max(1);
+        self
+    }
+    /// Most frames of an animation per second, frames of emoji shown shorter are skipped
+    pub fn max_fps(mut self, max_fps: u32) -> Self {
+        self.max_fps = max_fps.max(1);
+        self
+    }
+    /// Frames of an emoji image, tgs ones drawn as Lottie animations
+    fn frames_of(&self, image: &[u8]) -> Option<Vec<(RgbaImage, Duration)>> {
+        let frames = decode_frames(image);
+        #[cfg(feature = "tgs")]
+        let frames = frames.or_else(|| super::lottie::frames(image, self));
+        frames
+    }
+    /// Draw tgs custom emoji, such as Telegram premium emoji, with `renderer` instead of the
+    /// bundled one
+    #[cfg(feature = "tgs")]
+    pub fn lottie(mut self, renderer: impl LottieRenderer + Send + Sync + 'static) -> Self {
+        self.lottie = Some(Arc::new(renderer));
+        self
+    }
+}
+
+/// Format of animated images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AnimationFormat {
+    #[default]
+    Apng,
+    /// Gif, with colors reduced to a 6x6x6 cube
+    Gif,
+}
+
+/// Draw tokens as text on an image with the theme of `options`, with emoji and custom emoji
+/// images sitting on the lines as squares
+///
+/// Custom emoji without png or gif bytes are drawn as `:name:`, animated ones show their
+/// first frame.
+pub fn to_image(tokens: &[Token], options: &RenderOptions) -> RgbaImage {
+    layout(items_of(tokens, options), options).draw(&options.theme, Duration::ZERO)
+}
+
+/// Draw tokens like [`to_image`], as frames with the time each is shown, animating animated
+/// custom emoji together
+///
+/// The animation lasts as long as the longest emoji animation, shorter ones loop within it.
+/// A message without animated emoji is a single frame.
+pub fn to_frames(tokens: &[Token], options: &RenderOptions) -> Vec<(RgbaImage, Duration)> {
+    let layout = layout(items_of(tokens, options), options);
+    let animations = layout
+        .items
+        .iter()
+        .filter_map(|placed| match &placed.piece {
+            Piece::Frames(frames) if frames.len() > 1 => Some(frames),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let length = animations
+        .iter()
+        .map(|frames| frames.iter().map(|(_, delay)| *delay).sum())
+        .max()
+        .unwrap_or(Duration::ZERO);
+    // Times any emoji changes frame
+    let mut times = vec![Duration::ZERO];
+    for frames in &animations {
+        let mut time = Duration::ZERO;
+        'animation: loop {
+            for (_, delay) in frames.iter() {
+                time += *delay;
+                if time >= length {
+                    break 'animation;
+                }
+                times.push(time);
+            }
+        }
+    }
+    times.sort_unstable();
+    let interval = Duration::from_secs(1) / options.max_fps;
+    let mut kept: Vec<Duration> = Vec::new();
+    for time in times {
+        if kept.last().is_none_or(|last| time - *last >= interval) {
+            kept.push(time);
+        }
+    }
+    kept.truncate(options.max_frames);
+    let ends = kept.iter().skip(1).copied().chain([length]);
+    kept.iter()
+        .zip(ends)
+        .map(|(start, end)| {
+            (
+                layout.draw(&options.theme, *start),
+                end.saturating_sub(*start),
+            )
+        })
+        .collect()
+}
+
+/// Draw tokens like [`to_frames`], encoded as an animated image, `None` if it is too large
+/// for the format, such as gifs over 65535 pixels wide
+pub fn to_animation(
+    tokens: &[Token],
+    options: &RenderOptions,
+    format: AnimationFormat,
+) -> Option<Vec<u8>> {
+    let frames = to_frames(tokens, options);
+    match format {
+        AnimationFormat::Apng => Some(png::encode_apng(&frames)),
+        AnimationFormat::Gif => gif::encode(&frames),
+    }
+}
+
+impl Layout {
+    /// Draw with emoji showing their frame at `time`
+    fn draw(&self, theme: &RenderTheme, time: Duration) -> RgbaImage {
+        let padding = theme.padding as i64;
+        let mut image = RgbaImage::new(
+            self.width + theme.padding * 2,
+            self.height + theme.padding * 2,
+        );
+        image
+            .pixels
+            .chunks_mut(4)
+            .for_each(|p| p.copy_from_slice(&theme.background));
+        for Placed { x, y, piece } in &self.items {
+            let (x, y) = (x + padding, y + padding);
+            match piece {
+                Piece::Frames(frames) => image.draw(frame_at(frames, time), x, y),
+                Piece::Glyph(glyph) | Piece::Space(glyph) => {
+                    draw_glyph(&mut image, glyph, x, y + self.baseline, theme.color)
+                }
+                Piece::Break => {}
+            }
+        }
+        round_corners(&mut image, theme.corner_radius);
+        image
+    }
+}
+
+/// Make the corners outside a circle of `radius` transparent, blending its edge
+fn round_corners(image: &mut RgbaImage, radius: u32) {
+    let radius = radius.min(image.width / 2).min(image.height / 2);
+    let r = radius as f32;
+    for y in 0..radius {
+        for x in 0..radius {
+            // Distance from the center of the corner circle, of the center of the pixel
+            let (dx, dy) = (r - x as f32 - 0.5, r - y as f32 - 0.5);
+            let coverage = (r - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0);
+            let (right, bottom) = (image.width - 1 - x, image.height - 1 - y);
+            for (x, y) in [(x, y), (right, y), (x, bottom), (right, bottom)] {
+                let i = (y as usize * image.width as usize + x as usize) * 4 + 3;
+                image.pixels[i] = (image.pixels[i] as f32 * coverage) as u8;
+            }
+        }
+    }
+}
+
+/// Frame of a looping animation shown at `time`
+fn frame_at(frames: &[(RgbaImage, Duration)], time: Duration) -> &RgbaImage {
+    let length = frames.iter().map(|(_, delay)| *delay).sum::<Duration>();
+    let mut time = match length.is_zero() {
+        true => Duration::ZERO,
+        false => Duration::from_nanos((time.as_nanos() % length.as_nanos()) as u64),
+    };
+    for (image, delay) in frames {
+        if time < *delay {
+            return image;
+        }
+        time -= *delay;
+    }
+    &frames[0].0
+}
+
+fn draw_glyph(image: &mut RgbaImage, glyph: &Glyph, x: i64, baseline: i64, color: [u8; 4]) {
+    let (x, y) = (x + glyph.left as i64, baseline - glyph.top as i64);
+    for (i, coverage) in glyph.coverage.iter().enumerate() {
+        let (dx, dy) = (i as i64 % glyph.width as i64, i as i64 / glyph.width as i64);
+        let alpha = (color[3] as u32 * *coverage as u32 / 255) as u8;
+        image.blend(x + dx, y + dy, [color[0], color[1], color[2], alpha]);
+    }
+}
+
+fn items_of(tokens: &[Token], options: &RenderOptions) -> Vec<Item> {
+    let mut items = Vec::new();
+    tokens
+        .iter()
+        .for_each(|token| push_items(token, options, &mut items));
+    items
+}
+
+fn push_items(token: &Token, options: &RenderOptions, items: &mut Vec<Item>) {
+    #[cfg(feature = "discord")]
+    if let Token::Spoiler(tokens) = token {
+        return tokens
+            .iter()
+            .for_each(|token| push_items(token, options, items));
+    }
+    if let Some(frames) = token
+        .image_bytes()
+        .and_then(|image| options.frames_of(image))
+    {
+        return items.push(Item::Emoji(frames));
+    }
+    let text = match token {
+        Token::Text(s) => s.clone(),
+        Token::Emoji { .. } => return,
+        Token::EmojiUrl { emoji, .. } => emoji.clone(),
+        #[cfg(feature = "svg")]
+        Token::EmojiSvg { emoji, .. } => emoji.clone(),
+        #[cfg(feature = "custom-emoji")]
+        Token::CustomEmoji { name, .. } | Token::FetchedCustomEmoji { name, .. } => {
+            format!(":{}:", name)
+        }
+        #[cfg(feature = "discord")]
+        Token::Mention(mention) => {
+            let prefix = match mention {
+                crate::Mention::User(_) => "@",
+                crate::Mention::Channel(_) => "#",
+                crate::Mention::Role(_) => "@&",
+            };
+            format!("{}{}", prefix, mention.id())
+        }
+        #[cfg(feature = "discord")]
+        Token::CommandMention { name, .. } => format!("/{}", name),
+        #[cfg(feature = "discord")]
+        Token::Code(code) | Token::CodeBlock { code, .. } => code.clone(),
+        #[cfg(feature = "discord")]
+        Token::Spoiler(_) => return,
+        #[cfg(feature = "discord")]
+        Token::Timestamp { unix, .. } => unix.to_string(),
+        #[cfg(feature = "discord")]
+        Token::Sticker { name, .. } => name.clone(),
+        #[cfg(feature = "named-custom-emoji")]
+        Token::NamedCustomEmoji { name, .. } => format!(":{}:", name),
+    };
+    items.push(Item::Text(text));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    /// Glyphs are boxes of 6 by 8 pixels, sitting on the baseline
+    struct BoxFont;
+    impl Font for BoxFont {
+        fn line_metrics(&self, size: f32) -> (f32, f32) {
+            (size * 0.75, size * 0.25)
+        }
+        fn glyph(&self, c: char, _: f32) -> Glyph {
+            let (width, height) = match c {
+                ' ' => (0, 0),
+                _ => (6, 8),
+            };
+            Glyph {
+                width,
+                height,
+                coverage: vec![255; (width * height) as usize],
+                left: 1,
+                top: height as i32,
+                advance: 8.0,
+            }
+        }
+    }
+    #[test]
+    fn test_to_image() {
+        let tokens = [
+            Token::new_text("a "),
+            Token::new_emoji("👍", crate::get("👍").unwrap()),
+        ];
+        let image = to_image(&tokens, &RenderOptions::new(BoxFont));
+        assert_eq!((image.width, image.height), (32, 16));
+        // The glyph ends on the baseline
+        assert_eq!(image.pixel(1, 4), [0, 0, 0, 255]);
+        assert_eq!(image.pixel(1, 11), [0, 0, 0, 255]);
+        assert_eq!(image.pixel(1, 12), [0, 0, 0, 0]);
+        assert_eq!(image.pixel(9, 8), [0, 0, 0, 0]);
+        // The emoji fills the line
+        assert!(image.pixel(24, 8)[3] > 128);
+        let image = to_image(&[], &RenderOptions::new(BoxFont).font_size(32.0));
+        assert_eq!((image.width, image.height), (1, 32));
+    }
+    #[test]
+    fn test_theme() {
+        let theme = RenderTheme {
+            background: [255, 255, 255, 255],
+            color: [255, 0, 0, 255],
+            padding: 4,
+            emoji_scale: 1.5,
+            corner_radius: 4,
+            ..RenderTheme::default()
+        };
+        let tokens = [
+            Token::new_text("a"),
+            Token::new_emoji("👍", crate::get("👍").unwrap()),
+        ];
+        let image = to_image(&tokens, &RenderOptions::new(BoxFont).theme(theme));
+        // The line is as high as the emoji, with the glyph in the middle of it
+        assert_eq!((image.width, image.height), (40, 32));
+        assert_eq!(image.pixel(0, 0)[3], 0);
+        assert_eq!(image.pixel(4, 4), [255, 255, 255, 255]);
+        assert_eq!(image.pixel(5, 19), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(5, 20), [255, 255, 255, 255]);
+        assert!(image.pixel(24, 16)[3] > 128);
+    }
+    #[test]
+    fn test_shape() {
+        /// Shapes "fi" as a ligature of one glyph
+        struct Ligatures;
+        impl Font for Ligatures {
+            fn line_metrics(&self, size: f32) -> (f32, f32) {
+                BoxFont.line_metrics(size)
+            }
+            fn glyph(&self, c: char, size: f32) -> Glyph {
+                BoxFont.glyph(c, size)
+            }
+            fn shape(&self, word: &str, size: f32) -> Vec<Glyph> {
+                match word {
+                    "fi" => vec![self.glyph('f', size)],
+                    _ => word.chars().map(|c| self.glyph(c, size)).collect(),
+                }
+            }
+        }
+        let tokens = [Token::new_text("fi fx")];
+        assert_eq!(to_image(&tokens, &RenderOptions::new(Ligatures)).width, 32);
+        assert_eq!(to_image(&tokens, &RenderOptions::new(BoxFont)).width, 40);
+    }
+    #[test]
+    fn test_layout_scripts() {
+        let options = RenderOptions::new(BoxFont).max_width(24);
+        // Lines break between ideographs
+        let image = to_image(&[Token::new_text("a 日本")], &options);
+        assert_eq!((image.width, image.height), (24, 32));
+        assert_eq!(image.pixel(17, 11)[3], 255);
+        // Words of a right to left line go from right to left, emoji included
+        let tokens = [
+            Token::new_text("ب "),
+            Token::new_emoji("👍", crate::get("👍").unwrap()),
+        ];
+        let image = to_image(&tokens, &RenderOptions::new(BoxFont));
+        assert_eq!(image.width, 32);
+        assert!(image.pixel(8, 8)[3] > 128);
+        assert_eq!(image.pixel(25, 11)[3], 255);
+        assert_eq!(image.pixel(17, 11)[3], 0);
+    }
+    #[test]
+    fn test_layout() {
+        let options = RenderOptions::new(BoxFont).max_width(40);
+        let tokens = [
+            Token::new_text("ab cd "),
+            Token::new_emoji("👍", crate::get("👍").unwrap()),
+            Token::new_text("abcdefgh\nb"),
+        ];
+        let image = to_image(&tokens, &options);
+        // "ab cd " | emoji | "abcde" | "fgh" | "b", as a word not fitting is split on a line
+        // of its own
+        assert_eq!((image.width, image.height), (40, 80));
+        assert_eq!(image.pixel(1, 11)[3], 255);
+        assert_eq!(image.pixel(17, 11)[3], 0);
+        assert!(image.pixel(8, 24)[3] > 128);
+        assert_eq!(image.pixel(33, 43)[3], 255);
+        assert_eq!(image.pixel(17, 59)[3], 255);
+        assert_eq!(image.pixel(25, 59)[3], 0);
+        assert_eq!(image.pixel(1, 75)[3], 255);
+        assert_eq!(image.pixel(9, 75)[3], 0);
+        let image = to_image(&tokens, &options.clone().line_spacing(1.5));
+        assert_eq!(image.height, 112);
+        // The last line is a single glyph against the right edge
+        let image = to_image(&tokens, &options.clone().align(Align::Right));
+        assert_eq!(image.pixel(33, 75)[3], 255);
+        assert_eq!(image.pixel(1, 75)[3], 0);
+        let image = to_image(&tokens, &options.align(Align::Center));
+        assert_eq!(image.pixel(17, 75)[3], 255);
+    }
+    #[test]
+    #[cfg(feature = "custom-emoji")]
+    fn test_to_frames() {
+        let frame = |rgba, delay| {
+            let mut image = RgbaImage::new(1, 1);
+            image.blend(0, 0, rgba);
+            (image, Duration::from_millis(delay))
+        };
+        let animated = |frames: &[(RgbaImage, Duration)]| Token::FetchedCustomEmoji {
+            id: 123456789012345678,
+            name: "blob".to_string(),
+            animated: true,
+            image: gif::encode(frames).unwrap(),
+        };
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let tokens = [
+            animated(&[frame(red, 100), frame(blue, 100)]),
+            animated(&[frame(red, 50), frame(blue, 50), frame(red, 300)]),
+        ];
+        let options = RenderOptions::new(BoxFont);
+        let frames = to_frames(&tokens, &options);
+        let delays = frames
+            .iter()
+            .map(|(_, d)| d.as_millis())
+            .collect::<Vec<_>>();
+        // The shorter animation loops twice within the longer one
+        assert_eq!(delays, [50, 50, 100, 100, 100]);
+        assert_eq!(frames[0].0.pixel(0, 0), red);
+        assert_eq!(frames[0].0.pixel(16, 0), red);
+        assert_eq!(frames[1].0.pixel(16, 0), blue);
+        assert_eq!(frames[2].0.pixel(0, 0), blue);
+        let frames = to_frames(&tokens, &options.clone().max_fps(10).max_frames(3));
+        let delays = frames
+            .iter()
+            .map(|(_, d)| d.as_millis())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [100, 100, 200]);
+        let gif = to_animation(&tokens, &options, AnimationFormat::Gif).unwrap();
+        assert_eq!(gif::decode(&gif).unwrap().len(), 5);
+        let frames = to_frames(&[Token::new_text("a")], &options);
+        assert_eq!(frames.len(), 1);
+    }
+}
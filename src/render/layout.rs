@@ -0,0 +1,355 @@
+use super::compose::{Glyph, RenderOptions};
+use crate::png::RgbaImage;
+use std::time::Duration;
+
+/// Alignment of the lines of a rendered message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Text and emoji of a message, in order
+pub(super) enum Item {
+    Text(String),
+    Emoji(Vec<(RgbaImage, Duration)>),
+}
+
+/// Glyphs and emoji placed on their lines
+pub(super) struct Layout {
+    pub width: u32,
+    pub height: u32,
+    /// Baseline below the top of a line
+    pub baseline: i64,
+    pub items: Vec<Placed>,
+}
+
+/// Glyph at `x` on the line whose top is at `y`, or emoji with its top left corner there
+pub(super) struct Placed {
+    pub x: i64,
+    pub y: i64,
+    pub piece: Piece,
+}
+
+pub(super) enum Piece {
+    Glyph(Glyph),
+    /// Glyph of a space, which lines may break after
+    Space(Glyph),
+    /// Frames of an emoji with the time each is shown
+    Frames(Vec<(RgbaImage, Duration)>),
+    /// Forced line break
+    Break,
+}
+
+impl Piece {
+    fn is_space(&self) -> bool {
+        matches!(self, Piece::Space(_))
+    }
+    fn width(&self, emoji: u32) -> f32 {
+        match self {
+            Piece::Glyph(glyph) | Piece::Space(glyph) => glyph.advance,
+            Piece::Frames(_) => emoji as f32,
+            Piece::Break => 0.0,
+        }
+    }
+}
+
+/// Lines filled word by word
+struct Lines {
+    lines: Vec<Vec<Piece>>,
+    /// Width of the last line, with its trailing spaces
+    width: f32,
+    max_width: Option<f32>,
+    /// Size of emoji
+    emoji: u32,
+    /// Whether words go from right to left
+    rtl: bool,
+    /// Index in the last line the next piece of a word goes to
+    at: usize,
+}
+
+impl Lines {
+    fn break_line(&mut self) {
+        self.lines.push(Vec::new());
+        self.width = 0.0;
+        self.at = 0;
+    }
+    fn fits(&self, width: f32) -> bool {
+        let empty = self.lines.last().is_none_or(|line| line.is_empty());
+        empty || self.max_width.is_none_or(|max| self.width + width <= max)
+    }
+    fn push(&mut self, piece: Piece) {
+        self.width += piece.width(self.emoji);
+        // Right to left words go in front of the ones before them
+        let at = match self.rtl {
+            true => self.at,
+            false => self.lines.last().map_or(0, Vec::len),
+        };
+        if let Some(line) = self.lines.last_mut() {
+            line.insert(at, piece);
+            self.at = at + 1;
+        }
+    }
+    /// Add a word and its trailing spaces, on the next line if it doesn't fit, split if it
+    /// is wider than a line
+    fn push_word(&mut self, mut word: Vec<Piece>) {
+        let body = word
+            .iter()
+            .filter(|piece| !piece.is_space())
+            .map(|piece| piece.width(self.emoji))
+            .sum();
+        if !self.fits(body) {
+            self.break_line();
+        }
+        self.at = 0;
+        if self.rtl {
+            // Spaces after a word are left of it
+            let spaces = word
+                .iter()
+                .rev()
+                .take_while(|piece| piece.is_space())
+                .count();
+            word.rotate_right(spaces);
+        }
+        let split = self.max_width.is_some_and(|max| body > max);
+        for piece in word {
+            if split && !piece.is_space() && !self.fits(piece.width(self.emoji)) {
+                self.break_line();
+            }
+            self.push(piece);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Run {
+    Word,
+    /// Chinese or Japanese character, lines may break before it
+    Ideograph,
+    Space,
+    Break,
+}
+
+/// Whether lines may break before `c`, as in scripts written without spaces
+fn is_ideograph(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}'
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{20000}'..='\u{3ffff}'
+    )
+}
+
+/// Words, spaces and line breaks of a text
+fn runs(text: &str) -> Vec<(&str, Run)> {
+    let kind = |c: char| match c {
+        '\n' => Run::Break,
+        // No break space
+        '\u{a0}' => Run::Word,
+        _ if c.is_whitespace() => Run::Space,
+        _ if is_ideograph(c) => Run::Ideograph,
+        _ => Run::Word,
+    };
+    let mut runs = Vec::new();
+    let (mut start, mut current) = (0, None);
+    for (i, c) in text.char_indices() {
+        let run = kind(c);
+        match current {
+            Some(current) if current == run && !matches!(run, Run::Break | Run::Ideograph) => {}
+            Some(current) => {
+                runs.push((&text[start..i], current));
+                start = i;
+            }
+            None => {}
+        }
+        current = Some(run);
+    }
+    if let Some(current) = current {
+        runs.push((&text[start..], current));
+    }
+    runs
+}
+
+/// Width of a line without its trailing spaces, which are left of right to left lines
+fn visible_width(pieces: &[Piece], emoji: u32, rtl: bool) -> f32 {
+    let visible = match rtl {
+        true => pieces
+            .iter()
+            .position(|piece| !piece.is_space())
+            .map_or(&[][..], |i| &pieces[i..]),
+        false => {
+            let end = pieces
+                .iter()
+                .rposition(|piece| !piece.is_space())
+                .map_or(0, |i| i + 1);
+            &pieces[..end]
+        }
+    };
+    visible.iter().map(|piece| piece.width(emoji)).sum()
+}
+
+/// Whether the first letter of the text is written right to left
+fn is_rtl_text(items: &[Item]) -> bool {
+    items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Text(text) => text.chars().find(|c| c.is_alphabetic()),
+            Item::Emoji(_) => None,
+        })
+        .next()
+        .is_some_and(is_rtl)
+}
+
+/// Whether `c` is written right to left, digits are not
+fn is_rtl(c: char) -> bool {
+    matches!(c,
+        '\u{590}'..='\u{65f}'
+        | '\u{66a}'..='\u{6ef}'
+        | '\u{6fa}'..='\u{8ff}'
+        | '\u{fb1d}'..='\u{fdff}'
+        | '\u{fe70}'..='\u{feff}'
+        | '\u{10800}'..='\u{10fff}'
+        | '\u{1e800}'..='\u{1efff}'
+    )
+}
+
+/// Height of text on a line, from the metrics of the font
+fn text_height(options: &RenderOptions) -> u32 {
+    let (ascent, descent) = options.font.line_metrics(options.theme.font_size);
+    (ascent + descent).ceil().max(1.0) as u32
+}
+
+/// Width and height of emoji on a line
+pub(super) fn emoji_size(options: &RenderOptions) -> u32 {
+    (text_height(options) as f32 * options.theme.emoji_scale)
+        .round()
+        .max(1.0) as u32
+}
+
+/// Break items into lines no wider than the maximum width, at spaces and around emoji
+pub(super) fn layout(items: Vec<Item>, options: &RenderOptions) -> Layout {
+    let font_size = options.theme.font_size;
+    let (ascent, _) = options.font.line_metrics(font_size);
+    let (text, emoji) = (text_height(options), emoji_size(options));
+    // Text and emoji are centered on lines as high as the highest of them
+    let line = text.max(emoji);
+    let rtl = is_rtl_text(&items);
+    let mut words = Vec::new();
+    let mut word = Vec::new();
+    for item in items {
+        match item {
+            Item::Emoji(frames) => {
+                let frames = frames
+                    .into_iter()
+                    .map(|(image, delay)| (image.resized(emoji, emoji), delay))
+                    .collect();
+                words.push(std::mem::take(&mut word));
+                word.push(Piece::Frames(frames));
+            }
+            Item::Text(text) => {
+                for (run, kind) in runs(&text) {
+                    let pieces = match kind {
+                        Run::Break => vec![Piece::Break],
+                        Run::Space => run
+                            .chars()
+                            .map(|c| Piece::Space(options.font.glyph(c, font_size)))
+                            .collect(),
+                        Run::Word | Run::Ideograph => options
+                            .font
+                            .shape(run, font_size)
+                            .into_iter()
+                            .map(Piece::Glyph)
+                            .collect(),
+                    };
+                    // A word ends at its first glyph after a space, or after an emoji
+                    let ends = word.last().is_some_and(|last: &Piece| match kind {
+                        Run::Break => true,
+                        Run::Space => matches!(last, Piece::Frames(_) | Piece::Break),
+                        Run::Word => !matches!(last, Piece::Glyph(_)),
+                        Run::Ideograph => true,
+                    });
+                    if ends {
+                        words.push(std::mem::take(&mut word));
+                    }
+                    word.extend(pieces);
+                }
+            }
+        }
+    }
+    words.push(word);
+    let mut lines = Lines {
+        lines: vec![Vec::new()],
+        width: 0.0,
+        max_width: options.max_width.map(|max| max as f32),
+        emoji,
+        rtl,
+        at: 0,
+    };
+    for word in words {
+        match word.first() {
+            Some(Piece::Break) => lines.break_line(),
+            Some(_) => lines.push_word(word),
+            None => {}
+        }
+    }
+    let widths = lines
+        .lines
+        .iter()
+        .map(|pieces| visible_width(pieces, emoji, rtl))
+        .collect::<Vec<_>>();
+    let widest = widths.iter().copied().fold(0.0, f32::max);
+    let advance = line as f32 * options.line_spacing;
+    let mut placed = Vec::new();
+    for (i, (pieces, width)) in lines.lines.into_iter().zip(&widths).enumerate() {
+        let y = (i as f32 * advance).round() as i64;
+        // Trailing spaces of right to left lines are left of the edge
+        let spaces = match rtl {
+            true => pieces.iter().map(|piece| piece.width(emoji)).sum::<f32>() - width,
+            false => 0.0,
+        };
+        let mut pen = match options.align {
+            Align::Left => 0.0,
+            Align::Center => (widest - width) / 2.0,
+            Align::Right => widest - width,
+        } - spaces;
+        for piece in pieces {
+            let width = piece.width(emoji);
+            let x = pen.round() as i64;
+            let y = match piece {
+                Piece::Frames(_) => y + (line - emoji) as i64 / 2,
+                _ => y + (line - text) as i64 / 2,
+            };
+            placed.push(Placed { x, y, piece });
+            pen += width;
+        }
+    }
+    let count = widths.len().max(1) as f32;
+    Layout {
+        width: (widest.ceil() as u32).max(1),
+        height: ((count - 1.0) * advance + line as f32).ceil().max(1.0) as u32,
+        baseline: ascent.round() as i64,
+        items: placed,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_runs() {
+        let runs = runs("ab  c\u{a0}d\n\né");
+        let kinds = runs.iter().map(|(run, _)| *run).collect::<Vec<_>>();
+        assert_eq!(kinds, ["ab", "  ", "c\u{a0}d", "\n", "\n", "é"]);
+        assert!(runs[1].1 == Run::Space && runs[3].1 == Run::Break);
+    }
+    #[test]
+    fn test_runs_cjk() {
+        let runs = runs("日本語です。ok 한국");
+        let kinds = runs.iter().map(|(run, _)| *run).collect::<Vec<_>>();
+        assert_eq!(kinds, ["日", "本", "語", "で", "す", "。ok", " ", "한국"]);
+        assert!(runs[0].1 == Run::Ideograph && runs[5].1 == Run::Word);
+    }
+}
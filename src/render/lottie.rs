@@ -0,0 +1,186 @@
+mod animation;
+mod raster;
+
+use super::compose::RenderOptions;
+use super::layout::emoji_size;
+use crate::png::RgbaImage;
+use crate::LottieError;
+use animation::Animation;
+use std::time::Duration;
+
+/// Largest Lottie json unpacked from a tgs, which Telegram caps at 64KiB gzipped
+const MAX_LOTTIE_SIZE: usize = 16 << 20;
+
+/// Most frames of an animation drawn by [`lottie_to_frames`], Telegram stickers last at most 3
+/// seconds at 60 frames a second
+const MAX_LOTTIE_FRAMES: usize = 600;
+
+/// Rasterizer of Lottie animations replacing the bundled one, such as over rlottie bindings for
+/// the features it lacks, set with [`RenderOptions::lottie`](super::RenderOptions::lottie)
+pub trait LottieRenderer {
+    /// Frames of the Lottie json `animation` at `size` by `size` pixels, with the time each
+    /// is shown
+    fn render(&self, animation: &[u8], size: u32) -> Vec<(RgbaImage, Duration)>;
+}
+
+/// Lottie json of a tgs sticker or premium custom emoji, a gzipped Lottie animation
+pub fn tgs_to_lottie(tgs: &[u8]) -> Option<Vec<u8>> {
+    const FEXTRA: u8 = 4;
+    const FNAME: u8 = 8;
+    const FCOMMENT: u8 = 16;
+    const FHCRC: u8 = 2;
+    let [0x1f, 0x8b, 8, flags, ..] = *tgs else {
+        return None;
+    };
+    let mut at = 10;
+    if flags & FEXTRA != 0 {
+        let len = u16::from_le_bytes(tgs.get(at..at + 2)?.try_into().ok()?);
+        at += 2 + len as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            at += tgs.get(at..)?.iter().position(|b| *b == 0)? + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        at += 2;
+    }
+    let body = tgs.get(at..tgs.len().checked_sub(8)?)?;
+    let lottie = miniz_oxide::inflate::decompress_to_vec_with_limit(body, MAX_LOTTIE_SIZE).ok()?;
+    let trailer = &tgs[tgs.len() - 8..];
+    let crc = u32::from_le_bytes(trailer[..4].try_into().ok()?);
+    let size = u32::from_le_bytes(trailer[4..].try_into().ok()?);
+    (crate::png::crc32(lottie.iter()) == crc && size == lottie.len() as u32).then_some(lottie)
+}
+
+/// Frames of the Lottie json `animation` at `size` by `size` pixels, with the time each is
+/// shown
+///
+/// Shape, solid and precomposition layers are drawn with their parents, mattes, fills, strokes,
+/// gradients and trim paths, as Telegram stickers use them. Animations with expressions, masks,
+/// effects, text, images or other shape modifiers such as repeaters are
+/// [`LottieError::Unsupported`], with the features they use, to draw with a
+/// [`LottieRenderer`] instead.
+pub fn lottie_to_frames(
+    animation: &[u8],
+    size: u32,
+) -> Result<Vec<(RgbaImage, Duration)>, LottieError> {
+    Ok(Animation::parse(animation)?.frames(size, u32::MAX, MAX_LOTTIE_FRAMES))
+}
+
+/// Frames of a tgs at the size of emoji on a line, drawn by the Lottie renderer of `options`
+/// or the bundled one at its frame rate, which leaves out animations it can't draw whole
+pub(super) fn frames(tgs: &[u8], options: &RenderOptions) -> Option<Vec<(RgbaImage, Duration)>> {
+    let lottie = tgs_to_lottie(tgs)?;
+    let size = emoji_size(options);
+    let frames = match &options.lottie {
+        Some(renderer) => renderer.render(&lottie, size),
+        None => Animation::parse(&lottie)
+            .ok()?
+            .frames(size, options.max_fps, options.max_frames),
+    };
+    (!frames.is_empty()).then_some(frames)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    /// Gzip of `data`, with a file name
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut gz = vec![0x1f, 0x8b, 8, 8, 0, 0, 0, 0, 0, 3];
+        gz.extend(b"sticker.json\0");
+        gz.extend(miniz_oxide::deflate::compress_to_vec(data, 6));
+        gz.extend(crate::png::crc32(data.iter()).to_le_bytes());
+        gz.extend((data.len() as u32).to_le_bytes());
+        gz
+    }
+    #[test]
+    fn test_tgs_to_lottie() {
+        let lottie = br#"{"v":"5.5.2","fr":60,"ip":0,"op":180,"w":512,"h":512}"#;
+        let tgs = gzip(lottie);
+        assert_eq!(tgs_to_lottie(&tgs).as_deref(), Some(lottie.as_slice()));
+        let mut broken = tgs.clone();
+        let last = broken.len() - 5;
+        broken[last] ^= 1;
+        assert_eq!(tgs_to_lottie(&broken), None);
+        assert_eq!(tgs_to_lottie(b"GIF89a"), None);
+    }
+    #[test]
+    #[cfg(feature = "custom-emoji")]
+    fn test_to_frames_tgs() {
+        /// Font without glyphs, 10 pixels high
+        struct Blank;
+        impl crate::render::Font for Blank {
+            fn line_metrics(&self, _size: f32) -> (f32, f32) {
+                (8.0, 2.0)
+            }
+            fn glyph(&self, _c: char, _size: f32) -> crate::render::Glyph {
+                crate::render::Glyph::default()
+            }
+        }
+        /// Renderer of as many red frames as the animation is long
+        struct Frames;
+        impl LottieRenderer for Frames {
+            fn render(&self, animation: &[u8], size: u32) -> Vec<(RgbaImage, Duration)> {
+                let mut frame = RgbaImage::new(size, size);
+                frame
+                    .pixels
+                    .chunks_mut(4)
+                    .for_each(|p| p[0..4].copy_from_slice(&[255, 0, 0, 255]));
+                vec![(frame, Duration::from_millis(50)); animation.len()]
+            }
+        }
+        let token = crate::Token::FetchedCustomEmoji {
+            id: 5368324170671202286,
+            name: "👍".to_string(),
+            animated: true,
+            image: gzip(b"{}{}"),
+        };
+        let options = RenderOptions::new(Blank);
+        let still = crate::render::to_image(std::slice::from_ref(&token), &options);
+        assert_eq!(still.pixel(0, 0), [0; 4]);
+        let frames = crate::render::to_frames(&[token], &options.lottie(Frames));
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].0.pixel(5, 5), [255, 0, 0, 255]);
+        assert_eq!(
+            (frames[0].0.width, frames[0].1),
+            (10, Duration::from_millis(50))
+        );
+    }
+    #[test]
+    #[cfg(feature = "custom-emoji")]
+    fn test_to_frames_bundled() {
+        struct Blank;
+        impl crate::render::Font for Blank {
+            fn line_metrics(&self, _size: f32) -> (f32, f32) {
+                (8.0, 2.0)
+            }
+            fn glyph(&self, _c: char, _size: f32) -> crate::render::Glyph {
+                crate::render::Glyph::default()
+            }
+        }
+        // A blue square, fading in over a second
+        let lottie = br#"{"v":"5.5.2","fr":10,"ip":0,"op":10,"w":100,"h":100,"layers":[
+            {"ty":4,"ind":1,"ip":0,"op":10,"st":0,
+                "ks":{"o":{"a":1,"k":[{"t":0,"s":[0]},{"t":10,"s":[100]}]}},
+                "shapes":[{"ty":"rc","p":{"a":0,"k":[50,50]},"s":{"a":0,"k":[100,100]},"r":{"a":0,"k":0}},
+                    {"ty":"fl","c":{"a":0,"k":[0,0,1,1]},"o":{"a":0,"k":100}}]}]}"#;
+        let token = crate::Token::FetchedCustomEmoji {
+            id: 5368324170671202286,
+            name: "👍".to_string(),
+            animated: true,
+            image: gzip(lottie),
+        };
+        let frames = crate::render::to_frames(&[token], &RenderOptions::new(Blank));
+        assert_eq!(frames.len(), 10);
+        assert_eq!(frames[0].1, Duration::from_millis(100));
+        assert_eq!(frames[0].0.pixel(5, 5), [0; 4]);
+        assert_eq!(frames[5].0.pixel(5, 5), [0, 0, 255, 127]);
+        let frames = lottie_to_frames(lottie, 20).unwrap();
+        assert_eq!(
+            (frames.len(), frames[9].0.pixel(10, 10)),
+            (10, [0, 0, 255, 229])
+        );
+        assert_eq!(lottie_to_frames(b"{}", 20), Err(LottieError::Malformed));
+    }
+}
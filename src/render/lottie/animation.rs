@@ -0,0 +1,957 @@
+//! Frames of Lottie animations, with the shape, solid and precomposition layers, mattes,
+//! gradients and trim paths Telegram stickers are made of
+//!
+//! Expressions, masks, effects, text, images and the shape modifiers other than trim paths,
+//! which Telegram stickers don't use, are left out and make animations using them an error.
+
+use super::raster::{Canvas, Cap, Contour, FillRule, Join, Matrix, Paint, Point};
+use crate::png::RgbaImage;
+use crate::LottieError;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Deepest chain of parents, precompositions and groups drawn
+const MAX_DEPTH: usize = 32;
+
+/// Handles of a cubic bezier quarter of a circle, relative to its radius
+const KAPPA: f32 = 0.552_284_8;
+
+pub(super) struct Animation {
+    root: Value,
+    width: f32,
+    height: f32,
+    frame_rate: f32,
+    in_point: f32,
+    out_point: f32,
+}
+
+/// Cubic bezier path, by vertex with its in and out handles
+struct Bezier {
+    vertices: Vec<[Point; 3]>,
+    closed: bool,
+}
+
+impl Animation {
+    /// Animation of Lottie json, an error if it is malformed, has no frames or uses features
+    /// that aren't drawn
+    pub fn parse(lottie: &[u8]) -> Result<Animation, LottieError> {
+        let root = serde_json::from_slice::<Value>(lottie).map_err(|_| LottieError::Malformed)?;
+        let number = |key: &str| root.number_of(key).map(|n| n as f32);
+        let (Some(width), Some(height), Some(frame_rate), Some(in_point), Some(out_point)) = (
+            number("w"),
+            number("h"),
+            number("fr"),
+            number("ip"),
+            number("op"),
+        ) else {
+            return Err(LottieError::Malformed);
+        };
+        if width <= 0.0 || height <= 0.0 || frame_rate <= 0.0 || out_point <= in_point {
+            return Err(LottieError::Malformed);
+        }
+        let unsupported = unsupported(&root);
+        if !unsupported.is_empty() {
+            return Err(LottieError::Unsupported(unsupported));
+        }
+        Ok(Animation {
+            root,
+            width,
+            height,
+            frame_rate,
+            in_point,
+            out_point,
+        })
+    }
+    /// Frames at `size` by `size` pixels, at most `max_fps` a second and `max_frames` of them,
+    /// with the time each is shown
+    pub fn frames(&self, size: u32, max_fps: u32, max_frames: usize) -> Vec<(RgbaImage, Duration)> {
+        let step = (self.frame_rate / max_fps.max(1) as f32).max(1.0);
+        let delay = Duration::from_secs_f64(step as f64 / self.frame_rate as f64);
+        let count = ((self.out_point - self.in_point) / step).ceil() as usize;
+        (0..count.min(max_frames))
+            .map(|i| (self.draw(self.in_point + i as f32 * step, size), delay))
+            .collect()
+    }
+    /// Frame at `frame`, fit in the middle of a `size` by `size` image
+    fn draw(&self, frame: f32, size: u32) -> RgbaImage {
+        let scale = size as f32 / self.width.max(self.height);
+        let matrix = Matrix::scale(scale, scale).then(&Matrix::translate(
+            (size as f32 - self.width * scale) / 2.0,
+            (size as f32 - self.height * scale) / 2.0,
+        ));
+        let mut canvas = Canvas::new(size as usize, size as usize);
+        self.draw_layers(items(&self.root, "layers"), frame, &matrix, &mut canvas, 0);
+        canvas.to_image()
+    }
+    /// Draw `layers` of a composition at `frame`, the first on top
+    fn draw_layers(
+        &self,
+        layers: &[Value],
+        frame: f32,
+        matrix: &Matrix,
+        canvas: &mut Canvas,
+        depth: usize,
+    ) {
+        if depth > MAX_DEPTH {
+            return;
+        }
+        let size = (canvas.width, canvas.height);
+        for (i, layer) in layers.iter().enumerate().rev() {
+            // Mattes are only drawn for the layer after them
+            if flag(layer, "td") {
+                continue;
+            }
+            let Some(mut content) = self.draw_layer(layers, layer, frame, matrix, size, depth)
+            else {
+                continue;
+            };
+            let matte = layer.number_of("tt").unwrap_or(0.0) as u8;
+            if (1..=4).contains(&matte) && i > 0 {
+                let source = self.draw_layer(layers, &layers[i - 1], frame, matrix, size, depth);
+                let source = source.unwrap_or_else(|| Canvas::new(size.0, size.1));
+                content.matte(&source, matte >= 3, matte.is_multiple_of(2));
+            }
+            canvas.draw(&content, 1.0);
+        }
+    }
+    /// Layer of a composition drawn on its own at `frame` with its opacity, `None` when it
+    /// isn't shown then
+    fn draw_layer(
+        &self,
+        layers: &[Value],
+        layer: &Value,
+        frame: f32,
+        matrix: &Matrix,
+        (width, height): (usize, usize),
+        depth: usize,
+    ) -> Option<Canvas> {
+        let (in_point, out_point) = (layer.number_of("ip")? as f32, layer.number_of("op")? as f32);
+        if flag(layer, "hd") || frame < in_point || frame >= out_point {
+            return None;
+        }
+        let time = local_time(layer, frame);
+        let transform = layer_matrix(layers, layer, frame, depth)?.then(matrix);
+        let opacity = layer
+            .get("ks")
+            .map_or(100.0, |ks| number_at(ks.get("o"), time, 100.0))
+            / 100.0;
+        let mut content = Canvas::new(width, height);
+        match layer.number_of("ty")? as u8 {
+            // Precomposition
+            0 => {
+                let id = layer.get("refId").and_then(Value::as_str)?;
+                let assets = items(&self.root, "assets");
+                let asset = assets
+                    .iter()
+                    .find(|a| a.get("id").and_then(Value::as_str) == Some(id))?;
+                self.draw_layers(
+                    items(asset, "layers"),
+                    time,
+                    &transform,
+                    &mut content,
+                    depth + 1,
+                );
+            }
+            // Solid
+            1 => {
+                let color = layer
+                    .get("sc")
+                    .and_then(Value::as_str)
+                    .and_then(hex_color)?;
+                let (w, h) = (layer.number_of("sw")? as f32, layer.number_of("sh")? as f32);
+                let rectangle = Bezier {
+                    vertices: [[0.0, 0.0], [w, 0.0], [w, h], [0.0, h]]
+                        .map(|p| [p; 3])
+                        .to_vec(),
+                    closed: true,
+                };
+                let contour = flatten(&rectangle, &transform);
+                content.fill(&[contour], FillRule::NonZero, &Paint::Solid(color), 1.0);
+            }
+            // Shapes
+            4 => draw_items(
+                items(layer, "shapes"),
+                time,
+                &transform,
+                &[],
+                &mut content,
+                0,
+            ),
+            _ => {}
+        }
+        let mut drawn = Canvas::new(width, height);
+        drawn.draw(&content, opacity);
+        Some(drawn)
+    }
+}
+
+/// Features of the layers of an animation and its precompositions that aren't drawn, sorted
+fn unsupported(root: &Value) -> Vec<&'static str> {
+    let mut features = Vec::new();
+    let layers = items(root, "assets")
+        .iter()
+        .flat_map(|asset| items(asset, "layers"))
+        .chain(items(root, "layers"));
+    for layer in layers {
+        match layer.number_of("ty") {
+            // Precompositions, solids, nulls, shapes and audio
+            Some(0.0 | 1.0 | 3.0 | 4.0 | 6.0) => {}
+            Some(2.0) => features.push("images"),
+            Some(5.0) => features.push("text"),
+            _ => features.push("unknown layers"),
+        }
+        if flag(layer, "hasMask") || !items(layer, "masksProperties").is_empty() {
+            features.push("masks");
+        }
+        if !items(layer, "ef").is_empty() {
+            features.push("effects");
+        }
+        unsupported_shapes(items(layer, "shapes"), &mut features);
+    }
+    if has_expression(root) {
+        features.push("expressions");
+    }
+    features.sort_unstable();
+    features.dedup();
+    features
+}
+
+/// Push the features of shape `items` and their groups that aren't drawn to `features`
+fn unsupported_shapes(items: &[Value], features: &mut Vec<&'static str>) {
+    for item in items {
+        let feature = match item.get("ty").and_then(Value::as_str).unwrap_or_default() {
+            "gr" => {
+                unsupported_shapes(self::items(item, "it"), features);
+                continue;
+            }
+            "sh" | "rc" | "el" | "sr" | "fl" | "gf" | "st" | "gs" | "tm" | "tr" => continue,
+            "rp" => "repeaters",
+            "mm" => "merge paths",
+            "rd" => "rounded corners",
+            "op" => "offset paths",
+            "pb" => "pucker and bloat",
+            "tw" => "twists",
+            "zz" => "zig zags",
+            _ => "unknown shapes",
+        };
+        features.push(feature);
+    }
+}
+
+/// Whether a property of `value` is driven by an expression, the script in its `x`
+fn has_expression(value: &Value) -> bool {
+    match value {
+        Value::Object(entries) => entries
+            .iter()
+            .any(|(key, value)| (key == "x" && value.is_string()) || has_expression(value)),
+        Value::Array(items) => items.iter().any(has_expression),
+        _ => false,
+    }
+}
+
+/// Lookups in Lottie json
+trait Lookup {
+    /// Number of `key` of an object
+    fn number_of(&self, key: &str) -> Option<f64>;
+    /// Items of an array, none for other values
+    fn items(&self) -> &[Value];
+}
+
+impl Lookup for Value {
+    fn number_of(&self, key: &str) -> Option<f64> {
+        number(self.get(key)?)
+    }
+    fn items(&self) -> &[Value] {
+        self.as_array().map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Number of a value, flags are numbers or booleans
+fn number(value: &Value) -> Option<f64> {
+    value
+        .as_f64()
+        .or_else(|| Some(value.as_bool()? as u8 as f64))
+}
+
+fn items<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value.get(key).map_or(&[], Value::items)
+}
+
+fn flag(value: &Value, key: &str) -> bool {
+    value.number_of(key).is_some_and(|n| n != 0.0)
+}
+
+/// Frame of the animations of a layer at `frame` of its composition
+fn local_time(layer: &Value, frame: f32) -> f32 {
+    let start = layer.number_of("st").unwrap_or(0.0) as f32;
+    let stretch = layer.number_of("sr").unwrap_or(1.0) as f32;
+    (frame - start) / if stretch > 0.0 { stretch } else { 1.0 }
+}
+
+/// Transform of a layer and its parents to its composition, `None` if they loop
+fn layer_matrix(layers: &[Value], layer: &Value, frame: f32, depth: usize) -> Option<Matrix> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    let own = layer.get("ks").map_or(Matrix::IDENTITY, |ks| {
+        transform(ks, local_time(layer, frame)).0
+    });
+    let Some(parent) = layer.number_of("parent") else {
+        return Some(own);
+    };
+    match layers.iter().find(|l| l.number_of("ind") == Some(parent)) {
+        Some(parent) => Some(own.then(&layer_matrix(layers, parent, frame, depth + 1)?)),
+        None => Some(own),
+    }
+}
+
+/// Matrix and opacity of a layer or group transform at `frame`
+fn transform(transform: &Value, frame: f32) -> (Matrix, f32) {
+    let anchor = point_at(transform.get("a"), frame);
+    let position = match transform.get("p") {
+        Some(p) if flag(p, "s") => [
+            number_at(p.get("x"), frame, 0.0),
+            number_at(p.get("y"), frame, 0.0),
+        ],
+        p => point_at(p, frame),
+    };
+    let scale = numbers_at(transform.get("s"), frame).unwrap_or_default();
+    let scale = |i: usize| scale.get(i).copied().unwrap_or(100.0) / 100.0;
+    let rotation = match transform.get("r") {
+        Some(r) => number_at(Some(r), frame, 0.0),
+        None => number_at(transform.get("rz"), frame, 0.0),
+    };
+    let skew = number_at(transform.get("sk"), frame, 0.0);
+    let matrix = Matrix::translate(-anchor[0], -anchor[1])
+        .then(&Matrix::scale(scale(0), scale(1)))
+        .then(&Matrix::skew(
+            skew,
+            number_at(transform.get("sa"), frame, 0.0),
+        ))
+        .then(&Matrix::rotate(rotation))
+        .then(&Matrix::translate(position[0], position[1]));
+    (matrix, number_at(transform.get("o"), frame, 100.0) / 100.0)
+}
+
+/// Draw the shapes and groups of `items` with their fills and strokes, the first on top, with
+/// their paths trimmed by `trims` of the groups around them too
+fn draw_items(
+    items: &[Value],
+    frame: f32,
+    matrix: &Matrix,
+    trims: &[&Value],
+    canvas: &mut Canvas,
+    depth: usize,
+) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+    let (matrix, opacity) = group_transform(items, frame, matrix);
+    for (i, item) in items.iter().enumerate().rev() {
+        if flag(item, "hd") {
+            continue;
+        }
+        // Trim paths apply to all paths before them, even those of fills and strokes before them
+        let trims = items[i + 1..]
+            .iter()
+            .filter(|item| {
+                item.get("ty").and_then(Value::as_str) == Some("tm") && !flag(item, "hd")
+            })
+            .chain(trims.iter().copied())
+            .collect::<Vec<_>>();
+        let kind = item.get("ty").and_then(Value::as_str).unwrap_or_default();
+        match kind {
+            "gr" => {
+                let mut group = Canvas::new(canvas.width, canvas.height);
+                draw_items(
+                    self::items(item, "it"),
+                    frame,
+                    &matrix,
+                    &trims,
+                    &mut group,
+                    depth + 1,
+                );
+                canvas.draw(&group, opacity);
+            }
+            "fl" | "gf" | "st" | "gs" => {
+                let contours = trims.iter().fold(
+                    paths(&items[..i], frame, &matrix, depth),
+                    |contours, item| trim(contours, item, frame),
+                );
+                let Some(paint) = paint(item, frame, &matrix) else {
+                    continue;
+                };
+                let opacity = opacity * number_at(item.get("o"), frame, 100.0) / 100.0;
+                match kind {
+                    "fl" | "gf" => {
+                        let rule = match item.number_of("r") {
+                            Some(2.0) => FillRule::EvenOdd,
+                            _ => FillRule::NonZero,
+                        };
+                        canvas.fill(&contours, rule, &paint, opacity);
+                    }
+                    _ => {
+                        let width = number_at(item.get("w"), frame, 0.0) * matrix.scale_factor();
+                        let cap = match item.number_of("lc") {
+                            Some(2.0) => Cap::Round,
+                            Some(3.0) => Cap::Square,
+                            _ => Cap::Butt,
+                        };
+                        let join = match item.number_of("lj") {
+                            Some(2.0) => Join::Round,
+                            Some(3.0) => Join::Bevel,
+                            _ => Join::Miter(item.number_of("ml").unwrap_or(4.0) as f32),
+                        };
+                        canvas.stroke(&contours, width, (cap, join), &paint, opacity);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Matrix of the transform of a group of `items` within `matrix`, and its opacity
+fn group_transform(items: &[Value], frame: f32, matrix: &Matrix) -> (Matrix, f32) {
+    let group = items
+        .iter()
+        .find(|item| item.get("ty").and_then(Value::as_str) == Some("tr"));
+    match group {
+        Some(group) => {
+            let (own, opacity) = transform(group, frame);
+            (own.then(matrix), opacity)
+        }
+        None => (*matrix, 1.0),
+    }
+}
+
+/// Outlines of the shapes of `items` and their groups, trimmed by the trim paths among them
+fn paths(items: &[Value], frame: f32, matrix: &Matrix, depth: usize) -> Vec<Contour> {
+    let mut contours = Vec::new();
+    if depth > MAX_DEPTH {
+        return contours;
+    }
+    for item in items {
+        if flag(item, "hd") {
+            continue;
+        }
+        match item.get("ty").and_then(Value::as_str).unwrap_or_default() {
+            "gr" => {
+                let group = self::items(item, "it");
+                let (matrix, _) = group_transform(group, frame, matrix);
+                contours.extend(paths(group, frame, &matrix, depth + 1));
+            }
+            "tm" => contours = trim(contours, item, frame),
+            _ => contours.extend(geometry(item, frame).map(|bezier| flatten(&bezier, matrix))),
+        }
+    }
+    contours
+}
+
+/// Path of a path, rectangle, ellipse or star item at `frame`
+fn geometry(item: &Value, frame: f32) -> Option<Bezier> {
+    let kind = item.get("ty").and_then(Value::as_str)?;
+    if kind == "sh" {
+        return shape_at(item.get("ks")?, frame);
+    }
+    let [x, y] = point_at(item.get("p"), frame);
+    let [width, height] = point_at(item.get("s"), frame).map(|s| s / 2.0);
+    let vertex = |p: Point, before: Point, after: Point| {
+        [
+            p,
+            [p[0] + before[0], p[1] + before[1]],
+            [p[0] + after[0], p[1] + after[1]],
+        ]
+    };
+    let vertices = match kind {
+        "rc" => {
+            let r = number_at(item.get("r"), frame, 0.0)
+                .min(width)
+                .min(height)
+                .max(0.0);
+            let (left, right, top, bottom) = (x - width, x + width, y - height, y + height);
+            let k = r * KAPPA;
+            match r > 0.0 {
+                false => [[right, top], [right, bottom], [left, bottom], [left, top]]
+                    .map(|p| [p; 3])
+                    .to_vec(),
+                true => vec![
+                    vertex([right, top + r], [0.0, 0.0], [0.0, 0.0]),
+                    vertex([right, bottom - r], [0.0, 0.0], [0.0, k]),
+                    vertex([right - r, bottom], [k, 0.0], [0.0, 0.0]),
+                    vertex([left + r, bottom], [0.0, 0.0], [-k, 0.0]),
+                    vertex([left, bottom - r], [0.0, k], [0.0, 0.0]),
+                    vertex([left, top + r], [0.0, 0.0], [0.0, -k]),
+                    vertex([left + r, top], [-k, 0.0], [0.0, 0.0]),
+                    vertex([right - r, top], [0.0, 0.0], [k, 0.0]),
+                    vertex([right, top + r], [0.0, -k], [0.0, 0.0]),
+                ],
+            }
+        }
+        "el" => {
+            let (kx, ky) = (width * KAPPA, height * KAPPA);
+            vec![
+                vertex([x, y - height], [-kx, 0.0], [kx, 0.0]),
+                vertex([x + width, y], [0.0, -ky], [0.0, ky]),
+                vertex([x, y + height], [kx, 0.0], [-kx, 0.0]),
+                vertex([x - width, y], [0.0, ky], [0.0, -ky]),
+            ]
+        }
+        "sr" => {
+            let points = number_at(item.get("pt"), frame, 5.0)
+                .round()
+                .clamp(3.0, 100.0) as usize;
+            let outer = number_at(item.get("or"), frame, 0.0);
+            let inner = number_at(item.get("ir"), frame, 0.0);
+            let star = item.number_of("sy") != Some(2.0);
+            let corners = if star { points * 2 } else { points };
+            let rotation = number_at(item.get("r"), frame, 0.0);
+            (0..corners)
+                .map(|i| {
+                    let angle = (rotation - 90.0 + i as f32 * 360.0 / corners as f32).to_radians();
+                    let radius = if star && i % 2 == 1 { inner } else { outer };
+                    [[x + radius * angle.cos(), y + radius * angle.sin()]; 3]
+                })
+                .collect()
+        }
+        _ => return None,
+    };
+    Some(Bezier {
+        vertices,
+        closed: true,
+    })
+}
+
+/// Contour of `bezier` transformed by `matrix`
+fn flatten(bezier: &Bezier, matrix: &Matrix) -> Contour {
+    let mut contour = Contour {
+        points: Vec::new(),
+        closed: bezier.closed,
+    };
+    let Some(first) = bezier.vertices.first() else {
+        return contour;
+    };
+    contour.points.push(matrix.apply(first[0]));
+    let closing = bezier
+        .closed
+        .then_some((bezier.vertices.last(), Some(first)));
+    let pairs = bezier
+        .vertices
+        .windows(2)
+        .map(|pair| (pair.first(), pair.get(1)));
+    for (from, to) in pairs.chain(closing) {
+        if let (Some(from), Some(to)) = (from, to) {
+            contour.cubic(
+                matrix.apply(from[2]),
+                matrix.apply(to[1]),
+                matrix.apply(to[0]),
+            );
+        }
+    }
+    contour
+}
+
+/// Parts of `contours` a trim path item keeps at `frame`
+fn trim(contours: Vec<Contour>, item: &Value, frame: f32) -> Vec<Contour> {
+    let start = number_at(item.get("s"), frame, 0.0) / 100.0;
+    let end = number_at(item.get("e"), frame, 100.0) / 100.0;
+    let offset = number_at(item.get("o"), frame, 0.0) / 360.0;
+    let (start, end) = (start.min(end), start.max(end));
+    if end - start >= 1.0 {
+        return contours;
+    }
+    let length = end - start;
+    if length <= 0.0 {
+        return Vec::new();
+    }
+    let start = (start + offset).rem_euclid(1.0);
+    let end = start + length;
+    // Parts from `start` to `end` of a contour or of all of them, from 0 to 1 and wrapping
+    let ranges = match end > 1.0 {
+        true => vec![(start, 1.0), (0.0, end - 1.0)],
+        false => vec![(start, end)],
+    };
+    let lengths = contours.iter().map(Contour::length).collect::<Vec<_>>();
+    match item.number_of("m") {
+        // One after the other
+        Some(2.0) => {
+            let total = lengths.iter().sum::<f32>();
+            let mut at = 0.0;
+            let mut parts = Vec::new();
+            for (contour, length) in contours.iter().zip(&lengths) {
+                for (from, to) in &ranges {
+                    let (from, to) = (from * total - at, to * total - at);
+                    if to > 0.0 && from < *length {
+                        parts.push(contour.trim(from, to));
+                    }
+                }
+                at += length;
+            }
+            parts
+        }
+        _ => contours
+            .iter()
+            .zip(&lengths)
+            .flat_map(|(contour, length)| {
+                ranges
+                    .iter()
+                    .map(|(from, to)| contour.trim(from * length, to * length))
+                    .collect::<Vec<_>>()
+            })
+            .collect(),
+    }
+}
+
+/// Colors of a fill, stroke or gradient item at `frame`, with gradients in the space of
+/// `matrix`
+fn paint(item: &Value, frame: f32, matrix: &Matrix) -> Option<Paint> {
+    let kind = item.get("ty").and_then(Value::as_str)?;
+    if kind == "fl" || kind == "st" {
+        let color = numbers_at(item.get("c"), frame)?;
+        return Some(Paint::Solid(color_of(&color)));
+    }
+    let gradient = item.get("g")?;
+    let count = gradient.number_of("p")? as usize;
+    let values = numbers_at(gradient.get("k"), frame)?;
+    let colors = values.get(..count * 4)?;
+    let opacities = &values[count * 4..];
+    let opacity_at = |t: f32| {
+        let stops = opacities
+            .chunks_exact(2)
+            .map(|s| (s[0], s[1]))
+            .collect::<Vec<_>>();
+        let after = stops.iter().position(|(offset, _)| *offset >= t);
+        match after {
+            None if stops.is_empty() => 1.0,
+            Some(0) => stops[0].1,
+            Some(i) => {
+                let ((t0, a0), (t1, a1)) = (stops[i - 1], stops[i]);
+                a0 + (a1 - a0) * (t - t0) / (t1 - t0).max(1e-6)
+            }
+            None => stops[stops.len() - 1].1,
+        }
+    };
+    let stops = colors
+        .chunks_exact(4)
+        .map(|s| (s[0], [s[1], s[2], s[3], opacity_at(s[0])]))
+        .collect();
+    Some(Paint::Gradient {
+        radial: item.number_of("t") == Some(2.0),
+        start: point_at(item.get("s"), frame),
+        end: point_at(item.get("e"), frame),
+        stops,
+        inverse: matrix.invert()?,
+    })
+}
+
+/// Rgba of Lottie color components, from 0 to 1 or in old files to 255
+fn color_of(components: &[f32]) -> [f32; 4] {
+    let scale = match components.iter().take(3).any(|c| *c > 1.0) {
+        true => 255.0,
+        false => 1.0,
+    };
+    let at = |i: usize, default: f32| {
+        (components.get(i).copied().unwrap_or(default) / scale).clamp(0.0, 1.0)
+    };
+    [at(0, 0.0), at(1, 0.0), at(2, 0.0), at(3, scale)]
+}
+
+/// Rgba of a `#rrggbb` color
+fn hex_color(hex: &str) -> Option<[f32; 4]> {
+    let hex = hex.strip_prefix('#')?;
+    let channel = |i: usize| Some(u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()? as f32 / 255.0);
+    Some([channel(0)?, channel(2)?, channel(4)?, 1.0])
+}
+
+/// Number of an animated property at `frame`, `default` without one
+fn number_at(property: Option<&Value>, frame: f32, default: f32) -> f32 {
+    numbers_at(property, frame)
+        .and_then(|n| n.first().copied())
+        .unwrap_or(default)
+}
+
+/// Point of an animated property at `frame`, the origin without one
+fn point_at(property: Option<&Value>, frame: f32) -> Point {
+    let numbers = numbers_at(property, frame).unwrap_or_default();
+    let at = |i: usize| numbers.get(i).copied().unwrap_or(0.0);
+    [at(0), at(1)]
+}
+
+fn numbers(value: &Value) -> Option<Vec<f32>> {
+    match value {
+        Value::Number(n) => Some(vec![n.as_f64()? as f32]),
+        Value::Array(items) => items.iter().map(|n| number(n).map(|n| n as f32)).collect(),
+        _ => None,
+    }
+}
+
+/// Numbers of an animated property at `frame`
+fn numbers_at(property: Option<&Value>, frame: f32) -> Option<Vec<f32>> {
+    animated(property?, frame, &numbers)
+}
+
+/// Path of an animated shape property at `frame`
+fn shape_at(property: &Value, frame: f32) -> Option<Bezier> {
+    // Shapes are animated as their vertices and handles, then whether they are closed
+    let values = |value: &Value| {
+        let shape = match value {
+            Value::Array(items) => items.first()?,
+            shape => shape,
+        };
+        let points = |key: &str| {
+            shape.get(key).map_or(Some(Vec::new()), |points| {
+                points
+                    .items()
+                    .iter()
+                    .map(numbers)
+                    .collect::<Option<Vec<_>>>()
+            })
+        };
+        let (vertices, before, after) = (points("v")?, points("i")?, points("o")?);
+        let mut values = Vec::with_capacity(vertices.len() * 6 + 1);
+        for (i, vertex) in vertices.iter().enumerate() {
+            for point in [Some(vertex), before.get(i), after.get(i)] {
+                let point = point.map_or(&[][..], Vec::as_slice);
+                values.push(point.first().copied().unwrap_or(0.0));
+                values.push(point.get(1).copied().unwrap_or(0.0));
+            }
+        }
+        values.push(flag(shape, "c") as u8 as f32);
+        Some(values)
+    };
+    let values = animated(property, frame, &values)?;
+    let (closed, values) = values.split_last()?;
+    let vertices = values
+        .chunks_exact(6)
+        .map(|v| {
+            [
+                [v[0], v[1]],
+                [v[0] + v[2], v[1] + v[3]],
+                [v[0] + v[4], v[1] + v[5]],
+            ]
+        })
+        .collect();
+    Some(Bezier {
+        vertices,
+        closed: *closed >= 0.5,
+    })
+}
+
+/// Values of a property, still or interpolated between its keyframes at `frame`
+fn animated(
+    property: &Value,
+    frame: f32,
+    values: &dyn Fn(&Value) -> Option<Vec<f32>>,
+) -> Option<Vec<f32>> {
+    let k = property.get("k")?;
+    let keyframes = k.items();
+    let is_animated = keyframes
+        .first()
+        .is_some_and(|first| first.get("t").is_some());
+    if !is_animated {
+        return values(k);
+    }
+    let time = |keyframe: &Value| keyframe.number_of("t").unwrap_or(0.0) as f32;
+    // Keyframes start with their value, old files also give the value they end at
+    let start = |i: usize| {
+        let keyframe: &Value = &keyframes[i];
+        match keyframe.get("s") {
+            Some(s) => values(s),
+            None => keyframes[..i].last()?.get("e").and_then(values),
+        }
+    };
+    let i = keyframes
+        .iter()
+        .rposition(|keyframe| time(keyframe) <= frame)
+        .unwrap_or(0);
+    let keyframe = &keyframes[i];
+    let Some(next) = keyframes.get(i + 1) else {
+        return start(i);
+    };
+    if frame < time(keyframe) || flag(keyframe, "h") {
+        return start(i);
+    }
+    let from = start(i)?;
+    let to = match keyframe.get("e") {
+        Some(e) => values(e),
+        None => start(i + 1),
+    };
+    let to = to
+        .filter(|to| to.len() == from.len())
+        .unwrap_or_else(|| from.clone());
+    let progress =
+        ((frame - time(keyframe)) / (time(next) - time(keyframe)).max(1e-6)).clamp(0.0, 1.0);
+    let eased = |dimension: usize| ease(keyframe, dimension, progress);
+    // Points move along curves between keyframes with handles
+    let handles = keyframe
+        .get("to")
+        .and_then(numbers)
+        .zip(keyframe.get("ti").and_then(numbers));
+    if let Some((out, into)) =
+        handles.filter(|(out, into)| from.len() >= 2 && out.len() >= 2 && into.len() >= 2)
+    {
+        let t = eased(0);
+        let u = 1.0 - t;
+        let (w0, w1, w2, w3) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+        return Some(
+            (0..from.len())
+                .map(|d| {
+                    let (c0, c1) = (
+                        from[d] + out.get(d).unwrap_or(&0.0),
+                        to[d] + into.get(d).unwrap_or(&0.0),
+                    );
+                    w0 * from[d] + w1 * c0 + w2 * c1 + w3 * to[d]
+                })
+                .collect(),
+        );
+    }
+    Some(
+        from.iter()
+            .zip(&to)
+            .enumerate()
+            .map(|(d, (from, to))| from + (to - from) * eased(d))
+            .collect(),
+    )
+}
+
+/// Progress eased by the bezier curve between a keyframe and the next, for `dimension`
+fn ease(keyframe: &Value, dimension: usize, progress: f32) -> f32 {
+    let handle = |key: &str, axis: &str, default: f32| {
+        let value = keyframe.get(key).and_then(|handle| handle.get(axis));
+        let numbers = value.and_then(numbers).unwrap_or_default();
+        let at = numbers.get(dimension).or(numbers.last());
+        at.copied().unwrap_or(default)
+    };
+    let (x1, y1) = (handle("o", "x", 0.0).clamp(0.0, 1.0), handle("o", "y", 0.0));
+    let (x2, y2) = (handle("i", "x", 1.0).clamp(0.0, 1.0), handle("i", "y", 1.0));
+    let bezier = |a: f32, b: f32, s: f32| {
+        let u = 1.0 - s;
+        3.0 * u * u * s * a + 3.0 * u * s * s * b + s * s * s
+    };
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..24 {
+        let middle = (low + high) / 2.0;
+        match bezier(x1, x2, middle) < progress {
+            true => low = middle,
+            false => high = middle,
+        }
+    }
+    bezier(y1, y2, (low + high) / 2.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    /// Lottie of a square red at the start and blue at the end, moving right, over a green
+    /// circle in a precomposition
+    const LOTTIE: &[u8] = br##"{"v":"5.5.2","fr":30,"ip":0,"op":30,"w":100,"h":100,
+        "assets":[{"id":"comp","layers":[{"ty":4,"ind":1,"ip":0,"op":30,"st":0,
+            "ks":{"p":{"a":0,"k":[50,50]}},
+            "shapes":[{"ty":"el","p":{"a":0,"k":[0,0]},"s":{"a":0,"k":[100,100]}},
+                {"ty":"fl","c":{"a":0,"k":[0,1,0,1]},"o":{"a":0,"k":100}}]}]}],
+        "layers":[
+            {"ty":4,"ind":1,"ip":0,"op":30,"st":0,
+                "ks":{"p":{"a":1,"k":[{"t":0,"s":[20,50],"o":{"x":0,"y":0},"i":{"x":1,"y":1}},{"t":30,"s":[80,50]}]},
+                    "o":{"a":0,"k":100}},
+                "shapes":[{"ty":"gr","it":[
+                    {"ty":"rc","p":{"a":0,"k":[0,0]},"s":{"a":0,"k":[20,20]},"r":{"a":0,"k":0}},
+                    {"ty":"fl","c":{"a":1,"k":[{"t":0,"s":[1,0,0,1]},{"t":30,"s":[0,0,1,1]}]},"o":{"a":0,"k":100}},
+                    {"ty":"tr","p":{"a":0,"k":[0,0]},"a":{"a":0,"k":[0,0]},"s":{"a":0,"k":[100,100]},"r":{"a":0,"k":0},"o":{"a":0,"k":100}}]}]},
+            {"ty":0,"ind":2,"refId":"comp","ip":0,"op":30,"st":0,"w":100,"h":100,"ks":{}}]}"##;
+    #[test]
+    fn test_frames() {
+        let animation = Animation::parse(LOTTIE).unwrap();
+        let frames = animation.frames(50, 10, 100);
+        assert_eq!(frames.len(), 10);
+        assert_eq!(frames[0].1, Duration::from_millis(100));
+        let (first, last) = (&frames[0].0, &frames[5].0);
+        assert_eq!((first.width, first.height), (50, 50));
+        // The square starts at the left, over the circle
+        assert_eq!(first.pixel(10, 25), [255, 0, 0, 255]);
+        assert_eq!(first.pixel(25, 25), [0, 255, 0, 255]);
+        assert_eq!(first.pixel(1, 1), [0; 4]);
+        // And is halfway through at half the time
+        assert_eq!(last.pixel(10, 25), [0, 255, 0, 255]);
+        let [r, g, b, a] = last.pixel(25, 25);
+        assert!((120..=135).contains(&r) && g == 0 && (120..=135).contains(&b) && a == 255);
+        assert_eq!(animation.frames(50, 60, 4).len(), 4);
+    }
+    #[test]
+    fn test_parse() {
+        let malformed = |json: &[u8]| matches!(Animation::parse(json), Err(LottieError::Malformed));
+        assert!(malformed(br#"{"fr":60,"ip":0,"op":0,"w":512,"h":512}"#));
+        assert!(malformed(b"{}"));
+        assert!(malformed(b"{"));
+    }
+    #[test]
+    fn test_parse_unsupported() {
+        let lottie = br#"{"fr":60,"ip":0,"op":60,"w":512,"h":512,
+            "assets":[{"id":"a","layers":[{"ty":5,"ip":0,"op":60}]}],
+            "layers":[
+                {"ty":0,"refId":"a","ip":0,"op":60,"hasMask":true,"masksProperties":[{}]},
+                {"ty":4,"ip":0,"op":60,
+                    "ks":{"o":{"a":0,"k":100,"x":"var $bm_rt = 50;"}},
+                    "shapes":[{"ty":"gr","it":[{"ty":"rc"},{"ty":"rp"},{"ty":"fl"}]},{"ty":"mm"}]}]}"#;
+        match Animation::parse(lottie) {
+            Err(LottieError::Unsupported(features)) => assert_eq!(
+                features,
+                ["expressions", "masks", "merge paths", "repeaters", "text"]
+            ),
+            _ => panic!("drawn without its unsupported features"),
+        }
+        // Easing handles are named `x` too
+        let eased = br#"{"fr":60,"ip":0,"op":60,"w":512,"h":512,"layers":[{"ty":4,"ip":0,"op":60,
+            "ks":{"o":{"a":1,"k":[{"t":0,"s":[0],"o":{"x":[0.5],"y":[0]},"i":{"x":[0.5],"y":[1]}},{"t":60,"s":[100]}]}}}]}"#;
+        assert!(Animation::parse(eased).is_ok());
+    }
+    #[test]
+    fn test_animated() {
+        let property = serde_json::from_slice::<Value>(
+            br#"{"a":1,"k":[{"t":0,"s":[0],"h":1},{"t":10,"s":[10],"o":{"x":[0.5],"y":[0]},"i":{"x":[0.5],"y":[1]}},{"t":20,"s":[20]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(number_at(Some(&property), 5.0, 0.0), 0.0);
+        let eased = number_at(Some(&property), 12.0, 0.0);
+        assert!(eased > 10.0 && eased < 12.0, "{eased}");
+        assert!((number_at(Some(&property), 15.0, 0.0) - 15.0).abs() < 0.01);
+        assert_eq!(number_at(Some(&property), 25.0, 0.0), 20.0);
+        let still = serde_json::from_slice::<Value>(br#"{"a":0,"k":[3,4]}"#).unwrap();
+        assert_eq!(point_at(Some(&still), 25.0), [3.0, 4.0]);
+    }
+    #[test]
+    fn test_trim() {
+        let line = Contour {
+            points: vec![[0.0, 0.0], [10.0, 0.0]],
+            closed: false,
+        };
+        let item = serde_json::from_slice::<Value>(
+            br#"{"ty":"tm","s":{"a":0,"k":50},"e":{"a":0,"k":100},"o":{"a":0,"k":90}}"#,
+        )
+        .unwrap();
+        let parts = trim(vec![line], &item, 0.0);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].points, [[7.5, 0.0], [10.0, 0.0]]);
+        assert_eq!(parts[1].points, [[0.0, 0.0], [2.5, 0.0]]);
+        let empty = serde_json::from_slice::<Value>(
+            br#"{"ty":"tm","s":{"a":0,"k":30},"e":{"a":0,"k":30}}"#,
+        )
+        .unwrap();
+        assert!(trim(parts, &empty, 0.0).is_empty());
+    }
+    #[test]
+    fn test_draw_trimmed() {
+        // The trim after the stroke, in the group around it, still trims its line to the left half
+        let items = serde_json::from_slice::<Value>(
+            br#"[{"ty":"gr","it":[
+                {"ty":"sh","ks":{"a":0,"k":{"v":[[0,5],[20,5]],"i":[[0,0],[0,0]],"o":[[0,0],[0,0]],"c":false}}},
+                {"ty":"st","c":{"a":0,"k":[1,1,1,1]},"o":{"a":0,"k":100},"w":{"a":0,"k":4}}]},
+                {"ty":"tm","s":{"a":0,"k":0},"e":{"a":0,"k":50}}]"#,
+        )
+        .unwrap();
+        let mut canvas = Canvas::new(20, 10);
+        draw_items(items.items(), 0.0, &Matrix::IDENTITY, &[], &mut canvas, 0);
+        let image = canvas.to_image();
+        assert_eq!(image.pixel(5, 5), [255; 4]);
+        assert_eq!(image.pixel(15, 5), [0; 4]);
+    }
+}
@@ -0,0 +1,599 @@
+//! Rasterizer of filled and stroked paths, antialiased by the area they cover in each pixel
+
+use crate::png::RgbaImage;
+
+pub(super) type Point = [f32; 2];
+
+/// Affine transform of points, `[a, b, c, d, e, f]` maps `x`, `y` to
+/// `a x + c y + e`, `b x + d y + f`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct Matrix(pub [f32; 6]);
+
+impl Matrix {
+    pub const IDENTITY: Matrix = Matrix([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+    pub fn translate(x: f32, y: f32) -> Self {
+        Matrix([1.0, 0.0, 0.0, 1.0, x, y])
+    }
+    pub fn scale(x: f32, y: f32) -> Self {
+        Matrix([x, 0.0, 0.0, y, 0.0, 0.0])
+    }
+    /// Clockwise rotation by `degrees`, as y grows down
+    pub fn rotate(degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Matrix([cos, sin, -sin, cos, 0.0, 0.0])
+    }
+    /// Skew by `degrees` along the axis at `axis` degrees
+    pub fn skew(degrees: f32, axis: f32) -> Self {
+        let shear = Matrix([1.0, 0.0, -degrees.to_radians().tan(), 1.0, 0.0, 0.0]);
+        Matrix::rotate(axis)
+            .then(&shear)
+            .then(&Matrix::rotate(-axis))
+    }
+    /// Transform applying `self`, then `after`
+    pub fn then(&self, after: &Matrix) -> Matrix {
+        let [a, b, c, d, e, f] = self.0;
+        let [a2, b2, c2, d2, e2, f2] = after.0;
+        Matrix([
+            a2 * a + c2 * b,
+            b2 * a + d2 * b,
+            a2 * c + c2 * d,
+            b2 * c + d2 * d,
+            a2 * e + c2 * f + e2,
+            b2 * e + d2 * f + f2,
+        ])
+    }
+    pub fn apply(&self, [x, y]: Point) -> Point {
+        let [a, b, c, d, e, f] = self.0;
+        [a * x + c * y + e, b * x + d * y + f]
+    }
+    /// Inverse transform, `None` if it flattens points on a line
+    pub fn invert(&self) -> Option<Matrix> {
+        let [a, b, c, d, e, f] = self.0;
+        let det = a * d - b * c;
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let (a, b, c, d) = (d / det, -b / det, -c / det, a / det);
+        Some(Matrix([a, b, c, d, -(a * e + c * f), -(b * e + d * f)]))
+    }
+    /// How much lengths grow, on average over directions
+    pub fn scale_factor(&self) -> f32 {
+        let [a, b, c, d, ..] = self.0;
+        (a * d - b * c).abs().sqrt()
+    }
+}
+
+/// Line through points, closed back to its first one or open
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(super) struct Contour {
+    pub points: Vec<Point>,
+    pub closed: bool,
+}
+
+impl Contour {
+    /// Add the cubic bezier curve from the last point through controls `c0`, `c1` to `to`
+    pub fn cubic(&mut self, c0: Point, c1: Point, to: Point) {
+        let from = *self.points.last().unwrap_or(&to);
+        if c0 == from && c1 == to {
+            return self.points.push(to);
+        }
+        let length = distance(from, c0) + distance(c0, c1) + distance(c1, to);
+        // Segments less than about a pixel long
+        let steps = (length / 1.5).ceil().clamp(1.0, 100.0) as usize;
+        for step in 1..=steps {
+            let t = step as f32 / steps as f32;
+            let u = 1.0 - t;
+            let (w0, w1, w2, w3) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+            self.points.push([
+                w0 * from[0] + w1 * c0[0] + w2 * c1[0] + w3 * to[0],
+                w0 * from[1] + w1 * c0[1] + w2 * c1[1] + w3 * to[1],
+            ]);
+        }
+    }
+    /// Segments of the contour, with the closing one
+    fn segments(&self) -> impl Iterator<Item = (Point, Point)> + '_ {
+        let closing = match self.closed {
+            true => self.points.last().zip(self.points.first()),
+            false => None,
+        };
+        self.points
+            .windows(2)
+            .map(|pair| (pair[0], pair[1]))
+            .chain(closing.map(|(a, b)| (*a, *b)))
+    }
+    pub fn length(&self) -> f32 {
+        self.segments().map(|(a, b)| distance(a, b)).sum()
+    }
+    /// Open part of the contour from length `start` to `end` along it
+    pub fn trim(&self, start: f32, end: f32) -> Contour {
+        let mut part = Contour::default();
+        let mut at = 0.0;
+        for (a, b) in self.segments() {
+            let length = distance(a, b);
+            let (from, to) = ((start - at).max(0.0), (end - at).min(length));
+            if from <= to && length > 0.0 {
+                let point = |t: f32| lerp(a, b, t / length);
+                if part.points.is_empty() {
+                    part.points.push(point(from));
+                }
+                part.points.push(point(to));
+            }
+            at += length;
+        }
+        part
+    }
+}
+
+fn distance(a: Point, b: Point) -> f32 {
+    (a[0] - b[0]).hypot(a[1] - b[1])
+}
+
+fn lerp(a: Point, b: Point, t: f32) -> Point {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t]
+}
+
+/// Whether points in overlaps of a path are inside it, or only in odd ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum FillRule {
+    NonZero,
+    EvenOdd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Cap {
+    Butt,
+    Round,
+    Square,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Join {
+    /// Miter with its limit relative to half the width
+    Miter(f32),
+    Round,
+    Bevel,
+}
+
+/// Colors of a fill or stroke, straight rgba from 0 to 1
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Paint {
+    Solid([f32; 4]),
+    /// Gradient between `start` and `end` of `stops`, in the space `inverse` maps pixels to
+    Gradient {
+        radial: bool,
+        start: Point,
+        end: Point,
+        stops: Vec<(f32, [f32; 4])>,
+        inverse: Matrix,
+    },
+}
+
+impl Paint {
+    fn at(&self, point: Point) -> [f32; 4] {
+        let (radial, start, end, stops, inverse) = match self {
+            Paint::Solid(color) => return *color,
+            Paint::Gradient {
+                radial,
+                start,
+                end,
+                stops,
+                inverse,
+            } => (radial, start, end, stops, inverse),
+        };
+        let [x, y] = inverse.apply(point);
+        let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+        let squared = (dx * dx + dy * dy).max(1e-6);
+        let t = match radial {
+            true => ((x - start[0]).hypot(y - start[1]) / squared.sqrt()).min(1.0),
+            false => (((x - start[0]) * dx + (y - start[1]) * dy) / squared).clamp(0.0, 1.0),
+        };
+        let after = stops.iter().position(|(offset, _)| *offset >= t);
+        match after {
+            Some(0) => stops[0].1,
+            Some(i) => {
+                let ((t0, c0), (t1, c1)) = (stops[i - 1], stops[i]);
+                let f = (t - t0) / (t1 - t0).max(1e-6);
+                std::array::from_fn(|k| c0[k] + (c1[k] - c0[k]) * f)
+            }
+            None => stops.last().map_or([0.0; 4], |(_, color)| *color),
+        }
+    }
+}
+
+/// Premultiplied rgba pixels from 0 to 1, row by row
+#[derive(Debug, Clone)]
+pub(super) struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Canvas {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0.0; 4]; width * height],
+        }
+    }
+    /// Paint the inside of `contours`, all closed, with `opacity`
+    pub fn fill(&mut self, contours: &[Contour], rule: FillRule, paint: &Paint, opacity: f32) {
+        let mut coverage = Coverage::new(self.width, self.height);
+        for contour in contours {
+            let points = &contour.points;
+            for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+                coverage.line(*a, *b);
+            }
+        }
+        self.paint(&coverage.finish(rule), paint, opacity);
+    }
+    /// Paint a line of `width` along `contours`
+    pub fn stroke(
+        &mut self,
+        contours: &[Contour],
+        width: f32,
+        (cap, join): (Cap, Join),
+        paint: &Paint,
+        opacity: f32,
+    ) {
+        let mut coverage = Coverage::new(self.width, self.height);
+        for contour in contours {
+            stroke(&mut coverage, contour, width / 2.0, cap, join);
+        }
+        self.paint(&coverage.finish(FillRule::NonZero), paint, opacity);
+    }
+    fn paint(&mut self, coverage: &[f32], paint: &Paint, opacity: f32) {
+        for (i, (pixel, coverage)) in self.pixels.iter_mut().zip(coverage).enumerate() {
+            if *coverage <= 0.0 {
+                continue;
+            }
+            let point = [(i % self.width) as f32 + 0.5, (i / self.width) as f32 + 0.5];
+            let color = paint.at(point);
+            let alpha = color[3] * opacity * coverage;
+            let source = [color[0] * alpha, color[1] * alpha, color[2] * alpha, alpha];
+            *pixel = std::array::from_fn(|k| source[k] + pixel[k] * (1.0 - alpha));
+        }
+    }
+    /// Draw `layer` over the canvas with `opacity`
+    pub fn draw(&mut self, layer: &Canvas, opacity: f32) {
+        for (pixel, source) in self.pixels.iter_mut().zip(&layer.pixels) {
+            let alpha = source[3] * opacity;
+            *pixel = std::array::from_fn(|k| source[k] * opacity + pixel[k] * (1.0 - alpha));
+        }
+    }
+    /// Keep the canvas where `matte` is opaque, or transparent when `inverted`, or bright
+    /// with `luma`
+    pub fn matte(&mut self, matte: &Canvas, luma: bool, inverted: bool) {
+        for (pixel, matte) in self.pixels.iter_mut().zip(&matte.pixels) {
+            let mut alpha = match luma {
+                true => 0.299 * matte[0] + 0.587 * matte[1] + 0.114 * matte[2],
+                false => matte[3],
+            };
+            if inverted {
+                alpha = 1.0 - alpha;
+            }
+            *pixel = pixel.map(|c| c * alpha);
+        }
+    }
+    pub fn to_image(&self) -> RgbaImage {
+        let mut image = RgbaImage::new(self.width as u32, self.height as u32);
+        for (out, pixel) in image.pixels.chunks_mut(4).zip(&self.pixels) {
+            let alpha = pixel[3].clamp(0.0, 1.0);
+            if alpha > 0.0 {
+                let straight = |c: f32| ((c / alpha).clamp(0.0, 1.0) * 255.0).round() as u8;
+                out.copy_from_slice(&[
+                    straight(pixel[0]),
+                    straight(pixel[1]),
+                    straight(pixel[2]),
+                    (alpha * 255.0).round() as u8,
+                ]);
+            }
+        }
+        image
+    }
+}
+
+/// Signed area of lines in each pixel, summed along rows into coverage
+struct Coverage {
+    width: usize,
+    height: usize,
+    /// Rows of a column more than the canvas, where lines right of it end up
+    cells: Vec<f32>,
+}
+
+impl Coverage {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![0.0; (width + 2) * height],
+        }
+    }
+    /// Add the closed polygon of `points`, turning the same way as all others so that they
+    /// add up where they overlap
+    fn polygon(&mut self, points: &[Point]) {
+        let area = points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .map(|(a, b)| a[0] * b[1] - b[0] * a[1])
+            .sum::<f32>();
+        let edges = points.iter().zip(points.iter().cycle().skip(1));
+        for (a, b) in edges {
+            match area >= 0.0 {
+                true => self.line(*a, *b),
+                false => self.line(*b, *a),
+            }
+        }
+    }
+    fn line(&mut self, a: Point, b: Point) {
+        if a[1] == b[1]
+            || !(a[0].is_finite() && a[1].is_finite() && b[0].is_finite() && b[1].is_finite())
+        {
+            return;
+        }
+        // Parts left or right of the canvas run along its sides instead
+        let right = self.width as f32;
+        let mut cuts = [0.0, right]
+            .iter()
+            .filter(|x| (a[0] - **x) * (b[0] - **x) < 0.0)
+            .map(|x| (x - a[0]) / (b[0] - a[0]))
+            .collect::<Vec<_>>();
+        cuts.sort_by(f32::total_cmp);
+        let mut from = a;
+        for t in cuts.into_iter().chain([1.0]) {
+            let to = lerp(a, b, t);
+            let clamp = |p: Point| [p[0].clamp(0.0, right), p[1]];
+            self.clipped(clamp(from), clamp(to));
+            from = to;
+        }
+    }
+    /// Add a line within the columns of the canvas
+    fn clipped(&mut self, a: Point, b: Point) {
+        let (direction, top, bottom) = match a[1] < b[1] {
+            true => (1.0, a, b),
+            false => (-1.0, b, a),
+        };
+        let slope = (bottom[0] - top[0]) / (bottom[1] - top[1]);
+        let first = top[1].max(0.0).floor() as usize;
+        let last = (bottom[1].ceil().max(0.0) as usize).min(self.height);
+        let stride = self.width + 2;
+        for row in first..last {
+            let y0 = (row as f32).max(top[1]);
+            let y1 = ((row + 1) as f32).min(bottom[1]);
+            if y1 <= y0 {
+                continue;
+            }
+            let x0 = top[0] + (y0 - top[1]) * slope;
+            let x1 = top[0] + (y1 - top[1]) * slope;
+            let height = (y1 - y0) * direction;
+            let (left, right) = (x0.min(x1), x0.max(x1));
+            let cells = &mut self.cells[row * stride..(row + 1) * stride];
+            let first_column = left.floor() as usize;
+            let last_column = (right.ceil() as usize).max(first_column + 1);
+            for column in first_column..last_column.min(stride - 1) {
+                let (from, to) = (left.max(column as f32), right.min(column as f32 + 1.0));
+                let share = match right > left {
+                    true => (to - from) / (right - left),
+                    false => 1.0,
+                };
+                let middle = (from + to) / 2.0 - column as f32;
+                cells[column] += height * share * (1.0 - middle);
+                cells[column + 1] += height * share * middle;
+            }
+        }
+    }
+    fn finish(self, rule: FillRule) -> Vec<f32> {
+        let stride = self.width + 2;
+        let mut coverage = Vec::with_capacity(self.width * self.height);
+        for row in self.cells.chunks(stride) {
+            let mut sum = 0.0;
+            for cell in &row[..self.width] {
+                sum += cell;
+                let winding = f32::abs(sum);
+                coverage.push(match rule {
+                    FillRule::NonZero => winding.min(1.0),
+                    FillRule::EvenOdd => 1.0 - (winding % 2.0 - 1.0).abs(),
+                });
+            }
+        }
+        coverage
+    }
+}
+
+/// Add the outline of a line of half width `half` along `contour`
+fn stroke(coverage: &mut Coverage, contour: &Contour, half: f32, cap: Cap, join: Join) {
+    let mut points = contour.points.clone();
+    points.dedup_by(|a, b| distance(*a, *b) < 1e-4);
+    if contour.closed && points.len() > 1 && distance(points[0], points[points.len() - 1]) < 1e-4 {
+        points.pop();
+    }
+    let Some(&first) = points.first() else {
+        return;
+    };
+    if points.len() == 1 {
+        if cap == Cap::Round {
+            coverage.polygon(&circle(first, half));
+        }
+        return;
+    }
+    let count = points.len();
+    let direction = |from: Point, to: Point| {
+        let length = distance(from, to);
+        [(to[0] - from[0]) / length, (to[1] - from[1]) / length]
+    };
+    if !contour.closed && cap == Cap::Square {
+        let start = direction(points[1], points[0]);
+        points[0] = [first[0] + start[0] * half, first[1] + start[1] * half];
+        let end = direction(points[count - 2], points[count - 1]);
+        let last = points[count - 1];
+        points[count - 1] = [last[0] + end[0] * half, last[1] + end[1] * half];
+    }
+    let segments = match contour.closed {
+        true => count,
+        false => count - 1,
+    };
+    let normal = |d: Point| [-d[1] * half, d[0] * half];
+    let offset = |p: Point, n: Point, s: f32| [p[0] + n[0] * s, p[1] + n[1] * s];
+    for i in 0..segments {
+        let (a, b) = (points[i], points[(i + 1) % count]);
+        let n = normal(direction(a, b));
+        coverage.polygon(&[
+            offset(a, n, 1.0),
+            offset(b, n, 1.0),
+            offset(b, n, -1.0),
+            offset(a, n, -1.0),
+        ]);
+    }
+    let joins = match contour.closed {
+        true => 0..count,
+        false => 1..count - 1,
+    };
+    for i in joins {
+        let (before, at, after) = (
+            points[(i + count - 1) % count],
+            points[i],
+            points[(i + 1) % count],
+        );
+        let (d0, d1) = (direction(before, at), direction(at, after));
+        let cross = d0[0] * d1[1] - d0[1] * d1[0];
+        // The side the line turns away from
+        let side = if cross > 0.0 { -1.0 } else { 1.0 };
+        let (a, b) = (offset(at, normal(d0), side), offset(at, normal(d1), side));
+        match join {
+            Join::Round => coverage.polygon(&circle(at, half)),
+            Join::Miter(limit) if cross.abs() > 1e-6 => {
+                // Where the outer sides of both segments meet
+                let t = ((b[0] - a[0]) * d1[1] - (b[1] - a[1]) * d1[0]) / cross;
+                let miter = [a[0] + d0[0] * t, a[1] + d0[1] * t];
+                match distance(miter, at) <= limit * half {
+                    true => coverage.polygon(&[at, a, miter, b]),
+                    false => coverage.polygon(&[at, a, b]),
+                }
+            }
+            _ => coverage.polygon(&[at, a, b]),
+        }
+    }
+    if !contour.closed && cap == Cap::Round {
+        coverage.polygon(&circle(first, half));
+        coverage.polygon(&circle(points[count - 1], half));
+    }
+}
+
+fn circle(center: Point, radius: f32) -> Vec<Point> {
+    let steps = (radius * std::f32::consts::TAU / 1.5)
+        .ceil()
+        .clamp(8.0, 64.0) as usize;
+    (0..steps)
+        .map(|i| {
+            let (sin, cos) = (i as f32 * std::f32::consts::TAU / steps as f32).sin_cos();
+            [center[0] + cos * radius, center[1] + sin * radius]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn square(left: f32, top: f32, size: f32) -> Contour {
+        Contour {
+            points: vec![
+                [left, top],
+                [left + size, top],
+                [left + size, top + size],
+                [left, top + size],
+            ],
+            closed: true,
+        }
+    }
+    fn alpha(canvas: &Canvas, x: usize, y: usize) -> f32 {
+        canvas.to_image().pixel(x as u32, y as u32)[3] as f32 / 255.0
+    }
+    #[test]
+    fn test_fill() {
+        let mut canvas = Canvas::new(10, 10);
+        let red = Paint::Solid([1.0, 0.0, 0.0, 1.0]);
+        canvas.fill(&[square(2.5, 2.0, 4.0)], FillRule::NonZero, &red, 1.0);
+        let image = canvas.to_image();
+        assert_eq!(image.pixel(4, 4), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(2, 3), [255, 0, 0, 128]);
+        assert_eq!(image.pixel(6, 3), [255, 0, 0, 128]);
+        assert_eq!(image.pixel(1, 3), [0; 4]);
+        assert_eq!(image.pixel(4, 6), [0; 4]);
+        // Shapes beyond the sides still cover what is in the canvas
+        let mut canvas = Canvas::new(4, 4);
+        canvas.fill(&[square(-10.0, -10.0, 30.0)], FillRule::NonZero, &red, 0.5);
+        assert_eq!(canvas.to_image().pixel(3, 3), [255, 0, 0, 128]);
+    }
+    #[test]
+    fn test_fill_rule() {
+        let red = Paint::Solid([1.0, 0.0, 0.0, 1.0]);
+        let squares = [square(0.0, 0.0, 8.0), square(2.0, 2.0, 4.0)];
+        let mut canvas = Canvas::new(8, 8);
+        canvas.fill(&squares, FillRule::NonZero, &red, 1.0);
+        assert_eq!(alpha(&canvas, 4, 4), 1.0);
+        let mut canvas = Canvas::new(8, 8);
+        canvas.fill(&squares, FillRule::EvenOdd, &red, 1.0);
+        assert_eq!(alpha(&canvas, 4, 4), 0.0);
+        assert_eq!(alpha(&canvas, 1, 4), 1.0);
+    }
+    #[test]
+    fn test_stroke() {
+        let line = Contour {
+            points: vec![[2.0, 5.0], [8.0, 5.0], [8.0, 9.0]],
+            closed: false,
+        };
+        let paint = Paint::Solid([0.0, 0.0, 1.0, 1.0]);
+        let mut canvas = Canvas::new(12, 12);
+        canvas.stroke(
+            std::slice::from_ref(&line),
+            2.0,
+            (Cap::Butt, Join::Miter(4.0)),
+            &paint,
+            1.0,
+        );
+        assert_eq!(alpha(&canvas, 4, 4), 1.0);
+        assert_eq!(alpha(&canvas, 4, 6), 0.0);
+        assert_eq!(alpha(&canvas, 1, 5), 0.0);
+        // The corner is mitered
+        assert_eq!(alpha(&canvas, 8, 4), 1.0);
+        let mut canvas = Canvas::new(12, 12);
+        canvas.stroke(&[line], 2.0, (Cap::Square, Join::Bevel), &paint, 1.0);
+        assert_eq!(alpha(&canvas, 1, 5), 1.0);
+        assert!(alpha(&canvas, 8, 4) < 1.0);
+    }
+    #[test]
+    fn test_gradient() {
+        let paint = Paint::Gradient {
+            radial: false,
+            start: [0.0, 0.0],
+            end: [5.0, 0.0],
+            stops: vec![(0.0, [0.0, 0.0, 0.0, 1.0]), (1.0, [1.0, 1.0, 1.0, 1.0])],
+            inverse: Matrix::scale(0.5, 0.5),
+        };
+        assert_eq!(paint.at([0.0, 3.0]), [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(paint.at([5.0, 3.0]), [0.5, 0.5, 0.5, 1.0]);
+        assert_eq!(paint.at([20.0, 3.0]), [1.0; 4]);
+    }
+    #[test]
+    fn test_matrix() {
+        let matrix = Matrix::translate(-1.0, 0.0)
+            .then(&Matrix::rotate(90.0))
+            .then(&Matrix::scale(2.0, 2.0));
+        let [x, y] = matrix.apply([1.0, 1.0]);
+        assert!((x + 2.0).abs() < 1e-5 && y.abs() < 1e-5, "{x} {y}");
+        let [x, y] = matrix.invert().unwrap().apply([-2.0, 0.0]);
+        assert!((x - 1.0).abs() < 1e-5 && (y - 1.0).abs() < 1e-5, "{x} {y}");
+    }
+    #[test]
+    fn test_trim() {
+        let line = Contour {
+            points: vec![[0.0, 0.0], [4.0, 0.0], [4.0, 4.0]],
+            closed: false,
+        };
+        assert_eq!(line.length(), 8.0);
+        assert_eq!(
+            line.trim(2.0, 6.0).points,
+            [[2.0, 0.0], [4.0, 0.0], [4.0, 2.0]]
+        );
+    }
+}
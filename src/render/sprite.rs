@@ -0,0 +1,214 @@
+use super::escape;
+use crate::emoji_url::{emoji_of, file_stem};
+use crate::png::RgbaImage;
+use crate::Token;
+use std::fmt::Write;
+
+/// Options of [`to_sprite_sheet`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpriteOptions {
+    /// Width and height of an emoji in the sheet, in pixels
+    pub size: u32,
+    /// Emoji per row of the sheet
+    pub columns: u32,
+    /// Class of every sprite, each one also gets `{class}-{name}`
+    pub class: String,
+    /// Url of the sheet in the css
+    pub url: String,
+}
+
+impl Default for SpriteOptions {
+    fn default() -> Self {
+        Self {
+            size: 72,
+            columns: 16,
+            class: "emoji".to_string(),
+            url: "emoji.png".to_string(),
+        }
+    }
+}
+
+/// Emoji of a token stream packed into one image, see [`to_sprite_sheet`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpriteSheet {
+    /// Png of the sheet
+    pub png: Vec<u8>,
+    /// Rules of the sprite classes
+    pub css: String,
+    /// The tokens as html, emoji become `<span>` of their sprite class
+    pub html: String,
+    /// Sprites in the order their emoji first appear
+    pub sprites: Vec<Sprite>,
+}
+
+/// Emoji in a [`SpriteSheet`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sprite {
+    /// Alt text, the emoji or `:name:` of a custom emoji
+    pub alt: String,
+    /// Class of the sprite, without the class every sprite has
+    pub class: String,
+    /// Left of the sprite in the sheet
+    pub x: u32,
+    /// Top of the sprite in the sheet
+    pub y: u32,
+}
+
+/// Pack the distinct emoji of `tokens` into a sprite sheet, with its css and the tokens as
+/// html using it, so a page loads one image instead of one per emoji
+///
+/// Emoji and fetched custom emoji with a png or gif image are packed, the first frame of
+/// animated ones. Other tokens are rendered like [`to_html`](super::to_html).
+pub fn to_sprite_sheet(tokens: &[Token], options: &SpriteOptions) -> SpriteSheet {
+    let mut sheet = Sheet {
+        options,
+        images: Vec::new(),
+        sprites: Vec::new(),
+    };
+    let mut html = String::new();
+    tokens
+        .iter()
+        .for_each(|token| sheet.push_html(token, &mut html));
+    let size = options.size.max(1);
+    let columns = options
+        .columns
+        .max(1)
+        .min(sheet.sprites.len().max(1) as u32);
+    let rows = (sheet.sprites.len() as u32).div_ceil(columns).max(1);
+    let mut png = RgbaImage::new(columns * size, rows * size);
+    let mut css = format!(
+        ".{}{{display:inline-block;width:{}px;height:{}px;background:url(\"{}\") no-repeat;vertical-align:middle}}\n",
+        options.class,
+        size,
+        size,
+        options.url.replace(['"', '\\'], "")
+    );
+    for (image, sprite) in sheet.images.iter().zip(&sheet.sprites) {
+        png.draw(&image.resized(size, size), sprite.x as i64, sprite.y as i64);
+        let _ = writeln!(
+            css,
+            ".{}-{}{{background-position:-{}px -{}px}}",
+            options.class, sprite.class, sprite.x, sprite.y
+        );
+    }
+    SpriteSheet {
+        png: png.to_png(),
+        css,
+        html,
+        sprites: sheet.sprites,
+    }
+}
+
+/// Sprites found so far with their images
+struct Sheet<'a> {
+    options: &'a SpriteOptions,
+    images: Vec<RgbaImage>,
+    sprites: Vec<Sprite>,
+}
+
+impl Sheet<'_> {
+    fn push_html(&mut self, token: &Token, out: &mut String) {
+        #[cfg(feature = "discord")]
+        if let Token::Spoiler(tokens) = token {
+            out.push_str(r#"<span class="spoiler">"#);
+            tokens.iter().for_each(|token| self.push_html(token, out));
+            out.push_str("</span>");
+            return;
+        }
+        match self.sprite(token) {
+            Some(i) => {
+                let (sprite, class) = (&self.sprites[i], &self.options.class);
+                let _ = write!(
+                    out,
+                    r#"<span class="{} {}-{}" role="img" aria-label=""#,
+                    class, class, sprite.class
+                );
+                escape(&sprite.alt, out);
+                out.push_str(r#""></span>"#);
+            }
+            None => super::push_html(token, &Default::default(), out),
+        }
+    }
+
+    /// Index of the sprite of an emoji token, added to the sheet the first time
+    fn sprite(&mut self, token: &Token) -> Option<usize> {
+        let (alt, class) = name_of(token)?;
+        if let Some(i) = self.sprites.iter().position(|sprite| sprite.class == class) {
+            return Some(i);
+        }
+        let image = token.decode_image()?;
+        let (i, size) = (self.sprites.len() as u32, self.options.size.max(1));
+        let columns = self.options.columns.max(1);
+        self.images.push(image);
+        self.sprites.push(Sprite {
+            alt,
+            class,
+            x: i % columns * size,
+            y: i / columns * size,
+        });
+        Some(i as usize)
+    }
+}
+
+/// Alt text and sprite class of an emoji token
+fn name_of(token: &Token) -> Option<(String, String)> {
+    match token {
+        // Pngs the index doesn't know, such as from another emoji set, are told apart by a hash
+        Token::Emoji { emoji, image } => {
+            let class = emoji_of(image).map_or_else(|| format!("x{:016x}", hash(image)), file_stem);
+            Some((emoji.clone(), class))
+        }
+        Token::EmojiUrl { emoji, .. } => Some((emoji.clone(), file_stem(emoji))),
+        #[cfg(feature = "svg")]
+        Token::EmojiSvg { emoji, .. } => Some((emoji.clone(), file_stem(emoji))),
+        #[cfg(feature = "custom-emoji")]
+        Token::FetchedCustomEmoji { id, name, .. } => {
+            Some((format!(":{}:", name), format!("c{}", id)))
+        }
+        _ => None,
+    }
+}
+
+/// Fnv-1a hash of `bytes`
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_to_sprite_sheet() {
+        let tokens = crate::parse("a👍 <😀👍").unwrap();
+        let options = SpriteOptions {
+            size: 16,
+            columns: 1,
+            ..SpriteOptions::default()
+        };
+        let sheet = to_sprite_sheet(&tokens, &options);
+        assert_eq!(
+            sheet.html,
+            concat!(
+                r#"a<span class="emoji emoji-1f44d" role="img" aria-label="👍"></span> &lt;"#,
+                r#"<span class="emoji emoji-1f600" role="img" aria-label="😀"></span>"#,
+                r#"<span class="emoji emoji-1f44d" role="img" aria-label="👍"></span>"#,
+            )
+        );
+        assert_eq!(
+            sheet.css,
+            concat!(
+                ".emoji{display:inline-block;width:16px;height:16px;background:url(\"emoji.png\") no-repeat;vertical-align:middle}\n",
+                ".emoji-1f44d{background-position:-0px -0px}\n",
+                ".emoji-1f600{background-position:-0px -16px}\n",
+            )
+        );
+        assert_eq!(sheet.sprites[1].y, 16);
+        let png = crate::png::decode(&sheet.png).unwrap();
+        assert_eq!((png.width, png.height), (16, 32));
+        assert_ne!(png.pixel(8, 8), [0; 4]);
+        let empty = to_sprite_sheet(&[], &options);
+        assert!(empty.sprites.is_empty() && empty.html.is_empty());
+    }
+}
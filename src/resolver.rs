@@ -0,0 +1,258 @@
+use crate::{Parser, Token};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::task::Poll;
+
+/// What a [`CustomEmojiResolver`] knows about a custom emoji
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResolvedEmoji {
+    /// Current name, replacing the one of the markup
+    pub name: Option<String>,
+    /// Bytes of the image
+    pub image: Option<Vec<u8>>,
+}
+
+/// Source of custom emoji details, such as a guild cache or the CDN
+pub trait CustomEmojiResolver {
+    /// Details of the emoji with `id`, in decimal for Discord ids, `None` if it is unknown
+    fn resolve(&self, id: &str) -> impl Future<Output = Option<ResolvedEmoji>> + Send;
+    /// Whether the emoji with `id` still exists, `None` if the resolver can't tell
+    fn exists(&self, _id: &str) -> impl Future<Output = Option<bool>> + Send {
+        async { None }
+    }
+}
+
+/// Resolver knowing no emoji, which leaves tokens as they are
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopResolver;
+
+impl CustomEmojiResolver for NoopResolver {
+    async fn resolve(&self, _id: &str) -> Option<ResolvedEmoji> {
+        None
+    }
+}
+
+impl Parser {
+    /// Fill in custom emoji tokens with what `resolver` knows, once per distinct emoji
+    ///
+    /// Emoji with an image become [`Token::FetchedCustomEmoji`], unknown ones are kept as is.
+    pub async fn resolve_custom_emoji(
+        &self,
+        mut tokens: Vec<Token>,
+        resolver: &impl CustomEmojiResolver,
+    ) -> Vec<Token> {
+        let mut ids = Vec::new();
+        custom_emoji_ids(&tokens, &mut ids);
+        ids.sort_unstable();
+        ids.dedup();
+        let ids = ids.iter().map(u64::to_string).collect::<Vec<_>>();
+        let emojis = join_all(ids.iter().map(|id| resolver.resolve(id)).collect()).await;
+        let resolved = ids
+            .iter()
+            .zip(emojis)
+            .filter_map(|(id, emoji)| Some((id.parse().ok()?, emoji?)))
+            .collect();
+        apply(&mut tokens, &resolved);
+        tokens
+    }
+
+    /// Turn custom emoji that `resolver` knows to be deleted into `:name:` text, as their
+    /// images would be missing
+    pub async fn validate_custom_emoji(
+        &self,
+        mut tokens: Vec<Token>,
+        resolver: &impl CustomEmojiResolver,
+    ) -> Vec<Token> {
+        let mut ids = Vec::new();
+        custom_emoji_ids(&tokens, &mut ids);
+        ids.sort_unstable();
+        ids.dedup();
+        let ids = ids.iter().map(u64::to_string).collect::<Vec<_>>();
+        let exists = join_all(ids.iter().map(|id| resolver.exists(id)).collect()).await;
+        let deleted = ids
+            .iter()
+            .zip(exists)
+            .filter(|(_, exists)| *exists == Some(false))
+            .filter_map(|(id, _)| id.parse().ok())
+            .collect::<HashSet<_>>();
+        if !deleted.is_empty() {
+            downgrade(&mut tokens, &deleted);
+        }
+        tokens
+    }
+}
+
+/// Run `futures` concurrently, returning their outputs in order
+pub(crate) async fn join_all<F: Future>(futures: Vec<F>) -> Vec<F::Output> {
+    let mut futures = futures
+        .into_iter()
+        .map(|future| Some(Box::pin(future)))
+        .collect::<Vec<_>>();
+    let mut outputs = futures.iter().map(|_| None).collect::<Vec<_>>();
+    std::future::poll_fn(|cx| {
+        let mut done = true;
+        for (future, output) in futures.iter_mut().zip(&mut outputs) {
+            let Some(pinned) = future else {
+                continue;
+            };
+            match pinned.as_mut().poll(cx) {
+                Poll::Ready(value) => {
+                    *output = Some(value);
+                    *future = None;
+                }
+                Poll::Pending => done = false,
+            }
+        }
+        match done {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        }
+    })
+    .await;
+    outputs.into_iter().flatten().collect()
+}
+
+fn custom_emoji_ids(tokens: &[Token], out: &mut Vec<u64>) {
+    for token in tokens {
+        match *token {
+            Token::CustomEmoji { id, .. } => out.push(id),
+            #[cfg(feature = "discord")]
+            Token::Spoiler(ref tokens) => custom_emoji_ids(tokens, out),
+            _ => {}
+        }
+    }
+}
+
+fn apply(tokens: &mut [Token], resolved: &HashMap<u64, ResolvedEmoji>) {
+    for token in tokens {
+        match token {
+            Token::CustomEmoji { id, name, animated } => {
+                let Some(emoji) = resolved.get(id) else {
+                    continue;
+                };
+                if let Some(current) = &emoji.name {
+                    current.clone_into(name);
+                }
+                if let Some(image) = &emoji.image {
+                    *token = Token::FetchedCustomEmoji {
+                        id: *id,
+                        name: std::mem::take(name),
+                        animated: *animated,
+                        image: image.clone(),
+                    };
+                }
+            }
+            #[cfg(feature = "discord")]
+            Token::Spoiler(tokens) => apply(tokens, resolved),
+            _ => {}
+        }
+    }
+}
+
+fn downgrade(tokens: &mut [Token], deleted: &HashSet<u64>) {
+    for token in tokens {
+        match token {
+            Token::CustomEmoji { id, name, .. } if deleted.contains(id) => {
+                *token = Token::new_text(format!(":{}:", name));
+            }
+            #[cfg(feature = "discord")]
+            Token::Spoiler(tokens) => downgrade(tokens, deleted),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    #[derive(Default)]
+    struct Resolver {
+        calls: AtomicUsize,
+    }
+    impl CustomEmojiResolver for Resolver {
+        async fn resolve(&self, id: &str) -> Option<ResolvedEmoji> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            match id {
+                "1" => Some(ResolvedEmoji {
+                    name: Some("renamed".to_string()),
+                    image: None,
+                }),
+                "2" => Some(ResolvedEmoji {
+                    name: None,
+                    image: Some(vec![1, 2, 3]),
+                }),
+                _ => None,
+            }
+        }
+        async fn exists(&self, id: &str) -> Option<bool> {
+            match id {
+                "1" | "2" => Some(true),
+                "3" => Some(false),
+                _ => None,
+            }
+        }
+    }
+    /// Poll a future that never waits
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+    #[test]
+    fn test_resolve_custom_emoji() {
+        let tokens = vec![
+            Token::new_custom_emoji(1, "old", false),
+            Token::new_text(" "),
+            Token::new_custom_emoji(2, "two", true),
+            Token::new_custom_emoji(3, "three", false),
+            Token::new_custom_emoji(2, "two", true),
+        ];
+        let resolver = Resolver::default();
+        let parser = Parser::new();
+        let tokens = block_on(parser.resolve_custom_emoji(tokens, &resolver));
+        let fetched = || Token::FetchedCustomEmoji {
+            id: 2,
+            name: "two".to_string(),
+            animated: true,
+            image: vec![1, 2, 3],
+        };
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_custom_emoji(1, "renamed", false),
+                Token::new_text(" "),
+                fetched(),
+                Token::new_custom_emoji(3, "three", false),
+                fetched(),
+            ]
+        );
+        assert_eq!(resolver.calls.load(Ordering::Relaxed), 3);
+        let tokens = vec![Token::new_custom_emoji(2, "two", true)];
+        assert_eq!(
+            block_on(parser.resolve_custom_emoji(tokens, &NoopResolver)),
+            vec![Token::new_custom_emoji(2, "two", true)]
+        );
+    }
+    #[test]
+    fn test_validate_custom_emoji() {
+        let tokens = vec![
+            Token::new_custom_emoji(1, "one", false),
+            Token::new_custom_emoji(3, "deleted", false),
+            Token::new_custom_emoji(4, "unknown", false),
+        ];
+        let tokens = block_on(Parser::new().validate_custom_emoji(tokens, &Resolver::default()));
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_custom_emoji(1, "one", false),
+                Token::new_text(":deleted:"),
+                Token::new_custom_emoji(4, "unknown", false),
+            ]
+        );
+    }
+}
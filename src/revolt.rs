@@ -0,0 +1,59 @@
+use crate::{ParseError, Parser, Token};
+
+/// Url of a Revolt custom emoji on its file server
+pub(crate) fn emoji_url(id: &str) -> String {
+    format!("https://autumn.revolt.chat/emojis/{id}")
+}
+
+/// Revolt emoji ids are ULIDs, 26 characters of Crockford's base32
+fn is_ulid(s: &str) -> bool {
+    s.len() == 26
+        && s.starts_with(|c| ('0'..='7').contains(&c))
+        && s.bytes().all(|b| {
+            (b.is_ascii_digit() || b.is_ascii_uppercase())
+                && !matches!(b, b'I' | b'L' | b'O' | b'U')
+        })
+}
+
+impl Parser {
+    /// Parse a Revolt message, `:id:` markup becomes custom emoji tokens named by their id
+    pub fn parse_revolt(&self, s: &str) -> Result<Vec<Token>, ParseError> {
+        let tokens = self.parse(s)?;
+        Ok(crate::replace_shortcodes(tokens, &|id| {
+            is_ulid(id).then(|| Token::NamedCustomEmoji {
+                name: id.to_string(),
+                url: emoji_url(id),
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_is_ulid() {
+        assert!(is_ulid("01GX773A8JPQ0VP64NAGHSZW1M"));
+        assert!(!is_ulid("01GX773A8JPQ0VP64NAGHSZW1"));
+        assert!(!is_ulid("81GX773A8JPQ0VP64NAGHSZW1M"));
+        assert!(!is_ulid("01gx773a8jpq0vp64naghszw1m"));
+        assert!(!is_ulid("01GX773A8JPQ0VP64NAGHSZWIL"));
+    }
+    #[test]
+    fn test_parse_revolt() {
+        let tokens = Parser::new()
+            .parse_revolt("👍:01GX773A8JPQ0VP64NAGHSZW1M: :nope:")
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_emoji("👍", crate::get("👍").unwrap()),
+                Token::NamedCustomEmoji {
+                    name: "01GX773A8JPQ0VP64NAGHSZW1M".to_string(),
+                    url: "https://autumn.revolt.chat/emojis/01GX773A8JPQ0VP64NAGHSZW1M".to_string(),
+                },
+                Token::new_text(" :nope:"),
+            ]
+        );
+    }
+}
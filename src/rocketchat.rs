@@ -0,0 +1,60 @@
+use crate::{ParseError, Parser, Token};
+use std::collections::HashMap;
+
+/// Url a Rocket.Chat server serves a custom emoji at, from the `name` and `extension`
+/// fields of its `emoji-custom.list` endpoint
+pub fn rocketchat_emoji_url(server: &str, name: &str, extension: &str) -> String {
+    format!(
+        "{}/emoji-custom/{}.{}",
+        server.trim_end_matches('/'),
+        name,
+        extension
+    )
+}
+
+impl Parser {
+    /// Parse a Rocket.Chat message, `:shortcode:` of `emojis` become custom emoji tokens
+    /// with the url they map to
+    pub fn parse_rocketchat(
+        &self,
+        s: &str,
+        emojis: &HashMap<String, String>,
+    ) -> Result<Vec<Token>, ParseError> {
+        let tokens = self.parse(s)?;
+        Ok(crate::replace_shortcodes(tokens, &|name| {
+            Some(Token::NamedCustomEmoji {
+                name: name.to_string(),
+                url: emojis.get(name)?.clone(),
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_parse_rocketchat() {
+        let url = rocketchat_emoji_url("https://chat.example.com/", "party_parrot", "gif");
+        assert_eq!(
+            url,
+            "https://chat.example.com/emoji-custom/party_parrot.gif"
+        );
+        let emojis = HashMap::from([("party_parrot".to_string(), url.clone())]);
+        let tokens = Parser::new()
+            .parse_rocketchat("yay :party_parrot: :nope: 👍", &emojis)
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_text("yay "),
+                Token::NamedCustomEmoji {
+                    name: "party_parrot".to_string(),
+                    url,
+                },
+                Token::new_text(" :nope: "),
+                Token::new_emoji("👍", crate::get("👍").unwrap()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{DiscordMessage, GuildEmojiCache, Reaction, StickerFormat, StickerItem};
+use ::serenity::model::channel::{Message, ReactionType};
+use ::serenity::model::guild::Emoji;
+
+impl<'a> Extend<&'a Emoji> for GuildEmojiCache {
+    fn extend<I: IntoIterator<Item = &'a Emoji>>(&mut self, iter: I) {
+        iter.into_iter()
+            .for_each(|emoji| self.insert(emoji.id.get(), emoji.name.as_str(), emoji.animated));
+    }
+}
+
+impl<'a> FromIterator<&'a Emoji> for GuildEmojiCache {
+    fn from_iter<I: IntoIterator<Item = &'a Emoji>>(iter: I) -> Self {
+        let mut cache = Self::new();
+        cache.extend(iter);
+        cache
+    }
+}
+
+impl DiscordMessage for Message {
+    fn content(&self) -> &str {
+        &self.content
+    }
+    fn reactions(&self) -> Vec<Reaction<'_>> {
+        self.reactions
+            .iter()
+            .filter_map(|reaction| match reaction.reaction_type {
+                ReactionType::Custom {
+                    animated,
+                    id,
+                    ref name,
+                } => Some(Reaction::Custom {
+                    id: id.get(),
+                    name: name.as_deref(),
+                    animated,
+                }),
+                ReactionType::Unicode(ref s) => Some(Reaction::Unicode(s)),
+                _ => None,
+            })
+            .collect()
+    }
+    fn stickers(&self) -> Vec<StickerItem> {
+        self.sticker_items
+            .iter()
+            .map(|sticker| StickerItem {
+                id: sticker.id.get(),
+                name: sticker.name.clone(),
+                format: StickerFormat::from_format_type(sticker.format_type.into()),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Parser, Token};
+    use ::serenity::model::channel::MessageReaction;
+    use serde_json::json;
+    fn emoji(id: &str, name: &str) -> Emoji {
+        serde_json::from_value(json!({ "id": id, "name": name })).unwrap()
+    }
+    fn reaction(emoji: serde_json::Value) -> MessageReaction {
+        serde_json::from_value(json!({
+            "count": 1,
+            "count_details": { "burst": 0, "normal": 1 },
+            "me": false,
+            "me_burst": false,
+            "emoji": emoji,
+            "burst_colors": [],
+        }))
+        .unwrap()
+    }
+    #[test]
+    fn test_parse_message() {
+        let emojis: GuildEmojiCache = [emoji("123456789012345678", "renamed")].iter().collect();
+        let mut message = Message::default();
+        message.content = "hi <:old:123456789012345678>".to_string();
+        message.reactions = vec![
+            reaction(json!({ "id": null, "name": "👍" })),
+            reaction(json!({ "id": "123456789012345678", "name": null })),
+            reaction(json!({ "id": "234567890123456789", "name": "other", "animated": true })),
+        ];
+        message.sticker_items = vec![serde_json::from_value(
+            json!({ "id": "345678901234567890", "name": "wave", "format_type": 1 }),
+        )
+        .unwrap()];
+        let tokens = Parser::new().parse_message(&message, &emojis).unwrap();
+        assert_eq!(
+            tokens.content,
+            vec![
+                Token::new_text("hi "),
+                Token::new_custom_emoji(123456789012345678, "renamed", false),
+            ]
+        );
+        assert_eq!(
+            tokens.reactions,
+            vec![
+                Token::new_emoji("👍", crate::get("👍").unwrap()),
+                Token::new_custom_emoji(123456789012345678, "renamed", false),
+                Token::new_custom_emoji(234567890123456789, "other", true),
+            ]
+        );
+        assert_eq!(
+            tokens.stickers,
+            vec![StickerItem {
+                id: 345678901234567890,
+                name: "wave".to_string(),
+                format: StickerFormat::Png,
+            }]
+        );
+    }
+}
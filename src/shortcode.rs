@@ -0,0 +1,162 @@
+use crate::{ParseError, Parser, TokenRef, SKIN_TONES, VS16};
+use std::borrow::Cow;
+use std::ops::Range;
+use twemoji_assets::png::PngTwemojiAsset;
+
+/// What a shortcode registered with [`Parser::register_alias`] stands for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Alias {
+    /// Unicode emoji
+    Emoji(String),
+    #[cfg(feature = "discord")]
+    /// Discord custom emoji
+    CustomEmoji { id: u64, animated: bool },
+    /// Url of an image
+    Url(String),
+}
+
+impl Parser {
+    /// Make `:name:` stand for `target`, taking precedence over the bundled names
+    ///
+    /// `target` is an `http(s)` image url, custom emoji markup such as `<:kekw:id>`
+    /// with the `discord` feature, or else an emoji. Names only match when made of
+    /// ascii letters, digits, `_`, `+` and `-`.
+    pub fn register_alias(
+        &mut self,
+        name: impl Into<String>,
+        target: &str,
+    ) -> Result<(), ParseError> {
+        let alias = alias(target).ok_or_else(|| ParseError::UnknownEmoji(target.to_string()))?;
+        self.aliases.insert(name.into(), alias);
+        self.changed();
+        Ok(())
+    }
+    /// What `:name:` was registered to stand for
+    pub fn alias(&self, name: &str) -> Option<&Alias> {
+        self.aliases.get(name)
+    }
+}
+
+fn alias(target: &str) -> Option<Alias> {
+    if target.starts_with("https://") || target.starts_with("http://") {
+        return Some(Alias::Url(target.to_string()));
+    }
+    #[cfg(feature = "discord")]
+    if let Some((id, animated)) = custom_emoji(target) {
+        return Some(Alias::CustomEmoji { id, animated });
+    }
+    crate::get(target).map(|_| Alias::Emoji(target.to_string()))
+}
+
+/// Id and animation of `s` if it is custom emoji markup and nothing else
+#[cfg(feature = "discord")]
+fn custom_emoji(s: &str) -> Option<(u64, bool)> {
+    match crate::discord::find_markup(s, &Default::default())? {
+        (range, TokenRef::CustomEmoji { id, animated, .. }) if range == (0..s.len()) => {
+            Some((id, animated))
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-')
+}
+
+/// Find the first `:name:` of a known emoji, returns its range and token
+pub(crate) fn find_shortcode<'a>(
+    s: &'a str,
+    parser: &Parser,
+) -> Option<(Range<usize>, TokenRef<'a>)> {
+    let mut from = 0;
+    while let Some(i) = s[from..].find(':') {
+        let start = from + i;
+        let name = &s[start + 1..];
+        let name = &name[..name.find(|c| !is_name_char(c)).unwrap_or(name.len())];
+        let end = start + name.len() + 2;
+        if !name.is_empty() && s[start + 1 + name.len()..].starts_with(':') {
+            if let Some((token, len)) = named(name, &s[end..], parser) {
+                return Some((start..end + len, token));
+            }
+        }
+        // The closing colon of an unknown name may open a known one
+        from = start + 1;
+    }
+    None
+}
+
+/// Token of a known `name`, with the length of the skin tone suffix it took from `after`
+fn named<'a>(name: &'a str, after: &str, parser: &Parser) -> Option<(TokenRef<'a>, usize)> {
+    if let Some(alias) = parser.aliases.get(name) {
+        let token = match *alias {
+            Alias::Emoji(ref emoji) => TokenRef::Emoji {
+                emoji: Cow::Owned(emoji.clone()),
+                image: parser.emoji_png(emoji)?,
+            },
+            #[cfg(feature = "discord")]
+            Alias::CustomEmoji { id, animated } => TokenRef::CustomEmoji { id, name, animated },
+            Alias::Url(_) => TokenRef::Alias(name),
+        };
+        return Some((token, 0));
+    }
+    if let Some(emoji) = emoji_by_name(name) {
+        let image = parser.emoji_png(emoji)?;
+        // An unknown tone keeps the base emoji and leaves the suffix as text
+        let toned = skin_tone(after).and_then(|(tone, len)| {
+            let emoji = toned(emoji, tone);
+            Some((parser.emoji_png(&emoji)?, Cow::Owned(emoji), len))
+        });
+        return Some(match toned {
+            Some((image, emoji, len)) => (TokenRef::Emoji { emoji, image }, len),
+            None => (
+                TokenRef::Emoji {
+                    emoji: Cow::Borrowed(emoji),
+                    image,
+                },
+                0,
+            ),
+        });
+    }
+    #[cfg(feature = "gemoji")]
+    if let Some(url) = crate::gemoji::custom_url(name) {
+        return Some((TokenRef::NamedCustomEmoji { name, url }, 0));
+    }
+    None
+}
+
+/// Unicode emoji called `name` in the bundled table, by Discord or in gemoji
+fn emoji_by_name(name: &str) -> Option<&'static str> {
+    let emoji = PngTwemojiAsset::from_name(name).map(|asset| asset.emoji);
+    #[cfg(feature = "discord")]
+    let emoji = emoji.or_else(|| crate::discord_names::emoji_by_name(name));
+    #[cfg(feature = "gemoji")]
+    let emoji = emoji.or_else(|| crate::gemoji::emoji_by_name(name));
+    emoji
+}
+
+/// Modifier of a Slack `:skin-tone-2:` to `:skin-tone-6:` suffix, from light to dark, and its length
+fn skin_tone(s: &str) -> Option<(char, usize)> {
+    let rest = s.strip_prefix(":skin-tone-")?;
+    let n = rest
+        .chars()
+        .next()?
+        .to_digit(10)
+        .filter(|n| (2..=6).contains(n))?;
+    let tone = char::from_u32(*SKIN_TONES.start() as u32 + n - 2)?;
+    rest[1..]
+        .starts_with(':')
+        .then_some((tone, ":skin-tone-0:".len()))
+}
+
+/// `emoji` with `tone` applied to its first character
+fn toned(emoji: &str, tone: char) -> String {
+    let mut chars = emoji.chars();
+    let base = chars.next().unwrap_or_default();
+    let rest = chars.as_str();
+    format!(
+        "{}{}{}",
+        base,
+        tone,
+        rest.strip_prefix(VS16).unwrap_or(rest)
+    )
+}
@@ -0,0 +1,59 @@
+use crate::{ParseError, Parser, Token};
+
+/// Emoticons every Steam account can use in chat, sorted
+const EMOTICONS: &[&str] = &[
+    "steambored",
+    "steamboss",
+    "steamfacepalm",
+    "steamhappy",
+    "steammocking",
+    "steamsad",
+    "steamsalty",
+    "steamthis",
+    "steamthumbsdown",
+    "steamthumbsup",
+];
+
+/// Url of a Steam emoticon on the community CDN
+pub(crate) fn emoticon_url(name: &str) -> String {
+    format!("https://community.cloudflare.steamstatic.com/economy/emoticon/{name}")
+}
+
+impl Parser {
+    /// Parse a Steam chat message, `:name:` of the default emoticons become custom emoji
+    /// tokens with their CDN url
+    pub fn parse_steam(&self, s: &str) -> Result<Vec<Token>, ParseError> {
+        let tokens = self.parse(s)?;
+        Ok(crate::replace_shortcodes(tokens, &|name| {
+            EMOTICONS.binary_search(&name).ok()?;
+            Some(Token::NamedCustomEmoji {
+                name: name.to_string(),
+                url: emoticon_url(name),
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_parse_steam() {
+        assert!(EMOTICONS.windows(2).all(|w| w[0] < w[1]));
+        let tokens = Parser::new()
+            .parse_steam("gg :steamhappy: :steamnope:")
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_text("gg "),
+                Token::NamedCustomEmoji {
+                    name: "steamhappy".to_string(),
+                    url: "https://community.cloudflare.steamstatic.com/economy/emoticon/steamhappy"
+                        .to_string(),
+                },
+                Token::new_text(" :steamnope:"),
+            ]
+        );
+    }
+}
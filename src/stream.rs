@@ -0,0 +1,513 @@
+#[cfg(feature = "discord")]
+use crate::discord::{find_markup, find_spoiler, is_command_char, is_escaped};
+#[cfg(feature = "shortcode")]
+use crate::shortcode::{find_shortcode, is_name_char};
+#[cfg(feature = "discord")]
+use crate::TokenRef;
+use crate::{ParseError, Parser, ParserOptions, Token};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Incremental parser for input arriving in chunks
+///
+/// The last grapheme of every chunk is held back until more input arrives,
+/// as it could still be extended (e.g. by a ZWJ or a skin tone modifier),
+/// along with a backslash escaping it, unless it closes markup.
+/// With the `discord` feature, unfinished custom emoji markup is held back too,
+/// and so are unfinished `:name:` shortcodes with the `shortcode` feature.
+/// Unfinished markup is held back up to [`Self::max_held_back`] bytes, after which it is
+/// parsed as text.
+#[derive(Debug, Clone)]
+pub struct StreamingParser {
+    parser: Parser,
+    buffer: String,
+    max_held_back: usize,
+}
+
+/// Bytes of unclosed markup held back by default
+const MAX_HELD_BACK: usize = 16 << 10;
+
+impl Default for StreamingParser {
+    fn default() -> Self {
+        Self::with_parser(Parser::default())
+    }
+}
+
+impl StreamingParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_options(options: ParserOptions) -> Self {
+        Self::with_parser(Parser::with_options(options))
+    }
+    /// Streaming parser using `parser`, with its options and aliases
+    pub fn with_parser(parser: Parser) -> Self {
+        Self {
+            parser,
+            buffer: String::new(),
+            max_held_back: MAX_HELD_BACK,
+        }
+    }
+    /// Hold back at most `max` bytes of unclosed code, spoilers, shortcodes and emoticons,
+    /// 16KiB by default, so a stray backtick doesn't buffer the rest of the input
+    pub fn max_held_back(mut self, max: usize) -> Self {
+        self.max_held_back = max;
+        self
+    }
+    /// Feed a chunk and return the tokens that are complete so far
+    pub fn feed(&mut self, chunk: &str) -> Result<Vec<Token>, ParseError> {
+        self.buffer.push_str(chunk);
+        let len = self.buffer.len();
+        let held = |i: &usize| len - i <= self.max_held_back;
+        let keep = self
+            .buffer
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i);
+        #[cfg(feature = "discord")]
+        let keep = markup_cut(&self.buffer, self.parser.options(), keep, held);
+        let keep = self.named_cut(keep, held);
+        // A backslash escapes what follows it, so it stays with it
+        #[cfg(feature = "discord")]
+        let keep = match is_escaped(&self.buffer[..keep]) {
+            true => keep - 1,
+            false => keep,
+        };
+        let rest = self.buffer.split_off(keep);
+        let tokens = self.parser.parse(&self.buffer);
+        self.buffer = rest;
+        tokens
+    }
+    /// Move `keep` before shortcodes and emoticons it would split or leave unfinished, when they
+    /// are `held`, until moving it for one no longer splits the other
+    fn named_cut(&self, mut keep: usize, held: impl Fn(&usize) -> bool) -> usize {
+        let options = self.parser.options();
+        loop {
+            let cut = keep;
+            #[cfg(feature = "shortcode")]
+            if options.shortcodes {
+                if let Some(i) = unfinished_shortcode(&self.buffer).filter(&held) {
+                    keep = keep.min(i);
+                }
+                if let Some(i) = split_shortcode(&self.buffer, keep, &self.parser).filter(&held) {
+                    keep = i;
+                }
+            }
+            if let Some(emoticons) = options.emoticons.as_ref() {
+                if let Some(i) = emoticons.unfinished(&self.buffer).filter(&held) {
+                    keep = keep.min(i);
+                }
+                keep = Some(emoticons.cut(&self.buffer, keep))
+                    .filter(&held)
+                    .unwrap_or(keep);
+            }
+            if keep == cut {
+                return keep;
+            }
+        }
+    }
+    /// Return the tokens of the input held back and reset the parser
+    pub fn finish(&mut self) -> Result<Vec<Token>, ParseError> {
+        let tokens = self.parser.parse(&self.buffer);
+        self.buffer.clear();
+        tokens
+    }
+}
+
+/// Where to cut `s` before the input held back from `keep`, so markup ending in the held
+/// grapheme isn't split, and before the first code, spoiler or `<` markup later input could
+/// still close or change, when it is `held`
+///
+/// As in parsing, whichever markup starts first contains the others. Code ending the input or
+/// opened by three backticks could still grow into a longer one.
+#[cfg(feature = "discord")]
+fn markup_cut(
+    s: &str,
+    options: &ParserOptions,
+    keep: usize,
+    held: impl Fn(&usize) -> bool,
+) -> usize {
+    if !options.discord {
+        return keep;
+    }
+    let (mut from, mut keep) = (0, keep);
+    loop {
+        let rest = &s[from..];
+        let spoiler = find_spoiler(rest).filter(|_| options.spoilers);
+        let found = [find_markup(rest, options), spoiler]
+            .into_iter()
+            .flatten()
+            .min_by_key(|(range, _)| range.start);
+        let before = found.as_ref().map_or(rest.len(), |(range, _)| range.start);
+        let opener = rest[..before].char_indices().find(|(i, _)| {
+            let rest = &rest[*i..];
+            (options.code && rest.starts_with('`'))
+                || (options.spoilers && (rest.starts_with("||") || rest == "|"))
+                || is_markup_prefix(rest)
+        });
+        if let Some((i, c)) = opener {
+            if held(&(from + i)) {
+                return keep.min(from + i);
+            }
+            from += i + c.len_utf8();
+            continue;
+        }
+        let Some((range, token)) = found else {
+            return keep;
+        };
+        let (start, end) = (from + range.start, from + range.end);
+        let grows = match token {
+            TokenRef::Code(_) if s[start..].starts_with("```") => true,
+            TokenRef::Code(_) | TokenRef::CodeBlock { .. } => end == s.len() && s.ends_with('`'),
+            _ => false,
+        };
+        if grows && held(&start) {
+            return keep.min(start);
+        }
+        if (start..end).contains(&keep) {
+            keep = end;
+        }
+        from = end;
+    }
+}
+
+/// Start of the trailing `:name:` run later input could still complete or add a skin
+/// tone to
+#[cfg(feature = "shortcode")]
+fn unfinished_shortcode(s: &str) -> Option<usize> {
+    let run = s.trim_end_matches(|c| c == ':' || is_name_char(c));
+    s[run.len()..].find(':').map(|i| run.len() + i)
+}
+
+/// Start of the shortcode of `s` cutting it at `at` would split, such as when its closing colon
+/// is held back
+#[cfg(feature = "shortcode")]
+fn split_shortcode(s: &str, at: usize, parser: &Parser) -> Option<usize> {
+    let mut from = s[..at]
+        .trim_end_matches(|c| c == ':' || is_name_char(c))
+        .len();
+    while let Some((range, _)) = find_shortcode(&s[from..], parser) {
+        let (start, end) = (from + range.start, from + range.end);
+        if start >= at {
+            return None;
+        }
+        if end > at {
+            return Some(start);
+        }
+        from = end;
+    }
+    None
+}
+
+/// Whether `s` could be completed into Discord markup
+#[cfg(feature = "discord")]
+fn is_markup_prefix(s: &str) -> bool {
+    let Some(s) = s.strip_prefix('<') else {
+        return false;
+    };
+    is_emoji_prefix(s) || is_mention_prefix(s) || is_timestamp_prefix(s)
+}
+
+/// Whether `s` could be completed into `a?:name:id>`
+#[cfg(feature = "discord")]
+fn is_emoji_prefix(s: &str) -> bool {
+    let s = s.strip_prefix('a').unwrap_or(s);
+    let Some(s) = s.strip_prefix(':') else {
+        return s.is_empty();
+    };
+    let name_end = s
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(s.len());
+    let Some(id) = s[name_end..].strip_prefix(':') else {
+        return name_end == s.len();
+    };
+    name_end > 0 && is_id_prefix(id)
+}
+
+/// Whether `s` could be completed into `@!?id>`, `@&id>`, `#id>` or `/name:id>`
+#[cfg(feature = "discord")]
+fn is_mention_prefix(s: &str) -> bool {
+    if let Some(s) = s.strip_prefix('/') {
+        return match s.split_once(':') {
+            Some((name, id)) => !name.is_empty() && is_id_prefix(id),
+            None => s.chars().all(|c| c == ' ' || is_command_char(c)),
+        };
+    }
+    let id = match s.strip_prefix('@') {
+        Some(s) => s.strip_prefix(['!', '&']).unwrap_or(s),
+        None => match s.strip_prefix('#') {
+            Some(s) => s,
+            None => return false,
+        },
+    };
+    is_id_prefix(id)
+}
+
+/// Whether `s` could be completed into `t:unix>` or `t:unix:style>`
+#[cfg(feature = "discord")]
+fn is_timestamp_prefix(s: &str) -> bool {
+    let Some(s) = s.strip_prefix('t') else {
+        return false;
+    };
+    let Some(s) = s.strip_prefix(':') else {
+        return s.is_empty();
+    };
+    let s = s.strip_prefix('-').unwrap_or(s);
+    let digits = s.bytes().take_while(u8::is_ascii_digit).count();
+    match s[digits..].strip_prefix(':') {
+        Some(style) => digits > 0 && style.len() <= 1,
+        None => digits == s.len(),
+    }
+}
+
+#[cfg(feature = "discord")]
+fn is_id_prefix(s: &str) -> bool {
+    s.len() <= 19 && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "discord")]
+    use crate::{Mention, TimestampStyle};
+    #[test]
+    fn test_streaming() {
+        let mut parser = StreamingParser::new();
+        let mut tokens = Vec::new();
+        for chunk in ["Hi 👩", "\u{200d}💻 🇯", "🇵!"] {
+            tokens.extend(parser.feed(chunk).unwrap());
+        }
+        tokens.extend(parser.finish().unwrap());
+        let tokens = tokens
+            .into_iter()
+            .filter(|t| !matches!(t, Token::Text(_)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            crate::parse_ref("👩‍💻🇯🇵")
+                .unwrap()
+                .into_iter()
+                .map(Token::from)
+                .collect::<Vec<_>>()
+        );
+    }
+    #[test]
+    fn test_streaming_emoticon() {
+        let options = ParserOptions::builder()
+            .emoticons(crate::Emoticons::new())
+            .build();
+        let mut parser = StreamingParser::with_options(options);
+        assert_eq!(parser.feed("Hi :-").unwrap(), vec![Token::new_text("Hi ")]);
+        assert_eq!(
+            parser.feed(") ").unwrap(),
+            vec![Token::new_emoji("🙂", crate::get("🙂").unwrap())]
+        );
+        assert_eq!(parser.finish().unwrap(), vec![Token::new_text(" ")]);
+    }
+    #[test]
+    fn test_streaming_emoticon_at_end() {
+        let options = ParserOptions::builder()
+            .emoticons(crate::Emoticons::new())
+            .build();
+        let mut parser = StreamingParser::with_options(options.clone());
+        assert_eq!(parser.feed(":)").unwrap(), vec![]);
+        assert_eq!(
+            parser.finish().unwrap(),
+            vec![Token::new_emoji("🙂", crate::get("🙂").unwrap())]
+        );
+        for input in ["a:)", ":):)", ":) :)", "x:-)y"] {
+            let mut parser = StreamingParser::with_options(options.clone());
+            let mut tokens = parser.feed(input).unwrap();
+            tokens.extend(parser.finish().unwrap());
+            let expected = Parser::with_options(options.clone()).parse(input).unwrap();
+            assert_eq!(tokens, expected, "{input}");
+        }
+    }
+    #[cfg(feature = "shortcode")]
+    #[test]
+    fn test_streaming_shortcode() {
+        let mut parser = StreamingParser::new();
+        assert_eq!(parser.feed("Hi :sm").unwrap(), vec![Token::new_text("Hi ")]);
+        assert_eq!(
+            parser.feed("ile: ").unwrap(),
+            vec![Token::new_emoji("😄", crate::get("😄").unwrap())]
+        );
+        assert_eq!(parser.feed(":+1:").unwrap(), vec![Token::new_text(" ")]);
+        assert_eq!(
+            parser.feed(":skin-tone-3: ").unwrap(),
+            vec![Token::new_emoji("👍🏼", crate::get("👍🏼").unwrap())]
+        );
+        assert_eq!(parser.finish().unwrap(), vec![Token::new_text(" ")]);
+    }
+    #[cfg(feature = "shortcode")]
+    #[test]
+    fn test_streaming_shortcode_closed_in_grapheme() {
+        for input in [":smile:\u{e007f}", ":smile:\u{301}", ":+1:\u{200d}\u{200d}"] {
+            let mut parser = StreamingParser::new();
+            let mut tokens = parser.feed(input).unwrap();
+            tokens.extend(parser.finish().unwrap());
+            assert_eq!(tokens, Parser::new().parse(input).unwrap(), "{input:?}");
+        }
+        let mut parser = StreamingParser::new();
+        assert_eq!(parser.feed(":smi").unwrap(), vec![]);
+        assert_eq!(
+            parser.feed("le:\u{e007f}é").unwrap(),
+            vec![
+                Token::new_emoji("😄", crate::get("😄").unwrap()),
+                Token::new_text("\u{e007f}")
+            ]
+        );
+        assert_eq!(parser.finish().unwrap(), vec![Token::new_text("é")]);
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_streaming_custom_emoji() {
+        let mut parser = StreamingParser::new();
+        assert_eq!(
+            parser.feed("Hello <a:pep").unwrap(),
+            vec![Token::new_text("Hello ")]
+        );
+        assert_eq!(
+            parser.feed("ega:123456789012345678> ").unwrap(),
+            vec![Token::new_custom_emoji(123456789012345678, "pepega", true)]
+        );
+        assert_eq!(parser.finish().unwrap(), vec![Token::new_text(" ")]);
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_streaming_mention() {
+        let mut parser = StreamingParser::new();
+        assert_eq!(
+            parser.feed("Hi <@&1234").unwrap(),
+            vec![Token::new_text("Hi ")]
+        );
+        assert_eq!(
+            parser.feed("56789012345678>!").unwrap(),
+            vec![Token::Mention(Mention::Role(123456789012345678))]
+        );
+        assert_eq!(
+            parser.feed("</config s").unwrap(),
+            vec![Token::new_text("!")]
+        );
+        assert_eq!(
+            parser.feed("et:123456789012345678>.").unwrap(),
+            vec![Token::CommandMention {
+                name: "config set".to_string(),
+                id: 123456789012345678,
+            }]
+        );
+        assert_eq!(parser.finish().unwrap(), vec![Token::new_text(".")]);
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_streaming_code() {
+        let mut parser = StreamingParser::new();
+        assert_eq!(parser.feed("a `b").unwrap(), vec![Token::new_text("a ")]);
+        assert_eq!(parser.feed(" 👍").unwrap(), vec![]);
+        assert_eq!(
+            parser.feed("` c").unwrap(),
+            vec![Token::Code("b 👍".to_string()), Token::new_text(" ")]
+        );
+        assert_eq!(parser.finish().unwrap(), vec![Token::new_text("c")]);
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_streaming_escape() {
+        let mut parser = StreamingParser::new();
+        assert_eq!(parser.feed("a \\").unwrap(), vec![Token::new_text("a ")]);
+        assert_eq!(parser.feed("😄").unwrap(), vec![]);
+        assert_eq!(parser.feed(" \\").unwrap(), vec![Token::new_text("😄 ")]);
+        assert_eq!(
+            parser.feed("<:x:123456789012345678> ").unwrap(),
+            vec![Token::new_text("<:x:123456789012345678>")]
+        );
+        assert_eq!(parser.finish().unwrap(), vec![Token::new_text(" ")]);
+        // Pairs of backslashes don't escape
+        let mut parser = StreamingParser::new();
+        assert_eq!(parser.feed("\\\\").unwrap(), vec![]);
+        assert_eq!(
+            parser.feed("😄 ").unwrap(),
+            vec![
+                Token::new_text("\\\\"),
+                Token::new_emoji("😄", crate::get("😄").unwrap())
+            ]
+        );
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_streaming_held_back() {
+        let mut parser = StreamingParser::new().max_held_back(4);
+        assert_eq!(parser.feed("a `b").unwrap(), vec![Token::new_text("a ")]);
+        assert_eq!(
+            parser.feed("cdef ").unwrap(),
+            vec![Token::new_text("`bcdef")]
+        );
+        assert_eq!(parser.feed("||gh").unwrap(), vec![Token::new_text(" ")]);
+        assert_eq!(parser.feed("ijk").unwrap(), vec![Token::new_text("||ghij")]);
+        assert_eq!(parser.finish().unwrap(), vec![Token::new_text("k")]);
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_streaming_spoiler() {
+        let mut parser = StreamingParser::new();
+        assert_eq!(parser.feed("a ||b").unwrap(), vec![Token::new_text("a ")]);
+        assert_eq!(
+            parser.feed("|| c").unwrap(),
+            vec![
+                Token::Spoiler(vec![Token::new_text("b")]),
+                Token::new_text(" ")
+            ]
+        );
+        assert_eq!(parser.finish().unwrap(), vec![Token::new_text("c")]);
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_streaming_timestamp() {
+        let mut parser = StreamingParser::new();
+        assert_eq!(
+            parser.feed("At <t:16999").unwrap(),
+            vec![Token::new_text("At ")]
+        );
+        assert_eq!(parser.feed("99999:").unwrap(), vec![]);
+        assert_eq!(
+            parser.feed("R>.").unwrap(),
+            vec![Token::Timestamp {
+                unix: 1699999999,
+                style: TimestampStyle::Relative,
+            }]
+        );
+        assert_eq!(parser.finish().unwrap(), vec![Token::new_text(".")]);
+    }
+    #[cfg(feature = "discord")]
+    #[test]
+    fn test_streaming_closed_markup() {
+        let inputs = [
+            "`a`",
+            "see `code`",
+            "``a```",
+            "```a``",
+            "||x||",
+            "<:a:123456789012345678>",
+            "<t:1700000000>",
+            "<@123456789012345678>\u{fe0f}",
+        ];
+        for input in inputs {
+            let mut parser = StreamingParser::new();
+            let mut tokens = parser.feed(input).unwrap();
+            tokens.extend(parser.finish().unwrap());
+            assert_eq!(tokens, Parser::new().parse(input).unwrap(), "{input}");
+        }
+        // Code opened by three backticks and closed by two can still be a code block
+        let mut parser = StreamingParser::new();
+        assert_eq!(parser.feed("```a``").unwrap(), vec![]);
+        assert_eq!(
+            parser.feed("` b").unwrap(),
+            vec![
+                Token::CodeBlock {
+                    language: None,
+                    code: "a".to_string()
+                },
+                Token::new_text(" ")
+            ]
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{ParseError, Parser, Token};
+
+/// `(code)` emoticons of Teams, sorted by code
+const CODES: &[(&str, &str)] = &[
+    ("angel", "😇"),
+    ("angry", "😠"),
+    ("beer", "🍺"),
+    ("blush", "😊"),
+    ("brokenheart", "💔"),
+    ("cake", "🎂"),
+    ("cat", "🐱"),
+    ("clap", "👏"),
+    ("coffee", "☕"),
+    ("cool", "😎"),
+    ("cry", "😢"),
+    ("devil", "😈"),
+    ("dog", "🐶"),
+    ("facepalm", "🤦"),
+    ("fingerscrossed", "🤞"),
+    ("fire", "🔥"),
+    ("gift", "🎁"),
+    ("giggle", "🤭"),
+    ("happy", "😊"),
+    ("heart", "❤️"),
+    ("hug", "🤗"),
+    ("inlove", "😍"),
+    ("kiss", "😗"),
+    ("laugh", "😃"),
+    ("like", "👍"),
+    ("mail", "✉️"),
+    ("muscle", "💪"),
+    ("music", "🎵"),
+    ("n", "👎"),
+    ("no", "👎"),
+    ("ok", "👌"),
+    ("party", "🎉"),
+    ("phone", "📞"),
+    ("praying", "🙏"),
+    ("rainbow", "🌈"),
+    ("rofl", "🤣"),
+    ("sad", "🙁"),
+    ("shock", "😱"),
+    ("sick", "🤢"),
+    ("sleepy", "😪"),
+    ("smile", "🙂"),
+    ("star", "⭐"),
+    ("sun", "☀️"),
+    ("surprised", "😮"),
+    ("sweat", "😓"),
+    ("think", "🤔"),
+    ("tongueout", "😛"),
+    ("wave", "👋"),
+    ("wink", "😉"),
+    ("worry", "😟"),
+    ("y", "👍"),
+    ("yes", "👍"),
+];
+
+/// Token of `emoji` with its asset
+fn emoji(emoji: &str, parser: &Parser) -> Option<Token> {
+    Some(Token::new_emoji(emoji, parser.emoji_png(emoji)?))
+}
+
+/// Token of the emoticon with `code`
+fn emoticon(code: &str, parser: &Parser) -> Option<Token> {
+    let i = CODES.binary_search_by_key(&code, |(c, _)| c).ok()?;
+    emoji(CODES[i].1, parser)
+}
+
+/// Token and length of the `(code)` or `<emoji>` tag at the start of `s`
+fn find_markup(s: &str, parser: &Parser) -> Option<(Token, usize)> {
+    if let Some(rest) = s.strip_prefix('(') {
+        let len = rest.find(')')?;
+        return Some((emoticon(&rest[..len], parser)?, len + 2));
+    }
+    if !s.starts_with("<emoji") {
+        return None;
+    }
+    let tag = &s[..s.find('>')? + 1];
+    // `alt` holds the emoji itself, `id` the code of the emoticon
+    let alt = crate::attribute(tag, "alt").and_then(|alt| emoji(alt, parser));
+    let emoji = alt.or_else(|| emoticon(crate::attribute(tag, "id")?, parser))?;
+    let len = match tag.ends_with("/>") {
+        true => tag.len(),
+        false => tag.len() + s[tag.len()..].strip_prefix("</emoji>").map_or(0, |_| 8),
+    };
+    Some((emoji, len))
+}
+
+impl Parser {
+    /// Parse a Teams message, `(code)` emoticons and `<emoji>` tags become emoji tokens
+    pub fn parse_teams(&self, s: &str) -> Result<Vec<Token>, ParseError> {
+        let mut tokens = Vec::new();
+        let (mut text, mut at) = (0, 0);
+        while let Some(start) = s[at..].find(['(', '<']).map(|i| at + i) {
+            match find_markup(&s[start..], self) {
+                Some((emoji, len)) => {
+                    tokens.extend(self.tokenize(&s[text..start])?);
+                    tokens.push(emoji);
+                    at = start + len;
+                    text = at;
+                }
+                None => at = start + 1,
+            }
+        }
+        tokens.extend(self.tokenize(&s[text..])?);
+        Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_codes() {
+        assert!(CODES.windows(2).all(|w| w[0].0 < w[1].0));
+        for (code, emoji) in CODES {
+            assert!(crate::get(emoji).is_some(), "no asset for ({})", code);
+        }
+    }
+    #[test]
+    fn test_parse_teams() {
+        let emoji = |s| Token::new_emoji(s, crate::get(s).unwrap());
+        let s = r#"hi (smile)(like) <emoji id="heart" alt="❤️" title="Heart"></emoji><emoji id="laugh"/> (nope) <b>"#;
+        assert_eq!(
+            Parser::new().parse_teams(s).unwrap(),
+            vec![
+                Token::new_text("hi "),
+                emoji("🙂"),
+                emoji("👍"),
+                Token::new_text(" "),
+                emoji("❤️"),
+                emoji("😃"),
+                Token::new_text(" (nope) <b>"),
+            ]
+        );
+    }
+}
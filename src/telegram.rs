@@ -0,0 +1,105 @@
+use crate::{ParseError, Parser, Token};
+
+/// Kind of a Telegram message entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EntityKind {
+    /// Premium custom emoji, the text it covers is its fallback emoji
+    CustomEmoji(u64),
+    /// Any other entity, which doesn't change the tokens
+    Other,
+}
+
+/// Entity of a Telegram message, with offsets in UTF-16 code units like the Bot API
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entity {
+    pub offset: usize,
+    pub length: usize,
+    pub kind: EntityKind,
+}
+
+impl Parser {
+    /// Parse the text of a Telegram message, custom emoji entities become custom emoji tokens
+    ///
+    /// Entities past the text, inside a character or overlapping a previous one are ignored.
+    pub fn parse_with_entities(
+        &self,
+        text: &str,
+        entities: &[Entity],
+    ) -> Result<Vec<Token>, ParseError> {
+        let mut emojis = entities
+            .iter()
+            .filter_map(|entity| {
+                let EntityKind::CustomEmoji(id) = entity.kind else {
+                    return None;
+                };
+                let start = byte_offset(text, entity.offset)?;
+                let end = byte_offset(text, entity.offset.checked_add(entity.length)?)?;
+                Some((start..end, id))
+            })
+            .collect::<Vec<_>>();
+        emojis.sort_by_key(|(range, _)| range.start);
+        let mut tokens = Vec::new();
+        let mut at = 0;
+        for (range, id) in emojis {
+            if range.start < at || range.is_empty() {
+                continue;
+            }
+            tokens.extend(self.tokenize(&text[at..range.start])?);
+            tokens.push(Token::new_custom_emoji(id, &text[range.clone()], false));
+            at = range.end;
+        }
+        tokens.extend(self.tokenize(&text[at..])?);
+        Ok(tokens)
+    }
+}
+
+/// Byte offset of the UTF-16 offset `utf16` in `s`, `None` inside a character or past the end
+fn byte_offset(s: &str, utf16: usize) -> Option<usize> {
+    let mut units = 0;
+    for (i, c) in s.char_indices() {
+        if units >= utf16 {
+            return (units == utf16).then_some(i);
+        }
+        units += c.len_utf16();
+    }
+    (units == utf16).then_some(s.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn custom_emoji(offset: usize, length: usize, id: u64) -> Entity {
+        Entity {
+            offset,
+            length,
+            kind: EntityKind::CustomEmoji(id),
+        }
+    }
+    #[test]
+    fn test_parse_with_entities() {
+        let entities = [
+            custom_emoji(3, 2, 5368324170671202286),
+            Entity {
+                offset: 0,
+                length: 2,
+                kind: EntityKind::Other,
+            },
+            // Inside the surrogate pair of 😀
+            custom_emoji(1, 2, 1),
+            custom_emoji(3, usize::MAX, 2),
+        ];
+        let tokens = Parser::new()
+            .parse_with_entities("😀 👍!", &entities)
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::new_emoji("😀", crate::get("😀").unwrap()),
+                Token::new_text(" "),
+                Token::new_custom_emoji(5368324170671202286, "👍", false),
+                Token::new_text("!"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use crate::{Entity, EntityKind, ParseError, Parser, Token};
+use teloxide::types::{Message, MessageEntity, MessageEntityKind};
+
+impl From<&MessageEntity> for Entity {
+    fn from(entity: &MessageEntity) -> Self {
+        let kind = match entity.kind {
+            MessageEntityKind::CustomEmoji {
+                ref custom_emoji_id,
+            } => custom_emoji_id
+                .parse()
+                .map_or(EntityKind::Other, EntityKind::CustomEmoji),
+            _ => EntityKind::Other,
+        };
+        Self {
+            offset: entity.offset,
+            length: entity.length,
+            kind,
+        }
+    }
+}
+
+impl Parser {
+    /// Parse the text of a teloxide message with its entities, or its caption for media
+    pub fn parse_telegram_message(&self, message: &Message) -> Result<Vec<Token>, ParseError> {
+        let (text, entities) = match message.text() {
+            Some(text) => (text, message.entities()),
+            None => (message.caption().unwrap_or(""), message.caption_entities()),
+        };
+        let entities = entities
+            .unwrap_or_default()
+            .iter()
+            .map(Entity::from)
+            .collect::<Vec<_>>();
+        self.parse_with_entities(text, &entities)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    #[test]
+    fn test_parse_telegram_message() {
+        let message: Message = serde_json::from_value(json!({
+            "message_id": 1,
+            "date": 0,
+            "chat": { "id": 1, "type": "private", "first_name": "user" },
+            "text": "hi 👍 😀",
+            "entities": [
+                { "type": "bold", "offset": 0, "length": 2 },
+                {
+                    "type": "custom_emoji",
+                    "offset": 3,
+                    "length": 2,
+                    "custom_emoji_id": "5368324170671202286",
+                },
+            ],
+        }))
+        .unwrap();
+        assert_eq!(
+            Parser::new().parse_telegram_message(&message).unwrap(),
+            vec![
+                Token::new_text("hi "),
+                Token::new_custom_emoji(5368324170671202286, "👍", false),
+                Token::new_text(" "),
+                Token::new_emoji("😀", crate::get("😀").unwrap()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Rendering of tokens for terminals showing images inline
+
+use crate::Token;
+#[cfg(any(feature = "discord", feature = "sixel"))]
+use std::fmt::Write;
+
+/// Largest base64 payload of a kitty escape sequence
+const KITTY_CHUNK: usize = 4096;
+
+/// Escape sequences of a terminal to show images with
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TerminalProtocol {
+    /// Kitty graphics protocol, also supported by WezTerm and Ghostty
+    Kitty,
+    /// Inline images of iTerm2, OSC 1337
+    ITerm2,
+    /// Sixel graphics, for foot, mlterm and xterm
+    #[cfg(feature = "sixel")]
+    Sixel(SixelOptions),
+}
+
+/// Render tokens with the images of `protocol`, emoji images take the space of a wide
+/// character and everything else is plain text
+///
+/// Custom emoji without png bytes are shown as `:name:`.
+pub fn render(tokens: &[Token], protocol: &TerminalProtocol) -> String {
+    let mut out = String::new();
+    let image = |png: &[u8], out: &mut String| match protocol {
+        TerminalProtocol::Kitty => push_kitty(png, out),
+        TerminalProtocol::ITerm2 => push_iterm2(png, out),
+        #[cfg(feature = "sixel")]
+        TerminalProtocol::Sixel(options) => push_sixel(png, options, out),
+    };
+    tokens
+        .iter()
+        .for_each(|token| push_token(token, &image, &mut out));
+    out
+}
+
+/// Render tokens with the kitty graphics protocol, emoji images are placed over two cells
+/// like a wide character and everything else is plain text
+///
+/// Custom emoji without png bytes are shown as `:name:`.
+pub fn to_kitty(tokens: &[Token]) -> String {
+    render(tokens, &TerminalProtocol::Kitty)
+}
+
+/// Options of the sixel renderer
+#[cfg(feature = "sixel")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SixelOptions {
+    /// Height of a terminal cell in pixels, which emoji are scaled to
+    pub cell_height: u32,
+}
+
+#[cfg(feature = "sixel")]
+impl Default for SixelOptions {
+    fn default() -> Self {
+        Self { cell_height: 20 }
+    }
+}
+
+/// Render tokens as sixel graphics, for terminals such as foot and mlterm, emoji images are
+/// scaled to the height of a cell and everything else is plain text
+///
+/// Custom emoji without png bytes are shown as `:name:`.
+#[cfg(feature = "sixel")]
+pub fn to_sixel(tokens: &[Token], options: &SixelOptions) -> String {
+    render(tokens, &TerminalProtocol::Sixel(options.clone()))
+}
+
+/// Writes the image of a png, or nothing if it can't be shown
+type PushImage<'a> = dyn Fn(&[u8], &mut String) -> bool + 'a;
+
+fn push_token(token: &Token, image: &PushImage, out: &mut String) {
+    #[cfg(feature = "discord")]
+    if let Token::Spoiler(tokens) = token {
+        return tokens
+            .iter()
+            .for_each(|token| push_token(token, image, out));
+    }
+    match png_of(token) {
+        Some(png) if image(png, out) => {}
+        _ => push_text(token, out),
+    }
+}
+
+/// Png image of an emoji token
+fn png_of(token: &Token) -> Option<&[u8]> {
+    match token {
+        Token::Emoji { image, .. } => Some(image.as_ref()),
+        #[cfg(feature = "emoji-url")]
+        Token::EmojiUrl { emoji, .. } => crate::get(emoji),
+        // Drawn from the png of the same emoji
+        #[cfg(feature = "svg")]
+        Token::EmojiSvg { emoji, .. } => crate::get(emoji),
+        #[cfg(feature = "custom-emoji")]
+        Token::FetchedCustomEmoji { image, .. } => {
+            Some(image.as_slice()).filter(|image| image.starts_with(b"\x89PNG"))
+        }
+        _ => None,
+    }
+}
+
+/// Transmit and show a png at the cursor, which is moved past it
+fn push_kitty(png: &[u8], out: &mut String) -> bool {
+    let payload = crate::base64::encode(png);
+    let chunks = payload.as_bytes().chunks(KITTY_CHUNK);
+    let last = chunks.len() - 1;
+    for (i, chunk) in chunks.enumerate() {
+        let more = (i < last) as u8;
+        match i {
+            // Quiet, so the terminal doesn't answer on the input
+            0 => out.push_str(&format!("\x1b_Ga=T,f=100,c=2,r=1,q=2,m={};", more)),
+            _ => out.push_str(&format!("\x1b_Gm={};", more)),
+        }
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push_str("\x1b\\");
+    }
+    true
+}
+
+/// Show a png inline, two cells wide and one high
+fn push_iterm2(png: &[u8], out: &mut String) -> bool {
+    out.push_str(&format!(
+        "\x1b]1337;File=inline=1;size={};width=2;height=1;preserveAspectRatio=1:{}\x07",
+        png.len(),
+        crate::base64::encode(png)
+    ));
+    true
+}
+
+/// Decode, scale and write a png as sixel
+#[cfg(feature = "sixel")]
+fn push_sixel(png: &[u8], options: &SixelOptions, out: &mut String) -> bool {
+    let Some(image) = crate::png::decode(png) else {
+        return false;
+    };
+    let height = options.cell_height.max(1);
+    let width = (image.width * height / image.height).max(1);
+    let image = image.resized(width, height);
+    // Colors of a 6x6x6 cube, transparent pixels are left unset
+    let colors = image
+        .pixels
+        .chunks(4)
+        .map(|p| (p[3] >= 128).then(|| p[..3].iter().fold(0, |n, c| n * 6 + *c as usize / 43)))
+        .collect::<Vec<_>>();
+    let mut used = colors.iter().flatten().copied().collect::<Vec<_>>();
+    used.sort_unstable();
+    used.dedup();
+    // Transparent background, at one to one aspect ratio
+    let _ = write!(out, "\x1bP0;1;0q\"1;1;{};{}", width, height);
+    for &color in &used {
+        let [r, g, b] = [color / 36, color / 6 % 6, color % 6].map(|c| c * 20);
+        let _ = write!(out, "#{};2;{};{};{}", color, r, g, b);
+    }
+    let (width, height) = (width as usize, height as usize);
+    for band in (0..height).step_by(6) {
+        for &color in &used {
+            let sixels = (0..width)
+                .map(|x| {
+                    (0..6)
+                        .filter(|dy| band + dy < height)
+                        .filter(|dy| colors[(band + dy) * width + x] == Some(color))
+                        .fold(0u8, |bits, dy| bits | 1 << dy)
+                })
+                .collect::<Vec<_>>();
+            if sixels.iter().all(|bits| *bits == 0) {
+                continue;
+            }
+            let _ = write!(out, "#{}", color);
+            push_runs(&sixels, out);
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    true
+}
+
+/// Sixels with runs of the same one written as `!count`
+#[cfg(feature = "sixel")]
+fn push_runs(sixels: &[u8], out: &mut String) {
+    let mut rest = sixels;
+    while let Some(&bits) = rest.first() {
+        let run = rest.iter().take_while(|b| **b == bits).count();
+        let c = (b'?' + bits) as char;
+        match run {
+            1..=3 => (0..run).for_each(|_| out.push(c)),
+            _ => {
+                let _ = write!(out, "!{}{}", run, c);
+            }
+        }
+        rest = &rest[run..];
+    }
+}
+
+/// Text of a token without an image
+fn push_text(token: &Token, out: &mut String) {
+    match token {
+        Token::Text(s) => out.push_str(s),
+        Token::Emoji { .. } => {}
+        #[cfg(feature = "emoji-url")]
+        Token::EmojiUrl { emoji, .. } => out.push_str(emoji),
+        #[cfg(feature = "svg")]
+        Token::EmojiSvg { emoji, .. } => out.push_str(emoji),
+        #[cfg(feature = "custom-emoji")]
+        Token::CustomEmoji { name, .. } | Token::FetchedCustomEmoji { name, .. } => {
+            out.push(':');
+            out.push_str(name);
+            out.push(':');
+        }
+        #[cfg(feature = "discord")]
+        Token::Mention(mention) => {
+            let prefix = match mention {
+                crate::Mention::User(_) => "@",
+                crate::Mention::Channel(_) => "#",
+                crate::Mention::Role(_) => "@&",
+            };
+            let _ = write!(out, "{}{}", prefix, mention.id());
+        }
+        #[cfg(feature = "discord")]
+        Token::CommandMention { name, .. } => {
+            out.push('/');
+            out.push_str(name);
+        }
+        #[cfg(feature = "discord")]
+        Token::Code(code) | Token::CodeBlock { code, .. } => out.push_str(code),
+        #[cfg(feature = "discord")]
+        // Handled by `push_token`, as they may hold images
+        Token::Spoiler(_) => {}
+        #[cfg(feature = "discord")]
+        Token::Timestamp { unix, .. } => {
+            let _ = write!(out, "{}", unix);
+        }
+        #[cfg(feature = "discord")]
+        Token::Sticker { name, .. } => out.push_str(name),
+        #[cfg(feature = "named-custom-emoji")]
+        Token::NamedCustomEmoji { name, .. } => {
+            out.push(':');
+            out.push_str(name);
+            out.push(':');
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_to_kitty() {
+        let png = b"\x89PNG";
+        assert_eq!(
+            to_kitty(&[
+                Token::new_text("hi "),
+                Token::new_emoji("👍", png.as_slice())
+            ]),
+            "hi \x1b_Ga=T,f=100,c=2,r=1,q=2,m=0;iVBORw==\x1b\\"
+        );
+        // Large images are sent in chunks
+        let png = vec![0; KITTY_CHUNK];
+        let out = to_kitty(&[Token::new_emoji("👍", png)]);
+        assert_eq!(out.matches("\x1b_G").count(), 2);
+        assert!(out.contains("m=1;"));
+        assert!(out.contains("\x1b_Gm=0;"));
+    }
+    #[test]
+    fn test_render() {
+        let tokens = [
+            Token::new_emoji("👍", b"\x89PNG".as_slice()),
+            Token::new_text("!"),
+        ];
+        assert_eq!(
+            render(&tokens, &TerminalProtocol::ITerm2),
+            "\x1b]1337;File=inline=1;size=4;width=2;height=1;preserveAspectRatio=1:iVBORw==\x07!"
+        );
+        assert_eq!(render(&tokens, &TerminalProtocol::Kitty), to_kitty(&tokens));
+    }
+    #[test]
+    #[cfg(feature = "sixel")]
+    fn test_to_sixel() {
+        let tokens = [
+            Token::new_text("hi "),
+            Token::new_emoji("👍", crate::get("👍").unwrap()),
+        ];
+        let sixel = to_sixel(&tokens, &SixelOptions { cell_height: 12 });
+        let sixel = sixel.strip_prefix("hi \x1bP0;1;0q\"1;1;12;12#").unwrap();
+        assert!(sixel.ends_with("-\x1b\\"), "{sixel}");
+        // Images that aren't png are shown as text
+        let tokens = [Token::new_emoji("👍", b"GIF89a".as_slice())];
+        assert_eq!(to_sixel(&tokens, &SixelOptions::default()), "");
+    }
+    #[test]
+    #[cfg(feature = "sixel")]
+    fn test_push_runs() {
+        let mut out = String::new();
+        push_runs(&[0, 0, 63, 63, 63, 63, 1], &mut out);
+        assert_eq!(out, "??!4~@");
+    }
+    #[test]
+    #[cfg(feature = "discord")]
+    fn test_to_kitty_discord() {
+        let tokens = crate::parse("<:blobcat:123456789012345678> <@234567890123456789>").unwrap();
+        assert_eq!(to_kitty(&tokens), ":blobcat: @234567890123456789");
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{DiscordMessage, GuildEmojiCache, Reaction, StickerFormat, StickerItem};
+use twilight_model::channel::message::{EmojiReactionType, Message};
+use twilight_model::guild::Emoji;
+
+impl<'a> Extend<&'a Emoji> for GuildEmojiCache {
+    fn extend<I: IntoIterator<Item = &'a Emoji>>(&mut self, iter: I) {
+        iter.into_iter()
+            .for_each(|emoji| self.insert(emoji.id.get(), emoji.name.as_str(), emoji.animated));
+    }
+}
+
+impl<'a> FromIterator<&'a Emoji> for GuildEmojiCache {
+    fn from_iter<I: IntoIterator<Item = &'a Emoji>>(iter: I) -> Self {
+        let mut cache = Self::new();
+        cache.extend(iter);
+        cache
+    }
+}
+
+impl DiscordMessage for Message {
+    fn content(&self) -> &str {
+        &self.content
+    }
+    fn reactions(&self) -> Vec<Reaction<'_>> {
+        self.reactions
+            .iter()
+            .map(|reaction| match reaction.emoji {
+                EmojiReactionType::Custom {
+                    animated,
+                    id,
+                    ref name,
+                } => Reaction::Custom {
+                    id: id.get(),
+                    name: name.as_deref(),
+                    animated,
+                },
+                EmojiReactionType::Unicode { ref name } => Reaction::Unicode(name),
+            })
+            .collect()
+    }
+    fn stickers(&self) -> Vec<StickerItem> {
+        self.sticker_items
+            .iter()
+            .map(|sticker| StickerItem {
+                id: sticker.id.get(),
+                name: sticker.name.clone(),
+                format: StickerFormat::from_format_type(sticker.format_type.into()),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Parser, Token};
+    use serde_json::json;
+    fn reaction(emoji: serde_json::Value) -> serde_json::Value {
+        json!({
+            "burst_colors": [],
+            "count": 1,
+            "count_details": { "burst": 0, "normal": 1 },
+            "emoji": emoji,
+            "me": false,
+            "me_burst": false,
+        })
+    }
+    #[test]
+    fn test_parse_message() {
+        let emoji: Emoji =
+            serde_json::from_value(json!({ "id": "123456789012345678", "name": "renamed" }))
+                .unwrap();
+        let emojis: GuildEmojiCache = [emoji].iter().collect();
+        let message: Message = serde_json::from_value(json!({
+            "attachments": [],
+            "author": {
+                "avatar": null,
+                "discriminator": "0",
+                "id": "1",
+                "username": "user",
+            },
+            "channel_id": "2",
+            "content": "hi <:old:123456789012345678>",
+            "edited_timestamp": null,
+            "embeds": [],
+            "id": "3",
+            "type": 0,
+            "mention_everyone": false,
+            "mention_roles": [],
+            "mentions": [],
+            "pinned": false,
+            "reactions": [
+                reaction(json!({ "id": null, "name": "👍" })),
+                reaction(json!({ "id": "123456789012345678", "name": null })),
+            ],
+            "sticker_items": [{ "format_type": 1, "id": "345678901234567890", "name": "wave" }],
+            "timestamp": "2021-01-01T00:00:00.000000+00:00",
+            "tts": false,
+        }))
+        .unwrap();
+        let tokens = Parser::new().parse_message(&message, &emojis).unwrap();
+        let renamed = Token::new_custom_emoji(123456789012345678, "renamed", false);
+        assert_eq!(tokens.content, vec![Token::new_text("hi "), renamed]);
+        assert_eq!(
+            tokens.reactions,
+            vec![
+                Token::new_emoji("👍", crate::get("👍").unwrap()),
+                Token::new_custom_emoji(123456789012345678, "renamed", false),
+            ]
+        );
+        assert_eq!(
+            tokens.stickers,
+            vec![StickerItem {
+                id: 345678901234567890,
+                name: "wave".to_string(),
+                format: StickerFormat::Png,
+            }]
+        );
+    }
+}
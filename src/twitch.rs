@@ -0,0 +1,116 @@
+use crate::{ParseError, Parser, Token};
+
+/// Emotes of a Twitch chat message, from the `emotes` IRC tag
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EmoteRanges {
+    /// Emote id and inclusive range of characters
+    emotes: Vec<(String, usize, usize)>,
+}
+
+impl EmoteRanges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Parse the value of the tag, such as `25:0-4,12-16/1902:6-10`, `None` if malformed
+    pub fn parse(tag: &str) -> Option<Self> {
+        let mut ranges = Self::new();
+        for emote in tag.split('/').filter(|emote| !emote.is_empty()) {
+            let (id, positions) = emote.split_once(':')?;
+            for position in positions.split(',') {
+                let (start, end) = position.split_once('-')?;
+                ranges.insert(id, start.parse().ok()?, end.parse().ok()?);
+            }
+        }
+        Some(ranges)
+    }
+    /// Add emote `id` over the characters `start` to `end`, both included
+    pub fn insert(&mut self, id: impl Into<String>, start: usize, end: usize) {
+        self.emotes.push((id.into(), start, end));
+    }
+}
+
+/// Url of the emote image on the Twitch CDN
+pub(crate) fn emote_url(id: &str) -> String {
+    format!("https://static-cdn.jtvnw.net/emoticons/v2/{id}/default/dark/1.0")
+}
+
+impl Parser {
+    /// Parse a Twitch chat message, the ranges of `emotes` become custom emoji tokens
+    ///
+    /// Ranges past the text or overlapping a previous one are ignored.
+    pub fn parse_twitch(&self, text: &str, emotes: &EmoteRanges) -> Result<Vec<Token>, ParseError> {
+        // Twitch counts characters, not bytes
+        let offsets = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain([text.len()])
+            .collect::<Vec<_>>();
+        let mut ranges = emotes
+            .emotes
+            .iter()
+            .filter_map(|(id, start, end)| {
+                let end = end
+                    .checked_add(1)
+                    .filter(|end| start < end && *end < offsets.len())?;
+                Some((offsets[*start]..offsets[end], id))
+            })
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|(range, _)| range.start);
+        let mut tokens = Vec::new();
+        let mut at = 0;
+        for (range, id) in ranges {
+            if range.start < at {
+                continue;
+            }
+            tokens.extend(self.tokenize(&text[at..range.start])?);
+            tokens.push(Token::NamedCustomEmoji {
+                name: text[range.clone()].to_string(),
+                url: emote_url(id),
+            });
+            at = range.end;
+        }
+        tokens.extend(self.tokenize(&text[at..])?);
+        Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn emote(id: &str, name: &str) -> Token {
+        Token::NamedCustomEmoji {
+            name: name.to_string(),
+            url: emote_url(id),
+        }
+    }
+    #[test]
+    fn test_emote_ranges() {
+        let mut ranges = EmoteRanges::new();
+        ranges.insert("25", 0, 4);
+        ranges.insert("25", 12, 16);
+        ranges.insert("1902", 6, 10);
+        assert_eq!(EmoteRanges::parse("25:0-4,12-16/1902:6-10"), Some(ranges));
+        assert_eq!(EmoteRanges::parse(""), Some(EmoteRanges::new()));
+        assert_eq!(EmoteRanges::parse("25:0-x"), None);
+    }
+    #[test]
+    fn test_parse_twitch() {
+        let ranges = EmoteRanges::parse("25:0-4,8-12/1902:2-6/9:20-30").unwrap();
+        let tokens = Parser::new()
+            .parse_twitch("Kappa 👍 Kappa", &ranges)
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                emote("25", "Kappa"),
+                Token::new_text(" "),
+                Token::new_emoji("👍", crate::get("👍").unwrap()),
+                Token::new_text(" "),
+                emote("25", "Kappa"),
+            ]
+        );
+        let ranges = EmoteRanges::parse("25:0-18446744073709551615").unwrap();
+        let tokens = Parser::new().parse_twitch("Kappa", &ranges).unwrap();
+        assert_eq!(tokens, vec![Token::new_text("Kappa")]);
+    }
+}
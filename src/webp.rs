@@ -0,0 +1,321 @@
+//! Small webp decoder and lossless encoder, enough for emoji images and rendered messages
+
+#[cfg(feature = "webp")]
+mod encode;
+mod lossless;
+mod lossy;
+mod tables;
+
+use crate::png::RgbaImage;
+#[cfg(feature = "webp")]
+pub(crate) use encode::encode;
+use lossless::Bits;
+use std::time::Duration;
+
+/// Larger images are not emoji, and would take a lot of memory
+const MAX_DIMENSION: u32 = 4096;
+
+/// Memory the decoded frames of an image may take, later frames are dropped
+const MAX_FRAMES_SIZE: usize = 64 << 20;
+
+/// Frames of a webp with the time each is shown, composited over the previous ones, `None`
+/// if it is invalid
+///
+/// Lossy and lossless images are decoded, with their alpha and animation.
+pub(crate) fn decode(webp: &[u8]) -> Option<Vec<(RgbaImage, Duration)>> {
+    if webp.get(..4)? != b"RIFF" || webp.get(8..12)? != b"WEBP" {
+        return None;
+    }
+    let size = u32::from_le_bytes(webp[4..8].try_into().ok()?) as usize;
+    let chunks = read_chunks(webp.get(12..(8 + size).min(webp.len()))?);
+    let Some((b"VP8X", header)) = chunks.first().copied() else {
+        return Some(vec![(image(&chunks)?, Duration::ZERO)]);
+    };
+    let (width, height) = (1 + u24(header.get(4..7)?), 1 + u24(header.get(7..10)?));
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return None;
+    }
+    // Still images with metadata or alpha
+    if header[0] & 0x02 == 0 {
+        let image = image(&chunks)?;
+        return ((image.width, image.height) == (width, height))
+            .then(|| vec![(image, Duration::ZERO)]);
+    }
+    let mut canvas = RgbaImage::new(width, height);
+    let mut frames = Vec::new();
+    // Area of the previous frame if it is cleared before the next one is drawn
+    let mut dispose = None;
+    for (_, frame) in chunks.iter().filter(|(id, _)| *id == b"ANMF") {
+        if frames.len() * canvas.pixels.len() > MAX_FRAMES_SIZE {
+            break;
+        }
+        let (x, y) = (2 * u24(frame.get(..3)?), 2 * u24(frame.get(3..6)?));
+        let duration = Duration::from_millis(u24(frame.get(12..15)?) as u64);
+        let flags = *frame.get(15)?;
+        let image = image(&read_chunks(&frame[16..]))?;
+        let size = (1 + u24(&frame[6..9]), 1 + u24(&frame[9..12]));
+        if size != (image.width, image.height) || x + size.0 > width || y + size.1 > height {
+            return None;
+        }
+        if let Some((x, y, w, h)) = dispose.take() {
+            fill(&mut canvas, &RgbaImage::new(w, h), x, y);
+        }
+        match flags & 0x02 {
+            0 => canvas.draw(&image, x as i64, y as i64),
+            _ => fill(&mut canvas, &image, x, y),
+        }
+        if flags & 0x01 == 1 {
+            dispose = Some((x, y, image.width, image.height));
+        }
+        frames.push((canvas.clone(), duration));
+    }
+    (!frames.is_empty()).then_some(frames)
+}
+
+/// Chunks of a RIFF body, id and data
+fn read_chunks(mut body: &[u8]) -> Vec<(&[u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    while let (Some(id), Some(size)) = (body.get(..4), body.get(4..8)) {
+        let size = u32::from_le_bytes(size.try_into().unwrap_or_default()) as usize;
+        let data = &body[8..];
+        let Some(chunk) = data.get(..size) else {
+            break;
+        };
+        chunks.push((id.try_into().unwrap_or(&[0; 4]), chunk));
+        // Chunks are padded to an even size
+        body = data.get(size + size % 2..).unwrap_or_default();
+    }
+    chunks
+}
+
+fn u24(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0])
+}
+
+/// Copy `image` over the canvas with its top left corner at `x`, `y`, alpha included
+fn fill(canvas: &mut RgbaImage, image: &RgbaImage, x: u32, y: u32) {
+    let row = image.width as usize * 4;
+    for dy in 0..image.height as usize {
+        let at = ((y as usize + dy) * canvas.width as usize + x as usize) * 4;
+        canvas.pixels[at..at + row].copy_from_slice(&image.pixels[dy * row..][..row]);
+    }
+}
+
+/// Image of the lossless or lossy chunk of a still image or animation frame, with its alpha
+fn image(chunks: &[(&[u8; 4], &[u8])]) -> Option<RgbaImage> {
+    let find = |id: &[u8; 4]| chunks.iter().find(|(i, _)| *i == id).map(|(_, data)| *data);
+    if let Some(data) = find(b"VP8L") {
+        return lossless(data);
+    }
+    let (width, height, mut pixels) = lossy::decode(find(b"VP8 ")?)?;
+    if let Some(data) = find(b"ALPH") {
+        let alpha = alpha(data, width as usize, height as usize)?;
+        for (pixel, alpha) in pixels.chunks_exact_mut(4).zip(alpha) {
+            pixel[3] = alpha;
+        }
+    }
+    Some(RgbaImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Image of a VP8L chunk
+fn lossless(data: &[u8]) -> Option<RgbaImage> {
+    let mut bits = Bits::new(data.strip_prefix(&[0x2f])?);
+    let (width, height) = (bits.read(14)? + 1, bits.read(14)? + 1);
+    // Alpha hint and version
+    if bits.read(4)? >> 1 != 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return None;
+    }
+    let argb = lossless::decode_stream(&mut bits, width as usize, height as usize)?;
+    Some(RgbaImage {
+        width,
+        height,
+        pixels: argb
+            .into_iter()
+            .flat_map(|pixel| {
+                let [a, r, g, b] = pixel.to_be_bytes();
+                [r, g, b, a]
+            })
+            .collect(),
+    })
+}
+
+/// Alpha values of an ALPH chunk, raw or in the green of a VP8L image, then unfiltered
+fn alpha(data: &[u8], width: usize, height: usize) -> Option<Vec<u8>> {
+    let (&header, data) = data.split_first()?;
+    let mut alpha = match header & 0x03 {
+        0 => data.get(..width * height)?.to_vec(),
+        1 => lossless::decode_stream(&mut Bits::new(data), width, height)?
+            .into_iter()
+            .map(|pixel| (pixel >> 8) as u8)
+            .collect(),
+        _ => return None,
+    };
+    let filter = (header >> 2) & 0x03;
+    if filter == 0 {
+        return Some(alpha);
+    }
+    for i in 1..width * height {
+        let (x, y) = (i % width, i / width);
+        let prediction = match (x, y) {
+            (_, 0) => alpha[i - 1],
+            (0, _) => alpha[i - width],
+            _ => match filter {
+                1 => alpha[i - 1],
+                2 => alpha[i - width],
+                _ => {
+                    let (left, top) = (alpha[i - 1] as i32, alpha[i - width] as i32);
+                    (left + top - alpha[i - width - 1] as i32).clamp(0, 255) as u8
+                }
+            },
+        };
+        alpha[i] = alpha[i].wrapping_add(prediction);
+    }
+    Some(alpha)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    /// Encoded by libwebp from `gradient` at quality 90, `pattern` and `stripes`
+    const LOSSY: &[u8] = &[
+        82, 73, 70, 70, 150, 0, 0, 0, 87, 69, 66, 80, 86, 80, 56, 88, 10, 0, 0, 0, 16, 0, 0, 0, 15,
+        0, 0, 15, 0, 0, 65, 76, 80, 72, 26, 0, 0, 0, 1, 15, 240, 192, 255, 136, 136, 64, 144, 109,
+        195, 48, 133, 177, 12, 238, 232, 71, 136, 232, 127, 17, 96, 43, 189, 45, 86, 80, 56, 32,
+        86, 0, 0, 0, 208, 2, 0, 157, 1, 42, 16, 0, 16, 0, 0, 192, 18, 37, 176, 2, 116, 115, 1, 114,
+        1, 188, 0, 175, 0, 19, 119, 45, 185, 90, 232, 0, 254, 255, 171, 204, 253, 124, 65, 78, 224,
+        89, 119, 131, 170, 88, 136, 104, 60, 15, 5, 187, 255, 246, 93, 99, 31, 228, 46, 187, 77,
+        216, 31, 116, 92, 23, 191, 184, 181, 63, 255, 151, 25, 197, 169, 255, 252, 219, 238, 44,
+        241, 12, 255, 0, 0, 0,
+    ];
+    const LOSSLESS: &[u8] = &[
+        82, 73, 70, 70, 222, 0, 0, 0, 87, 69, 66, 80, 86, 80, 56, 76, 209, 0, 0, 0, 47, 15, 192, 3,
+        16, 137, 50, 68, 244, 63, 22, 17, 209, 255, 0, 130, 182, 109, 99, 8, 229, 207, 118, 251,
+        223, 83, 24, 8, 39, 182, 109, 187, 201, 115, 219, 209, 92, 138, 163, 218, 14, 19, 64, 131,
+        10, 138, 104, 154, 170, 46, 42, 62, 58, 142, 153, 48, 54, 130, 73, 26, 63, 31, 61, 62, 212,
+        182, 109, 195, 12, 105, 220, 156, 51, 136, 140, 25, 12, 70, 213, 35, 6, 128, 240, 234, 17,
+        126, 133, 71, 232, 197, 32, 28, 34, 42, 69, 2, 16, 143, 3, 188, 76, 114, 160, 132, 112, 0,
+        58, 61, 204, 244, 28, 1, 194, 129, 33, 196, 247, 17, 238, 226, 4, 16, 78, 14, 168, 122,
+        152, 228, 103, 103, 142, 11, 132, 211, 87, 24, 103, 135, 47, 132, 179, 194, 211, 149, 155,
+        4, 37, 132, 51, 183, 5, 52, 16, 217, 159, 220, 228, 184, 64, 56, 7, 128, 216, 158, 90, 203,
+        13, 128, 216, 159, 214, 97, 6, 241, 56, 48, 68, 21, 206, 10, 141, 41, 126, 128, 162, 247,
+        111, 244, 241, 132, 216, 158, 22, 112, 142, 255, 47, 166, 19, 223, 3, 32, 178, 83, 3, 138,
+        248, 159, 25, 0,
+    ];
+    const PALETTE: &[u8] = &[
+        82, 73, 70, 70, 54, 0, 0, 0, 87, 69, 66, 80, 86, 80, 56, 76, 42, 0, 0, 0, 47, 8, 0, 1, 16,
+        23, 48, 200, 2, 129, 36, 147, 253, 41, 31, 173, 249, 15, 176, 11, 10, 1, 160, 97, 218, 67,
+        72, 46, 132, 81, 132, 18, 118, 68, 255, 131, 105, 171, 254, 192, 180, 85,
+    ];
+    fn image(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> RgbaImage {
+        RgbaImage {
+            width,
+            height,
+            pixels: (0..width * height)
+                .flat_map(|i| pixel(i % width, i / width))
+                .collect(),
+        }
+    }
+    fn alpha(x: u32, y: u32) -> u8 {
+        match (x + y) % 4 {
+            0 => 128,
+            _ => 255,
+        }
+    }
+    fn gradient() -> RgbaImage {
+        image(16, 16, |x, y| {
+            [x as u8 * 16, y as u8 * 16, 128, alpha(x, y)]
+        })
+    }
+    fn pattern() -> RgbaImage {
+        image(16, 16, |x, y| {
+            [x as u8 * 16, y as u8 * 16, (x * y * 7) as u8, alpha(x, y)]
+        })
+    }
+    fn stripes() -> RgbaImage {
+        let colors = [[255, 0, 0, 255], [0; 4], [30, 200, 90, 255]];
+        image(9, 5, |x, y| colors[(x / 3 + y) as usize % 3])
+    }
+    /// Simple file holding `body` as its chunks
+    fn riff(body: &[u8]) -> Vec<u8> {
+        let size = (body.len() as u32 + 4).to_le_bytes();
+        [b"RIFF", &size[..], b"WEBP", body].concat()
+    }
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        [id, &(data.len() as u32).to_le_bytes()[..], data].concat()
+    }
+    /// ANMF chunk drawing `STRIPES` at `x`, `y` for 100ms
+    fn frame(x: u8, y: u8, flags: u8) -> Vec<u8> {
+        let header = [x / 2, 0, 0, y / 2, 0, 0, 8, 0, 0, 4, 0, 0, 100, 0, 0, flags];
+        chunk(b"ANMF", &[&header[..], &PALETTE[12..]].concat())
+    }
+    #[test]
+    fn test_decode_lossless() {
+        assert_eq!(decode(LOSSLESS), Some(vec![(pattern(), Duration::ZERO)]));
+        assert_eq!(decode(PALETTE), Some(vec![(stripes(), Duration::ZERO)]));
+    }
+    #[test]
+    fn test_decode_lossy() {
+        let frames = decode(LOSSY).unwrap();
+        assert_eq!(frames.len(), 1);
+        let (image, expected) = (&frames[0].0, gradient());
+        assert_eq!((image.width, image.height), (16, 16));
+        for (pixel, expected) in image.pixels.chunks(4).zip(expected.pixels.chunks(4)) {
+            for (value, expected) in pixel[..3].iter().zip(expected) {
+                assert!(value.abs_diff(*expected) <= 16);
+            }
+            assert_eq!(pixel[3], expected[3]);
+        }
+    }
+    #[test]
+    fn test_decode_animation() {
+        let header = chunk(b"VP8X", &[0x12, 0, 0, 0, 11, 0, 0, 7, 0, 0]);
+        let anim = chunk(b"ANIM", &[0; 6]);
+        let frames = [frame(0, 0, 0x01), frame(2, 2, 0x00), frame(0, 0, 0x02)];
+        let webp = riff(&[header, anim, frames.concat()].concat());
+        let frames = decode(&webp).unwrap();
+        let delay = Duration::from_millis(100);
+        let mut canvas = RgbaImage::new(12, 8);
+        fill(&mut canvas, &stripes(), 0, 0);
+        assert_eq!(frames[0], (canvas.clone(), delay));
+        // The first frame is cleared, transparent pixels of the second don't cover anything
+        let mut canvas = RgbaImage::new(12, 8);
+        fill(&mut canvas, &stripes(), 2, 2);
+        assert_eq!(frames[1], (canvas.clone(), delay));
+        // Without blending the third frame replaces what is under it
+        fill(&mut canvas, &stripes(), 0, 0);
+        assert_eq!(frames[2], (canvas, delay));
+        assert_eq!(frames.len(), 3);
+    }
+    #[test]
+    fn test_decode_broken() {
+        assert_eq!(decode(b"RIFF\0\0\0\0WEBPVP8 "), None);
+        for webp in [LOSSY, LOSSLESS, PALETTE] {
+            assert_eq!(decode(&webp[..webp.len() - 8]), None);
+            // Corrupt bytes give any image or none, without panicking
+            let mut flipped = webp.to_vec();
+            for at in 20..webp.len() {
+                flipped[at] ^= 0x5a;
+                decode(&flipped);
+                flipped[at] ^= 0x5a;
+            }
+        }
+        // Frames outside of the canvas
+        let header = chunk(b"VP8X", &[0x02, 0, 0, 0, 7, 0, 0, 4, 0, 0]);
+        assert_eq!(decode(&riff(&[header, frame(2, 0, 0)].concat())), None);
+    }
+    #[test]
+    #[cfg(feature = "custom-emoji")]
+    fn test_decode_fetched_emoji() {
+        let token = crate::Token::FetchedCustomEmoji {
+            id: 123456789012345678,
+            name: "blob".to_string(),
+            animated: false,
+            image: LOSSLESS.to_vec(),
+        };
+        assert_eq!(token.decode_image(), Some(pattern()));
+    }
+}
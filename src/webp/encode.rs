@@ -0,0 +1,444 @@
+//! Small lossless webp (VP8L) encoder, enough for emoji images and rendered messages
+
+use super::lossless::CODE_LENGTH_ORDER;
+use crate::png::RgbaImage;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Largest width and height of a VP8L image
+const MAX_SIZE: u32 = 1 << 14;
+/// Shortest and longest backward reference
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 4096;
+/// Pixels back a reference can reach, and candidates tried for each one
+const WINDOW: usize = 1 << 16;
+const CHAIN: usize = 16;
+
+/// Lossless webp of `image`, `None` if it is wider or taller than 16384 pixels
+pub(crate) fn encode(image: &RgbaImage) -> Option<Vec<u8>> {
+    let data = vp8l(image)?;
+    let mut webp = Vec::with_capacity(data.len() + 21);
+    let padded = data.len() + data.len() % 2;
+    webp.extend_from_slice(b"RIFF");
+    webp.extend_from_slice(&(12 + padded as u32).to_le_bytes());
+    webp.extend_from_slice(b"WEBPVP8L");
+    webp.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    webp.extend_from_slice(&data);
+    webp.resize(20 + padded, 0);
+    Some(webp)
+}
+
+/// Bits written from the least significant one
+#[derive(Default)]
+struct Bits {
+    out: Vec<u8>,
+    acc: u64,
+    len: u32,
+}
+
+impl Bits {
+    fn put(&mut self, value: u32, bits: u32) {
+        self.acc |= (value as u64) << self.len;
+        self.len += bits;
+        while self.len >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.len -= 8;
+        }
+    }
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+/// Pixel or backward reference of the image data
+enum Symbol {
+    Pixel(u32),
+    /// Length and distance code of a copy of earlier pixels
+    Copy(usize, usize),
+}
+
+/// VP8L bitstream of `image`, its pixels with the green subtracted and compressed with
+/// backward references and a single set of prefix codes
+fn vp8l(image: &RgbaImage) -> Option<Vec<u8>> {
+    let (width, height) = (image.width.max(1), image.height.max(1));
+    if width > MAX_SIZE || height > MAX_SIZE {
+        return None;
+    }
+    let argb = (0..width as usize * height as usize)
+        .map(|i| match image.pixels.get(i * 4..i * 4 + 4) {
+            Some(&[r, g, b, a]) => u32::from_be_bytes([a, r.wrapping_sub(g), g, b.wrapping_sub(g)]),
+            _ => 0,
+        })
+        .collect::<Vec<_>>();
+    let symbols = backward_refs(&argb, width as usize);
+    let mut bits = Bits::default();
+    bits.put(0x2f, 8);
+    bits.put(width - 1, 14);
+    bits.put(height - 1, 14);
+    let alpha = argb.iter().any(|p| p >> 24 != 0xff);
+    bits.put(alpha as u32, 1);
+    bits.put(0, 3);
+    // The subtract green transform, then no more transforms, color cache or meta prefix codes
+    bits.put(1, 1);
+    bits.put(2, 2);
+    bits.put(0, 1);
+    bits.put(0, 1);
+    bits.put(0, 1);
+    // Green with the length prefixes, red, blue, alpha and distance prefixes
+    let mut counts = [256 + 24, 256, 256, 256, 40].map(|size| vec![0u32; size]);
+    for symbol in &symbols {
+        match *symbol {
+            Symbol::Pixel(p) => {
+                counts[0][(p >> 8 & 0xff) as usize] += 1;
+                counts[1][(p >> 16 & 0xff) as usize] += 1;
+                counts[2][(p & 0xff) as usize] += 1;
+                counts[3][(p >> 24) as usize] += 1;
+            }
+            Symbol::Copy(len, dist) => {
+                counts[0][256 + prefix(len).0 as usize] += 1;
+                counts[4][prefix(dist).0 as usize] += 1;
+            }
+        }
+    }
+    let codes = counts.map(|counts| Code::write(&mut bits, &counts));
+    for symbol in symbols {
+        match symbol {
+            Symbol::Pixel(p) => {
+                codes[0].put(&mut bits, (p >> 8 & 0xff) as usize);
+                codes[1].put(&mut bits, (p >> 16 & 0xff) as usize);
+                codes[2].put(&mut bits, (p & 0xff) as usize);
+                codes[3].put(&mut bits, (p >> 24) as usize);
+            }
+            Symbol::Copy(len, dist) => {
+                for (code, value, offset) in [(&codes[0], len, 256), (&codes[4], dist, 0)] {
+                    let (prefix, extra, extra_bits) = prefix(value);
+                    code.put(&mut bits, offset + prefix as usize);
+                    bits.put(extra, extra_bits);
+                }
+            }
+        }
+    }
+    Some(bits.finish())
+}
+
+/// Prefix code, extra bits and their count of a length or distance code
+fn prefix(value: usize) -> (u32, u32, u32) {
+    let d = value as u32 - 1;
+    if d < 4 {
+        return (d, 0, 0);
+    }
+    let high = 31 - d.leading_zeros();
+    let second = d >> (high - 1) & 1;
+    (2 * high + second, d & ((1 << (high - 1)) - 1), high - 1)
+}
+
+/// Pixels and greedy backward references to the longest earlier match
+fn backward_refs(argb: &[u32], width: usize) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    // Last position of each pair of pixels, and the position before it with the same pair
+    let mut head = HashMap::new();
+    let mut prev = vec![usize::MAX; argb.len()];
+    let insert = |at: usize, head: &mut HashMap<_, _>, prev: &mut Vec<usize>| {
+        if let Some(pair) = argb.get(at..at + 2) {
+            prev[at] = head.insert((pair[0], pair[1]), at).unwrap_or(usize::MAX);
+        }
+    };
+    let mut i = 0;
+    while i < argb.len() {
+        let max = MAX_MATCH.min(argb.len() - i);
+        let matched = |from: usize| {
+            (0..max)
+                .take_while(|k| argb[from + k] == argb[i + k])
+                .count()
+        };
+        let mut best = (0, 0);
+        let mut candidate = head
+            .get(&(argb[i], argb.get(i + 1).copied().unwrap_or_default()))
+            .copied()
+            .filter(|_| i + 1 < argb.len())
+            .unwrap_or(usize::MAX);
+        // The pixel before and the one above have short distance codes
+        let near = [1, width].map(|dist| i.checked_sub(dist).unwrap_or(usize::MAX));
+        let chain = std::iter::from_fn(|| {
+            let at = candidate;
+            candidate = prev.get(at).copied().unwrap_or(usize::MAX);
+            Some(at)
+        });
+        for from in near.into_iter().chain(chain.take(CHAIN)) {
+            if from == usize::MAX || i - from > WINDOW {
+                continue;
+            }
+            let len = matched(from);
+            if len > best.0 {
+                best = (len, i - from);
+            }
+        }
+        match best {
+            (len, dist) if len >= MIN_MATCH => {
+                let code = match dist {
+                    _ if dist == width => 1,
+                    1 => 2,
+                    _ => dist + 120,
+                };
+                symbols.push(Symbol::Copy(len, code));
+                (i..i + len).for_each(|at| insert(at, &mut head, &mut prev));
+                i += len;
+            }
+            _ => {
+                symbols.push(Symbol::Pixel(argb[i]));
+                insert(i, &mut head, &mut prev);
+                i += 1;
+            }
+        }
+    }
+    symbols
+}
+
+/// Prefix code of an alphabet, with the bits written for each symbol reversed
+struct Code {
+    lengths: Vec<u8>,
+    codes: Vec<u32>,
+}
+
+impl Code {
+    fn put(&self, bits: &mut Bits, symbol: usize) {
+        bits.put(self.codes[symbol], self.lengths[symbol] as u32);
+    }
+
+    /// Code for symbols seen `counts` times, written to `bits`
+    fn write(bits: &mut Bits, counts: &[u32]) -> Code {
+        let used = (0..counts.len())
+            .filter(|s| counts[*s] > 0)
+            .collect::<Vec<_>>();
+        if used.len() <= 2 && used.iter().all(|s| *s < 256) {
+            // Simple code of one or two 8 bit symbols
+            let first = used.first().copied().unwrap_or_default();
+            bits.put(1, 1);
+            bits.put(used.len().max(1) as u32 - 1, 1);
+            let wide = first >= 2;
+            bits.put(wide as u32, 1);
+            bits.put(first as u32, if wide { 8 } else { 1 });
+            if let Some(second) = used.get(1) {
+                bits.put(*second as u32, 8);
+            }
+            let mut lengths = vec![0; counts.len()];
+            if used.len() == 2 {
+                used.iter().for_each(|s| lengths[*s] = 1);
+            }
+            return Code::new(lengths);
+        }
+        let lengths = code_lengths(counts, 15);
+        let tokens = run_lengths(&lengths);
+        let mut counts = [0; 19];
+        tokens
+            .iter()
+            .for_each(|(symbol, _, _)| counts[*symbol] += 1);
+        let code_length_lengths = code_lengths(&counts, 7);
+        let written = CODE_LENGTH_ORDER
+            .iter()
+            .rposition(|s| code_length_lengths[*s] > 0)
+            .map_or(0, |i| i + 1)
+            .max(4);
+        bits.put(0, 1);
+        bits.put(written as u32 - 4, 4);
+        for s in &CODE_LENGTH_ORDER[..written] {
+            bits.put(code_length_lengths[*s] as u32, 3);
+        }
+        let length_code = Code::new(code_length_lengths);
+        // Lengths of the whole alphabet
+        bits.put(0, 1);
+        for (symbol, extra, extra_bits) in tokens {
+            length_code.put(bits, symbol);
+            bits.put(extra, extra_bits);
+        }
+        Code::new(lengths)
+    }
+
+    /// Canonical code of `lengths`, a code with a single symbol takes no bits
+    fn new(mut lengths: Vec<u8>) -> Code {
+        if lengths.iter().filter(|l| **l > 0).count() == 1 {
+            lengths.iter_mut().for_each(|l| *l = 0);
+        }
+        let mut count = [0u32; 16];
+        lengths.iter().for_each(|l| count[*l as usize] += 1);
+        count[0] = 0;
+        let mut next = [0u32; 16];
+        for len in 1..16 {
+            next[len] = (next[len - 1] + count[len - 1]) << 1;
+        }
+        let codes = lengths
+            .iter()
+            .map(|&len| {
+                let code = next[len as usize];
+                next[len as usize] += 1;
+                match len {
+                    0 => 0,
+                    _ => code.reverse_bits() >> (32 - len as u32),
+                }
+            })
+            .collect();
+        Code { lengths, codes }
+    }
+}
+
+/// Huffman code lengths for `counts`, halving the counts until none is longer than `limit`
+fn code_lengths(counts: &[u32], limit: u8) -> Vec<u8> {
+    let mut counts = counts.to_vec();
+    loop {
+        let lengths = huffman(&counts);
+        if lengths.iter().all(|len| *len <= limit) {
+            return lengths;
+        }
+        for count in counts.iter_mut().filter(|count| **count > 0) {
+            *count = (*count / 2).max(1);
+        }
+    }
+}
+
+fn huffman(counts: &[u32]) -> Vec<u8> {
+    let mut lengths = vec![0u8; counts.len()];
+    let mut heap = counts
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(symbol, count)| Reverse((*count as u64, symbol)))
+        .collect::<BinaryHeap<_>>();
+    if heap.len() == 1 {
+        if let Some(Reverse((_, symbol))) = heap.pop() {
+            lengths[symbol] = 1;
+        }
+        return lengths;
+    }
+    // Parent of each node, leaves are the symbols and merged nodes come after them
+    let mut parent = vec![usize::MAX; counts.len()];
+    while let (Some(Reverse((a, x))), Some(Reverse((b, y)))) = (heap.pop(), heap.pop()) {
+        let node = parent.len();
+        parent.push(usize::MAX);
+        parent[x] = node;
+        parent[y] = node;
+        heap.push(Reverse((a + b, node)));
+        if heap.len() == 1 {
+            break;
+        }
+    }
+    for (symbol, len) in lengths.iter_mut().enumerate() {
+        let mut node = symbol;
+        while parent[node] != usize::MAX {
+            node = parent[node];
+            *len += 1;
+        }
+    }
+    lengths
+}
+
+/// Code lengths as symbols of the code length code with their extra bits, runs of zeros and
+/// repeated lengths shortened
+fn run_lengths(lengths: &[u8]) -> Vec<(usize, u32, u32)> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let len = lengths[i];
+        let run = lengths[i..].iter().take_while(|l| **l == len).count();
+        match (len, run) {
+            (0, 11..) => {
+                let run = run.min(138);
+                tokens.push((18, run as u32 - 11, 7));
+                i += run;
+            }
+            (0, 3..) => {
+                tokens.push((17, run as u32 - 3, 3));
+                i += run;
+            }
+            (_, 4..) => {
+                // Code 16 repeats the previous length, which is this one
+                let run = run.min(7);
+                tokens.push((len as usize, 0, 0));
+                tokens.push((16, run as u32 - 4, 2));
+                i += run;
+            }
+            _ => {
+                tokens.push((len as usize, 0, 0));
+                i += 1;
+            }
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_prefix() {
+        assert_eq!(prefix(1), (0, 0, 0));
+        assert_eq!(prefix(4), (3, 0, 0));
+        assert_eq!(prefix(5), (4, 0, 1));
+        assert_eq!(prefix(7), (5, 0, 1));
+        assert_eq!(prefix(4096), (23, 1023, 10));
+    }
+    #[test]
+    fn test_code_lengths() {
+        let lengths = code_lengths(&[1, 1, 2, 4, 0], 15);
+        assert_eq!(lengths, [3, 3, 2, 1, 0]);
+        let counts = (0..40).map(|i| 1 << (i / 2)).collect::<Vec<u32>>();
+        assert!(code_lengths(&counts, 7).iter().all(|len| *len <= 7));
+        let code = Code::new(vec![2, 1, 3, 3]);
+        assert_eq!(code.codes, [0b01, 0b0, 0b011, 0b111]);
+    }
+    #[test]
+    fn test_encode() {
+        let mut image = RgbaImage::new(3, 2);
+        image.blend(1, 1, [255, 0, 0, 255]);
+        let webp = encode(&image).unwrap();
+        assert_eq!(&webp[..4], b"RIFF");
+        assert_eq!(&webp[8..16], b"WEBPVP8L");
+        assert_eq!(webp.len() % 2, 0);
+        assert_eq!(
+            u32::from_le_bytes(webp[4..8].try_into().unwrap()) as usize,
+            webp.len() - 8
+        );
+        // Signature, then 14 bits of width - 1 and of height - 1
+        let header = u32::from_le_bytes(webp[21..25].try_into().unwrap());
+        assert_eq!((header & 0x3fff, header >> 14 & 0x3fff), (2, 1));
+        assert!(encode(&RgbaImage::new(MAX_SIZE + 1, 1)).is_none());
+    }
+    #[test]
+    fn test_round_trip() {
+        // Noise, repeats for backward references and a single color
+        let mut seed = 1u32;
+        let mut noise = || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as u8
+        };
+        let pixels = (0..37 * 23 * 4).map(|_| noise()).collect();
+        let images = [
+            RgbaImage {
+                width: 37,
+                height: 23,
+                pixels,
+            },
+            RgbaImage {
+                width: 64,
+                height: 9,
+                pixels: (0..64 * 9)
+                    .flat_map(|i| [i as u8 % 5 * 50, 0, 9, 255])
+                    .collect(),
+            },
+            RgbaImage {
+                width: 5,
+                height: 300,
+                pixels: [30, 200, 90, 128].repeat(5 * 300),
+            },
+            RgbaImage::new(1, 1),
+            crate::png::decode(crate::get("👍").unwrap()).unwrap(),
+        ];
+        for image in images {
+            let frames = super::super::decode(&encode(&image).unwrap()).unwrap();
+            assert_eq!(frames, [(image, std::time::Duration::ZERO)]);
+        }
+    }
+}
@@ -0,0 +1,421 @@
+//! VP8L lossless decoder
+
+use super::tables::DISTANCES;
+
+/// Order of the code lengths of the code length code
+pub(super) const CODE_LENGTH_ORDER: [usize; 19] = [
+    17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+/// Length prefix codes after the 256 green values, before the color cache indices
+const LENGTH_CODES: usize = 24;
+const DISTANCE_CODES: usize = 40;
+
+/// Bits read from the least significant one
+pub(super) struct Bits<'a> {
+    data: &'a [u8],
+    acc: u64,
+    len: u32,
+}
+
+impl<'a> Bits<'a> {
+    pub(super) fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            acc: 0,
+            len: 0,
+        }
+    }
+    pub(super) fn read(&mut self, bits: u32) -> Option<u32> {
+        while self.len < bits {
+            let (&byte, rest) = self.data.split_first()?;
+            self.acc |= (byte as u64) << self.len;
+            self.data = rest;
+            self.len += 8;
+        }
+        let value = (self.acc & ((1 << bits) - 1)) as u32;
+        self.acc >>= bits;
+        self.len -= bits;
+        Some(value)
+    }
+}
+
+/// Canonical prefix code, decoded a bit at a time
+struct Code {
+    /// Number of codes of each length
+    counts: [u16; 16],
+    /// Symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Code {
+    /// Code of the symbols with these code lengths, `None` unless the lengths fill the code
+    /// space or there is a single symbol
+    fn new(lengths: &[u8]) -> Option<Code> {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = Vec::new();
+        for length in 1..16 {
+            symbols.extend(
+                (0..lengths.len() as u16).filter(|&symbol| lengths[symbol as usize] == length),
+            );
+        }
+        if symbols.len() == 1 {
+            // A single symbol takes no bits
+            return Some(Code {
+                counts: [0; 16],
+                symbols,
+            });
+        }
+        let space = (1..16).try_fold(1i32, |left, length| {
+            let left = left * 2 - counts[length] as i32;
+            (left >= 0).then_some(left)
+        });
+        (space == Some(0)).then_some(Code { counts, symbols })
+    }
+    fn decode(&self, bits: &mut Bits) -> Option<u16> {
+        if self.symbols.len() == 1 {
+            return Some(self.symbols[0]);
+        }
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for &count in &self.counts[1..] {
+            code |= bits.read(1)? as i32;
+            if code - first < count as i32 {
+                return Some(self.symbols[(index + code - first) as usize]);
+            }
+            index += count as i32;
+            first = (first + count as i32) << 1;
+            code <<= 1;
+        }
+        None
+    }
+    /// Code read from the bitstream, for an alphabet of `size` symbols
+    fn read(bits: &mut Bits, size: usize) -> Option<Code> {
+        let mut lengths = vec![0; size];
+        if bits.read(1)? == 1 {
+            // One or two symbols of one bit
+            let symbols = bits.read(1)? + 1;
+            let first = match bits.read(1)? {
+                0 => bits.read(1)?,
+                _ => bits.read(8)?,
+            };
+            *lengths.get_mut(first as usize)? = 1;
+            if symbols == 2 {
+                *lengths.get_mut(bits.read(8)? as usize)? = 1;
+            }
+            return Code::new(&lengths);
+        }
+        let mut length_lengths = [0; 19];
+        for &i in &CODE_LENGTH_ORDER[..4 + bits.read(4)? as usize] {
+            length_lengths[i] = bits.read(3)? as u8;
+        }
+        let length_code = Code::new(&length_lengths)?;
+        let mut max = match bits.read(1)? {
+            0 => size,
+            _ => {
+                let width = 2 + 2 * bits.read(3)?;
+                let max = 2 + bits.read(width)? as usize;
+                if max > size {
+                    return None;
+                }
+                max
+            }
+        };
+        let (mut symbol, mut previous) = (0, 8);
+        while symbol < size && max > 0 {
+            max -= 1;
+            let (length, repeat) = match length_code.decode(bits)? {
+                length @ 0..=15 => (length as u8, 1),
+                16 => (previous, 3 + bits.read(2)? as usize),
+                17 => (0, 3 + bits.read(3)? as usize),
+                _ => (0, 11 + bits.read(7)? as usize),
+            };
+            if symbol + repeat > size {
+                return None;
+            }
+            lengths[symbol..symbol + repeat].fill(length);
+            symbol += repeat;
+            if repeat == 1 && length != 0 {
+                previous = length;
+            }
+        }
+        Code::new(&lengths)
+    }
+}
+
+/// Green with length prefixes and cache indices, red, blue, alpha and distance codes
+type Group = [Code; 5];
+
+/// Transform applied to the pixels, undone in reverse order after decoding
+enum Transform {
+    /// Size bits and modes of the predicted blocks
+    Predictor(u32, Vec<u32>),
+    /// Size bits and multipliers of the transformed blocks
+    Color(u32, Vec<u32>),
+    SubtractGreen,
+    /// Width before packing and palette
+    ColorIndexing(usize, Vec<u32>),
+}
+
+/// Argb pixels of a VP8L image stream of `width` by `height`, the transforms included
+pub(super) fn decode_stream(bits: &mut Bits, width: usize, height: usize) -> Option<Vec<u32>> {
+    let mut transforms = Vec::new();
+    let mut packed = width;
+    while bits.read(1)? == 1 {
+        let kind = bits.read(2)?;
+        if transforms.iter().any(|(k, _)| *k == kind) {
+            return None;
+        }
+        let transform = match kind {
+            0 | 1 => {
+                let size = bits.read(3)? + 2;
+                let blocks = |n: usize| n.div_ceil(1 << size);
+                let data = decode_image(bits, blocks(packed), blocks(height), false)?;
+                match kind {
+                    0 => Transform::Predictor(size, data),
+                    _ => Transform::Color(size, data),
+                }
+            }
+            2 => Transform::SubtractGreen,
+            _ => {
+                let colors = bits.read(8)? as usize + 1;
+                let mut palette = decode_image(bits, colors, 1, false)?;
+                for i in 1..colors {
+                    palette[i] = add_pixels(palette[i], palette[i - 1]);
+                }
+                let unpacked = packed;
+                packed = packed.div_ceil(1 << pack_bits(colors));
+                Transform::ColorIndexing(unpacked, palette)
+            }
+        };
+        transforms.push((kind, (transform, packed)));
+    }
+    let mut pixels = decode_image(bits, packed, height, true)?;
+    for (_, (transform, width)) in transforms.into_iter().rev() {
+        match transform {
+            Transform::Predictor(size, modes) => predict(&mut pixels, width, size, &modes),
+            Transform::Color(size, multipliers) => {
+                uncorrelate(&mut pixels, width, size, &multipliers)
+            }
+            Transform::SubtractGreen => {
+                for pixel in &mut pixels {
+                    let green = (*pixel >> 8) & 0xff;
+                    *pixel = add_pixels(*pixel, green << 16 | green);
+                }
+            }
+            Transform::ColorIndexing(unpacked, palette) => {
+                pixels = unpack(&pixels, width, unpacked, height, &palette)
+            }
+        }
+    }
+    Some(pixels)
+}
+
+/// Bits of the width each packed pixel stands for, with palettes this small
+fn pack_bits(colors: usize) -> u32 {
+    match colors {
+        0..=2 => 3,
+        3..=4 => 2,
+        5..=16 => 1,
+        _ => 0,
+    }
+}
+
+/// Entropy coded pixels of `width` by `height`, with prefix code groups when `main`
+fn decode_image(bits: &mut Bits, width: usize, height: usize, main: bool) -> Option<Vec<u32>> {
+    let cache_bits = match bits.read(1)? {
+        0 => 0,
+        _ => match bits.read(4)? {
+            bits @ 1..=11 => bits,
+            _ => return None,
+        },
+    };
+    let mut cache = vec![0; (cache_bits > 0) as usize * (1 << cache_bits)];
+    let (meta_bits, meta) = match main && bits.read(1)? == 1 {
+        true => {
+            let size = bits.read(3)? + 2;
+            let blocks = |n: usize| n.div_ceil(1 << size);
+            let meta = decode_image(bits, blocks(width), blocks(height), false)?
+                .into_iter()
+                .map(|pixel| (pixel >> 8) & 0xffff)
+                .collect::<Vec<_>>();
+            (size, meta)
+        }
+        false => (0, Vec::new()),
+    };
+    let groups = (0..=meta.iter().copied().max().unwrap_or(0))
+        .map(|_| read_group(bits, cache.len()))
+        .collect::<Option<Vec<_>>>()?;
+    let meta_width = width.div_ceil(1 << meta_bits);
+    let total = width * height;
+    let mut pixels = Vec::with_capacity(total);
+    // Pixels inserted into the cache so far
+    let mut cached = 0;
+    while pixels.len() < total {
+        let i = pixels.len();
+        let group = match meta.is_empty() {
+            true => &groups[0],
+            false => {
+                let (x, y) = ((i % width) >> meta_bits, (i / width) >> meta_bits);
+                &groups[meta[y * meta_width + x] as usize]
+            }
+        };
+        match group[0].decode(bits)? as usize {
+            green @ 0..=255 => {
+                let red = group[1].decode(bits)? as u32;
+                let blue = group[2].decode(bits)? as u32;
+                let alpha = group[3].decode(bits)? as u32;
+                pixels.push(alpha << 24 | red << 16 | (green as u32) << 8 | blue);
+            }
+            code @ 256..=279 => {
+                let length = prefix_value(bits, code as u32 - 256)? as usize;
+                let distance = group[4].decode(bits)? as u32;
+                let distance = prefix_value(bits, distance)? as usize;
+                let distance = match distance.checked_sub(120) {
+                    Some(0) | None => {
+                        let (x, y) = DISTANCES[distance - 1];
+                        (y as isize * width as isize + x as isize).max(1) as usize
+                    }
+                    Some(distance) => distance,
+                };
+                if distance > i || i + length > total {
+                    return None;
+                }
+                for j in i..i + length {
+                    pixels.push(pixels[j - distance]);
+                }
+            }
+            index => pixels.push(*cache.get(index - 256 - LENGTH_CODES)?),
+        }
+        if !cache.is_empty() {
+            for &pixel in &pixels[cached..] {
+                let hash = 0x1e35a7bd_u32.wrapping_mul(pixel) >> (32 - cache_bits);
+                cache[hash as usize] = pixel;
+            }
+            cached = pixels.len();
+        }
+    }
+    Some(pixels)
+}
+
+fn read_group(bits: &mut Bits, cache: usize) -> Option<Group> {
+    Some([
+        Code::read(bits, 256 + LENGTH_CODES + cache)?,
+        Code::read(bits, 256)?,
+        Code::read(bits, 256)?,
+        Code::read(bits, 256)?,
+        Code::read(bits, DISTANCE_CODES)?,
+    ])
+}
+
+/// Length or distance of a prefix code and its extra bits
+fn prefix_value(bits: &mut Bits, code: u32) -> Option<u32> {
+    if code < 4 {
+        return Some(code + 1);
+    }
+    let extra = (code - 2) >> 1;
+    let offset = (2 + (code & 1)) << extra;
+    Some(offset + bits.read(extra)? + 1)
+}
+
+/// Channels of two argb pixels added one by one
+fn add_pixels(a: u32, b: u32) -> u32 {
+    let alpha_green = (a & 0xff00ff00).wrapping_add(b & 0xff00ff00) & 0xff00ff00;
+    let red_blue = (a & 0x00ff00ff).wrapping_add(b & 0x00ff00ff) & 0x00ff00ff;
+    alpha_green | red_blue
+}
+
+/// Channels of a pixel, alpha first
+fn channels(pixel: u32) -> [i32; 4] {
+    pixel.to_be_bytes().map(|c| c as i32)
+}
+
+fn from_channels(channels: [i32; 4]) -> u32 {
+    u32::from_be_bytes(channels.map(|c| c.clamp(0, 255) as u8))
+}
+
+fn average(a: u32, b: u32) -> u32 {
+    (((a ^ b) & 0xfefefefe) >> 1) + (a & b)
+}
+
+/// Undo the predictor transform in place
+fn predict(pixels: &mut [u32], width: usize, size: u32, modes: &[u32]) {
+    let blocks = width.div_ceil(1 << size);
+    for i in 0..pixels.len() {
+        let (x, y) = (i % width, i / width);
+        let prediction = match (x, y) {
+            (0, 0) => 0xff000000,
+            (_, 0) => pixels[i - 1],
+            (0, _) => pixels[i - width],
+            _ => {
+                let (left, top) = (pixels[i - 1], pixels[i - width]);
+                let (top_left, top_right) = (pixels[i - width - 1], pixels[i - width + 1]);
+                match (modes[(y >> size) * blocks + (x >> size)] >> 8) & 0xf {
+                    1 => left,
+                    2 => top,
+                    3 => top_right,
+                    4 => top_left,
+                    5 => average(average(left, top_right), top),
+                    6 => average(left, top_left),
+                    7 => average(left, top),
+                    8 => average(top_left, top),
+                    9 => average(top, top_right),
+                    10 => average(average(left, top_left), average(top, top_right)),
+                    11 => select(left, top, top_left),
+                    12 => {
+                        let (l, t, tl) = (channels(left), channels(top), channels(top_left));
+                        from_channels(std::array::from_fn(|c| l[c] + t[c] - tl[c]))
+                    }
+                    13 => {
+                        let (a, tl) = (channels(average(left, top)), channels(top_left));
+                        from_channels(std::array::from_fn(|c| a[c] + (a[c] - tl[c]) / 2))
+                    }
+                    _ => 0xff000000,
+                }
+            }
+        };
+        pixels[i] = add_pixels(pixels[i], prediction);
+    }
+}
+
+/// Left or top, whichever is closer to the gradient of the three pixels
+fn select(left: u32, top: u32, top_left: u32) -> u32 {
+    let (l, t, tl) = (channels(left), channels(top), channels(top_left));
+    let to_left: i32 = (0..4).map(|c| (t[c] - tl[c]).abs()).sum();
+    let to_top: i32 = (0..4).map(|c| (l[c] - tl[c]).abs()).sum();
+    match to_left < to_top {
+        true => left,
+        false => top,
+    }
+}
+
+/// Undo the color transform in place
+fn uncorrelate(pixels: &mut [u32], width: usize, size: u32, multipliers: &[u32]) {
+    let blocks = width.div_ceil(1 << size);
+    let delta = |multiplier: u32, color: u32| (multiplier as i8 as i32 * color as i8 as i32) >> 5;
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let (x, y) = (i % width, i / width);
+        let multiplier = multipliers[(y >> size) * blocks + (x >> size)];
+        let [alpha, red, green, blue] = pixel.to_be_bytes().map(|c| c as u32);
+        let red = (red as i32 + delta(multiplier, green)) as u32 & 0xff;
+        let blue = blue as i32 + delta(multiplier >> 8, green) + delta(multiplier >> 16, red);
+        *pixel = alpha << 24 | red << 16 | green << 8 | (blue as u32 & 0xff);
+    }
+}
+
+/// Pixels of the palette at the indices packed `pixels` hold
+fn unpack(pixels: &[u32], packed: usize, width: usize, height: usize, palette: &[u32]) -> Vec<u32> {
+    let bits = pack_bits(palette.len());
+    let per = 1 << bits;
+    let size = 8 >> bits;
+    (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let green = pixels[y * packed + x / per] >> 8;
+            let index = (green >> ((x % per) * size)) & ((1 << size) - 1);
+            palette.get(index as usize).copied().unwrap_or(0)
+        })
+        .collect()
+}
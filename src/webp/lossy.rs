@@ -0,0 +1,961 @@
+//! VP8 lossy decoder for the key frames webp holds, rounding as libwebp does
+
+use super::tables::{
+    AC_TABLE, BANDS, BMODE_PROBS, CATEGORIES, COEFF_PROBS, COEFF_UPDATE_PROBS, DC_TABLE, ZIGZAG,
+};
+
+/// Intra prediction modes, the first four also used for whole macroblocks
+const DC: u8 = 0;
+const TM: u8 = 1;
+const VE: u8 = 2;
+const HE: u8 = 3;
+const RD: u8 = 4;
+const VR: u8 = 5;
+const LD: u8 = 6;
+const VL: u8 = 7;
+const HD: u8 = 8;
+const HU: u8 = 9;
+
+/// Boolean entropy decoder of RFC 6386, reading zeros past the end
+struct BoolDecoder<'a> {
+    data: &'a [u8],
+    value: u32,
+    range: u32,
+    bit_count: u32,
+}
+
+impl<'a> BoolDecoder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        let mut decoder = Self {
+            data,
+            value: 0,
+            range: 255,
+            bit_count: 0,
+        };
+        decoder.value = decoder.byte() << 8 | decoder.byte();
+        decoder
+    }
+    fn byte(&mut self) -> u32 {
+        match self.data.split_first() {
+            Some((&byte, rest)) => {
+                self.data = rest;
+                byte as u32
+            }
+            None => 0,
+        }
+    }
+    fn bool(&mut self, prob: u8) -> bool {
+        let split = 1 + (((self.range - 1) * prob as u32) >> 8);
+        let bit = self.value >= split << 8;
+        match bit {
+            true => {
+                self.range -= split;
+                self.value -= split << 8;
+            }
+            false => self.range = split,
+        }
+        while self.range < 128 {
+            self.value <<= 1;
+            self.range <<= 1;
+            self.bit_count += 1;
+            if self.bit_count == 8 {
+                self.bit_count = 0;
+                self.value |= self.byte();
+            }
+        }
+        bit
+    }
+    fn flag(&mut self) -> bool {
+        self.bool(128)
+    }
+    fn literal(&mut self, bits: u32) -> i32 {
+        (0..bits).fold(0, |value, _| value << 1 | self.flag() as i32)
+    }
+    /// Signed value of `bits` after a flag saying whether it is there
+    fn optional_signed(&mut self, bits: u32) -> i32 {
+        match self.flag() {
+            true => {
+                let value = self.literal(bits);
+                match self.flag() {
+                    true => -value,
+                    false => value,
+                }
+            }
+            false => 0,
+        }
+    }
+}
+
+/// Dequantization factors of the DC and AC coefficients
+#[derive(Clone, Copy, Default)]
+struct Quant {
+    y: [i32; 2],
+    y2: [i32; 2],
+    uv: [i32; 2],
+}
+
+/// Loop filter of a macroblock
+#[derive(Clone, Copy, Default)]
+struct Filter {
+    /// Edge limit, no filtering if 0
+    limit: i32,
+    interior: i32,
+    hev_threshold: i32,
+    /// Whether the edges between its subblocks are filtered too
+    inner: bool,
+}
+
+/// Whether the blocks above or left of the next ones have coefficients: four luma, two of
+/// each chroma plane and the luma DC block
+type Contexts = [bool; 9];
+
+type Probs = [[[[u8; 11]; 3]; 8]; 4];
+
+/// Pixel planes of the whole macroblocks of a frame
+struct Planes {
+    y: Vec<u8>,
+    u: Vec<u8>,
+    v: Vec<u8>,
+    /// Width of the luma plane, the chroma planes are half as wide
+    stride: usize,
+}
+
+/// Width, height and rgba pixels of a VP8 key frame
+pub(super) fn decode(data: &[u8]) -> Option<(u32, u32, Vec<u8>)> {
+    let tag = u32::from_le_bytes([*data.first()?, *data.get(1)?, *data.get(2)?, 0]);
+    // Only key frames are images on their own
+    if tag & 1 != 0 || data.get(3..6)? != [0x9d, 0x01, 0x2a] {
+        return None;
+    }
+    let width = u16::from_le_bytes([data[6], *data.get(7)?]) as usize & 0x3fff;
+    let height = u16::from_le_bytes([*data.get(8)?, *data.get(9)?]) as usize & 0x3fff;
+    if width == 0 || height == 0 || width.max(height) > super::MAX_DIMENSION as usize {
+        return None;
+    }
+    let first = 10 + (tag >> 5) as usize;
+    let mut header = BoolDecoder::new(data.get(10..first)?);
+    // Color space and clamping
+    header.literal(2);
+    let segmentation = header.flag();
+    let (mut update_map, mut absolute) = (false, false);
+    let (mut quantizers, mut strengths, mut segment_probs) = ([0; 4], [0; 4], [255; 3]);
+    if segmentation {
+        update_map = header.flag();
+        if header.flag() {
+            absolute = header.flag();
+            quantizers = std::array::from_fn(|_| header.optional_signed(7));
+            strengths = std::array::from_fn(|_| header.optional_signed(6));
+        }
+        if update_map {
+            segment_probs = std::array::from_fn(|_| match header.flag() {
+                true => header.literal(8) as u8,
+                false => 255,
+            });
+        }
+    }
+    let simple = header.flag();
+    let level = header.literal(6);
+    let sharpness = header.literal(3);
+    let (mut ref_delta, mut mode_delta) = (0, 0);
+    let deltas = header.flag();
+    if deltas && header.flag() {
+        let refs: [i32; 4] = std::array::from_fn(|_| header.optional_signed(6));
+        let modes: [i32; 4] = std::array::from_fn(|_| header.optional_signed(6));
+        (ref_delta, mode_delta) = (refs[0], modes[0]);
+    }
+    let partitions = 1 << header.literal(2);
+    let base = header.literal(7);
+    let [y_dc, y2_dc, y2_ac, uv_dc, uv_ac]: [i32; 5] =
+        std::array::from_fn(|_| header.optional_signed(4));
+    let quants: [Quant; 4] = std::array::from_fn(|segment| {
+        let q = match segmentation {
+            true if absolute => quantizers[segment],
+            true => quantizers[segment] + base,
+            false => base,
+        };
+        let index = |delta: i32, max: i32| (q + delta).clamp(0, max) as usize;
+        Quant {
+            y: [
+                DC_TABLE[index(y_dc, 127)] as i32,
+                AC_TABLE[index(0, 127)] as i32,
+            ],
+            y2: [
+                DC_TABLE[index(y2_dc, 127)] as i32 * 2,
+                ((AC_TABLE[index(y2_ac, 127)] as i32 * 101581) >> 16).max(8),
+            ],
+            uv: [
+                DC_TABLE[index(uv_dc, 117)] as i32,
+                AC_TABLE[index(uv_ac, 127)] as i32,
+            ],
+        }
+    });
+    // Filter of each segment, and of its macroblocks with subblock modes
+    let filters: [[Filter; 2]; 4] = std::array::from_fn(|segment| {
+        let base = match segmentation {
+            true if absolute => strengths[segment],
+            true => strengths[segment] + level,
+            false => level,
+        };
+        std::array::from_fn(|subblocks| {
+            let mut level = base;
+            if deltas {
+                level += ref_delta + (subblocks == 1) as i32 * mode_delta;
+            }
+            let level = level.clamp(0, 63);
+            if level == 0 {
+                return Filter::default();
+            }
+            let mut interior = level;
+            if sharpness > 0 {
+                interior >>= if sharpness > 4 { 2 } else { 1 };
+                interior = interior.min(9 - sharpness);
+            }
+            let interior = interior.max(1);
+            Filter {
+                limit: 2 * level + interior,
+                interior,
+                hev_threshold: (level >= 15) as i32 + (level >= 40) as i32,
+                inner: subblocks == 1,
+            }
+        })
+    });
+    // Whether each entropy context is refreshed
+    header.flag();
+    let mut probs: Probs = COEFF_PROBS;
+    for (t, bands) in probs.iter_mut().enumerate() {
+        for (b, contexts) in bands.iter_mut().enumerate() {
+            for (c, branches) in contexts.iter_mut().enumerate() {
+                for (p, prob) in branches.iter_mut().enumerate() {
+                    if header.bool(COEFF_UPDATE_PROBS[t][b][c][p]) {
+                        *prob = header.literal(8) as u8;
+                    }
+                }
+            }
+        }
+    }
+    let skip_prob = header.flag().then(|| header.literal(8) as u8);
+
+    let sizes = first + 3 * (partitions - 1);
+    let mut rest = data.get(sizes..)?;
+    let mut tokens = Vec::with_capacity(partitions);
+    for i in 0..partitions - 1 {
+        let at = first + 3 * i;
+        let size = u32::from_le_bytes([data[at], data[at + 1], data[at + 2], 0]) as usize;
+        let (partition, after) = rest.split_at(size.min(rest.len()));
+        tokens.push(BoolDecoder::new(partition));
+        rest = after;
+    }
+    tokens.push(BoolDecoder::new(rest));
+
+    let (mb_width, mb_height) = (width.div_ceil(16), height.div_ceil(16));
+    let stride = mb_width * 16;
+    let mut planes = Planes {
+        y: vec![0; stride * mb_height * 16],
+        u: vec![0; stride / 2 * mb_height * 8],
+        v: vec![0; stride / 2 * mb_height * 8],
+        stride,
+    };
+    let mut mb_filters = Vec::with_capacity(mb_width * mb_height);
+    let mut top_modes = vec![[DC; 4]; mb_width];
+    let mut top_contexts = vec![Contexts::default(); mb_width];
+    for mb_y in 0..mb_height {
+        let mut left_modes = [DC; 4];
+        let mut left_contexts = Contexts::default();
+        let tokens = &mut tokens[mb_y % partitions];
+        for mb_x in 0..mb_width {
+            let segment = match update_map {
+                true => match header.bool(segment_probs[0]) {
+                    false => header.bool(segment_probs[1]) as usize,
+                    true => 2 + header.bool(segment_probs[2]) as usize,
+                },
+                false => 0,
+            };
+            let skip = skip_prob.is_some_and(|prob| header.bool(prob));
+            let modes = read_modes(&mut header, &mut top_modes[mb_x], &mut left_modes);
+            let mut coeffs = [0i32; 24 * 16];
+            let subblocks = modes.0.is_none();
+            let coded = match skip {
+                false => read_residuals(
+                    tokens,
+                    &probs,
+                    &quants[segment],
+                    subblocks,
+                    &mut coeffs,
+                    (&mut top_contexts[mb_x], &mut left_contexts),
+                ),
+                true => {
+                    let dc = (top_contexts[mb_x][8], left_contexts[8]);
+                    top_contexts[mb_x] = Contexts::default();
+                    left_contexts = Contexts::default();
+                    if subblocks {
+                        (top_contexts[mb_x][8], left_contexts[8]) = dc;
+                    }
+                    false
+                }
+            };
+            planes.reconstruct(mb_x, mb_y, mb_width, &modes, &coeffs);
+            let mut filter = filters[segment][subblocks as usize];
+            filter.inner |= coded;
+            mb_filters.push(filter);
+        }
+    }
+    if level > 0 {
+        for (i, filter) in mb_filters.iter().enumerate() {
+            planes.filter(i % mb_width, i / mb_width, filter, simple);
+        }
+    }
+    Some((width as u32, height as u32, planes.to_rgba(width, height)))
+}
+
+/// Luma mode of the whole macroblock, or its 16 subblock modes, and the chroma mode
+type Modes = (Option<u8>, [u8; 16], u8);
+
+fn read_modes(header: &mut BoolDecoder, top: &mut [u8; 4], left: &mut [u8; 4]) -> Modes {
+    let mut modes = [DC; 16];
+    let luma = match header.bool(145) {
+        true => {
+            let mode = match header.bool(156) {
+                true => [HE, TM][header.bool(128) as usize],
+                false => [DC, VE][header.bool(163) as usize],
+            };
+            (*top, *left) = ([mode; 4], [mode; 4]);
+            Some(mode)
+        }
+        false => {
+            for y in 0..4 {
+                for x in 0..4 {
+                    let probs = &BMODE_PROBS[top[x] as usize][left[y] as usize];
+                    let mode = match () {
+                        _ if !header.bool(probs[0]) => DC,
+                        _ if !header.bool(probs[1]) => TM,
+                        _ if !header.bool(probs[2]) => VE,
+                        _ if !header.bool(probs[3]) => match () {
+                            _ if !header.bool(probs[4]) => HE,
+                            _ => [RD, VR][header.bool(probs[5]) as usize],
+                        },
+                        _ if !header.bool(probs[6]) => LD,
+                        _ if !header.bool(probs[7]) => VL,
+                        _ => [HD, HU][header.bool(probs[8]) as usize],
+                    };
+                    (top[x], left[y], modes[y * 4 + x]) = (mode, mode, mode);
+                }
+            }
+            None
+        }
+    };
+    let chroma = match () {
+        _ if !header.bool(142) => DC,
+        _ if !header.bool(114) => VE,
+        _ => [HE, TM][header.bool(183) as usize],
+    };
+    (luma, modes, chroma)
+}
+
+/// Dequantized coefficients of the 16 luma and 8 chroma blocks, whether any block has
+/// some
+fn read_residuals(
+    tokens: &mut BoolDecoder,
+    probs: &Probs,
+    quant: &Quant,
+    subblocks: bool,
+    coeffs: &mut [i32; 24 * 16],
+    (top, left): (&mut Contexts, &mut Contexts),
+) -> bool {
+    let mut coded = false;
+    let (first, kind) = match subblocks {
+        true => (0, 3),
+        false => {
+            let mut dc = [0; 16];
+            let context = top[8] as usize + left[8] as usize;
+            let n = read_coefficients(tokens, &probs[1], context, quant.y2, 0, &mut dc);
+            (top[8], left[8]) = (n > 0, n > 0);
+            for (i, dc) in walsh_hadamard(&dc).into_iter().enumerate() {
+                coeffs[i * 16] = dc;
+            }
+            (1, 0)
+        }
+    };
+    for y in 0..4 {
+        for x in 0..4 {
+            let block = &mut coeffs[(y * 4 + x) * 16..][..16];
+            let context = top[x] as usize + left[y] as usize;
+            let n = read_coefficients(tokens, &probs[kind], context, quant.y, first, block);
+            (top[x], left[y]) = (n > first, n > first);
+            coded |= n > 1 || block[0] != 0;
+        }
+    }
+    for plane in 0..2 {
+        for y in 0..2 {
+            for x in 0..2 {
+                let block = &mut coeffs[(16 + plane * 4 + y * 2 + x) * 16..][..16];
+                let (t, l) = (4 + plane * 2 + x, 4 + plane * 2 + y);
+                let context = top[t] as usize + left[l] as usize;
+                let n = read_coefficients(tokens, &probs[2], context, quant.uv, 0, block);
+                (top[t], left[l]) = (n > 0, n > 0);
+                coded |= n > 1 || block[0] != 0;
+            }
+        }
+    }
+    coded
+}
+
+/// Tokens of a block from coefficient `first`, the position after the last one coded
+fn read_coefficients(
+    tokens: &mut BoolDecoder,
+    probs: &[[[u8; 11]; 3]; 8],
+    context: usize,
+    quant: [i32; 2],
+    first: usize,
+    out: &mut [i32],
+) -> usize {
+    let mut n = first;
+    let mut prob = &probs[BANDS[n]][context];
+    while n < 16 {
+        if !tokens.bool(prob[0]) {
+            return n;
+        }
+        while !tokens.bool(prob[1]) {
+            n += 1;
+            if n == 16 {
+                return 16;
+            }
+            prob = &probs[BANDS[n]][0];
+        }
+        let (value, context) = match tokens.bool(prob[2]) {
+            false => (1, 1),
+            true => (large_value(tokens, prob), 2),
+        };
+        let value = match tokens.flag() {
+            true => -value,
+            false => value,
+        };
+        out[ZIGZAG[n]] = value * quant[(n > 0) as usize];
+        n += 1;
+        prob = &probs[BANDS[n]][context];
+    }
+    16
+}
+
+/// Value of a token above one
+fn large_value(tokens: &mut BoolDecoder, prob: &[u8; 11]) -> i32 {
+    if !tokens.bool(prob[3]) {
+        return match tokens.bool(prob[4]) {
+            false => 2,
+            true => 3 + tokens.bool(prob[5]) as i32,
+        };
+    }
+    if !tokens.bool(prob[6]) {
+        return match tokens.bool(prob[7]) {
+            false => 5 + tokens.bool(159) as i32,
+            true => 7 + 2 * tokens.bool(165) as i32 + tokens.bool(145) as i32,
+        };
+    }
+    let high = tokens.bool(prob[8]) as usize;
+    let category = 2 * high + tokens.bool(prob[9 + high]) as usize;
+    let extra = CATEGORIES[category]
+        .iter()
+        .fold(0, |value, &prob| value << 1 | tokens.bool(prob) as i32);
+    extra + 3 + (8 << category)
+}
+
+/// Inverse Walsh-Hadamard transform of the luma DC block into the DC of each luma block
+fn walsh_hadamard(input: &[i32; 16]) -> [i32; 16] {
+    let mut tmp = [0; 16];
+    for i in 0..4 {
+        let a0 = input[i] + input[12 + i];
+        let a1 = input[4 + i] + input[8 + i];
+        let a2 = input[4 + i] - input[8 + i];
+        let a3 = input[i] - input[12 + i];
+        tmp[i] = a0 + a1;
+        tmp[8 + i] = a0 - a1;
+        tmp[4 + i] = a3 + a2;
+        tmp[12 + i] = a3 - a2;
+    }
+    let mut out = [0; 16];
+    for i in 0..4 {
+        let dc = tmp[i * 4] + 3;
+        let a0 = dc + tmp[3 + i * 4];
+        let a1 = tmp[1 + i * 4] + tmp[2 + i * 4];
+        let a2 = tmp[1 + i * 4] - tmp[2 + i * 4];
+        let a3 = dc - tmp[3 + i * 4];
+        out[i * 4] = (a0 + a1) >> 3;
+        out[i * 4 + 1] = (a3 + a2) >> 3;
+        out[i * 4 + 2] = (a0 - a1) >> 3;
+        out[i * 4 + 3] = (a3 - a2) >> 3;
+    }
+    out
+}
+
+/// Inverse DCT of a block added to the 4 by 4 pixels at `at`
+fn add_residual(plane: &mut [u8], stride: usize, at: usize, coeffs: &[i32]) {
+    if coeffs.iter().all(|&c| c == 0) {
+        return;
+    }
+    let mul1 = |a: i32| ((a * 20091) >> 16) + a;
+    let mul2 = |a: i32| (a * 35468) >> 16;
+    let mut tmp = [0; 16];
+    for i in 0..4 {
+        let a = coeffs[i] + coeffs[8 + i];
+        let b = coeffs[i] - coeffs[8 + i];
+        let c = mul2(coeffs[4 + i]) - mul1(coeffs[12 + i]);
+        let d = mul1(coeffs[4 + i]) + mul2(coeffs[12 + i]);
+        tmp[i * 4..i * 4 + 4].copy_from_slice(&[a + d, b + c, b - c, a - d]);
+    }
+    for y in 0..4 {
+        let dc = tmp[y] + 4;
+        let a = dc + tmp[8 + y];
+        let b = dc - tmp[8 + y];
+        let c = mul2(tmp[4 + y]) - mul1(tmp[12 + y]);
+        let d = mul1(tmp[4 + y]) + mul2(tmp[12 + y]);
+        for (x, value) in [a + d, b + c, b - c, a - d].into_iter().enumerate() {
+            let pixel = &mut plane[at + y * stride + x];
+            *pixel = (*pixel as i32 + (value >> 3)).clamp(0, 255) as u8;
+        }
+    }
+}
+
+fn avg2(a: u8, b: u8) -> u8 {
+    ((a as u32 + b as u32 + 1) >> 1) as u8
+}
+
+fn avg3(a: u8, b: u8, c: u8) -> u8 {
+    ((a as u32 + 2 * b as u32 + c as u32 + 2) >> 2) as u8
+}
+
+/// Pixels around a block a prediction is made from, 127 above the frame and 129 left of it
+struct Edges<const N: usize> {
+    /// Pixels above, and for subblocks the four above right after them
+    top: [u8; 8],
+    left: [u8; N],
+    top_left: u8,
+}
+
+impl Planes {
+    fn reconstruct(
+        &mut self,
+        mb_x: usize,
+        mb_y: usize,
+        mb_width: usize,
+        modes: &Modes,
+        coeffs: &[i32],
+    ) {
+        let stride = self.stride;
+        let (x0, y0) = (mb_x * 16, mb_y * 16);
+        match modes.0 {
+            Some(mode) => {
+                let edges = edges::<16>(&self.y, stride, x0, y0);
+                predict_block(
+                    &mut self.y,
+                    stride,
+                    x0,
+                    y0,
+                    mode,
+                    &edges,
+                    (mb_x == 0, mb_y == 0),
+                );
+                for i in 0..16 {
+                    let at = (y0 + i / 4 * 4) * stride + x0 + i % 4 * 4;
+                    add_residual(&mut self.y, stride, at, &coeffs[i * 16..][..16]);
+                }
+            }
+            None => {
+                // Above right of the subblocks on the right, from the macroblock above right
+                let top_right: [u8; 4] = match mb_y {
+                    0 => [127; 4],
+                    _ => {
+                        let row = (y0 - 1) * stride;
+                        match mb_x + 1 < mb_width {
+                            true => self.y[row + x0 + 16..][..4].try_into().unwrap_or([127; 4]),
+                            false => [self.y[row + x0 + 15]; 4],
+                        }
+                    }
+                };
+                for i in 0..16 {
+                    let (x, y) = (x0 + i % 4 * 4, y0 + i / 4 * 4);
+                    let mut edges = edges::<4>(&self.y, stride, x, y);
+                    match (i % 4 == 3, i / 4 == 0) {
+                        (true, _) => edges.top[4..].copy_from_slice(&top_right),
+                        (false, true) if mb_y == 0 => edges.top[4..].fill(127),
+                        (false, _) => {
+                            edges.top[4..].copy_from_slice(&self.y[(y - 1) * stride + x + 4..][..4])
+                        }
+                    }
+                    predict_subblock(&mut self.y[y * stride + x..], stride, modes.1[i], &edges);
+                    add_residual(&mut self.y, stride, y * stride + x, &coeffs[i * 16..][..16]);
+                }
+            }
+        }
+        let (x0, y0, stride) = (mb_x * 8, mb_y * 8, stride / 2);
+        for (plane, blocks) in [(&mut self.u, 16), (&mut self.v, 20)] {
+            let edges = edges::<8>(plane, stride, x0, y0);
+            predict_block(
+                plane,
+                stride,
+                x0,
+                y0,
+                modes.2,
+                &edges,
+                (mb_x == 0, mb_y == 0),
+            );
+            for i in 0..4 {
+                let at = (y0 + i / 2 * 4) * stride + x0 + i % 2 * 4;
+                add_residual(plane, stride, at, &coeffs[(blocks + i) * 16..][..16]);
+            }
+        }
+    }
+    /// Loop filter over the edges of a macroblock and of its subblocks
+    fn filter(&mut self, mb_x: usize, mb_y: usize, filter: &Filter, simple: bool) {
+        if filter.limit == 0 {
+            return;
+        }
+        let stride = self.stride;
+        let at = mb_y * 16 * stride + mb_x * 16;
+        let (edge, inner) = (filter.limit + 4, filter.limit);
+        if simple {
+            if mb_x > 0 {
+                simple_edge(&mut self.y, at, 1, stride, edge);
+            }
+            if filter.inner {
+                for x in [4, 8, 12] {
+                    simple_edge(&mut self.y, at + x, 1, stride, inner);
+                }
+            }
+            if mb_y > 0 {
+                simple_edge(&mut self.y, at, stride, 1, edge);
+            }
+            if filter.inner {
+                for y in [4, 8, 12] {
+                    simple_edge(&mut self.y, at + y * stride, stride, 1, inner);
+                }
+            }
+            return;
+        }
+        let (chroma, half) = (mb_y * 8 * stride / 2 + mb_x * 8, stride / 2);
+        let mut planes = [
+            (&mut self.y, at, stride, 16),
+            (&mut self.u, chroma, half, 8),
+            (&mut self.v, chroma, half, 8),
+        ];
+        if mb_x > 0 {
+            for (plane, at, stride, size) in planes.iter_mut() {
+                edge_filter(plane, *at, (1, *stride, *size), filter, true);
+            }
+        }
+        if filter.inner {
+            for (plane, at, stride, size) in planes.iter_mut() {
+                for x in (4..*size).step_by(4) {
+                    edge_filter(plane, *at + x, (1, *stride, *size), filter, false);
+                }
+            }
+        }
+        if mb_y > 0 {
+            for (plane, at, stride, size) in planes.iter_mut() {
+                edge_filter(plane, *at, (*stride, 1, *size), filter, true);
+            }
+        }
+        if filter.inner {
+            for (plane, at, stride, size) in planes.iter_mut() {
+                for y in (4..*size).step_by(4) {
+                    edge_filter(plane, *at + y * *stride, (*stride, 1, *size), filter, false);
+                }
+            }
+        }
+    }
+    /// Rgba pixels of the frame, with chroma upsampled between its samples
+    fn to_rgba(&self, width: usize, height: usize) -> Vec<u8> {
+        let mut rgba = vec![255; width * height * 4];
+        let half = self.stride / 2;
+        let row = |plane: &[u8], y: usize| plane[y * half..][..half].to_vec();
+        let mut upsample = |y: usize, u: (usize, usize)| {
+            let luma = &self.y[y * self.stride..];
+            let out = &mut rgba[y * width * 4..][..width * 4];
+            let (u_near, v_near) = (row(&self.u, u.0), row(&self.v, u.0));
+            let (u_far, v_far) = (row(&self.u, u.1), row(&self.v, u.1));
+            upsample_row(luma, (&u_near, &v_near), (&u_far, &v_far), out, width);
+        };
+        upsample(0, (0, 0));
+        for y in 1..height {
+            // Odd rows are nearer the chroma row above, even rows the one below
+            let (above, below) = ((y - 1) / 2, ((y - 1) / 2 + 1).min((height - 1) / 2));
+            match y % 2 {
+                1 => upsample(y, (above, below)),
+                _ => upsample(y, (below, above)),
+            }
+        }
+        rgba
+    }
+}
+
+/// Pixels above, left and above left of the `N` wide block at `x`, `y`
+fn edges<const N: usize>(plane: &[u8], stride: usize, x: usize, y: usize) -> Edges<N> {
+    let mut top = [127; 8];
+    if y > 0 {
+        let n = N.min(8);
+        top[..n].copy_from_slice(&plane[(y - 1) * stride + x..][..n]);
+    }
+    let left = std::array::from_fn(|i| match x {
+        0 => 129,
+        _ => plane[(y + i) * stride + x - 1],
+    });
+    let top_left = match (x, y) {
+        (_, 0) => 127,
+        (0, _) => 129,
+        _ => plane[(y - 1) * stride + x - 1],
+    };
+    Edges {
+        top,
+        left,
+        top_left,
+    }
+}
+
+/// Predict a whole 16 or 8 wide block, the DC mode only averaging the edges inside the frame
+fn predict_block<const N: usize>(
+    plane: &mut [u8],
+    stride: usize,
+    x: usize,
+    y: usize,
+    mode: u8,
+    edges: &Edges<N>,
+    (no_left, no_top): (bool, bool),
+) {
+    let top = match y {
+        0 => [127; N],
+        _ => std::array::from_fn(|i| plane[(y - 1) * stride + x + i]),
+    };
+    let shift = N.trailing_zeros();
+    let sum = |pixels: &[u8; N]| pixels.iter().map(|&p| p as u32).sum::<u32>();
+    let dc = match (no_left, no_top) {
+        (true, true) => 128,
+        (true, false) => (sum(&top) + (N as u32 >> 1)) >> shift,
+        (false, true) => (sum(&edges.left) + (N as u32 >> 1)) >> shift,
+        (false, false) => (sum(&top) + sum(&edges.left) + N as u32) >> (shift + 1),
+    } as u8;
+    for dy in 0..N {
+        let row = &mut plane[(y + dy) * stride + x..][..N];
+        for (dx, pixel) in row.iter_mut().enumerate() {
+            *pixel = match mode {
+                DC => dc,
+                VE => top[dx],
+                HE => edges.left[dy],
+                _ => {
+                    let value = edges.left[dy] as i32 + top[dx] as i32 - edges.top_left as i32;
+                    value.clamp(0, 255) as u8
+                }
+            };
+        }
+    }
+}
+
+/// Predict a 4 by 4 subblock into `out`, with the ten subblock modes
+fn predict_subblock(out: &mut [u8], stride: usize, mode: u8, edges: &Edges<4>) {
+    let [a, b, c, d, e, f, g, h] = edges.top;
+    let [i, j, k, l] = edges.left;
+    let x = edges.top_left;
+    let mut block = [[0u8; 4]; 4];
+    match mode {
+        DC => {
+            let sum = edges.top[..4]
+                .iter()
+                .chain(&edges.left)
+                .map(|&p| p as u32)
+                .sum::<u32>();
+            block = [[((sum + 4) >> 3) as u8; 4]; 4];
+        }
+        TM => {
+            for (dy, row) in block.iter_mut().enumerate() {
+                for (dx, pixel) in row.iter_mut().enumerate() {
+                    let value = edges.left[dy] as i32 + edges.top[dx] as i32 - x as i32;
+                    *pixel = value.clamp(0, 255) as u8;
+                }
+            }
+        }
+        VE => {
+            block = [[avg3(x, a, b), avg3(a, b, c), avg3(b, c, d), avg3(c, d, e)]; 4];
+        }
+        HE => {
+            let rows = [avg3(x, i, j), avg3(i, j, k), avg3(j, k, l), avg3(k, l, l)];
+            block = rows.map(|pixel| [pixel; 4]);
+        }
+        LD => {
+            let diagonal = [
+                avg3(a, b, c),
+                avg3(b, c, d),
+                avg3(c, d, e),
+                avg3(d, e, f),
+                avg3(e, f, g),
+                avg3(f, g, h),
+                avg3(g, h, h),
+            ];
+            block = std::array::from_fn(|y| std::array::from_fn(|x| diagonal[x + y]));
+        }
+        RD => {
+            // From the bottom left to the top right
+            let diagonal = [
+                avg3(j, k, l),
+                avg3(i, j, k),
+                avg3(x, i, j),
+                avg3(a, x, i),
+                avg3(b, a, x),
+                avg3(c, b, a),
+                avg3(d, c, b),
+            ];
+            block = std::array::from_fn(|y| std::array::from_fn(|x| diagonal[3 - y + x]));
+        }
+        VR => {
+            block = [
+                [avg2(x, a), avg2(a, b), avg2(b, c), avg2(c, d)],
+                [avg3(i, x, a), avg3(x, a, b), avg3(a, b, c), avg3(b, c, d)],
+                [avg3(j, i, x), avg2(x, a), avg2(a, b), avg2(b, c)],
+                [avg3(k, j, i), avg3(i, x, a), avg3(x, a, b), avg3(a, b, c)],
+            ];
+        }
+        VL => {
+            block = [
+                [avg2(a, b), avg2(b, c), avg2(c, d), avg2(d, e)],
+                [avg3(a, b, c), avg3(b, c, d), avg3(c, d, e), avg3(d, e, f)],
+                [avg2(b, c), avg2(c, d), avg2(d, e), avg3(e, f, g)],
+                [avg3(b, c, d), avg3(c, d, e), avg3(d, e, f), avg3(f, g, h)],
+            ];
+        }
+        HD => {
+            block = [
+                [avg2(i, x), avg3(i, x, a), avg3(x, a, b), avg3(a, b, c)],
+                [avg2(j, i), avg3(j, i, x), avg2(i, x), avg3(i, x, a)],
+                [avg2(k, j), avg3(k, j, i), avg2(j, i), avg3(j, i, x)],
+                [avg2(l, k), avg3(l, k, j), avg2(k, j), avg3(k, j, i)],
+            ];
+        }
+        _ => {
+            block = [
+                [avg2(i, j), avg3(i, j, k), avg2(j, k), avg3(j, k, l)],
+                [avg2(j, k), avg3(j, k, l), avg2(k, l), avg3(k, l, l)],
+                [avg2(k, l), avg3(k, l, l), l, l],
+                [l; 4],
+            ];
+        }
+    }
+    for (y, row) in block.iter().enumerate() {
+        out[y * stride..][..4].copy_from_slice(row);
+    }
+}
+
+fn needs_filter(plane: &[u8], at: usize, step: usize, limit: i32) -> bool {
+    let (p1, p0) = (plane[at - 2 * step] as i32, plane[at - step] as i32);
+    let (q0, q1) = (plane[at] as i32, plane[at + step] as i32);
+    4 * (p0 - q0).abs() + (p1 - q1).abs() <= 2 * limit + 1
+}
+
+/// Adjust the two pixels nearest the edge, using the two outer ones
+fn filter_common(plane: &mut [u8], at: usize, step: usize) {
+    let (p1, p0) = (plane[at - 2 * step] as i32, plane[at - step] as i32);
+    let (q0, q1) = (plane[at] as i32, plane[at + step] as i32);
+    let a = 3 * (q0 - p0) + (p1 - q1).clamp(-128, 127);
+    let a1 = ((a + 4) >> 3).clamp(-16, 15);
+    let a2 = ((a + 3) >> 3).clamp(-16, 15);
+    plane[at - step] = (p0 + a2).clamp(0, 255) as u8;
+    plane[at] = (q0 - a1).clamp(0, 255) as u8;
+}
+
+/// Simple filter over the 16 pixels of an edge, `step` across it and `along` between them
+fn simple_edge(plane: &mut [u8], at: usize, step: usize, along: usize, limit: i32) {
+    for i in 0..16 {
+        let at = at + i * along;
+        if needs_filter(plane, at, step, limit) {
+            filter_common(plane, at, step);
+        }
+    }
+}
+
+/// Normal filter over the pixels of an edge, changing three pixels on each side of it on
+/// macroblock edges and two inside them
+///
+/// The pixels are `step` apart across the edge and `along` apart along its `size`.
+fn edge_filter(
+    plane: &mut [u8],
+    at: usize,
+    (step, along, size): (usize, usize, usize),
+    filter: &Filter,
+    macroblock: bool,
+) {
+    let limit = filter.limit + 4 * macroblock as i32;
+    for n in 0..size {
+        let at = at + n * along;
+        let [p3, p2, p1, p0, q0, q1, q2, q3] =
+            std::array::from_fn(|i| plane[at + i * step - 4 * step] as i32);
+        if 4 * (p0 - q0).abs() + (p1 - q1).abs() > 2 * limit + 1 {
+            continue;
+        }
+        let steps = [p3 - p2, p2 - p1, p1 - p0, q3 - q2, q2 - q1, q1 - q0];
+        if steps.iter().any(|d| d.abs() > filter.interior) {
+            continue;
+        }
+        let hev = filter.hev_threshold;
+        if (p1 - p0).abs() > hev || (q1 - q0).abs() > hev {
+            filter_common(plane, at, step);
+        } else if macroblock {
+            let a = (3 * (q0 - p0) + (p1 - q1).clamp(-128, 127)).clamp(-128, 127);
+            let [a1, a2, a3] = [27, 18, 9].map(|w| (w * a + 63) >> 7);
+            for (i, value) in [p2 + a3, p1 + a2, p0 + a1, q0 - a1, q1 - a2, q2 - a3]
+                .into_iter()
+                .enumerate()
+            {
+                plane[at + (i + 1) * step - 4 * step] = value.clamp(0, 255) as u8;
+            }
+        } else {
+            let a = 3 * (q0 - p0);
+            let a1 = ((a + 4) >> 3).clamp(-16, 15);
+            let a2 = ((a + 3) >> 3).clamp(-16, 15);
+            let a3 = (a1 + 1) >> 1;
+            for (i, value) in [p1 + a3, p0 + a2, q0 - a1, q1 - a3].into_iter().enumerate() {
+                plane[at + (i + 2) * step - 4 * step] = value.clamp(0, 255) as u8;
+            }
+        }
+    }
+}
+
+/// A row of rgba pixels from its luma and the two chroma rows nearest it, the nearer one
+/// weighing three times as much
+fn upsample_row(
+    luma: &[u8],
+    near: (&[u8], &[u8]),
+    far: (&[u8], &[u8]),
+    out: &mut [u8],
+    width: usize,
+) {
+    let sample = |plane: (&[u8], &[u8]), x: usize| plane.0[x] as u32 | (plane.1[x] as u32) << 16;
+    // Chroma of a pixel between two samples of the row, splitting the packed u and v
+    let mut put = |x: usize, uv: u32| {
+        let rgb = yuv_to_rgb(luma[x], uv as u8, (uv >> 16) as u8);
+        out[x * 4..x * 4 + 3].copy_from_slice(&rgb);
+    };
+    let (mut left_near, mut left_far) = (sample(near, 0), sample(far, 0));
+    put(0, (3 * left_near + left_far + 0x00020002) >> 2);
+    for x in 1..=(width - 1) >> 1 {
+        let (near_uv, far_uv) = (sample(near, x), sample(far, x));
+        let average = left_far + far_uv + left_near + near_uv + 0x00080008;
+        let diagonal_12 = (average + 2 * (far_uv + left_near)) >> 3;
+        let diagonal_03 = (average + 2 * (left_far + near_uv)) >> 3;
+        put(2 * x - 1, ((diagonal_03 + left_near) >> 1) & 0x00ff00ff);
+        put(2 * x, ((diagonal_12 + near_uv) >> 1) & 0x00ff00ff);
+        (left_near, left_far) = (near_uv, far_uv);
+    }
+    if width.is_multiple_of(2) {
+        put(width - 1, (3 * left_near + left_far + 0x00020002) >> 2);
+    }
+}
+
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let high = |value: u8, coeff: i32| (value as i32 * coeff) >> 8;
+    let clip = |value: i32| match value & !16383 {
+        0 => (value >> 6) as u8,
+        _ if value < 0 => 0,
+        _ => 255,
+    };
+    let luma = high(y, 19077);
+    [
+        clip(luma + high(v, 26149) - 14234),
+        clip(luma - high(u, 6419) - high(v, 13320) + 8708),
+        clip(luma + high(u, 33050) - 17685),
+    ]
+}
@@ -3,4 +3,38 @@ cargo test -F async-std
 cargo test -F tokio
 cargo test -F discord
 cargo test -F discord,async-std
-cargo test -F discord,tokio
\ No newline at end of file
+cargo test -F discord,tokio
+cargo test -F discord,regex
+cargo test -F markdown
+cargo test -F shortcode
+cargo test -F discord,shortcode
+cargo test -F gemoji
+cargo test -F discord,markdown
+cargo test -F serenity
+cargo test -F twilight
+cargo test -F poise
+cargo test -F telegram
+cargo test -F teloxide
+cargo test -F matrix
+cargo test -F matrix-sdk
+cargo test -F mastodon
+cargo test -F twitch
+cargo test -F revolt
+cargo test -F guilded
+cargo test -F rocketchat
+cargo test -F teams
+cargo test -F steam
+cargo test -F bbcode
+cargo test -F http
+cargo test -F emoji-url
+cargo test -F svg
+cargo test -F emoji-files
+cargo test -F render
+cargo test -F image
+cargo test -F webp
+cargo test -F render-image
+cargo test -F avif
+cargo test -F tgs
+cargo test -F sprite
+cargo test -F terminal
+cargo test -F sixel